@@ -23,6 +23,31 @@ pub enum CommandMessage {
         kp: f32,
         ki: f32,
     },
+    /// Emergency stop, immediately zeroes the motor targets and turns off the Neato
+    Stop,
+    /// Stop the robot if no command has been received within this many milliseconds, 0
+    /// disables the watchdog
+    SetWatchdogTimeout {
+        ms: u16,
+    },
+    /// Request the PI parameters currently used by the motor controller, answered with
+    /// [`RobotMessage::MotorPiParams`]
+    GetMotorPiParams,
+    /// Persist the current motor PI parameters to flash so they are used after a reboot
+    SaveMotorPiParams,
+    /// Set the speed to spin the Neato at, limited by the firmware to a range it works well in
+    SetNeatoRpm {
+        rpm: u16,
+    },
+    /// Request the uptime and error counters of the firmware, answered with
+    /// [`RobotMessage::Diagnostics`]
+    GetDiagnostics,
+    /// Set the drivetrain geometry used to convert between wheel speeds (and distances) in
+    /// meters and encoder steps
+    SetOdometryParams {
+        steps_per_rev: u32,
+        wheel_diameter_mm: u16,
+    },
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -30,6 +55,21 @@ pub enum CommandMessage {
 pub enum RobotMessage {
     ScanFrame(ScanFrame),
     Pong,
+    /// The PI parameters currently used by the motor controller
+    MotorPiParams {
+        kp: f32,
+        ki: f32,
+    },
+    /// Counters of the errors the firmware has recovered from since boot
+    Diagnostics {
+        uptime_ms: u32,
+        /// Read errors on any of the UARTs
+        uart_overruns: u32,
+        /// Messages dropped because a channel was full
+        channel_full_drops: u32,
+        /// Neato packets that failed the checksum validation
+        neato_bad_packets: u32,
+    },
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
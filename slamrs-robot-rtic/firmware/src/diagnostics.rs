@@ -0,0 +1,30 @@
+//! Counters of the errors that are otherwise only logged, reported to the host on request.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use rtic_monotonics::Monotonic;
+
+use crate::Mono;
+
+/// Read errors (overruns, framing errors etc.) on any of the UARTs
+pub static UART_OVERRUNS: AtomicU32 = AtomicU32::new(0);
+
+/// Messages dropped because the channel they were sent to was full
+pub static CHANNEL_FULL_DROPS: AtomicU32 = AtomicU32::new(0);
+
+/// Packets received from the Neato that failed the checksum validation
+pub static NEATO_BAD_PACKETS: AtomicU32 = AtomicU32::new(0);
+
+/// Add `amount` to one of the counters.
+pub fn count(counter: &AtomicU32, amount: u32) {
+    // the RP2040 has no atomic read-modify-write instructions
+    cortex_m::interrupt::free(|_| {
+        let value = counter.load(Ordering::Relaxed);
+        counter.store(value.wrapping_add(amount), Ordering::Relaxed);
+    });
+}
+
+/// Time since boot in milliseconds.
+pub fn uptime_ms() -> u32 {
+    Mono::now().duration_since_epoch().to_millis() as u32
+}
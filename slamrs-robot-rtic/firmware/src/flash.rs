@@ -0,0 +1,62 @@
+//! Module for persisting settings in the last sector of the on-board flash, which is kept out of
+//! the program region in `memory.x`.
+
+use defmt::{info, warn};
+
+use crate::tasks::motors::{PiParameters, F32};
+
+/// Start of the memory mapped flash
+const XIP_BASE: u32 = 0x1000_0000;
+
+/// Size of the flash on the Pico
+const FLASH_SIZE: u32 = 2048 * 1024;
+
+/// The smallest erasable unit of the flash
+const SECTOR_SIZE: u32 = 4096;
+
+/// The smallest programmable unit of the flash
+const PAGE_SIZE: usize = 256;
+
+/// Offset (from the start of the flash) of the sector used for the settings
+const SETTINGS_OFFSET: u32 = FLASH_SIZE - SECTOR_SIZE;
+
+/// Marks a sector containing valid settings, erased flash reads as all ones. Change this if the
+/// layout of the stored settings changes.
+const MAGIC: u32 = 0x5049_0001;
+
+/// Reads the motor PI parameters stored in flash, if any.
+pub fn load_pi_parameters() -> Option<PiParameters> {
+    // Safety: the settings sector is memory mapped, never part of the program and only written
+    // by `save_pi_parameters` with interrupts disabled
+    let words =
+        unsafe { core::ptr::read_volatile((XIP_BASE + SETTINGS_OFFSET) as *const [u32; 3]) };
+
+    if words[0] != MAGIC {
+        info!("No motor PI parameters stored in flash");
+        return None;
+    }
+
+    Some(PiParameters {
+        kp: F32::from_bits(words[1] as i32),
+        ki: F32::from_bits(words[2] as i32),
+    })
+}
+
+/// Writes the motor PI parameters to flash so they are loaded after the next reboot.
+pub fn save_pi_parameters(params: &PiParameters) {
+    let mut page = [0xFFu8; PAGE_SIZE];
+    page[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    page[4..8].copy_from_slice(&params.kp.to_bits().to_le_bytes());
+    page[8..12].copy_from_slice(&params.ki.to_bits().to_le_bytes());
+
+    // the flash can not be read while it is being written to, so nothing may run from it
+    // (including interrupt handlers) until we are done
+    cortex_m::interrupt::free(|_| unsafe {
+        rp2040_flash::flash::flash_range_erase(SETTINGS_OFFSET, SECTOR_SIZE, true);
+        rp2040_flash::flash::flash_range_program(SETTINGS_OFFSET, &page, true);
+    });
+
+    if load_pi_parameters().is_none() {
+        warn!("Failed to verify the motor PI parameters written to flash");
+    }
+}
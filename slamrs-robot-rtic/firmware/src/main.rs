@@ -1,8 +1,11 @@
 #![no_main]
 #![no_std]
 
+mod diagnostics;
 mod encoder;
+mod flash;
 mod motor;
+mod odometry;
 mod tasks;
 mod util;
 mod ws2812b;
@@ -89,10 +92,8 @@ mod app {
 
     const ROBOT_MESSAGE_CAPACITY: usize = 16;
 
-    const MOTOR_STEPS_PER_REV: i32 = 2000;
-    const MOTOR_WHEEL_DIAMETER: f32 = 0.06; // meters
-    pub const MOTOR_STEPS_PER_METER: f32 =
-        MOTOR_STEPS_PER_REV as f32 / (MOTOR_WHEEL_DIAMETER * core::f32::consts::PI);
+    /// Default time without any commands before the robot is stopped
+    const WATCHDOG_TIMEOUT_MS: u16 = 500;
 
     // Shared resources go here
     #[shared]
@@ -402,7 +403,7 @@ mod app {
                 neato_downsampling: AtomicU8::new(2),
                 motor_speed_right: 0,
                 motor_speed_left: 0,
-                motor_pi_params: Default::default(),
+                motor_pi_params: crate::flash::load_pi_parameters().unwrap_or_default(),
             },
             Local {
                 led_rgb,
@@ -454,9 +455,31 @@ mod app {
     )]
     async fn event_loop(mut cx: event_loop::Context) {
         let mut is_connected = false;
+
+        // the watchdog is armed by the first command and disarmed once it has stopped the robot
+        let mut watchdog_timeout_ms = WATCHDOG_TIMEOUT_MS;
+        let mut watchdog_armed = false;
+        let mut last_command = crate::Mono::now();
+
         loop {
+            let watchdog = async move {
+                if watchdog_armed && watchdog_timeout_ms > 0 {
+                    crate::Mono::delay_until(last_command + (watchdog_timeout_ms as u64).millis()).await;
+                } else {
+                    core::future::pending::<()>().await;
+                }
+            };
+
             futures::select_biased! {
 
+            _ = watchdog.fuse() => {
+                warn!("No command received for {} ms, stopping the robot", watchdog_timeout_ms);
+                watchdog_armed = false;
+                cx.shared.motor_speed_right.lock(|speed| *speed = 0);
+                cx.shared.motor_speed_left.lock(|speed| *speed = 0);
+                crate::tasks::neato::MOTOR_ON.store(false, Ordering::Relaxed);
+            },
+
             _ = crate::Mono::delay(1000.millis()).fuse() => {
                 if is_connected {
                     // Send a ping message to the robot
@@ -468,6 +491,11 @@ mod app {
                 Ok(event) => {
                     info!("Received event: {}", event);
 
+                    if let Event::Command(_) = event {
+                        last_command = crate::Mono::now();
+                        watchdog_armed = true;
+                    }
+
                     match event {
                         Event::Connected => {
                             is_connected = true;
@@ -488,20 +516,56 @@ mod app {
                         Event::Command(CommandMessage::SetDownsampling { every }) => {
                             cx.shared.neato_downsampling.store(every, Ordering::Relaxed);
                         },
+                        Event::Command(CommandMessage::SetNeatoRpm { rpm }) => {
+                            let rpm = rpm.clamp(crate::tasks::neato::MIN_RPM, crate::tasks::neato::MAX_RPM);
+                            crate::tasks::neato::TARGET_RPM.store(rpm, Ordering::Relaxed);
+                        },
                         Event::Command(CommandMessage::SetMotorPiParams { kp, ki }) => {
                                 cx.shared.motor_pi_params.lock(| p| {
                                     p.kp = crate::tasks::motors::F32::from_num(kp);
                                     p.ki = crate::tasks::motors::F32::from_num(ki);
                                 });
                         },
+                        Event::Command(CommandMessage::GetMotorPiParams) => {
+                            let (kp, ki): (f32, f32) = cx.shared.motor_pi_params.lock(|p| (p.kp.to_num(), p.ki.to_num()));
+                            channel_send(cx.local.robot_message_sender, RobotMessage::MotorPiParams { kp, ki }, "event_loop");
+                            channel_send(cx.local.robot_message_sender_usb, RobotMessage::MotorPiParams { kp, ki }, "event_loop");
+                        },
+                        Event::Command(CommandMessage::GetDiagnostics) => {
+                            use crate::diagnostics::*;
+                            let diagnostics = RobotMessage::Diagnostics {
+                                uptime_ms: uptime_ms(),
+                                uart_overruns: UART_OVERRUNS.load(Ordering::Relaxed),
+                                channel_full_drops: CHANNEL_FULL_DROPS.load(Ordering::Relaxed),
+                                neato_bad_packets: NEATO_BAD_PACKETS.load(Ordering::Relaxed),
+                            };
+                            channel_send(cx.local.robot_message_sender, diagnostics, "event_loop");
+                            channel_send(cx.local.robot_message_sender_usb, diagnostics, "event_loop");
+                        },
+                        Event::Command(CommandMessage::SaveMotorPiParams) => {
+                            cx.shared.motor_pi_params.lock(|p| crate::flash::save_pi_parameters(p));
+                            info!("Saved motor PI parameters to flash");
+                        },
                         Event::Command(CommandMessage::Drive { left, right }) => {
+                            let steps_per_meter = crate::odometry::steps_per_meter();
                             cx.shared.motor_speed_right.lock(|speed|{
-                                *speed = (right * MOTOR_STEPS_PER_REV as f32 / (MOTOR_WHEEL_DIAMETER * core::f32::consts::PI)) as i32;
+                                *speed = (right * steps_per_meter) as i32;
                             });
                             cx.shared.motor_speed_left.lock(|speed|{
-                                *speed = (left * MOTOR_STEPS_PER_REV as f32 / (MOTOR_WHEEL_DIAMETER * core::f32::consts::PI)) as i32;
+                                *speed = (left * steps_per_meter) as i32;
                             });
                         },
+                        Event::Command(CommandMessage::SetOdometryParams { steps_per_rev, wheel_diameter_mm }) => {
+                            crate::odometry::set_params(steps_per_rev, wheel_diameter_mm);
+                        },
+                        Event::Command(CommandMessage::Stop) => {
+                            cx.shared.motor_speed_right.lock(|speed| *speed = 0);
+                            cx.shared.motor_speed_left.lock(|speed| *speed = 0);
+                            crate::tasks::neato::MOTOR_ON.store(false, Ordering::Relaxed);
+                        },
+                        Event::Command(CommandMessage::SetWatchdogTimeout { ms }) => {
+                            watchdog_timeout_ms = ms;
+                        },
 
                         _ => {}
                     }
@@ -0,0 +1,34 @@
+//! The drivetrain geometry used to convert between meters and encoder steps, can be changed by
+//! the host to calibrate a specific robot.
+
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+
+use defmt::warn;
+
+/// Encoder steps per revolution of a wheel
+static STEPS_PER_REV: AtomicU32 = AtomicU32::new(2000);
+
+/// Wheel diameter in millimeters
+static WHEEL_DIAMETER_MM: AtomicU16 = AtomicU16::new(60);
+
+/// Replaces the drivetrain geometry, zero values are ignored since they make the conversion
+/// meaningless.
+pub fn set_params(steps_per_rev: u32, wheel_diameter_mm: u16) {
+    if steps_per_rev == 0 || wheel_diameter_mm == 0 {
+        warn!(
+            "Ignoring invalid odometry parameters: {} steps/rev, {} mm",
+            steps_per_rev, wheel_diameter_mm
+        );
+        return;
+    }
+
+    STEPS_PER_REV.store(steps_per_rev, Ordering::Relaxed);
+    WHEEL_DIAMETER_MM.store(wheel_diameter_mm, Ordering::Relaxed);
+}
+
+/// The number of encoder steps for the wheels to travel one meter.
+pub fn steps_per_meter() -> f32 {
+    let steps_per_rev = STEPS_PER_REV.load(Ordering::Relaxed) as f32;
+    let wheel_diameter = WHEEL_DIAMETER_MM.load(Ordering::Relaxed) as f32 / 1000.0;
+    steps_per_rev / (wheel_diameter * core::f32::consts::PI)
+}
@@ -138,7 +138,7 @@ pub async fn init_esp(mut cx: init_esp::Context<'_>) {
 pub fn uart1_esp32(cx: uart1_esp32::Context<'_>) {
     let sender = cx.local.esp_sender;
     let rx = cx.local.uart1_rx;
-    cx.local.parser.consume(rx, move |message| match message {
+    let result = cx.local.parser.consume(rx, move |message| match message {
         ParsedMessage::Simple(m) => channel_send(sender, m, "uart1_esp32"),
         ParsedMessage::ReceivedData(data) => {
             info!("got data: {}", data);
@@ -155,5 +155,11 @@ pub fn uart1_esp32(cx: uart1_esp32::Context<'_>) {
                 "uart1_esp32",
             );
         }
+        ParsedMessage::Discarded(n) => warn!("Dropped {} bytes not fitting the AT parser", n),
     });
+
+    if result.is_err() {
+        crate::diagnostics::count(&crate::diagnostics::UART_OVERRUNS, 1);
+        warn!("UART error while reading from the ESP");
+    }
 }
@@ -4,7 +4,7 @@ use crate::{
     Mono,
 };
 use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
-use defmt::info;
+use defmt::{info, warn};
 use library::slamrs_message::{RobotMessage, ScanFrame};
 use rp_pico::hal::fugit::ExtU64;
 use rtic::Mutex;
@@ -14,6 +14,13 @@ use rtic_monotonics::Monotonic;
 pub static MOTOR_ON: AtomicBool = AtomicBool::new(false);
 pub static LAST_RPM: AtomicU16 = AtomicU16::new(0);
 
+/// The speed to spin the Neato at when it is on
+pub static TARGET_RPM: AtomicU16 = AtomicU16::new(300);
+
+/// The range of target speeds the Neato can be set to, outside of it the scans are unreliable
+pub const MIN_RPM: u16 = 180;
+pub const MAX_RPM: u16 = 350;
+
 pub async fn neato_motor_control(mut cx: neato_motor_control::Context<'_>) {
     // initialize the motor
     cx.shared.motor_controller.lock(|mc| {
@@ -28,7 +35,7 @@ pub async fn neato_motor_control(mut cx: neato_motor_control::Context<'_>) {
         Mono::delay(200.millis()).await;
 
         let rpm_target = if MOTOR_ON.load(Ordering::Relaxed) {
-            300
+            TARGET_RPM.load(Ordering::Relaxed)
         } else {
             0
         };
@@ -63,7 +70,7 @@ pub async fn neato_motor_control(mut cx: neato_motor_control::Context<'_>) {
 }
 
 pub fn uart0_neato(cx: uart0_neato::Context<'_>) {
-    cx.local.parser.consume(cx.local.uart0_rx_neato, |data| {
+    let result = cx.local.parser.consume(cx.local.uart0_rx_neato, |data| {
         // some exponential smoothing on the raw (*64) RPM value
         let rpm = data.parse_rpm_raw();
         *cx.local.rpm_accumulator += rpm as i32 - *cx.local.rpm_average as i32;
@@ -72,6 +79,11 @@ pub fn uart0_neato(cx: uart0_neato::Context<'_>) {
         LAST_RPM.store(rpm, core::sync::atomic::Ordering::Relaxed);
 
         info!("neato rpm: {:?}", rpm);
+
+        let bad_packets = data.bad_packets() as u32;
+        if bad_packets > 0 {
+            crate::diagnostics::count(&crate::diagnostics::NEATO_BAD_PACKETS, bad_packets);
+        }
         // TODO: should we add a data validation check?
         if rpm < 250 && rpm > 350 {
             // THIS WILL NEVER BE TRUE LOL
@@ -94,8 +106,9 @@ pub fn uart0_neato(cx: uart0_neato::Context<'_>) {
         *cx.local.last_odometry_left = odometry_left;
 
         // convert the odometry to meters
-        let odometry_right = odometry_diff_right as f32 / crate::app::MOTOR_STEPS_PER_METER;
-        let odometry_left = odometry_diff_left as f32 / crate::app::MOTOR_STEPS_PER_METER;
+        let steps_per_meter = crate::odometry::steps_per_meter();
+        let odometry_right = odometry_diff_right as f32 / steps_per_meter;
+        let odometry_left = odometry_diff_left as f32 / steps_per_meter;
 
         // need to copy the data to a new array because the data is borrowed from the parser
         let mut scan_data = [0; 1980];
@@ -127,4 +140,9 @@ pub fn uart0_neato(cx: uart0_neato::Context<'_>) {
             "uart0_neato",
         );
     });
+
+    if result.is_err() {
+        crate::diagnostics::count(&crate::diagnostics::UART_OVERRUNS, 1);
+        warn!("UART error while reading from the Neato");
+    }
 }
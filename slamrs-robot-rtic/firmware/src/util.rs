@@ -25,6 +25,7 @@ pub fn channel_send<T: defmt::Format, const N: usize>(
 ) {
     match sender.try_send(value) {
         Err(TrySendError::Full(m)) => {
+            crate::diagnostics::count(&crate::diagnostics::CHANNEL_FULL_DROPS, 1);
             warn!("ESP channel full, failed to send: {} ({})", m, context)
         }
         Err(TrySendError::NoReceiver(m)) => {
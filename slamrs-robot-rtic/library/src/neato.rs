@@ -20,11 +20,13 @@ impl RunningParser {
             state: RunningParserState::LookingForStart { previous_byte: 0 },
         }
     }
+    /// Reads all available bytes and calls `callback` for every complete frame. Returns the
+    /// read error that stopped the reading, if any.
     pub fn consume<R: embedded_hal_nb::serial::Read<u8>>(
         &mut self,
         reader: &mut R,
         mut callback: impl FnMut(NeatoFrame<'_>),
-    ) {
+    ) -> Result<(), R::Error> {
         loop {
             match reader.read() {
                 Ok(byte) => {
@@ -58,16 +60,16 @@ impl RunningParser {
                         }
                     };
                 }
-                Err(nb::Error::WouldBlock) => break,
-                Err(nb::Error::Other(_)) => {
-                    // TODO: what to do here? Return?
-                    break;
-                }
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(e),
             }
         }
     }
 }
 
+/// Length of each packet in a frame
+const PACKET_LEN: usize = 22;
+
 impl<'a> NeatoFrame<'a> {
     /// Parse the raw RPM value (RPM * 64) from the frame
     pub fn parse_rpm_raw(&self) -> u16 {
@@ -81,4 +83,41 @@ impl<'a> NeatoFrame<'a> {
     pub fn parse_rpm(&self) -> u16 {
         self.parse_rpm_raw() / 64
     }
+
+    /// Count the packets in the frame that fail the checksum validation
+    pub fn bad_packets(&self) -> usize {
+        self.data
+            .chunks_exact(PACKET_LEN)
+            .filter(|packet| !checksum_valid(packet))
+            .count()
+    }
+}
+
+fn checksum_valid(packet: &[u8]) -> bool {
+    // the checksum is calculated over the little-endian words of the packet, except the last one
+    let mut chk32: u32 = 0;
+    for word in packet[..PACKET_LEN - 2].chunks_exact(2) {
+        chk32 = (chk32 << 1) + (((word[1] as u32) << 8) | word[0] as u32);
+    }
+
+    let checksum = (chk32 & 0x7FFF) + (chk32 >> 15);
+    let checksum = (checksum & 0x7FFF) as u16;
+
+    checksum == ((packet[21] as u16) << 8) | packet[20] as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bad_packets() {
+        // an all-zero packet has a zero checksum
+        let mut data = [0u8; 1980];
+        assert_eq!(NeatoFrame { data: &data }.bad_packets(), 0);
+
+        data[5] = 1;
+        data[PACKET_LEN * 3 + 10] = 1;
+        assert_eq!(NeatoFrame { data: &data }.bad_packets(), 2);
+    }
 }
@@ -5,6 +5,9 @@ use core::str::FromStr;
 pub enum ParsedMessage<'a> {
     Simple(EspMessage),
     ReceivedData(&'a [u8]),
+    /// This many bytes were dropped because they do not fit in the parser buffer, either the
+    /// data of a `+IPD` message or a line without an end.
+    Discarded(usize),
 }
 
 /// A simple reference-less message received
@@ -73,9 +76,13 @@ impl FromStr for EspMessage {
     }
 }
 
+const IPD_PREFIX: &[u8] = b"+IPD,0,";
+
 pub struct AtParser<const N: usize> {
     buffer: [u8; N],
     index: usize,
+    /// The number of bytes still to drop of a `+IPD` message too large for the buffer.
+    skip: usize,
 }
 
 impl<const N: usize> AtParser<N> {
@@ -83,36 +90,54 @@ impl<const N: usize> AtParser<N> {
         Self {
             buffer: [0; N],
             index: 0,
+            skip: 0,
         }
     }
 
+    /// Reads all available bytes and calls `callback` for every parsed message. Returns the
+    /// read error that stopped the reading, if any.
+    ///
+    /// Data that can never fit in the buffer of `N` bytes is dropped (and reported with
+    /// [`ParsedMessage::Discarded`]) instead of blocking the parser.
     pub fn consume<R: embedded_hal_nb::serial::Read<u8>>(
         &mut self,
         reader: &mut R,
-        callback: impl FnMut(ParsedMessage<'_>),
-    ) {
+        mut callback: impl FnMut(ParsedMessage<'_>),
+    ) -> Result<(), R::Error> {
+        let mut result = Ok(());
+
         // first exhaust the reader, then try to parse the received bytes
         loop {
             match reader.read() {
+                Ok(_) if self.skip > 0 => self.skip -= 1,
                 Ok(data) => {
                     self.buffer[self.index] = data;
                     self.index += 1;
 
                     if self.index >= self.buffer.len() {
-                        // buffer is full, stop reading
-                        break;
+                        // buffer is full, make room by parsing what we have so far
+                        self.process_buffer(&mut callback);
+
+                        if self.index >= self.buffer.len() {
+                            // nothing could be parsed, the buffer will never be emptied
+                            callback(ParsedMessage::Discarded(self.index));
+                            self.index = 0;
+                        }
                     }
                 }
                 Err(nb::Error::WouldBlock) => break,
-                Err(nb::Error::Other(_)) => {
-                    // TODO: what to do here? Return?
+                Err(nb::Error::Other(e)) => {
+                    // still parse what was received before the error
+                    result = Err(e);
                     break;
                 }
             }
         }
 
         // we now have new data, parse the buffer!
-        self.process_buffer(callback)
+        self.process_buffer(callback);
+
+        result
     }
 
     fn process_buffer(&mut self, mut callback: impl FnMut(ParsedMessage<'_>)) {
@@ -128,20 +153,37 @@ impl<const N: usize> AtParser<N> {
 
             // check if the current line starts with any URC (even though we haven't hit
             // \r\n yet
-            if current_data.len() > 7 && &current_data[..7] == b"+IPD,0," {
+            if current_data.len() > IPD_PREFIX.len() && current_data.starts_with(IPD_PREFIX) {
                 // info!("FOUND +IDP URC!");
 
-                match parse_ipd(current_data) {
-                    Ok((used, data)) => {
-                        callback(ParsedMessage::ReceivedData(data));
+                match parse_ipd_header(current_data) {
+                    Ok(Some((header, length))) if header + length > N => {
+                        // can never fit in the buffer, drop the data as it arrives
+                        let buffered = (self.index - header).min(length);
+                        self.skip = length - buffered;
+                        callback(ParsedMessage::Discarded(length));
+
+                        let first_other_byte = header + buffered;
+                        self.buffer.copy_within(first_other_byte..self.index, 0);
+                        self.index -= first_other_byte;
+
+                        found = true;
+                    }
+                    Ok(Some((header, length))) if self.index >= header + length => {
+                        callback(ParsedMessage::ReceivedData(
+                            &current_data[header..header + length],
+                        ));
                         // info!("Received data: {}", data);
                         // reset the buffer by moving the remaining bytes to the front
-                        let first_other_byte = used;
+                        let first_other_byte = header + length;
                         self.buffer.copy_within(first_other_byte..self.index, 0);
-                        self.index = self.index - first_other_byte;
+                        self.index -= first_other_byte;
 
                         found = true;
                     }
+                    // the rest of the header or the data has not arrived yet, the data must
+                    // not be parsed as lines in the meantime
+                    Ok(_) => break,
                     Err(_err) => {
                         // error!("Error parsing IPD: {}", err);
                     }
@@ -193,29 +235,31 @@ impl<const N: usize> AtParser<N> {
 /// Tries to parse the +IPD message and returns a tuple with the number of bytes used as well
 /// as a slice containing the data bytes.
 pub fn parse_ipd<'a>(cmd: &'a [u8]) -> Result<(usize, &'a [u8]), &'static str> {
-    let separator = cmd
-        .iter()
-        .enumerate()
-        .find(|x| x.1 == &b':')
-        .ok_or("No separator found")?
-        .0;
+    let (header, length) = parse_ipd_header(cmd)?.ok_or("No separator found")?;
+
+    let remaining_data = &cmd[header..];
+    if remaining_data.len() >= length {
+        Ok((header + length, &remaining_data[..length]))
+    } else {
+        Err("All data not present")
+    }
+}
 
-    let length_str =
-        core::str::from_utf8(&cmd[7..separator]).map_err(|_| "Length string not valid Utf8 ")?;
+/// Parses the `+IPD,0,<length>:` header and returns the length of the header and the number of
+/// data bytes following it, or `None` if the whole header has not been received yet.
+fn parse_ipd_header(cmd: &[u8]) -> Result<Option<(usize, usize)>, &'static str> {
+    let Some(separator) = cmd.iter().position(|&b| b == b':') else {
+        return Ok(None);
+    };
 
-    let length_usize = length_str
+    let length_str = core::str::from_utf8(&cmd[IPD_PREFIX.len()..separator])
+        .map_err(|_| "Length string not valid Utf8 ")?;
+
+    let length = length_str
         .parse::<usize>()
         .map_err(|_| "Length string is not valid usize")?;
 
-    let remaining_data = &cmd[separator + 1..];
-    if remaining_data.len() >= length_usize {
-        Ok((
-            7 + length_str.len() + 1 + length_usize,
-            &remaining_data[..length_usize],
-        ))
-    } else {
-        Err("All data not present")
-    }
+    Ok(Some((separator + 1, length)))
 }
 
 // #[cfg(all(test, target_arch = "x86_64"))]
@@ -227,6 +271,7 @@ mod tests {
     use std::vec::Vec;
 
     use embedded_hal_nb::serial::Read;
+    use proptest::prelude::*;
 
     use super::*;
 
@@ -238,9 +283,11 @@ mod tests {
 
     impl VecReader {
         fn new(strings: &[&str]) -> Self {
-            let bytes = strings.into_iter().map(|s| s.bytes().collect()).collect();
+            Self::from_bytes(strings.iter().map(|s| s.bytes().collect()).collect())
+        }
+        fn from_bytes(strings: Vec<Vec<u8>>) -> Self {
             Self {
-                strings: bytes,
+                strings,
                 current_word: 0,
                 current_byte: 0,
             }
@@ -326,10 +373,12 @@ mod tests {
 
         let mut parser: AtParser<256> = AtParser::new();
         while !reader.is_exhausted() {
-            parser.consume(&mut reader, |m| match m {
-                ParsedMessage::Simple(m) => found_values.push(m),
-                o => panic!("Unexpected parsed message: {:?}", o),
-            });
+            parser
+                .consume(&mut reader, |m| match m {
+                    ParsedMessage::Simple(m) => found_values.push(m),
+                    o => panic!("Unexpected parsed message: {:?}", o),
+                })
+                .unwrap();
         }
         assert_eq!(
             found_values,
@@ -348,4 +397,140 @@ mod tests {
             ]
         );
     }
+
+    /// Consumes all of `input` and returns the parsed messages.
+    fn consume_all<const N: usize>(input: &[&str]) -> Vec<ParsedMessage<'static>> {
+        consume_reader::<N>(VecReader::new(input))
+    }
+
+    fn consume_reader<const N: usize>(mut reader: VecReader) -> Vec<ParsedMessage<'static>> {
+        let mut parser: AtParser<N> = AtParser::new();
+
+        let mut found = Vec::new();
+        while !reader.is_exhausted() {
+            parser
+                .consume(&mut reader, |m| {
+                    found.push(match m {
+                        ParsedMessage::ReceivedData(data) => {
+                            ParsedMessage::ReceivedData(data.to_vec().leak())
+                        }
+                        ParsedMessage::Simple(m) => ParsedMessage::Simple(m),
+                        ParsedMessage::Discarded(n) => ParsedMessage::Discarded(n),
+                    })
+                })
+                .unwrap();
+        }
+        found
+    }
+
+    #[test]
+    fn test_consume_ipd_split() {
+        // the data contains a line ending that must not be parsed as a message
+        let found = consume_all::<64>(&["OK\r\n+IP", "D,0,", "8:ab\r", "\ncdef", "SEND OK\r\n"]);
+        assert_eq!(
+            found,
+            vec![
+                ParsedMessage::Simple(EspMessage::Ok),
+                ParsedMessage::ReceivedData(b"ab\r\ncdef"),
+                ParsedMessage::Simple(EspMessage::SendOk),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_consume_ipd_overflow() {
+        // the data does not fit in the buffer, but the messages after it are still parsed
+        let data = "0123456789".repeat(5);
+        let ipd = format!("+IPD,0,{}:{}", data.len(), data);
+        let found = consume_all::<16>(&["OK\r\n", &ipd[..20], &ipd[20..], "SEND OK\r\n"]);
+        assert_eq!(
+            found,
+            vec![
+                ParsedMessage::Simple(EspMessage::Ok),
+                ParsedMessage::Discarded(50),
+                ParsedMessage::Simple(EspMessage::SendOk),
+            ]
+        );
+
+        // a line without an end is dropped once the buffer is full
+        let found = consume_all::<16>(&[&data, "\r\nOK\r\n"]);
+        assert_eq!(found.last(), Some(&ParsedMessage::Simple(EspMessage::Ok)));
+        assert!(found.contains(&ParsedMessage::Discarded(16)));
+    }
+
+    /// The lines the ESP sends and the messages they are parsed as.
+    const LINES: &[(&str, EspMessage)] = &[
+        ("OK", EspMessage::Ok),
+        ("ERROR", EspMessage::Error),
+        ("ready", EspMessage::Ready),
+        ("WIFI CONNECTED", EspMessage::WifiConnected),
+        ("WIFI GOT IP", EspMessage::GotIP),
+        ("0,CONNECT", EspMessage::ClientConnect),
+        ("0,CLOSED", EspMessage::ClientDisconnect),
+        (
+            "+CWSTATE:2",
+            EspMessage::WifiStatus(WifiStatus::ConnectedWithIp),
+        ),
+        (">", EspMessage::DataPrompt),
+        ("SEND OK", EspMessage::SendOk),
+    ];
+
+    #[derive(Debug, Clone)]
+    enum Item {
+        Line(&'static str, EspMessage),
+        /// Binary data received in a `+IPD` message, may contain anything including `\r\n`
+        Ipd(Vec<u8>),
+    }
+
+    fn item() -> impl Strategy<Value = Item> {
+        prop_oneof![
+            prop::sample::select(LINES).prop_map(|(line, m)| Item::Line(line, m)),
+            prop::collection::vec(any::<u8>(), 0..64).prop_map(Item::Ipd),
+            // payloads made of line endings and IPD prefixes are the most likely to confuse it
+            prop::collection::vec(prop::sample::select(b"\r\n+IPD,0:".to_vec()), 0..64)
+                .prop_map(Item::Ipd),
+        ]
+    }
+
+    proptest! {
+        /// Any sequence of lines and data, split into chunks of any size, is parsed back into
+        /// exactly the sent messages.
+        #[test]
+        fn prop_consume_interleaved(
+            items in prop::collection::vec(item(), 0..20),
+            chunk_sizes in prop::collection::vec(1usize..32, 1..8),
+        ) {
+            let mut stream = Vec::new();
+            let mut expected = Vec::new();
+            for item in &items {
+                match item {
+                    Item::Line(line, m) => {
+                        stream.extend_from_slice(line.as_bytes());
+                        stream.extend_from_slice(b"\r\n");
+                        expected.push(ParsedMessage::Simple(*m));
+                    }
+                    Item::Ipd(data) => {
+                        stream.extend_from_slice(format!("+IPD,0,{}:", data.len()).as_bytes());
+                        stream.extend_from_slice(data);
+                        expected.push(ParsedMessage::ReceivedData(data));
+                    }
+                }
+            }
+
+            let mut chunks = Vec::new();
+            let mut rest = &stream[..];
+            for &size in chunk_sizes.iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (chunk, remaining) = rest.split_at(size.min(rest.len()));
+                chunks.push(chunk.to_vec());
+                rest = remaining;
+            }
+
+            let found: Vec<ParsedMessage<'_>> =
+                consume_reader::<128>(VecReader::from_bytes(chunks));
+            prop_assert_eq!(found, expected);
+        }
+    }
 }
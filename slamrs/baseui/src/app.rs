@@ -1,56 +1,200 @@
 use std::sync::Arc;
 use web_time::Instant;
 
-use crate::config::Config;
+use crate::config::{Config, ConfigError, Settings};
 use common::{node::Node, world::WorldObj, PerfStats};
 use eframe::{egui, egui_glow, glow};
-use egui::{mutex::Mutex, Label, Pos2, RichText, Sense, Vec2};
+use egui::{mutex::Mutex, Label, Pos2, RichText, Sense, Vec2, Visuals};
 use graphics::{camera::Camera, shaperenderer::ShapeRenderer};
-use nalgebra::{Matrix4, Point2};
+use log::error;
+use nalgebra::{Matrix4, Point2, Vector2};
+use serde::{Deserialize, Serialize};
 
 use crate::editor::ConfigEditor;
+use crate::graph::TopicGraph;
 use pubsub::{ticker::PubSubTicker, PubSub};
 
 pub struct App {
     pubsub_ticker: PubSubTicker,
     nodes: Vec<Box<dyn Node>>,
+    /// The config the nodes were created from, `nodes[i]` belongs to `config.nodes[i]`.
+    config: Config,
+    /// The indices into `nodes` in the order they are drawn, see [`Config::draw_order`]. The
+    /// nodes themselves stay in config order so that they are updated in that order.
+    draw_order: Vec<usize>,
+    /// The problems with how the nodes of `config` are wired together, shown in a window.
+    config_errors: Vec<ConfigError>,
 
     /// Behind an `Arc<Mutex<…>>` so we can pass it to [`egui::PaintCallback`] and paint later.
     world_renderer: Arc<Mutex<WorldRenderer>>,
+    /// If eframe provided a GL context. Without one the world view is disabled and everything
+    /// drawn into the world is thrown away, but the nodes keep running and showing their windows.
+    has_gl: bool,
     config_editor: ConfigEditor,
     config_editor_visible: bool,
+    topic_graph: TopicGraph,
+    topic_graph_visible: bool,
     stats: PerfStats,
 }
 
+/// The parts of the UI that are restored when starting the next session. Window positions
+/// and such are persisted by egui itself.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+struct AppState {
+    camera_position: Vector2<f32>,
+    camera_zoom: f32,
+    config_editor_visible: bool,
+    topic_graph_visible: bool,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            camera_position: Vector2::zeros(),
+            camera_zoom: 1.0,
+            config_editor_visible: true,
+            topic_graph_visible: false,
+        }
+    }
+}
+
 impl App {
     /// Called once before the first frame.
     pub fn new(cc: &eframe::CreationContext<'_>, config: Config) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
-        let gl = cc
-            .gl
-            .as_ref()
-            .expect("You need to run eframe with the glow backend");
+        let state: AppState = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
 
         let mut pubsub = PubSub::new();
 
         // instantiate based on the config
         let nodes: Vec<Box<dyn Node>> = config.instantiate_nodes(&mut pubsub);
 
-        // TODO: do stuff with the config.settings object
-
-        // TODO: remove this once we have processing that is not dependent on UI updates...
-        let ctx = cc.egui_ctx.clone();
+        let mut world_renderer = match &cc.gl {
+            Some(gl) => WorldRenderer::new(ShapeRenderer::new(gl)),
+            None => {
+                error!("No GL context available, running without the world view");
+                WorldRenderer::new(ShapeRenderer::headless())
+            }
+        };
+        world_renderer.background = config
+            .settings
+            .background
+            .unwrap_or_else(|| default_background(cc.egui_ctx.style().visuals.dark_mode));
+        world_renderer
+            .camera
+            .set_view(state.camera_position, state.camera_zoom);
 
         Self {
             nodes,
-            pubsub_ticker: pubsub.to_ticker(move || ctx.request_repaint()),
-            world_renderer: Arc::new(Mutex::new(WorldRenderer::new(gl))),
+            pubsub_ticker: new_ticker(pubsub, &config.settings, &cc.egui_ctx),
+            world_renderer: Arc::new(Mutex::new(world_renderer)),
+            has_gl: cc.gl.is_some(),
             config_editor: ConfigEditor::new(),
-            config_editor_visible: true,
+            config_editor_visible: state.config_editor_visible,
+            topic_graph: TopicGraph::new(&config),
+            topic_graph_visible: state.topic_graph_visible,
             stats: PerfStats::new(),
+            draw_order: config.draw_order(),
+            config_errors: config.validate().err().unwrap_or_default(),
+            config,
+        }
+    }
+
+    /// Pauses or resumes the message distribution as well as all nodes producing data on their own.
+    fn set_paused(&mut self, paused: bool) {
+        if paused {
+            self.pubsub_ticker.pause();
+        } else {
+            self.pubsub_ticker.resume();
+        }
+
+        for n in &mut self.nodes {
+            n.set_paused(paused);
+        }
+    }
+
+    /// Switches to a new config, only terminating and recreating the nodes whose config changed
+    /// so that the others keep their state, see [`Config::kept_nodes`].
+    fn reload(&mut self, config: &Config, ctx: &egui::Context) {
+        let kept = self.config.kept_nodes(config);
+        let changed: Vec<usize> = (0..config.nodes.len())
+            .filter(|&i| kept[i].is_none())
+            .collect();
+
+        // topics are never removed from a running pubsub, so a changed node can not use an
+        // existing topic with a different type
+        let type_conflict = self.pubsub_ticker.with_pubsub(|pubsub| {
+            changed
+                .iter()
+                .flat_map(|&i| config.nodes[i].topics())
+                .any(|t| {
+                    pubsub
+                        .topic_type(&t.name)
+                        .is_some_and(|type_name| type_name != t.type_name)
+                })
+        });
+
+        // the kind of ticker can only be chosen when creating it
+        if type_conflict || config.settings.manual_tick != self.config.settings.manual_tick {
+            // start over with a fresh pubsub
+            for n in &mut self.nodes {
+                n.terminate();
+            }
+            self.nodes.clear();
+
+            let paused = self.pubsub_ticker.is_paused();
+
+            let mut pubsub = PubSub::new();
+            self.nodes = config.instantiate_nodes(&mut pubsub);
+
+            let new_ticker = new_ticker(pubsub, &config.settings, ctx);
+            core::mem::replace(&mut self.pubsub_ticker, new_ticker).stop();
+
+            // keep the pipeline paused
+            self.set_paused(paused);
+        } else {
+            let mut running: Vec<Option<Box<dyn Node>>> = self.nodes.drain(..).map(Some).collect();
+
+            // terminate the nodes that changed or were removed
+            for (j, n) in running.iter_mut().enumerate() {
+                if !kept.contains(&Some(j)) {
+                    if let Some(mut n) = n.take() {
+                        n.terminate();
+                    }
+                }
+            }
+
+            let paused = self.pubsub_ticker.is_paused();
+            self.nodes = self.pubsub_ticker.with_pubsub(|pubsub| {
+                kept.iter()
+                    .enumerate()
+                    .map(|(i, &k)| match k {
+                        Some(j) => running[j].take().expect("a node is only kept once"),
+                        None => {
+                            let mut node = config.nodes[i].instantiate(pubsub, &config.settings);
+                            node.set_paused(paused);
+                            node
+                        }
+                    })
+                    .collect()
+            });
         }
+
+        self.config = config.clone();
+        self.draw_order = config.draw_order();
+        self.config_errors = config.validate().err().unwrap_or_default();
+        self.topic_graph = TopicGraph::new(config);
+
+        self.world_renderer.lock().background = config
+            .settings
+            .background
+            .unwrap_or_else(|| default_background(ctx.style().visuals.dark_mode));
     }
 }
 
@@ -58,11 +202,10 @@ impl eframe::App for App {
     /// Called each time the UI needs repainting, which may be many times per second.
     /// Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`, `Window` or `Area`.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        #[cfg(target_arch = "wasm32")]
-        {
+        if self.pubsub_ticker.is_manual() {
             use std::time::Duration;
-            // On WASM the pubsub does not run in the background so we need to continously
-            // request repaint to keep the simulation and pubsub system running
+            // When the pubsub does not run in the background (always the case on WASM) we need to
+            // continously request repaint to keep the simulation and pubsub system running
             ctx.request_repaint_after(Duration::from_millis((1.0 / 30.0 * 1000.0) as u64));
         }
         let start_time = Instant::now();
@@ -85,6 +228,35 @@ impl eframe::App for App {
                     }
                 });
 
+                ui.menu_button("View", |ui| {
+                    let mut dark_mode = ctx.style().visuals.dark_mode;
+                    if ui.checkbox(&mut dark_mode, "Dark Mode").changed() {
+                        ctx.set_visuals(if dark_mode {
+                            Visuals::dark()
+                        } else {
+                            Visuals::light()
+                        });
+                        // a configured background stays regardless of the theme
+                        if self.config.settings.background.is_none() {
+                            self.world_renderer.lock().background = default_background(dark_mode);
+                        }
+                    }
+
+                    ui.checkbox(&mut self.topic_graph_visible, "Topic Graph");
+                });
+
+                let paused = self.pubsub_ticker.is_paused();
+                if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                    self.set_paused(!paused);
+                }
+                if ui
+                    .add_enabled(paused, egui::Button::new("Step"))
+                    .on_hover_text("Distribute the queued messages once")
+                    .clicked()
+                {
+                    self.pubsub_ticker.step();
+                }
+
                 ui.label(
                     RichText::new(format!(
                         "Render: {:>5} fps",
@@ -119,22 +291,25 @@ impl eframe::App for App {
                     ui.separator();
 
                     if let Some(config) = &self.config_editor.draw(ui) {
-                        // terminate and drop all the nodes
-                        for n in &mut self.nodes {
-                            n.terminate();
-                        }
-                        self.nodes.clear();
-
-                        let mut pubsub = PubSub::new();
-                        self.nodes = config.instantiate_nodes(&mut pubsub);
-
-                        let ctx = ctx.clone();
-                        let new_ticker = pubsub.to_ticker(move || ctx.request_repaint());
-                        core::mem::replace(&mut self.pubsub_ticker, new_ticker).stop();
+                        self.reload(config, ctx);
                     }
                 });
         }
 
+        egui::Window::new("Topic Graph")
+            .open(&mut self.topic_graph_visible)
+            .show(ctx, |ui| {
+                egui::ScrollArea::both().show(ui, |ui| self.topic_graph.ui(ui));
+            });
+
+        if !self.config_errors.is_empty() {
+            egui::Window::new("Config Problems").show(ctx, |ui| {
+                for e in &self.config_errors {
+                    ui.colored_label(egui::Color32::RED, e.to_string());
+                }
+            });
+        }
+
         for n in self.nodes.iter_mut() {
             n.update();
         }
@@ -142,22 +317,58 @@ impl eframe::App for App {
         egui::CentralPanel::default().show(ctx, |ui| {
             // The central panel the region left after adding TopPanel's and SidePanel's
 
+            if self.has_gl {
+                // Add the world paint callback first so that anything the nodes paint
+                // using egui ends up on top of the world
+                Self::custom_painting(&self.world_renderer, ui);
+            } else {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    "OpenGL is not available, so the world view is disabled. The nodes are \
+                     still running.",
+                );
+            }
+
             // Let all nodes do their drawing. Explicit scope for MutexGuard lifetime.
+            // Everything goes into the same shape renderer which is flushed once when painting
+            // the world, so the nodes drawn last end up on top.
             {
                 let mut world = self.world_renderer.lock();
 
                 let mut world_obj = world.as_world_object();
 
-                for n in self.nodes.iter_mut() {
-                    n.draw(ui, &mut world_obj);
+                for &i in &self.draw_order {
+                    self.nodes[i].draw(ui, &mut world_obj);
                 }
-            }
 
-            self.custom_painting(ui);
+                // nothing is going to paint the world
+                if !self.has_gl {
+                    world.sr.discard();
+                }
+            }
         });
 
         self.stats.update(start_time.elapsed());
     }
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // keep the previously stored state rather than losing the camera
+        if !self.has_gl {
+            return;
+        }
+        let (camera_position, camera_zoom) = self.world_renderer.lock().camera.view();
+
+        eframe::set_value(
+            storage,
+            eframe::APP_KEY,
+            &AppState {
+                camera_position,
+                camera_zoom,
+                config_editor_visible: self.config_editor_visible,
+                topic_graph_visible: self.topic_graph_visible,
+            },
+        );
+    }
+
     fn on_exit(&mut self, gl: Option<&glow::Context>) {
         if let Some(gl) = gl {
             self.world_renderer.lock().destroy(gl);
@@ -166,10 +377,10 @@ impl eframe::App for App {
 }
 
 impl App {
-    fn custom_painting(&mut self, ui: &mut egui::Ui) {
+    fn custom_painting(world_renderer: &Arc<Mutex<WorldRenderer>>, ui: &mut egui::Ui) {
         let (rect, response) = ui.allocate_exact_size(
             ui.available_size(), //egui::Vec2::splat(300.0)
-            egui::Sense::drag(),
+            egui::Sense::click_and_drag(),
         );
 
         let zoom_factor = if ui.rect_contains_pointer(rect) {
@@ -200,7 +411,12 @@ impl App {
         drag_delta.y *= -1.0;
 
         let size = rect.size();
-        let world_renderer = self.world_renderer.clone();
+        let world_renderer = world_renderer.clone();
+        {
+            let mut world_renderer = world_renderer.lock();
+            world_renderer.screen_offset = rect.min.to_vec2();
+            world_renderer.clicked = response.clicked();
+        }
 
         let callback = egui::PaintCallback {
             rect,
@@ -214,20 +430,46 @@ impl App {
     }
 }
 
+/// Creates the ticker distributing the messages of `pubsub`, on the UI thread if
+/// [`Settings::manual_tick`] is set and in the background otherwise.
+fn new_ticker(pubsub: PubSub, settings: &Settings, ctx: &egui::Context) -> PubSubTicker {
+    if settings.manual_tick {
+        pubsub.into_manual()
+    } else {
+        // TODO: remove this once we have processing that is not dependent on UI updates...
+        let ctx = ctx.clone();
+        pubsub.to_ticker(move || ctx.request_repaint())
+    }
+}
+
+/// The world background color that goes well with the light or dark egui theme.
+fn default_background(dark_mode: bool) -> [f32; 4] {
+    if dark_mode {
+        [0.35, 0.35, 0.35, 1.0]
+    } else {
+        [1.0, 1.0, 1.0, 1.0]
+    }
+}
+
 pub struct WorldRenderer {
     pub sr: ShapeRenderer,
     camera: Camera,
     pub last_mouse_pos: Point2<f32>,
+    /// If the world view was clicked this frame.
+    clicked: bool,
+    screen_offset: Vec2,
+    background: [f32; 4],
 }
 
 impl WorldRenderer {
-    fn new(gl: &glow::Context) -> Self {
-        // use glow::HasContext as _;
-
+    fn new(sr: ShapeRenderer) -> Self {
         Self {
-            sr: ShapeRenderer::new(gl),
+            sr,
             camera: Camera::new(),
             last_mouse_pos: Point2::new(0.0, 0.0),
+            clicked: false,
+            screen_offset: Vec2::ZERO,
+            background: default_background(false),
         }
     }
 
@@ -239,6 +481,9 @@ impl WorldRenderer {
         WorldObj {
             sr: &mut self.sr,
             last_mouse_pos: self.last_mouse_pos,
+            clicked: self.clicked,
+            camera: &self.camera,
+            screen_offset: self.screen_offset,
         }
     }
 
@@ -250,6 +495,15 @@ impl WorldRenderer {
         pan: Vec2,
         zoom_factor: f32,
     ) {
+        use glow::HasContext as _;
+
+        // clear the world area so it does not depend on the egui theme
+        let [r, g, b, a] = self.background;
+        unsafe {
+            gl.clear_color(r, g, b, a);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+        }
+
         // first update the camera with any zoom and resize change
         self.camera.resize(size);
         self.camera.pan(pan);
@@ -1,35 +1,143 @@
-use std::fs;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::anyhow;
-use common::node::{Node, NodeConfig};
+use anyhow::{anyhow, Context};
+use common::{
+    node::{Node, NodeConfig, TopicDecl, TopicDirection},
+    robot::AxisConvention,
+};
 use pubsub::PubSub;
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 use simulator::SimulatorNodeConfig;
-use slam::{EKFLandmarkSlamNodeConfig, GridMapSlamNodeConfig, IcpPointMapNodeConfig};
+use slam::{
+    EKFLandmarkSlamNodeConfig, FrontierNodeConfig, GridMapSlamNodeConfig, IcpPointMapNodeConfig,
+};
 
 use crate::node::{
-    controls::ControlsNodeConfig, frame_viz::FrameVizualizerNodeConfig,
-    gaussian::GaussianNodeConfig, mouse_position::MousePositionNodeConfig,
-    shape_rendering::ShapeRenderingNodeConfig, splitter::SplitterNodeConfig,
+    calibration::CalibrationNodeConfig, controls::ControlsNodeConfig,
+    error_eval::ErrorEvalNodeConfig, frame_viz::FrameVizualizerNodeConfig,
+    gaussian::GaussianNodeConfig, inspector::InspectorNodeConfig,
+    mouse_position::MousePositionNodeConfig, rate_limiter::CommandRateLimiterNodeConfig,
+    scenario::ScenarioNodeConfig, shape_rendering::ShapeRenderingNodeConfig,
+    splitter::SplitterNodeConfig, transform::FrameTransformNodeConfig,
+    velocity::VelocityCommandNodeConfig,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::node::logger::LoggerNodeConfig;
 #[cfg(not(target_arch = "wasm32"))]
 use neato::{FileLoaderNodeConfig, RobotConnectionNodeConfig};
 
-#[derive(Clone, Deserialize, Default)]
+#[derive(Clone, Deserialize, Default, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Only used from the top-level file, the settings of included files are ignored.
+    #[serde(default)]
     pub settings: Settings,
 
-    pub nodes: Vec<NodeEnum>,
+    /// Other config files to take nodes from, relative to this file. See [`Config::from_file`].
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    #[serde(default)]
+    pub nodes: Vec<NodeEntry>,
 }
 
-#[derive(Clone, Deserialize, Default)]
+#[derive(Clone, Deserialize, Default, PartialEq)]
 pub struct Settings {
     // headless: bool,
+    /// The RGBA clear color of the world view. Defaults to one matching the current theme.
+    #[serde(default)]
+    pub background: Option<[f32; 4]>,
+
+    /// Distribute the messages on the UI thread once per frame instead of in a background thread,
+    /// which is how it always works on wasm. Useful for reproducing timing dependent issues.
+    #[serde(default)]
+    pub manual_tick: bool,
+
+    /// Number of samples per pixel used for anti-aliasing the world view. Fewer samples are tried
+    /// if the requested number is not supported. Only applied when starting the native app.
+    #[serde(default)]
+    pub multisampling: u16,
+
+    /// The axis convention of the data coming from the robot and from recordings, it is
+    /// converted to the internal convention when received.
+    #[serde(default)]
+    pub convention: AxisConvention,
+}
+
+/// A node in the config together with the settings every node has, which are written next to
+/// the settings of the node itself:
+///
+/// ```yaml
+/// - !Visualizer
+///   name: map
+///   z_order: 1
+///   topics: ...
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct NodeEntry {
+    /// Identifies the node across config files, a node in a later file replaces an earlier
+    /// node with the same name. See [`Config::from_file`].
+    pub name: Option<String>,
+    /// Where the node is drawn relative to the others. Nodes are drawn in increasing order,
+    /// nodes with the same order in the order of the config. Everything drawn into the world
+    /// goes into a single `ShapeRenderer` that is only flushed once all nodes have drawn, so a
+    /// node drawn later ends up on top of those drawn before it. See [`Config::draw_order`].
+    pub z_order: i32,
+    pub node: NodeEnum,
 }
 
-#[derive(Clone, Deserialize)]
+impl<'de> Deserialize<'de> for NodeEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = serde_yaml::Value::deserialize(deserializer)?;
+
+        // take the entry settings out so that the node does not see them
+        let mut take = |key: &str| match &mut value {
+            serde_yaml::Value::Tagged(tagged) => tagged
+                .value
+                .as_mapping_mut()
+                .and_then(|mapping| mapping.remove(key)),
+            _ => None,
+        };
+        let name = take("name")
+            .map(serde_yaml::from_value)
+            .transpose()
+            .map_err(de::Error::custom)?;
+        let z_order = take("z_order")
+            .map(serde_yaml::from_value)
+            .transpose()
+            .map_err(de::Error::custom)?
+            .unwrap_or_default();
+
+        Ok(NodeEntry {
+            name,
+            z_order,
+            node: serde_yaml::from_value(value).map_err(de::Error::custom)?,
+        })
+    }
+}
+
+impl NodeEntry {
+    /// The name of the node if it has one, otherwise its kind.
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.node.name())
+    }
+
+    pub fn topics(&self) -> Vec<TopicDecl> {
+        self.node.topics()
+    }
+
+    pub fn instantiate(&self, pubsub: &mut PubSub, settings: &Settings) -> Box<dyn Node> {
+        self.node.instantiate(pubsub, settings)
+    }
+}
+
+#[derive(Clone, Deserialize, PartialEq)]
 pub enum NodeEnum {
     Simulator(SimulatorNodeConfig),
     Controls(ControlsNodeConfig),
@@ -45,10 +153,86 @@ pub enum NodeEnum {
     GaussianTest(GaussianNodeConfig),
     Splitter(SplitterNodeConfig),
     EKFLandmarkSlam(EKFLandmarkSlamNodeConfig),
+    FrameTransform(FrameTransformNodeConfig),
+    Scenario(ScenarioNodeConfig),
+    ErrorEval(ErrorEvalNodeConfig),
+    Inspector(InspectorNodeConfig),
+    Calibration(CalibrationNodeConfig),
+    CommandRateLimiter(CommandRateLimiterNodeConfig),
+    VelocityCommand(VelocityCommandNodeConfig),
+    Frontier(FrontierNodeConfig),
+    #[cfg(not(target_arch = "wasm32"))]
+    Logger(LoggerNodeConfig),
 }
 
 impl NodeEnum {
-    fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
+    fn name(&self) -> &'static str {
+        use NodeEnum::*;
+        match self {
+            Simulator(_) => "Simulator",
+            Controls(_) => "Controls",
+            MousePosition(_) => "MousePosition",
+            ShapeTest(_) => "ShapeTest",
+            #[cfg(not(target_arch = "wasm32"))]
+            FileLoader(_) => "FileLoader",
+            #[cfg(not(target_arch = "wasm32"))]
+            RobotConnection(_) => "RobotConnection",
+            IcpPointMapper(_) => "IcpPointMapper",
+            Visualizer(_) => "Visualizer",
+            GridMapSlam(_) => "GridMapSlam",
+            GaussianTest(_) => "GaussianTest",
+            Splitter(_) => "Splitter",
+            EKFLandmarkSlam(_) => "EKFLandmarkSlam",
+            FrameTransform(_) => "FrameTransform",
+            Scenario(_) => "Scenario",
+            ErrorEval(_) => "ErrorEval",
+            Inspector(_) => "Inspector",
+            Calibration(_) => "Calibration",
+            CommandRateLimiter(_) => "CommandRateLimiter",
+            VelocityCommand(_) => "VelocityCommand",
+            Frontier(_) => "Frontier",
+            #[cfg(not(target_arch = "wasm32"))]
+            Logger(_) => "Logger",
+        }
+    }
+
+    fn config(&self) -> &dyn NodeConfig {
+        use NodeEnum::*;
+        match self {
+            Simulator(c) => c,
+            Controls(c) => c,
+            MousePosition(c) => c,
+            ShapeTest(c) => c,
+            #[cfg(not(target_arch = "wasm32"))]
+            FileLoader(c) => c,
+            #[cfg(not(target_arch = "wasm32"))]
+            RobotConnection(c) => c,
+            IcpPointMapper(c) => c,
+            Visualizer(c) => c,
+            GridMapSlam(c) => c,
+            GaussianTest(c) => c,
+            Splitter(c) => c,
+            EKFLandmarkSlam(c) => c,
+            FrameTransform(c) => c,
+            Scenario(c) => c,
+            ErrorEval(c) => c,
+            Inspector(c) => c,
+            Calibration(c) => c,
+            CommandRateLimiter(c) => c,
+            VelocityCommand(c) => c,
+            Frontier(c) => c,
+            #[cfg(not(target_arch = "wasm32"))]
+            Logger(c) => c,
+        }
+    }
+
+    pub fn topics(&self) -> Vec<TopicDecl> {
+        self.config().topics()
+    }
+
+    /// Creates the node, the global `settings` are passed on to the nodes that need them.
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    pub fn instantiate(&self, pubsub: &mut PubSub, settings: &Settings) -> Box<dyn Node> {
         use NodeEnum::*;
         match self {
             Simulator(c) => c.instantiate(pubsub),
@@ -56,35 +240,451 @@ impl NodeEnum {
             MousePosition(c) => c.instantiate(pubsub),
             ShapeTest(c) => c.instantiate(pubsub),
             #[cfg(not(target_arch = "wasm32"))]
-            FileLoader(c) => c.instantiate(pubsub),
+            FileLoader(c) => c.with_convention(settings.convention).instantiate(pubsub),
             #[cfg(not(target_arch = "wasm32"))]
-            RobotConnection(c) => c.instantiate(pubsub),
+            RobotConnection(c) => c.with_convention(settings.convention).instantiate(pubsub),
             IcpPointMapper(c) => c.instantiate(pubsub),
             Visualizer(c) => c.instantiate(pubsub),
             GridMapSlam(c) => c.instantiate(pubsub),
             GaussianTest(c) => c.instantiate(pubsub),
             Splitter(c) => c.instantiate(pubsub),
             EKFLandmarkSlam(c) => c.instantiate(pubsub),
+            FrameTransform(c) => c.instantiate(pubsub),
+            Scenario(c) => c.instantiate(pubsub),
+            ErrorEval(c) => c.instantiate(pubsub),
+            Inspector(c) => c.instantiate(pubsub),
+            Calibration(c) => c.instantiate(pubsub),
+            CommandRateLimiter(c) => c.instantiate(pubsub),
+            VelocityCommand(c) => c.instantiate(pubsub),
+            Frontier(c) => c.instantiate(pubsub),
+            #[cfg(not(target_arch = "wasm32"))]
+            Logger(c) => c.instantiate(pubsub),
         }
     }
 }
 
 impl Config {
+    /// Reads a config file and resolves its includes. The nodes of the included files come
+    /// first, in the order they are listed, followed by the nodes of the file itself. A node
+    /// with a `name` replaces the earlier node with the same name, in its place. A node that is
+    /// exactly the same as an earlier one (e.g. from a file included twice) is only created once.
     pub fn from_file(path: &String) -> anyhow::Result<Self> {
+        Self::from_file_included(Path::new(path), &mut Vec::new())
+    }
+
+    /// `stack` holds the files currently being read, to detect include cycles.
+    fn from_file_included(path: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<Self> {
+        let path = fs::canonicalize(path)
+            .with_context(|| format!("Could not find config file {}", path.display()))?;
+
+        if let Some(start) = stack.iter().position(|p| *p == path) {
+            let cycle: Vec<String> = stack[start..]
+                .iter()
+                .chain([&path])
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(anyhow!(
+                "Config files include each other: {}",
+                cycle.join(" -> ")
+            ));
+        }
+
         // read file contents
-        let contents = fs::read_to_string(path)?;
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        let mut config = Self::from_contents(&contents)
+            .with_context(|| format!("Could not parse config file {}", path.display()))?;
+
+        let directory = path.parent().unwrap_or(Path::new(".")).to_owned();
+        stack.push(path.clone());
+        let mut nodes = Vec::new();
+        for include in &config.include {
+            let included = Self::from_file_included(&directory.join(include), stack)
+                .with_context(|| format!("Included from {}", path.display()))?;
+            merge_nodes(&mut nodes, included.nodes);
+        }
+        stack.pop();
+
+        merge_nodes(&mut nodes, std::mem::take(&mut config.nodes));
+        config.nodes = nodes;
 
-        Self::from_contents(&contents)
+        Ok(config)
     }
 
     pub fn from_contents(contents: &str) -> anyhow::Result<Self> {
         serde_yaml::from_str(contents).map_err(|e| anyhow!(e))
     }
 
+    /// Returns a descriptive name and the declared topics for each of the nodes.
+    pub fn node_topics(&self) -> Vec<(String, Vec<TopicDecl>)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (format!("{} (#{})", n.name(), i), n.topics()))
+            .collect()
+    }
+
+    /// Checks that the nodes are wired together correctly, i.e., that each topic is only used
+    /// with a single type and that all subscribed topics have a publisher. All problems found are
+    /// returned.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+
+        // the first node to use a topic decides its type
+        let mut types: HashMap<&str, (String, &'static str)> = HashMap::new();
+        let mut subscriptions = Vec::new();
+
+        let declarations: Vec<(String, TopicDecl)> = self
+            .node_topics()
+            .into_iter()
+            .flat_map(|(node, topics)| topics.into_iter().map(move |t| (node.clone(), t)))
+            .collect();
+
+        for (node, topic) in &declarations {
+            if let Some((first_node, first_type)) = types.get(topic.name.as_str()) {
+                if *first_type != topic.type_name {
+                    errors.push(ConfigError::TypeMismatch {
+                        topic: topic.name.clone(),
+                        node: node.clone(),
+                        type_name: topic.type_name,
+                        first_node: first_node.clone(),
+                        first_type,
+                    });
+                }
+            } else {
+                types.insert(&topic.name, (node.clone(), topic.type_name));
+            }
+
+            if topic.direction == TopicDirection::Sub {
+                subscriptions.push((node, topic));
+            }
+        }
+
+        for (node, topic) in subscriptions {
+            let has_publisher = declarations
+                .iter()
+                .any(|(_, t)| t.direction == TopicDirection::Pub && t.name == topic.name);
+
+            if !has_publisher {
+                errors.push(ConfigError::NoPublisher {
+                    topic: topic.name.clone(),
+                    node: node.clone(),
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     pub fn instantiate_nodes(&self, pubsub: &mut PubSub) -> Vec<Box<dyn Node>> {
         self.nodes
             .iter()
-            .map(|config| config.instantiate(pubsub))
+            .map(|config| config.instantiate(pubsub, &self.settings))
             .collect()
     }
+
+    /// The indices of the nodes in the order they should be drawn, sorted by their `z_order`.
+    /// The sort is stable so nodes with the same `z_order` keep their order from the config.
+    pub fn draw_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|&i| self.nodes[i].z_order);
+        order
+    }
+
+    /// For each node of the `next` config, the index of the node of this config that can keep
+    /// running in its place, `None` if it has to be created. A node is kept if `next` has a node
+    /// of the same kind with the same config, wherever it is in the list, so that adding or
+    /// moving a node does not restart the others. Nothing is kept if the settings changed since
+    /// the nodes are created using them.
+    pub fn kept_nodes(&self, next: &Config) -> Vec<Option<usize>> {
+        let settings_changed = next.settings.convention != self.settings.convention;
+        let mut claimed = vec![false; self.nodes.len()];
+
+        next.nodes
+            .iter()
+            .map(|node| {
+                if settings_changed {
+                    return None;
+                }
+
+                let j = (0..self.nodes.len())
+                    .find(|&j| !claimed[j] && self.nodes[j].node == node.node)?;
+                claimed[j] = true;
+                Some(j)
+            })
+            .collect()
+    }
+}
+
+/// Adds the `later` nodes to `nodes`, replacing the nodes with the same name and skipping the
+/// ones that are already there.
+fn merge_nodes(nodes: &mut Vec<NodeEntry>, later: Vec<NodeEntry>) {
+    for node in later {
+        let same_name = node
+            .name
+            .as_ref()
+            .and_then(|name| nodes.iter().position(|n| n.name.as_ref() == Some(name)));
+
+        if let Some(i) = same_name {
+            nodes[i] = node;
+        } else if !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+}
+
+/// A problem with how the nodes in a [`Config`] are wired together.
+#[derive(Debug, Clone)]
+pub enum ConfigError {
+    /// The topic is used with a different type than the first node that used it.
+    TypeMismatch {
+        topic: String,
+        node: String,
+        type_name: &'static str,
+        first_node: String,
+        first_type: &'static str,
+    },
+    /// The topic is subscribed to, but no node publishes to it.
+    NoPublisher { topic: String, node: String },
+}
+
+impl ConfigError {
+    /// The nodes of a config with a fatal error can not be created, the pubsub panics when a
+    /// topic is used with two types. The other errors only leave some nodes without data.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, ConfigError::TypeMismatch { .. })
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::TypeMismatch {
+                topic,
+                node,
+                type_name,
+                first_node,
+                first_type,
+            } => write!(
+                f,
+                "{node} uses topic '{topic}' with type '{type_name}', but {first_node} uses it with type '{first_type}'"
+            ),
+            ConfigError::NoPublisher { topic, node } => {
+                write!(f, "{node} subscribes to topic '{topic}' that nobody publishes to")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(contents: &str) -> Config {
+        Config::from_contents(contents).unwrap()
+    }
+
+    /// A node config that is easy to tell apart by its `topic`.
+    fn velocity(topic: &str) -> String {
+        format!("- !VelocityCommand {{topic_input: {topic}, topic_output: cmd, wheel_base: 0.2}}\n")
+    }
+
+    /// A directory that is removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn join(&self, file: &str) -> PathBuf {
+            self.0.join(file)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes the `files` (name and contents) to a new directory.
+    fn config_dir(name: &str, files: &[(&str, String)]) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("slamrs-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        TempDir(dir)
+    }
+
+    fn from_file(path: PathBuf) -> anyhow::Result<Config> {
+        Config::from_file(&path.display().to_string())
+    }
+
+    /// The message of the error from reading the config at `path`, with all its causes.
+    fn load_error(path: PathBuf) -> String {
+        match from_file(path) {
+            Ok(_) => panic!("the config was read without an error"),
+            Err(e) => format!("{e:#}"),
+        }
+    }
+
+    #[test]
+    fn includes_come_first() {
+        let dir = config_dir(
+            "includes",
+            &[
+                (
+                    "main.yaml",
+                    format!("include: [a.yaml, b.yaml]\nnodes:\n{}", velocity("main")),
+                ),
+                (
+                    "a.yaml",
+                    format!("include: [common.yaml]\nnodes:\n{}", velocity("a")),
+                ),
+                ("b.yaml", "include: [common.yaml]".to_owned()),
+                ("common.yaml", format!("nodes:\n{}", velocity("common"))),
+            ],
+        );
+
+        let nodes = from_file(dir.join("main.yaml")).unwrap().nodes;
+        let expected = config(&format!(
+            "nodes:\n{}{}{}",
+            velocity("common"),
+            velocity("a"),
+            velocity("main")
+        ));
+        // the file included twice only adds its node once
+        assert!(nodes == expected.nodes);
+    }
+
+    #[test]
+    fn named_nodes_are_replaced_by_later_files() {
+        let drive = |topic_output: &str| {
+            format!(
+                "- !VelocityCommand {{name: drive, topic_input: in, topic_output: {topic_output}, \
+                 wheel_base: 0.2}}\n"
+            )
+        };
+        let dir = config_dir(
+            "replace",
+            &[
+                (
+                    "main.yaml",
+                    format!("include: [base.yaml]\nnodes:\n{}", drive("fast")),
+                ),
+                (
+                    "base.yaml",
+                    format!("nodes:\n{}{}", drive("slow"), velocity("other")),
+                ),
+            ],
+        );
+
+        let nodes = from_file(dir.join("main.yaml")).unwrap().nodes;
+        let expected = config(&format!("nodes:\n{}{}", drive("fast"), velocity("other")));
+        // replaced in place, keeping the order of the base file
+        assert!(nodes == expected.nodes);
+        assert_eq!(nodes[0].name(), "drive");
+        assert_eq!(nodes[1].name(), "VelocityCommand");
+    }
+
+    #[test]
+    fn missing_include_is_an_error() {
+        let dir = config_dir(
+            "missing",
+            &[("main.yaml", "include: [missing.yaml]".to_owned())],
+        );
+
+        let error = load_error(dir.join("main.yaml"));
+        assert!(error.contains("missing.yaml"), "{error}");
+    }
+
+    #[test]
+    fn include_cycle_is_an_error() {
+        let dir = config_dir(
+            "cycle",
+            &[
+                ("main.yaml", "include: [a.yaml]".to_owned()),
+                ("a.yaml", "include: [b.yaml]".to_owned()),
+                ("b.yaml", "include: [a.yaml]".to_owned()),
+            ],
+        );
+
+        let error = load_error(dir.join("main.yaml"));
+        assert!(error.contains("include each other"), "{error}");
+    }
+
+    #[test]
+    fn only_type_mismatches_are_fatal() {
+        // nobody publishes the input
+        let errors = config(&format!("nodes:\n{}", velocity("a")))
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(!errors[0].is_fatal());
+
+        // the output of the first node is a command, not a velocity
+        let errors = config(&format!("nodes:\n{}{}", velocity("a"), velocity("cmd")))
+            .validate()
+            .unwrap_err();
+        assert!(errors.iter().any(|e| e.is_fatal()));
+    }
+
+    #[test]
+    fn shipped_configs_are_wired_with_matching_types() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../config");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "yaml") {
+                let config = from_file(path.clone()).unwrap();
+                let errors = config.validate().err().unwrap_or_default();
+                let fatal: Vec<_> = errors.iter().filter(|e| e.is_fatal()).collect();
+                assert!(fatal.is_empty(), "{}: {fatal:?}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn draw_order_sorts_by_z_order() {
+        let config = config(
+            "nodes:
+- !MousePosition {z_order: 1}
+- !MousePosition
+- !ShapeTest {z_order: -1}
+- !MousePosition {z_order: 0}
+- !ShapeTest {z_order: 1}
+- !VelocityCommand {topic_input: a, topic_output: b, wheel_base: 0.2, z_order: -2}
+",
+        );
+
+        // nodes with the same `z_order` keep their order from the config
+        assert_eq!(config.draw_order(), vec![5, 2, 1, 3, 0, 4]);
+    }
+
+    #[test]
+    fn nodes_are_kept_by_config_not_position() {
+        let before = config(&format!("nodes:\n{}{}", velocity("a"), velocity("b")));
+
+        // a node inserted in front does not restart the others
+        let after = config(&format!(
+            "nodes:\n{}{}{}",
+            velocity("c"),
+            velocity("a"),
+            velocity("b")
+        ));
+        assert_eq!(before.kept_nodes(&after), vec![None, Some(0), Some(1)]);
+
+        // each running node is kept in place of at most one node
+        let after = config(&format!("nodes:\n{}{}", velocity("b"), velocity("b")));
+        assert_eq!(before.kept_nodes(&after), vec![Some(1), None]);
+
+        // the nodes are created using the convention
+        let after = config(&format!(
+            "settings: {{convention: NeatoXV11}}\nnodes:\n{}{}",
+            velocity("a"),
+            velocity("b")
+        ));
+        assert_eq!(before.kept_nodes(&after), vec![None, None]);
+    }
 }
@@ -76,9 +76,16 @@ nodes:
             // show the result of trying to convert it to a valid config object
             if let Some(parsed_config) = &self.parsed_config {
                 match parsed_config {
-                    Ok(c) => {
-                        ui.label(format!("OK ({} nodes)", c.nodes.len()));
-                    }
+                    Ok(c) => match c.validate() {
+                        Ok(()) => {
+                            ui.label(format!("OK ({} nodes)", c.nodes.len()));
+                        }
+                        Err(errors) => {
+                            for e in errors {
+                                ui.label(format!("WARN: {}", e));
+                            }
+                        }
+                    },
                     Err(e) => {
                         ui.label(format!("ERR:\n{}", e));
                     }
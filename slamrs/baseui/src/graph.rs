@@ -0,0 +1,209 @@
+use common::node::TopicDirection;
+use eframe::egui;
+use egui::{Align2, FontId, Pos2, Rect, Sense, Stroke, Vec2};
+
+use crate::config::Config;
+
+const NODE_SIZE: Vec2 = Vec2::new(160.0, 30.0);
+const SPACING: Vec2 = Vec2::new(240.0, 60.0);
+const MARGIN: f32 = 10.0;
+
+/// Shows how the nodes of a [`Config`] are connected through their topics, with
+/// publishers to the left of their subscribers.
+pub struct TopicGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+struct GraphNode {
+    name: String,
+    layer: usize,
+    row: usize,
+}
+
+/// A topic connecting a publishing node to a subscribing node.
+struct GraphEdge {
+    from: usize,
+    to: usize,
+    topic: String,
+}
+
+/// Marks the edges that lead back to a node on the current path of a depth first search,
+/// including edges from a node to itself. Without them the graph has no cycles.
+fn back_edges(n_nodes: usize, edges: &[GraphEdge]) -> Vec<bool> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        OnPath,
+        Done,
+    }
+
+    fn visit(node: usize, edges: &[GraphEdge], state: &mut [State], back: &mut [bool]) {
+        state[node] = State::OnPath;
+        for (i, e) in edges.iter().enumerate().filter(|(_, e)| e.from == node) {
+            match state[e.to] {
+                State::New => visit(e.to, edges, state, back),
+                State::OnPath => back[i] = true,
+                State::Done => {}
+            }
+        }
+        state[node] = State::Done;
+    }
+
+    let mut state = vec![State::New; n_nodes];
+    let mut back = vec![false; edges.len()];
+    for node in 0..n_nodes {
+        if state[node] == State::New {
+            visit(node, edges, &mut state, &mut back);
+        }
+    }
+    back
+}
+
+impl TopicGraph {
+    pub fn new(config: &Config) -> Self {
+        let node_topics = config.node_topics();
+
+        // connect every publisher of a topic to all its subscribers
+        let mut edges = Vec::new();
+        for (from, (_, topics)) in node_topics.iter().enumerate() {
+            for publish in topics.iter().filter(|t| t.direction == TopicDirection::Pub) {
+                for (to, (_, topics)) in node_topics.iter().enumerate() {
+                    if topics
+                        .iter()
+                        .any(|t| t.direction == TopicDirection::Sub && t.name == publish.name)
+                    {
+                        edges.push(GraphEdge {
+                            from,
+                            to,
+                            topic: publish.name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // longest path layering, ignoring the edges that close a cycle (nodes often publish
+        // feedback to the nodes feeding them) so that every layer is below the number of nodes
+        let back_edges = back_edges(node_topics.len(), &edges);
+        let mut layers = vec![0; node_topics.len()];
+        for _ in 0..node_topics.len() {
+            let mut changed = false;
+            for (e, _) in edges.iter().zip(&back_edges).filter(|(_, &back)| !back) {
+                if layers[e.to] < layers[e.from] + 1 {
+                    layers[e.to] = layers[e.from] + 1;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // stack the nodes within each layer on top of each other
+        let mut rows = vec![0; node_topics.len()];
+        let nodes = node_topics
+            .into_iter()
+            .zip(layers)
+            .map(|((name, _), layer)| {
+                let row = rows[layer];
+                rows[layer] += 1;
+                GraphNode { name, layer, row }
+            })
+            .collect();
+
+        Self { nodes, edges }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        if self.nodes.is_empty() {
+            ui.label("No nodes");
+            return;
+        }
+
+        let layers = self.nodes.iter().map(|n| n.layer + 1).max().unwrap_or(0);
+        let rows = self.nodes.iter().map(|n| n.row + 1).max().unwrap_or(0);
+
+        let size = Vec2::new(
+            layers as f32 * SPACING.x - (SPACING.x - NODE_SIZE.x),
+            rows as f32 * SPACING.y - (SPACING.y - NODE_SIZE.y),
+        ) + Vec2::splat(2.0 * MARGIN);
+
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let origin = response.rect.min + Vec2::splat(MARGIN);
+
+        let node_rect = |n: &GraphNode| {
+            Rect::from_min_size(
+                origin + Vec2::new(n.layer as f32 * SPACING.x, n.row as f32 * SPACING.y),
+                NODE_SIZE,
+            )
+        };
+
+        let visuals = ui.visuals();
+        let text_color = visuals.text_color();
+        let edge_stroke = Stroke::new(1.0_f32, visuals.weak_text_color());
+
+        for e in &self.edges {
+            let from = node_rect(&self.nodes[e.from]).right_center();
+            let to = node_rect(&self.nodes[e.to]).left_center();
+
+            painter.arrow(from, to - from, edge_stroke);
+            painter.text(
+                Pos2::new((from.x + to.x) / 2.0, (from.y + to.y) / 2.0),
+                Align2::CENTER_BOTTOM,
+                &e.topic,
+                FontId::proportional(10.0),
+                text_color,
+            );
+        }
+
+        for n in &self.nodes {
+            let rect = node_rect(n);
+            painter.rect_filled(rect, 4.0, visuals.extreme_bg_color);
+            painter.rect_stroke(rect, 4.0, visuals.widgets.noninteractive.fg_stroke);
+            painter.text(
+                rect.center(),
+                Align2::CENTER_CENTER,
+                &n.name,
+                FontId::proportional(12.0),
+                text_color,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_are_layered() {
+        // the limiters feed each other, the last one also feeds itself
+        let config = Config::from_contents(
+            "nodes:
+- !CommandRateLimiter {topic_input: a, topic_output: b}
+- !CommandRateLimiter {topic_input: b, topic_output: c}
+- !CommandRateLimiter {topic_input: c, topic_output: a}
+- !CommandRateLimiter {topic_input: d, topic_output: d}
+",
+        )
+        .unwrap();
+
+        let graph = TopicGraph::new(&config);
+        assert_eq!(graph.edges.len(), 4);
+        let layers: Vec<usize> = graph.nodes.iter().map(|n| n.layer).collect();
+        assert_eq!(layers, vec![0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn shipped_configs_have_a_graph() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../config");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "yaml") {
+                let config = Config::from_file(&path.display().to_string()).unwrap();
+                TopicGraph::new(&config);
+            }
+        }
+    }
+}
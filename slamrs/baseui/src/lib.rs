@@ -3,4 +3,5 @@ pub use app::App;
 
 pub mod config;
 mod editor;
+mod graph;
 mod node;
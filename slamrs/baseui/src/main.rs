@@ -32,23 +32,48 @@ fn main() -> Result<(), eframe::Error> {
         Config::default()
     };
 
-    let native_options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([1280.0, 720.])
-            .with_resizable(true),
-        //multisampling: 8, // does not seem to work on my laptop
-        renderer: eframe::Renderer::Glow,
-        ..Default::default()
-    };
+    // fail early if the nodes can not be wired together, the other problems are shown in the app
+    if let Err(errors) = config.validate() {
+        if errors.iter().any(|e| e.is_fatal()) {
+            eprintln!("Invalid config file:");
+            for e in errors.iter().filter(|e| e.is_fatal()) {
+                eprintln!("  {e}");
+            }
+            std::process::exit(1);
+        }
+    }
+
+    // fall back to fewer samples if the requested number is not supported
+    let mut multisampling = config.settings.multisampling;
+    loop {
+        let native_options = eframe::NativeOptions {
+            viewport: egui::ViewportBuilder::default()
+                .with_inner_size([1280.0, 720.])
+                .with_resizable(true),
+            multisampling,
+            renderer: eframe::Renderer::Glow,
+            ..Default::default()
+        };
 
-    eframe::run_native(
-        "Base UI",
-        native_options,
-        Box::new(|cc| {
-            set_style(&cc.egui_ctx);
-            Ok(Box::new(baseui::App::new(cc, config)))
-        }),
-    )
+        let config = config.clone();
+        let result = eframe::run_native(
+            "Base UI",
+            native_options,
+            Box::new(move |cc| {
+                log::info!("Using {multisampling}x multisampling");
+                set_style(&cc.egui_ctx);
+                Ok(Box::new(baseui::App::new(cc, config)))
+            }),
+        );
+
+        match result {
+            Err(e) if multisampling > 0 => {
+                log::warn!("Could not start with {multisampling}x multisampling: {e}");
+                multisampling /= 2;
+            }
+            result => return result,
+        }
+    }
 }
 
 // when compiling to web using trunk.
@@ -0,0 +1,434 @@
+use std::sync::Arc;
+
+use common::{
+    math::angle_diff,
+    node::{Node, NodeConfig, TopicDecl},
+    robot::{Command, Observation, Odometry, Pose},
+    world::WorldObj,
+};
+use eframe::egui;
+use egui::RichText;
+use pubsub::{PubSub, Publisher, Subscription};
+use serde::Deserialize;
+use web_time::Instant;
+
+/// Calibrates the wheel odometry against the pose estimated by SLAM. Drives straight for a
+/// known distance and then turns a full revolution on the spot, and compares how far the
+/// wheels say the robot moved with how far SLAM says it moved. From that it suggests the wheel
+/// diameter and the wheel base to put into the `RobotConnection` (or `Simulator`) config.
+pub struct CalibrationNode {
+    sub_odometry: Subscription<(Observation, Odometry)>,
+    sub_pose: Subscription<Pose>,
+    pub_cmd: Publisher<Command>,
+    distance: f32,
+    speed: f32,
+    settle_time: f32,
+    wheel_diameter_mm: f32,
+    /// The wheel base used by the odometry, taken from the received odometry.
+    wheel_base: Option<f32>,
+    /// The latest pose estimated by SLAM.
+    pose: Option<Pose>,
+    phase: Phase,
+    /// The motion during the current maneuver.
+    run: Run,
+    /// The motion during the straight maneuver, once it is done.
+    straight: Option<Run>,
+}
+
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct CalibrationNodeConfig {
+    topic_command: String,
+    /// The observations with the odometry of the robot.
+    topic_observation_odometry: String,
+    /// The pose estimated by SLAM, used as the reference for the odometry.
+    topic_pose: String,
+    /// How far to drive straight (meters).
+    #[serde(default = "default_distance")]
+    distance: f32,
+    /// The wheel speed (m/s) used for both maneuvers, slow gives SLAM an easier time.
+    #[serde(default = "default_speed")]
+    speed: f32,
+    /// How long to wait (seconds) after each maneuver for the robot to come to a stop and SLAM
+    /// to catch up.
+    #[serde(default = "default_settle_time")]
+    settle_time: f32,
+    /// The wheel diameter (millimeters) currently configured for the robot in
+    /// `RobotConnection.odometry.wheel_diameter_mm`, used to suggest a corrected diameter.
+    #[serde(default = "default_wheel_diameter_mm")]
+    wheel_diameter_mm: f32,
+}
+
+fn default_distance() -> f32 {
+    1.0
+}
+
+fn default_speed() -> f32 {
+    0.1
+}
+
+fn default_settle_time() -> f32 {
+    1.0
+}
+
+fn default_wheel_diameter_mm() -> f32 {
+    60.0
+}
+
+/// Give up on a maneuver after it took this many times longer than expected.
+const TIMEOUT_FACTOR: f32 = 3.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Maneuver {
+    Straight,
+    /// A full revolution counter-clockwise on the spot.
+    Rotate,
+}
+
+enum Phase {
+    Idle,
+    Driving(Maneuver, Instant),
+    /// Waiting for the robot to stop after the maneuver.
+    Settling(Maneuver, Instant),
+    Finished(Result<Calibration, String>),
+}
+
+/// The motion of the robot during a maneuver, according to the wheels and to SLAM.
+#[derive(Clone, Copy, Default)]
+struct Run {
+    /// Pose estimated by SLAM when the maneuver started.
+    start: Pose,
+    /// Distance travelled by the left and right wheel (meters).
+    left: f32,
+    right: f32,
+    /// The change in heading estimated by SLAM (radians), accumulated so that full
+    /// revolutions are counted.
+    slam_rotation: f32,
+}
+
+/// The suggested odometry constants.
+#[derive(Clone, Copy)]
+struct Calibration {
+    scale_left: f32,
+    scale_right: f32,
+    wheel_base: f32,
+    /// From SLAM, for reference
+    slam_distance: f32,
+    odometry_distance: f32,
+}
+
+impl NodeConfig for CalibrationNodeConfig {
+    fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
+        Box::new(CalibrationNode {
+            sub_odometry: pubsub.subscribe(&self.topic_observation_odometry),
+            sub_pose: pubsub.subscribe(&self.topic_pose),
+            pub_cmd: pubsub.publish_registered(&self.topic_command),
+            distance: self.distance,
+            speed: self.speed,
+            settle_time: self.settle_time,
+            wheel_diameter_mm: self.wheel_diameter_mm,
+            wheel_base: None,
+            pose: None,
+            phase: Phase::Idle,
+            run: Run::default(),
+            straight: None,
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![
+            TopicDecl::publish::<Command>(&self.topic_command),
+            TopicDecl::subscribe::<(Observation, Odometry)>(&self.topic_observation_odometry),
+            TopicDecl::subscribe::<Pose>(&self.topic_pose),
+        ]
+    }
+}
+
+/// Computes the odometry corrections from a straight run and a full revolution, made with
+/// odometry using `wheel_base`. Returns `None` if the robot did not move enough to tell.
+fn calibrate(
+    straight: &Run,
+    straight_end: Pose,
+    rotation: &Run,
+    wheel_base: f32,
+) -> Option<Calibration> {
+    let odometry_distance = (straight.left + straight.right) / 2.0;
+    let slam_distance = (straight_end.xy() - straight.start.xy()).norm();
+    if odometry_distance.abs() < 1e-3 || slam_distance < 1e-3 || rotation.slam_rotation.abs() < 0.1
+    {
+        return None;
+    }
+
+    let scale = slam_distance / odometry_distance;
+
+    // turning while driving straight means that one wheel travelled further than measured
+    let imbalance = (straight.slam_rotation * wheel_base / scale
+        - (straight.right - straight.left))
+        / (straight.left + straight.right);
+    let scale_left = scale * (1.0 - imbalance);
+    let scale_right = scale * (1.0 + imbalance);
+
+    // the wheel base that turns the corrected wheel travel into the rotation seen by SLAM
+    let wheel_base =
+        (rotation.right * scale_right - rotation.left * scale_left) / rotation.slam_rotation;
+
+    Some(Calibration {
+        scale_left,
+        scale_right,
+        wheel_base,
+        slam_distance,
+        odometry_distance,
+    })
+}
+
+/// The config changes for the calibration, with the keys of the `RobotConnection` and the
+/// `Simulator` node configs. The wheels can only be scaled together, so the difference between
+/// them is only reported.
+fn suggestion(c: &Calibration, wheel_diameter_mm: f32) -> String {
+    // both wheels are assumed to have the same diameter on the robot, use the mean
+    let scale = (c.scale_left + c.scale_right) / 2.0;
+    format!(
+        "# wheel odometry calibration for !RobotConnection\n\
+         wheel_base: {:.4}\n\
+         odometry:\n\
+         \x20 wheel_diameter_mm: {:.0}\n\
+         # or for !Simulator\n\
+         parameters:\n\
+         \x20 wheel_base: {:.4}\n\
+         # the right wheel reports {:+.2}% more distance than the left one\n",
+        c.wheel_base,
+        wheel_diameter_mm * scale,
+        c.wheel_base,
+        (c.scale_left / c.scale_right - 1.0) * 100.0,
+    )
+}
+
+impl CalibrationNode {
+    fn recording(&self) -> bool {
+        matches!(self.phase, Phase::Driving(..) | Phase::Settling(..))
+    }
+
+    fn publish(&mut self, speed_left: f32, speed_right: f32) {
+        self.pub_cmd.publish(Arc::new(Command {
+            speed_left,
+            speed_right,
+        }));
+    }
+
+    fn start(&mut self, maneuver: Maneuver) {
+        self.run = Run {
+            start: self.pose.unwrap_or_default(),
+            ..Run::default()
+        };
+        self.phase = Phase::Driving(maneuver, Instant::now());
+
+        match maneuver {
+            Maneuver::Straight => self.publish(self.speed, self.speed),
+            Maneuver::Rotate => self.publish(-self.speed, self.speed),
+        }
+    }
+
+    fn abort(&mut self, reason: &str) {
+        self.publish(0.0, 0.0);
+        self.phase = Phase::Finished(Err(reason.to_owned()));
+    }
+
+    /// Returns true once the wheels say the maneuver is complete.
+    fn maneuver_done(&self, maneuver: Maneuver) -> bool {
+        match maneuver {
+            Maneuver::Straight => (self.run.left + self.run.right) / 2.0 >= self.distance,
+            Maneuver::Rotate => {
+                let wheel_base = self.wheel_base.unwrap_or(f32::INFINITY);
+                (self.run.right - self.run.left) / wheel_base >= std::f32::consts::TAU
+            }
+        }
+    }
+
+    /// How long the maneuver should take (seconds).
+    fn expected_duration(&self, maneuver: Maneuver) -> f32 {
+        let distance = match maneuver {
+            Maneuver::Straight => self.distance,
+            Maneuver::Rotate => std::f32::consts::PI * self.wheel_base.unwrap_or_default(),
+        };
+        distance / self.speed
+    }
+
+    fn finish(&mut self, maneuver: Maneuver) {
+        match maneuver {
+            Maneuver::Straight => {
+                self.straight = Some(self.run);
+                self.start(Maneuver::Rotate);
+            }
+            Maneuver::Rotate => {
+                let (Some(straight), Some(wheel_base)) = (self.straight.take(), self.wheel_base)
+                else {
+                    self.phase = Phase::Idle;
+                    return;
+                };
+
+                // the end of the straight run is where the rotation started
+                let result = calibrate(&straight, self.run.start, &self.run, wheel_base)
+                    .ok_or_else(|| "The robot did not move enough, is SLAM running?".to_owned());
+                self.phase = Phase::Finished(result);
+            }
+        }
+    }
+}
+
+impl Node for CalibrationNode {
+    fn update(&mut self) {
+        let recording = self.recording();
+
+        while let Some(o) = self.sub_odometry.try_recv() {
+            let odometry = &o.1;
+            self.wheel_base = Some(odometry.wheel_distance);
+            if recording {
+                self.run.left += odometry.distance_left;
+                self.run.right += odometry.distance_right;
+            }
+        }
+
+        while let Some(pose) = self.sub_pose.try_recv() {
+            if let (true, Some(last)) = (recording, self.pose) {
+                self.run.slam_rotation += angle_diff(last.theta as f64, pose.theta as f64) as f32;
+            }
+            self.pose = Some(*pose);
+        }
+
+        match self.phase {
+            Phase::Driving(maneuver, started) => {
+                if self.maneuver_done(maneuver) {
+                    self.publish(0.0, 0.0);
+                    self.phase = Phase::Settling(maneuver, Instant::now());
+                } else if started.elapsed().as_secs_f32()
+                    > TIMEOUT_FACTOR * self.expected_duration(maneuver)
+                {
+                    self.abort("The wheels did not report the expected motion in time");
+                }
+            }
+            Phase::Settling(maneuver, since) => {
+                if since.elapsed().as_secs_f32() >= self.settle_time {
+                    self.finish(maneuver);
+                }
+            }
+            Phase::Idle | Phase::Finished(_) => {}
+        }
+    }
+
+    fn draw(&mut self, ui: &egui::Ui, _world: &mut WorldObj<'_>) {
+        egui::Window::new("Odometry Calibration").show(ui.ctx(), |ui| {
+            ui.label(format!(
+                "Drives {:.2} m straight and turns a full revolution at {:.2} m/s. Make sure \
+                 there is room around the robot.",
+                self.distance, self.speed
+            ));
+
+            match &self.phase {
+                Phase::Idle => {}
+                Phase::Driving(maneuver, _) | Phase::Settling(maneuver, _) => {
+                    let action = match maneuver {
+                        Maneuver::Straight => "Driving straight",
+                        Maneuver::Rotate => "Rotating",
+                    };
+                    ui.label(format!(
+                        "{action}...\nWheels: {:.3} m / {:.3} m\nSLAM rotation: {:.1}°",
+                        self.run.left,
+                        self.run.right,
+                        self.run.slam_rotation.to_degrees()
+                    ));
+                }
+                Phase::Finished(Ok(c)) => {
+                    ui.label(format!(
+                        "Straight: {:.3} m by odometry, {:.3} m by SLAM",
+                        c.odometry_distance, c.slam_distance
+                    ));
+                    let suggestion = suggestion(c, self.wheel_diameter_mm);
+                    ui.label(RichText::new(&suggestion).monospace());
+                    if ui.button("Copy").clicked() {
+                        ui.ctx().copy_text(suggestion);
+                    }
+                }
+                Phase::Finished(Err(reason)) => {
+                    ui.colored_label(egui::Color32::RED, reason);
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if self.recording() {
+                    if ui.button("Abort").clicked() {
+                        self.abort("Aborted");
+                    }
+                } else {
+                    let ready = self.pose.is_some() && self.wheel_base.is_some();
+                    if ui
+                        .add_enabled(ready, egui::Button::new("Start"))
+                        .on_disabled_hover_text("Waiting for odometry and a SLAM pose")
+                        .clicked()
+                    {
+                        self.start(Maneuver::Straight);
+                    }
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{PI, TAU};
+
+    use super::*;
+
+    #[test]
+    fn calibrate_recovers_wheel_scale_and_base() {
+        // the left wheel reports 2% too little and the odometry assumes a too small wheel base
+        let (true_wheel_base, odometry_wheel_base) = (0.2, 0.18);
+        let left_error = 0.98;
+
+        // one meter straight ahead
+        let straight = Run {
+            start: Pose::default(),
+            left: left_error,
+            right: 1.0,
+            slam_rotation: 0.0,
+        };
+        let straight_end = Pose {
+            x: 1.0,
+            y: 0.0,
+            theta: 0.0,
+        };
+
+        // a full revolution, each wheel travels half of the circle around the other
+        let arc = PI * true_wheel_base;
+        let rotation = Run {
+            start: straight_end,
+            left: -arc * left_error,
+            right: arc,
+            slam_rotation: TAU,
+        };
+
+        let c = calibrate(&straight, straight_end, &rotation, odometry_wheel_base).unwrap();
+        assert!((c.scale_left - 1.0 / left_error).abs() < 1e-3);
+        assert!((c.scale_right - 1.0).abs() < 1e-3);
+        assert!((c.wheel_base - true_wheel_base).abs() < 1e-3);
+        assert!((c.slam_distance - 1.0).abs() < 1e-6);
+
+        // the suggestion uses the keys of the node configs
+        let suggestion: serde_yaml::Value = serde_yaml::from_str(&suggestion(&c, 60.0)).unwrap();
+        for wheel_base in [
+            &suggestion["wheel_base"],
+            &suggestion["parameters"]["wheel_base"],
+        ] {
+            assert!((wheel_base.as_f64().unwrap() - true_wheel_base as f64).abs() < 1e-3);
+        }
+        assert_eq!(
+            suggestion["odometry"]["wheel_diameter_mm"].as_u64(),
+            Some(61)
+        );
+    }
+
+    #[test]
+    fn calibrate_needs_motion() {
+        let still = Run::default();
+        assert!(calibrate(&still, Pose::default(), &still, 0.2).is_none());
+    }
+}
@@ -1,14 +1,17 @@
 use std::sync::Arc;
 
-use common::node::NodeConfig;
+use common::node::{NodeConfig, TopicDecl};
 use common::{node::Node, robot::Command};
 use eframe::egui;
 use egui::{Button, Key, Rgba, RichText, Slider};
-use pubsub::Publisher;
+use pubsub::{Publisher, Subscription};
 use serde::Deserialize;
 
 pub struct ControlsNode {
     pub_cmd: Publisher<Command>,
+    sub_stopped: Option<Subscription<bool>>,
+    /// Set while the emergency stop is engaged, no motion is published
+    stopped: bool,
     keyboard_enabled: bool,
     target_speed: f32,
     last_command: Command,
@@ -27,9 +30,10 @@ enum Control {
     Right,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct ControlsNodeConfig {
     topic_command: String,
+    topic_stopped: Option<String>,
     keyboard_enabled: bool,
     max_speed: f32,
 }
@@ -37,12 +41,26 @@ pub struct ControlsNodeConfig {
 impl NodeConfig for ControlsNodeConfig {
     fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
         Box::new(ControlsNode {
-            pub_cmd: pubsub.publish(&self.topic_command),
+            pub_cmd: pubsub.publish_registered(&self.topic_command),
+            sub_stopped: self
+                .topic_stopped
+                .as_ref()
+                .map(|topic| pubsub.subscribe(topic)),
+            stopped: false,
             keyboard_enabled: self.keyboard_enabled,
             target_speed: self.max_speed,
             last_command: Default::default(),
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = vec![TopicDecl::publish::<Command>(&self.topic_command)];
+        if let Some(topic) = &self.topic_stopped {
+            topics.push(TopicDecl::subscribe::<bool>(topic));
+        }
+
+        topics
+    }
 }
 
 impl Node for ControlsNode {
@@ -51,6 +69,12 @@ impl Node for ControlsNode {
 
         let mut ctrl = Stop;
 
+        if let Some(sub) = &mut self.sub_stopped {
+            while let Some(stopped) = sub.try_recv() {
+                self.stopped = *stopped;
+            }
+        }
+
         if self.keyboard_enabled {
             let (up, left, down, right) = ui.ctx().input(|i| {
                 (
@@ -107,6 +131,10 @@ impl Node for ControlsNode {
                     }
                 });
 
+                if self.stopped {
+                    ui.colored_label(egui::Color32::RED, "Emergency stop engaged");
+                }
+
                 ui.label(
                     RichText::new(format!(
                         "Last Command:\nLeft: {:+.3} | Right: {:+.3}",
@@ -116,6 +144,10 @@ impl Node for ControlsNode {
                 );
             });
 
+        if self.stopped {
+            ctrl = Stop;
+        }
+
         let cmd = match ctrl {
             Stop => Command {
                 speed_left: 0.0,
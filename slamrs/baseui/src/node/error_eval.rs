@@ -0,0 +1,298 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use common::{
+    math::angle_diff,
+    node::{Node, NodeConfig, TopicDecl},
+    robot::Pose,
+    world::WorldObj,
+};
+use eframe::egui;
+use egui::RichText;
+use log::info;
+use nalgebra::{Rotation2, Vector2};
+use pubsub::{PubSub, Publisher, Subscription};
+use serde::Deserialize;
+use web_time::{Duration, Instant};
+
+/// The maximum age of the ground truth poses kept around for matching with the estimates.
+const TRUTH_HISTORY: Duration = Duration::from_secs(2);
+
+/// The error of an estimated pose compared to the ground truth.
+#[derive(Debug, Clone, Copy)]
+pub struct PoseError {
+    /// The distance between the estimated and the true position, in meters.
+    pub translation: f32,
+
+    /// The absolute difference in heading, in radians.
+    pub rotation: f32,
+}
+
+/// Compares an estimated pose (e.g. from a SLAM node) with the ground truth (e.g. from the
+/// simulator) and accumulates statistics of the absolute (ATE) and relative (RPE) errors.
+pub struct ErrorEvalNode {
+    sub_truth: Subscription<Pose>,
+    sub_estimate: Subscription<Pose>,
+    pub_error: Option<Publisher<PoseError>>,
+    /// Recently received ground truth poses, oldest first.
+    truth: VecDeque<(Instant, Pose)>,
+    /// The last matched pair of (true, estimated) pose, used for the relative error.
+    last_pair: Option<(Pose, Pose)>,
+    latest: Option<PoseError>,
+    absolute: ErrorStats,
+    relative: ErrorStats,
+}
+
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct ErrorEvalNodeConfig {
+    topic_truth: String,
+    topic_estimate: String,
+    /// If set, the error of every estimate is published here.
+    topic_error: Option<String>,
+}
+
+impl NodeConfig for ErrorEvalNodeConfig {
+    fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
+        Box::new(ErrorEvalNode {
+            sub_truth: pubsub.subscribe(&self.topic_truth),
+            sub_estimate: pubsub.subscribe(&self.topic_estimate),
+            pub_error: self
+                .topic_error
+                .as_ref()
+                .map(|topic| pubsub.publish_registered(topic)),
+            truth: VecDeque::new(),
+            last_pair: None,
+            latest: None,
+            absolute: ErrorStats::default(),
+            relative: ErrorStats::default(),
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = vec![
+            TopicDecl::subscribe::<Pose>(&self.topic_truth),
+            TopicDecl::subscribe::<Pose>(&self.topic_estimate),
+        ];
+        if let Some(topic) = &self.topic_error {
+            topics.push(TopicDecl::publish::<PoseError>(topic));
+        }
+
+        topics
+    }
+}
+
+/// Accumulates the root mean square and maximum of a series of errors.
+#[derive(Default)]
+struct ErrorStats {
+    count: usize,
+    translation_sq_sum: f64,
+    rotation_sq_sum: f64,
+    translation_max: f32,
+    rotation_max: f32,
+}
+
+impl ErrorStats {
+    fn add(&mut self, error: PoseError) {
+        self.count += 1;
+        self.translation_sq_sum += (error.translation as f64).powi(2);
+        self.rotation_sq_sum += (error.rotation as f64).powi(2);
+        self.translation_max = self.translation_max.max(error.translation);
+        self.rotation_max = self.rotation_max.max(error.rotation);
+    }
+
+    fn translation_rmse(&self) -> f64 {
+        (self.translation_sq_sum / self.count.max(1) as f64).sqrt()
+    }
+
+    fn rotation_rmse(&self) -> f64 {
+        (self.rotation_sq_sum / self.count.max(1) as f64).sqrt()
+    }
+}
+
+impl std::fmt::Display for ErrorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "rmse {:.3} m / {:.2}°, max {:.3} m / {:.2}° ({} samples)",
+            self.translation_rmse(),
+            self.rotation_rmse().to_degrees(),
+            self.translation_max,
+            self.rotation_max.to_degrees(),
+            self.count
+        )
+    }
+}
+
+fn pose_error(truth: Pose, estimate: Pose) -> PoseError {
+    PoseError {
+        translation: (estimate.xy() - truth.xy()).norm(),
+        rotation: angle_diff(truth.theta as f64, estimate.theta as f64).abs() as f32,
+    }
+}
+
+/// Returns the motion from `from` to `to`, expressed in the frame of `from`.
+fn relative_motion(from: Pose, to: Pose) -> Pose {
+    let xy: Vector2<f32> = Rotation2::new(-from.theta) * (to.xy() - from.xy());
+    Pose {
+        x: xy.x,
+        y: xy.y,
+        theta: angle_diff(from.theta as f64, to.theta as f64) as f32,
+    }
+}
+
+impl ErrorEvalNode {
+    fn reset(&mut self) {
+        self.last_pair = None;
+        self.latest = None;
+        self.absolute = ErrorStats::default();
+        self.relative = ErrorStats::default();
+    }
+
+    /// Finds the ground truth pose that was received closest in time to `time`.
+    fn nearest_truth(&self, time: Instant) -> Option<Pose> {
+        self.truth
+            .iter()
+            .min_by_key(|(t, _)| if *t > time { *t - time } else { time - *t })
+            .map(|(_, pose)| *pose)
+    }
+
+    /// Compares `estimate` with the ground truth at `time`, when the estimate was received.
+    /// Poses carry no timestamp of their own.
+    fn evaluate(&mut self, time: Instant, estimate: Pose) {
+        let Some(truth) = self.nearest_truth(time) else {
+            return;
+        };
+
+        let error = pose_error(truth, estimate);
+        self.absolute.add(error);
+        self.latest = Some(error);
+
+        if let Some((last_truth, last_estimate)) = self.last_pair {
+            self.relative.add(pose_error(
+                relative_motion(last_truth, truth),
+                relative_motion(last_estimate, estimate),
+            ));
+        }
+        self.last_pair = Some((truth, estimate));
+
+        if let Some(pub_error) = &mut self.pub_error {
+            pub_error.publish(Arc::new(error));
+        }
+    }
+}
+
+impl Node for ErrorEvalNode {
+    fn update(&mut self) {
+        let now = Instant::now();
+        while let Some(pose) = self.sub_truth.try_recv() {
+            self.truth.push_back((now, *pose));
+        }
+
+        // forget truth that is too old to be matched against anything
+        while self
+            .truth
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > TRUTH_HISTORY)
+        {
+            self.truth.pop_front();
+        }
+
+        while let Some(estimate) = self.sub_estimate.try_recv() {
+            self.evaluate(now, *estimate);
+        }
+    }
+
+    fn draw(&mut self, ui: &egui::Ui, _world: &mut WorldObj<'_>) {
+        egui::Window::new("Pose Error").show(ui.ctx(), |ui| {
+            let latest = match self.latest {
+                Some(e) => format!("{:.3} m / {:.2}°", e.translation, e.rotation.to_degrees()),
+                None => "-".to_owned(),
+            };
+
+            ui.label(
+                RichText::new(format!(
+                    "Latest: {}\nATE: {}\nRPE: {}",
+                    latest, self.absolute, self.relative
+                ))
+                .monospace(),
+            );
+
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+        });
+    }
+
+    fn terminate(&mut self) {
+        info!("Absolute trajectory error: {}", self.absolute);
+        info!("Relative pose error: {}", self.relative);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn pose(x: f32, y: f32, theta: f32) -> Pose {
+        Pose { x, y, theta }
+    }
+
+    fn assert_pose_eq(a: Pose, b: Pose) {
+        assert!(
+            (a.x - b.x).abs() < 1e-5
+                && (a.y - b.y).abs() < 1e-5
+                && (a.theta - b.theta).abs() < 1e-5,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn pose_error_wraps_the_heading() {
+        let error = pose_error(pose(1.0, 1.0, 3.1), pose(4.0, 5.0, -3.1));
+        assert!((error.translation - 5.0).abs() < 1e-6);
+        assert!((error.rotation - (2.0 * std::f32::consts::PI - 6.2)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn relative_motion_is_in_the_frame_of_the_start() {
+        // one meter forward while facing along the y axis, then turning left
+        let motion = relative_motion(pose(1.0, 2.0, FRAC_PI_2), pose(1.0, 3.0, 0.0));
+        assert_pose_eq(motion, pose(1.0, 0.0, -FRAC_PI_2));
+
+        // the same motion from anywhere else is the same relative motion
+        let motion = relative_motion(pose(-3.0, 0.0, 0.0), pose(-2.0, 0.0, -FRAC_PI_2));
+        assert_pose_eq(motion, pose(1.0, 0.0, -FRAC_PI_2));
+    }
+
+    #[test]
+    fn estimates_are_matched_with_the_truth_at_their_time() {
+        let mut pubsub = PubSub::new();
+        let mut node = ErrorEvalNode {
+            sub_truth: pubsub.subscribe("truth"),
+            sub_estimate: pubsub.subscribe("estimate"),
+            pub_error: None,
+            truth: VecDeque::new(),
+            last_pair: None,
+            latest: None,
+            absolute: ErrorStats::default(),
+            relative: ErrorStats::default(),
+        };
+
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        node.truth.push_back((at(0), pose(0.0, 0.0, 0.0)));
+        node.truth.push_back((at(100), pose(1.0, 0.0, 0.0)));
+        node.truth.push_back((at(200), pose(2.0, 0.0, 0.0)));
+
+        node.evaluate(at(90), pose(1.0, 0.5, 0.0));
+        let error = node.latest.unwrap();
+        assert!((error.translation - 0.5).abs() < 1e-6);
+
+        // the robot moved one meter and so did the estimate
+        node.evaluate(at(210), pose(2.0, 0.5, 0.0));
+        assert!((node.absolute.translation_rmse() - 0.5).abs() < 1e-6);
+        assert_eq!(node.relative.count, 1);
+        assert!(node.relative.translation_max < 1e-6);
+    }
+}
@@ -1,30 +1,106 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
 use common::{
-    node::{Node, NodeConfig},
-    robot::{LandmarkObservations, Observation, Pose},
+    math::angle_diff,
+    node::{Node, NodeConfig, TopicDecl},
+    robot::{BumpEvent, LandmarkObservations, Observation, Pose, ScanQuality},
     world::WorldObj,
 };
 use eframe::egui;
 use egui::CollapsingHeader;
-use pubsub::{PubSub, Subscription};
+use egui_plot::{Bar, BarChart, Plot, PlotBounds, Points};
+use nalgebra::Vector3;
+use pubsub::{PubSub, Publisher, Subscription};
 
-use graphics::shaperenderer::ShapeRenderer;
+use graphics::{pointcloudrenderer::PointCloudId, shaperenderer::ShapeRenderer};
 use serde::Deserialize;
-use slam::{GridMapMessage, LandmarkMapMessage, PointMap};
+use slam::{GridMapMessage, HitCountMapMessage, LandmarkMapMessage, PointMap};
+use web_time::Instant;
 
 use super::visualize::{
-    GridMapVisualizeConfig, LandmarkMapMessageVisualizeConfig, LandmarkObservationVisualizeConfig,
-    ObservationVisualizeConfig, PointMapVisualizeConfig, PoseVisualizeConfig, Visualize,
-    VisualizeParametersUi,
+    visualize_point_cloud, BumpEventVisualizeConfig, FootprintVisualizeConfig,
+    GridMapVisualizeConfig, HitCountMapVisualizeConfig, LandmarkMapMessageVisualizeConfig,
+    LandmarkObservationVisualizeConfig, ObservationVisualizeConfig, PointMapBackend,
+    PointMapVisualizeConfig, PoseVisualizeConfig, Visualize, VisualizeParametersUi,
 };
 
 pub struct FrameVizualizer {
     vis: Vec<Box<dyn SubViz>>,
+    /// Set once the enabled state of the visualizers has been restored from the last session.
+    restored: bool,
+    /// Observations to show the strength and distance plots for, if configured.
+    plot_subscription: Option<Subscription<Observation>>,
+    last_frame: Option<Arc<Observation>>,
+    plot_ranges: PlotRanges,
+    pub_scan_quality: Option<Publisher<ScanQuality>>,
+}
+
+/// Scans with fewer valid measurements than this are highlighted.
+const LOW_VALID_RATIO: f32 = 0.5;
+
+/// The number of frames the plot bounds are computed over.
+const PLOT_WINDOW: usize = 20;
+
+/// Tracks the range of the measurements in the latest frames so that the plots can be scaled to
+/// fit the data.
+#[derive(Default)]
+struct PlotRanges {
+    /// The (strength, distance) ranges of each frame in the window.
+    frames: VecDeque<([f64; 2], [f64; 2])>,
+    /// Keep the current bounds (and allow zooming and panning) instead of following the data.
+    locked: bool,
+    /// Fit the bounds to the data on the next frame, even if locked.
+    reset: bool,
+}
+
+impl PlotRanges {
+    fn push(&mut self, frame: &Observation) {
+        let range = |values: &mut dyn Iterator<Item = f64>| {
+            values.fold(None, |range: Option<[f64; 2]>, v| {
+                Some(range.map_or([v, v], |[min, max]| [min.min(v), max.max(v)]))
+            })
+        };
+
+        let valid = || frame.measurements.iter().filter(|m| m.valid);
+        if let (Some(strength), Some(distance)) = (
+            range(&mut valid().map(|m| m.strength)),
+            range(&mut valid().map(|m| m.distance)),
+        ) {
+            self.frames.push_back((strength, distance));
+            while self.frames.len() > PLOT_WINDOW {
+                self.frames.pop_front();
+            }
+        }
+    }
+
+    /// The range of the strength over the window, defaults to the range of the Neato.
+    fn strength(&self) -> [f64; 2] {
+        Self::union(self.frames.iter().map(|(strength, _)| *strength)).unwrap_or([0.0, 2000.0])
+    }
+
+    /// The range of the distance over the window.
+    fn distance(&self) -> [f64; 2] {
+        Self::union(self.frames.iter().map(|(_, distance)| *distance)).unwrap_or([0.0, 5.0])
+    }
+
+    fn union(ranges: impl Iterator<Item = [f64; 2]>) -> Option<[f64; 2]> {
+        ranges.reduce(|a, b| [a[0].min(b[0]), a[1].max(b[1])])
+    }
+
+    /// Returns true if the bounds of the plots should be set from the data this frame.
+    fn follow(&mut self) -> bool {
+        !self.locked || std::mem::take(&mut self.reset)
+    }
+}
+
+/// Key for remembering if a visualizer is enabled in the persisted egui memory.
+fn enabled_id(name: &str) -> egui::Id {
+    egui::Id::new(("visualizer_enabled", name))
 }
 
 trait SubViz {
     fn visualize(&self, sr: &mut ShapeRenderer);
+    fn overlay(&self, painter: &egui::Painter, world: &WorldObj<'_>);
     fn poll(&mut self);
     fn name(&self) -> &str;
     fn enabled(&mut self) -> &mut bool;
@@ -42,7 +118,7 @@ struct SubscriptionVisualizer<
     C: VisualizeParametersUi,
     S: Send + Sync + 'static + Clone,
 > {
-    subscription: Subscription<T>,
+    subscription: Option<Subscription<T>>,
     secondary_value: SecondaryValue<S>,
     latest_value: Option<Arc<T>>,
     latest_secondary_value: Option<S>,
@@ -68,7 +144,7 @@ impl<
     ) -> Self {
         let name = format!("{} ({})", subscription.topic(), std::any::type_name::<T>());
         Self {
-            subscription,
+            subscription: Some(subscription),
             secondary_value,
             latest_value: None,
             latest_secondary_value: None,
@@ -77,6 +153,24 @@ impl<
             name,
         }
     }
+
+    /// Visualizes a value that never changes, positioned using the secondary value.
+    pub fn new_constant(
+        name: &str,
+        value: T,
+        config: C,
+        secondary_value: SecondaryValue<S>,
+    ) -> Self {
+        Self {
+            subscription: None,
+            secondary_value,
+            latest_value: Some(Arc::new(value)),
+            latest_secondary_value: None,
+            config,
+            enabled: true,
+            name: format!("{} ({})", name, std::any::type_name::<T>()),
+        }
+    }
 }
 
 impl<
@@ -86,8 +180,10 @@ impl<
     > SubViz for SubscriptionVisualizer<T, C, S>
 {
     fn poll(&mut self) {
-        while let Some(v) = self.subscription.try_recv() {
-            self.latest_value = Some(v);
+        if let Some(subscription) = &mut self.subscription {
+            while let Some(v) = subscription.try_recv() {
+                self.latest_value = Some(v);
+            }
         }
 
         match &mut self.secondary_value {
@@ -107,6 +203,12 @@ impl<
         }
     }
 
+    fn overlay(&self, painter: &egui::Painter, world: &WorldObj<'_>) {
+        if let Some(latest_value) = &self.latest_value {
+            latest_value.overlay(painter, world, &self.config, &self.latest_secondary_value);
+        }
+    }
+
     fn config_ui(&mut self, ui: &mut egui::Ui) {
         self.config.ui(ui)
     }
@@ -120,12 +222,208 @@ impl<
     }
 }
 
-#[derive(Clone, Deserialize, Debug)]
+/// Visualizes the latest pose, optionally extrapolated using the velocity between the last two
+/// poses so that the arrow moves smoothly even though the poses arrive at the scan rate.
+struct PoseVisualizer {
+    subscription: Subscription<Pose>,
+    /// The latest pose and when it was received.
+    latest: Option<(Instant, Pose)>,
+    /// (x, y, theta) per second
+    velocity: Vector3<f32>,
+    config: PoseVisualizeConfig,
+    enabled: bool,
+    name: String,
+}
+
+impl PoseVisualizer {
+    fn new(subscription: Subscription<Pose>, config: PoseVisualizeConfig) -> Self {
+        let name = format!(
+            "{} ({})",
+            subscription.topic(),
+            std::any::type_name::<Pose>()
+        );
+        Self {
+            subscription,
+            latest: None,
+            velocity: Vector3::zeros(),
+            config,
+            enabled: true,
+            name,
+        }
+    }
+
+    /// The latest pose moved by the velocity for the time since it was received, and whether it
+    /// will keep moving.
+    fn extrapolated(&self) -> Option<(Pose, bool)> {
+        let (received, pose) = self.latest?;
+        if !self.config.extrapolate {
+            return Some((pose, false));
+        }
+
+        // stop when the updates do, instead of running off
+        let elapsed = received.elapsed().as_secs_f32();
+        let t = elapsed.min(self.config.max_extrapolation);
+        let moving = elapsed < self.config.max_extrapolation && self.velocity != Vector3::zeros();
+
+        Some((
+            Pose::from(Vector3::<f32>::from(pose) + self.velocity * t),
+            moving,
+        ))
+    }
+}
+
+/// Poses received closer together than this are not used to estimate the velocity.
+const MIN_VELOCITY_DT: f32 = 1e-3;
+
+/// The velocity (x, y, theta per second) between two timestamped poses, or `None` if they are too
+/// close together in time to give a meaningful estimate.
+fn pose_velocity(from: (Instant, Pose), to: (Instant, Pose)) -> Option<Vector3<f32>> {
+    let ((t0, p0), (t1, p1)) = (from, to);
+    let dt = t1.checked_duration_since(t0)?.as_secs_f32();
+    if dt < MIN_VELOCITY_DT {
+        return None;
+    }
+
+    Some(Vector3::new(
+        (p1.x - p0.x) / dt,
+        (p1.y - p0.y) / dt,
+        angle_diff(p0.theta as f64, p1.theta as f64) as f32 / dt,
+    ))
+}
+
+impl SubViz for PoseVisualizer {
+    fn poll(&mut self) {
+        // only the newest of the poses drained in one poll matters, the others were received at
+        // (almost) the same instant and would give a velocity over a dt of zero
+        let Some(pose) = std::iter::from_fn(|| self.subscription.try_recv()).last() else {
+            return;
+        };
+
+        // poses carry no timestamp of their own, so measure between the receive times of the
+        // newest pose of the previous poll and of this one
+        let now = Instant::now();
+        self.velocity = match self.latest {
+            Some(last) => pose_velocity(last, (now, *pose)).unwrap_or(self.velocity),
+            None => Vector3::zeros(),
+        };
+
+        // snap to the true pose on every update
+        self.latest = Some((now, *pose));
+    }
+
+    fn visualize(&self, sr: &mut ShapeRenderer) {
+        if let Some((pose, _)) = self.extrapolated() {
+            pose.visualize(sr, &self.config, &None);
+        }
+    }
+
+    fn overlay(&self, painter: &egui::Painter, _world: &WorldObj<'_>) {
+        // nothing is published while extrapolating, so keep repainting ourselves
+        if let Some((_, true)) = self.extrapolated() {
+            painter.ctx().request_repaint();
+        }
+    }
+
+    fn config_ui(&mut self, ui: &mut egui::Ui) {
+        self.config.ui(ui)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enabled(&mut self) -> &mut bool {
+        &mut self.enabled
+    }
+}
+
+/// Visualizes the latest point map with the configured backend, keeping track of how the map
+/// changes so that the GPU backend only uploads the new points.
+struct PointMapVisualizer {
+    subscription: Subscription<PointMap>,
+    latest: Option<Arc<PointMap>>,
+    /// Incremented whenever a received map is not the previous map with points appended, e.g.
+    /// after a reset or when old scans are dropped from the map.
+    generation: u64,
+    cloud: PointCloudId,
+    config: PointMapVisualizeConfig,
+    enabled: bool,
+    name: String,
+}
+
+impl PointMapVisualizer {
+    fn new(subscription: Subscription<PointMap>, config: PointMapVisualizeConfig) -> Self {
+        let name = format!(
+            "{} ({})",
+            subscription.topic(),
+            std::any::type_name::<PointMap>()
+        );
+        Self {
+            subscription,
+            latest: None,
+            generation: 0,
+            cloud: PointCloudId::new(),
+            config,
+            enabled: true,
+            name,
+        }
+    }
+}
+
+impl SubViz for PointMapVisualizer {
+    fn poll(&mut self) {
+        while let Some(map) = self.subscription.try_recv() {
+            let appended = self
+                .latest
+                .as_ref()
+                .is_some_and(|last| map.0.as_slice().starts_with(last.0.as_slice()));
+            if !appended {
+                self.generation += 1;
+            }
+            self.latest = Some(map);
+        }
+    }
+
+    fn visualize(&self, sr: &mut ShapeRenderer) {
+        let Some(map) = &self.latest else {
+            return;
+        };
+
+        match self.config.backend {
+            PointMapBackend::Shapes => map.visualize(sr, &self.config, &None),
+            PointMapBackend::GpuPoints => {
+                visualize_point_cloud(map, sr, &self.config, self.cloud, self.generation)
+            }
+        }
+    }
+
+    fn overlay(&self, _painter: &egui::Painter, _world: &WorldObj<'_>) {}
+
+    fn config_ui(&mut self, ui: &mut egui::Ui) {
+        self.config.ui(ui)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn enabled(&mut self) -> &mut bool {
+        &mut self.enabled
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
 pub struct FrameVizualizerNodeConfig {
     topics: Vec<VizType>,
+    /// Show plots of the strength and distance of the measurements on this topic.
+    #[serde(default)]
+    topic_plot: Option<String>,
+    /// Publish the quality of each frame on `topic_plot` to this topic.
+    #[serde(default)]
+    topic_scan_quality: Option<String>,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug, PartialEq)]
 enum VizType {
     Pose {
         topic: String,
@@ -149,16 +447,56 @@ enum VizType {
         topic: String,
         config: GridMapVisualizeConfig,
     },
+    HitCountMap {
+        topic: String,
+        config: HitCountMapVisualizeConfig,
+    },
     LandmarkMap {
         topic: String,
         config: LandmarkMapMessageVisualizeConfig,
     },
+    Footprint {
+        topic_pose: String,
+        config: FootprintVisualizeConfig,
+    },
+    Bump {
+        topic: String,
+        config: BumpEventVisualizeConfig,
+    },
 }
 
 impl VizType {
+    fn topics(&self) -> Vec<TopicDecl> {
+        match self {
+            VizType::Pose { topic, .. } => vec![TopicDecl::subscribe::<Pose>(topic)],
+            VizType::Observation {
+                topic, topic_pose, ..
+            } => vec![
+                TopicDecl::subscribe::<Observation>(topic),
+                TopicDecl::subscribe::<Pose>(topic_pose),
+            ],
+            VizType::LandmarkObservation {
+                topic, topic_pose, ..
+            } => vec![
+                TopicDecl::subscribe::<LandmarkObservations>(topic),
+                TopicDecl::subscribe::<Pose>(topic_pose),
+            ],
+            VizType::PointMap { topic, .. } => vec![TopicDecl::subscribe::<PointMap>(topic)],
+            VizType::GridMap { topic, .. } => vec![TopicDecl::subscribe::<GridMapMessage>(topic)],
+            VizType::HitCountMap { topic, .. } => {
+                vec![TopicDecl::subscribe::<HitCountMapMessage>(topic)]
+            }
+            VizType::LandmarkMap { topic, .. } => {
+                vec![TopicDecl::subscribe::<LandmarkMapMessage>(topic)]
+            }
+            VizType::Footprint { topic_pose, .. } => vec![TopicDecl::subscribe::<Pose>(topic_pose)],
+            VizType::Bump { topic, .. } => vec![TopicDecl::subscribe::<BumpEvent>(topic)],
+        }
+    }
+
     fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn SubViz> {
         match self {
-            VizType::Pose { topic, config } => Box::new(SubscriptionVisualizer::new(
+            VizType::Pose { topic, config } => Box::new(PoseVisualizer::new(
                 pubsub.subscribe::<Pose>(topic),
                 config.clone(),
             )),
@@ -180,7 +518,7 @@ impl VizType {
                 config.clone(),
                 SecondaryValue::Subscription(pubsub.subscribe::<Pose>(topic_pose)),
             )),
-            VizType::PointMap { topic, config } => Box::new(SubscriptionVisualizer::new(
+            VizType::PointMap { topic, config } => Box::new(PointMapVisualizer::new(
                 pubsub.subscribe::<PointMap>(topic),
                 config.clone(),
             )),
@@ -188,10 +526,26 @@ impl VizType {
                 pubsub.subscribe::<GridMapMessage>(topic),
                 config.clone(),
             )),
+            VizType::HitCountMap { topic, config } => Box::new(SubscriptionVisualizer::new(
+                pubsub.subscribe::<HitCountMapMessage>(topic),
+                config.clone(),
+            )),
             VizType::LandmarkMap { topic, config } => Box::new(SubscriptionVisualizer::new(
                 pubsub.subscribe::<LandmarkMapMessage>(topic),
                 config.clone(),
             )),
+            VizType::Footprint { topic_pose, config } => {
+                Box::new(SubscriptionVisualizer::new_constant(
+                    topic_pose,
+                    config.shape.clone(),
+                    config.clone(),
+                    SecondaryValue::Subscription(pubsub.subscribe::<Pose>(topic_pose)),
+                ))
+            }
+            VizType::Bump { topic, config } => Box::new(SubscriptionVisualizer::new(
+                pubsub.subscribe::<BumpEvent>(topic),
+                config.clone(),
+            )),
         }
     }
 }
@@ -200,59 +554,155 @@ impl NodeConfig for FrameVizualizerNodeConfig {
     fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
         Box::new(FrameVizualizer {
             vis: self.topics.iter().map(|t| t.instantiate(pubsub)).collect(),
+            restored: false,
+            plot_subscription: self
+                .topic_plot
+                .as_ref()
+                .map(|topic| pubsub.subscribe(topic)),
+            last_frame: None,
+            plot_ranges: PlotRanges::default(),
+            pub_scan_quality: self
+                .topic_scan_quality
+                .as_ref()
+                .map(|topic| pubsub.publish_registered(topic)),
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics: Vec<TopicDecl> = self.topics.iter().flat_map(|t| t.topics()).collect();
+        if let Some(topic) = &self.topic_plot {
+            topics.push(TopicDecl::subscribe::<Observation>(topic));
+        }
+        if let Some(topic) = &self.topic_scan_quality {
+            topics.push(TopicDecl::publish::<ScanQuality>(topic));
+        }
+        topics
+    }
 }
 
-impl Node for FrameVizualizer {
-    fn draw(&mut self, ui: &egui::Ui, world: &mut WorldObj<'_>) {
-        // TODO: move this into the Visualizer directly?
-        // window that shows the strength vs angle
-        egui::Window::new("Visualizer").show(ui.ctx(), |ui| {
-            /*
-            let mut bars = Vec::new();
+impl FrameVizualizer {
+    /// Draws the strength vs angle and the strength vs distance plots of the last frame.
+    fn plots(&mut self, ui: &mut egui::Ui) {
+        if let Some(frame) = &self.last_frame {
+            let quality = frame.quality();
+            let text = format!(
+                "Valid: {:.0} %, Coverage: {:.0} %",
+                100.0 * quality.valid_ratio,
+                100.0 * quality.coverage
+            );
+            if quality.valid_ratio < LOW_VALID_RATIO {
+                ui.colored_label(egui::Color32::RED, text)
+                    .on_hover_text("Most measurements are invalid, is the sensor blocked?");
+            } else {
+                ui.label(text);
+            }
+        }
 
-            if let Some(o) = &self.last_frame {
-                for m in &o.measurements {
-                    bars.push(Bar::new(m.angle.to_degrees(), m.strength))
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.plot_ranges.locked, "Lock Zoom")
+                .on_hover_text("Keep the current bounds instead of following the data");
+            if ui.button("Reset Zoom").clicked() {
+                self.plot_ranges.frames.clear();
+                if let Some(frame) = &self.last_frame {
+                    self.plot_ranges.push(frame);
                 }
-            };
-
-            let chart = BarChart::new(bars).width(0.1).name("Strenght");
-
-            Plot::new("Strenght")
-                .view_aspect(2.0)
-                .include_x(0.0)
-                .include_y(0.0)
-                .auto_bounds_x()
-                .auto_bounds_y()
-                .allow_scroll(false)
-                .allow_drag(false)
-                .allow_boxed_zoom(false)
-                .show(ui, |plot_ui| {
-                    plot_ui.bar_chart(chart);
-                    plot_ui.set_plot_bounds(PlotBounds::from_min_max([0.0, 0.0], [360.0, 2000.0]));
-                });
+                self.plot_ranges.reset = true;
+            }
+        });
+
+        let follow = self.plot_ranges.follow();
+        let locked = self.plot_ranges.locked;
+        let strength = self.plot_ranges.strength();
+        let distance = self.plot_ranges.distance();
 
-            // Draw a strength-distance scatter plot
-            let mut points = Vec::new();
+        let (bars, points) = match &self.last_frame {
+            Some(frame) => (
+                frame
+                    .measurements
+                    .iter()
+                    .map(|m| Bar::new(m.angle.to_degrees(), m.strength))
+                    .collect(),
+                frame
+                    .measurements
+                    .iter()
+                    .filter(|m| m.valid)
+                    .map(|m| [m.strength, m.distance])
+                    .collect(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
 
-            if let Some(o) = &self.last_frame {
-                for m in &o.measurements {
-                    points.push([m.strength, m.distance])
+        Plot::new("Strength")
+            .view_aspect(2.0)
+            .allow_scroll(false)
+            .allow_drag(locked)
+            .allow_zoom(locked)
+            .allow_boxed_zoom(locked)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).width(0.1).name("Strength"));
+                let bounds = PlotBounds::from_min_max([0.0, 0.0], [360.0, strength[1]]);
+                if follow && bounds.is_valid() {
+                    plot_ui.set_plot_bounds(bounds);
                 }
+            });
+
+        Plot::new("Strength vs Distance")
+            .view_aspect(2.0)
+            .allow_scroll(false)
+            .allow_drag(locked)
+            .allow_zoom(locked)
+            .allow_boxed_zoom(locked)
+            .show(ui, |plot_ui| {
+                plot_ui.points(Points::new(points));
+                let bounds = PlotBounds::from_min_max(
+                    [strength[0], distance[0]],
+                    [strength[1], distance[1]],
+                );
+                // all points may be at the same place
+                if follow && bounds.is_valid() {
+                    plot_ui.set_plot_bounds(bounds);
+                }
+            });
+
+        ui.separator();
+    }
+}
+
+impl Node for FrameVizualizer {
+    fn draw(&mut self, ui: &egui::Ui, world: &mut WorldObj<'_>) {
+        if !self.restored {
+            self.restored = true;
+            ui.ctx().data_mut(|d| {
+                for v in self.vis.iter_mut() {
+                    if let Some(enabled) = d.get_persisted(enabled_id(v.name())) {
+                        *v.enabled() = enabled;
+                    }
+                }
+            });
+        }
+
+        if let Some(subscription) = &mut self.plot_subscription {
+            while let Some(frame) = subscription.try_recv() {
+                self.plot_ranges.push(&frame);
+                if let Some(publisher) = &mut self.pub_scan_quality {
+                    publisher.publish(Arc::new(frame.quality()));
+                }
+                self.last_frame = Some(frame);
             }
+        }
 
-            // let line = Line::new(points);
-            let points = Points::new(points);
-            Plot::new("my_plot")
-                .view_aspect(2.0)
-                .show(ui, |plot_ui| plot_ui.points(points));
-             */
+        egui::Window::new("Visualizer").show(ui.ctx(), |ui| {
+            if self.plot_subscription.is_some() {
+                self.plots(ui);
+            }
 
             for v in self.vis.iter_mut() {
                 ui.horizontal(|ui| {
-                    ui.checkbox(v.enabled(), "");
+                    if ui.checkbox(v.enabled(), "").changed() {
+                        let enabled = *v.enabled();
+                        ui.ctx()
+                            .data_mut(|d| d.insert_persisted(enabled_id(v.name()), enabled));
+                    }
 
                     CollapsingHeader::new(v.name())
                         // .default_open(true)
@@ -265,7 +715,48 @@ impl Node for FrameVizualizer {
             v.poll();
             if *v.enabled() {
                 v.visualize(world.sr);
+                v.overlay(ui.painter(), world);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn pose_velocity_requires_a_minimum_dt() {
+        let t0 = Instant::now();
+        let from = (
+            t0,
+            Pose {
+                x: 0.0,
+                y: 0.0,
+                theta: 0.0,
+            },
+        );
+        let to = Pose {
+            x: 1.0,
+            y: -0.5,
+            theta: 0.25,
+        };
+
+        // drained in the same poll: no estimate instead of a huge velocity
+        assert_eq!(pose_velocity(from, (t0, to)), None);
+        assert_eq!(
+            pose_velocity(from, (t0 + Duration::from_micros(100), to)),
+            None
+        );
+        // out of order
+        assert_eq!(
+            pose_velocity((t0 + Duration::from_secs(1), from.1), (t0, to)),
+            None
+        );
+
+        let v = pose_velocity(from, (t0 + Duration::from_millis(500), to)).unwrap();
+        assert!((v - Vector3::new(2.0, -1.0, 0.5)).norm() < 1e-4);
+    }
+}
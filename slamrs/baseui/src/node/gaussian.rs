@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use common::{
     gaussian::Gaussian2D,
-    node::{Node, NodeConfig},
+    node::{Node, NodeConfig, TopicDecl},
     world::WorldObj,
 };
 use eframe::egui;
@@ -17,7 +17,7 @@ pub struct GaussianRendering {
     p: f32,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct GaussianNodeConfig {
     topic: String,
 }
@@ -25,11 +25,15 @@ pub struct GaussianNodeConfig {
 impl NodeConfig for GaussianNodeConfig {
     fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
         Box::new(GaussianRendering {
-            publish: pubsub.publish(&self.topic),
+            publish: pubsub.publish_registered(&self.topic),
             gaussian: Gaussian2D::default(),
             p: 0.95,
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![TopicDecl::publish::<Gaussian2D>(&self.topic)]
+    }
 }
 
 impl Node for GaussianRendering {
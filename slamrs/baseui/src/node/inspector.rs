@@ -0,0 +1,142 @@
+use std::{any::Any, sync::Arc};
+
+use common::{
+    node::{Node, NodeConfig},
+    world::WorldObj,
+};
+use eframe::egui;
+use egui::RichText;
+use pubsub::{AnySubscription, PubSub};
+use serde::Deserialize;
+use web_time::{Duration, Instant};
+
+/// How often the publish rate of each topic is updated.
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shows the latest message, publish rate and message count of any topic, for checking that
+/// something is actually being published. Only topics whose type has been registered (see
+/// [`PubSub::register`]) can show their messages.
+pub struct InspectorNode {
+    topics: Vec<InspectedTopic>,
+    /// Index into `topics`
+    selected: Option<usize>,
+}
+
+struct InspectedTopic {
+    subscription: AnySubscription,
+    latest: Option<Arc<dyn Any + Send + Sync>>,
+    count: usize,
+    /// Messages received since `rate_start`
+    rate_count: usize,
+    rate_start: Instant,
+    /// Messages per second, measured over the last [`RATE_INTERVAL`]
+    rate: f32,
+}
+
+/// The inspector lists the topics that exist when it is instantiated, so it should be placed
+/// after the nodes using the topics of interest.
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct InspectorNodeConfig {}
+
+impl NodeConfig for InspectorNodeConfig {
+    fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
+        let mut names: Vec<String> = pubsub.topics().map(str::to_owned).collect();
+        names.sort();
+
+        let topics = names
+            .iter()
+            .filter_map(|name| pubsub.subscribe_any(name))
+            .map(|subscription| InspectedTopic {
+                subscription,
+                latest: None,
+                count: 0,
+                rate_count: 0,
+                rate_start: Instant::now(),
+                rate: 0.0,
+            })
+            .collect();
+
+        Box::new(InspectorNode {
+            topics,
+            selected: None,
+        })
+    }
+}
+
+impl InspectedTopic {
+    fn update(&mut self) {
+        while let Some(value) = self.subscription.try_recv() {
+            self.latest = Some(value);
+            self.count += 1;
+            self.rate_count += 1;
+        }
+
+        let elapsed = self.rate_start.elapsed();
+        if elapsed >= RATE_INTERVAL {
+            self.rate = self.rate_count as f32 / elapsed.as_secs_f32();
+            self.rate_count = 0;
+            self.rate_start = Instant::now();
+        }
+    }
+
+    fn latest_text(&self) -> String {
+        match (&self.latest, self.subscription.vtable()) {
+            (None, _) => "No messages received yet".to_owned(),
+            (Some(value), Some(vtable)) => (vtable.debug_fmt)(value.as_ref()),
+            (Some(_), None) => "The type of this topic is not registered".to_owned(),
+        }
+    }
+}
+
+impl Node for InspectorNode {
+    fn update(&mut self) {
+        for topic in self.topics.iter_mut() {
+            topic.update();
+        }
+    }
+
+    fn draw(&mut self, ui: &egui::Ui, _world: &mut WorldObj<'_>) {
+        egui::Window::new("Inspector").show(ui.ctx(), |ui| {
+            if self.topics.is_empty() {
+                ui.label("No topics in use");
+                return;
+            }
+
+            let selected_name = self
+                .selected
+                .map_or("Select a topic", |i| self.topics[i].subscription.topic());
+
+            egui::ComboBox::from_label("Topic")
+                .selected_text(selected_name.to_owned())
+                .show_ui(ui, |ui| {
+                    for (i, topic) in self.topics.iter().enumerate() {
+                        ui.selectable_value(
+                            &mut self.selected,
+                            Some(i),
+                            topic.subscription.topic(),
+                        );
+                    }
+                });
+
+            let Some(topic) = self.selected.map(|i| &self.topics[i]) else {
+                return;
+            };
+
+            let type_name = topic
+                .subscription
+                .vtable()
+                .map_or("unregistered type", |v| v.name);
+            ui.label(format!(
+                "Type: {}\nMessages: {}\nRate: {:.1} Hz",
+                type_name, topic.count, topic.rate
+            ));
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    ui.label(RichText::new(topic.latest_text()).monospace());
+                });
+        });
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use common::{
+    node::{Node, NodeConfig, TopicDecl},
+    robot::Pose,
+    world::WorldObj,
+};
+use eframe::egui;
+use log::{error, info};
+use pubsub::{PubSub, Subscription};
+use serde::Deserialize;
+use slam::{Cell, GridMapMessage};
+use web_time::Instant;
+
+/// Writes snapshots of the pose and the map to a directory in the background, intended for
+/// long unattended runs.
+pub struct LoggerNode {
+    directory: PathBuf,
+    sub_pose: Option<Subscription<Pose>>,
+    sub_map: Option<Subscription<GridMapMessage>>,
+    pose_writer: Option<BufWriter<File>>,
+    map_interval: f32,
+    latest_map: Option<Arc<GridMapMessage>>,
+    /// Set if a map has been received since the last one was saved.
+    map_changed: bool,
+    last_map_save: Instant,
+    start: Instant,
+    poses_logged: usize,
+    maps_saved: usize,
+    error: Option<String>,
+}
+
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct LoggerNodeConfig {
+    /// The directory to write the snapshots to, created if it does not exist.
+    directory: PathBuf,
+    /// Every pose published here is appended to `poses.csv`.
+    topic_pose: Option<String>,
+    /// The latest map published here is saved as a PGM image every `map_interval` seconds.
+    topic_map: Option<String>,
+    #[serde(default = "default_map_interval")]
+    map_interval: f32,
+}
+
+fn default_map_interval() -> f32 {
+    10.0
+}
+
+impl NodeConfig for LoggerNodeConfig {
+    fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
+        let mut node = LoggerNode {
+            directory: self.directory.clone(),
+            sub_pose: self
+                .topic_pose
+                .as_ref()
+                .map(|topic| pubsub.subscribe(topic)),
+            sub_map: self.topic_map.as_ref().map(|topic| pubsub.subscribe(topic)),
+            pose_writer: None,
+            map_interval: self.map_interval,
+            latest_map: None,
+            map_changed: false,
+            last_map_save: Instant::now(),
+            start: Instant::now(),
+            poses_logged: 0,
+            maps_saved: 0,
+            error: None,
+        };
+
+        if let Err(e) = node.open() {
+            node.fail(e);
+        }
+
+        Box::new(node)
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = Vec::new();
+        if let Some(topic) = &self.topic_pose {
+            topics.push(TopicDecl::subscribe::<Pose>(topic));
+        }
+        if let Some(topic) = &self.topic_map {
+            topics.push(TopicDecl::subscribe::<GridMapMessage>(topic));
+        }
+
+        topics
+    }
+}
+
+impl LoggerNode {
+    fn open(&mut self) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+
+        if self.sub_pose.is_some() {
+            let mut writer = BufWriter::new(File::create(self.directory.join("poses.csv"))?);
+            writeln!(writer, "time,x,y,theta")?;
+            self.pose_writer = Some(writer);
+        }
+
+        info!("Logging to {}", self.directory.display());
+        Ok(())
+    }
+
+    /// Stops all logging after an error, so a full disk does not spam the log every frame.
+    fn fail(&mut self, e: anyhow::Error) {
+        error!("Logger stopped: {:#}", e);
+        self.error = Some(e.to_string());
+        self.sub_pose = None;
+        self.sub_map = None;
+        self.pose_writer = None;
+        self.latest_map = None;
+    }
+
+    fn log_poses(&mut self) -> anyhow::Result<()> {
+        let (Some(sub_pose), Some(writer)) = (&mut self.sub_pose, &mut self.pose_writer) else {
+            return Ok(());
+        };
+
+        while let Some(pose) = sub_pose.try_recv() {
+            writeln!(
+                writer,
+                "{:.3},{},{},{}",
+                self.start.elapsed().as_secs_f32(),
+                pose.x,
+                pose.y,
+                pose.theta
+            )?;
+            self.poses_logged += 1;
+        }
+
+        Ok(())
+    }
+
+    fn save_map(&mut self) -> anyhow::Result<()> {
+        let Some(map) = &self.latest_map else {
+            return Ok(());
+        };
+
+        let path = self
+            .directory
+            .join(format!("map_{:06}.pgm", self.start.elapsed().as_secs()));
+        write_pgm(map, &path)?;
+
+        self.map_changed = false;
+        self.last_map_save = Instant::now();
+        self.maps_saved += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.map_changed {
+            self.save_map()?;
+        }
+        if let Some(writer) = &mut self.pose_writer {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn log(&mut self) -> anyhow::Result<()> {
+        self.log_poses()?;
+
+        if let Some(sub_map) = &mut self.sub_map {
+            while let Some(map) = sub_map.try_recv() {
+                self.latest_map = Some(map);
+                self.map_changed = true;
+            }
+        }
+
+        if self.map_changed && self.last_map_save.elapsed().as_secs_f32() >= self.map_interval {
+            self.save_map()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the occupancy probabilities of the map as a binary PGM image, with free cells
+/// white and occupied cells black.
+fn write_pgm(map: &GridMapMessage, path: &Path) -> anyhow::Result<()> {
+    let size = map.data.size();
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "P5\n{} {}\n255", size.x, size.y)?;
+
+    // images are stored top to bottom while the rows of the map go upwards
+    for row in (0..size.y).rev() {
+        let pixels: Vec<u8> = (0..size.x)
+            .map(|column| {
+                let p = map.data.get(Cell::new(column, row)).value();
+                ((1.0 - p) * 255.0).round() as u8
+            })
+            .collect();
+        writer.write_all(&pixels)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+impl Node for LoggerNode {
+    fn update(&mut self) {
+        if let Err(e) = self.log() {
+            self.fail(e);
+        }
+    }
+
+    fn draw(&mut self, ui: &egui::Ui, _world: &mut WorldObj<'_>) {
+        egui::Window::new("Logger").show(ui.ctx(), |ui| {
+            ui.label(format!("Directory: {}", self.directory.display()));
+
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, format!("Error: {error}"));
+            }
+
+            ui.label(format!("Poses logged: {}", self.poses_logged));
+            ui.label(format!("Maps saved: {}", self.maps_saved));
+        });
+    }
+
+    fn terminate(&mut self) {
+        // make sure the latest state ends up on disk
+        if let Err(e) = self.flush() {
+            self.fail(e);
+        }
+    }
+}
@@ -1,7 +1,16 @@
+pub mod calibration;
 pub mod controls;
+pub mod error_eval;
 pub mod frame_viz;
 pub mod gaussian;
+pub mod inspector;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logger;
 pub mod mouse_position;
+pub mod rate_limiter;
+pub mod scenario;
 pub mod shape_rendering;
 pub mod splitter;
+pub mod transform;
+pub mod velocity;
 mod visualize;
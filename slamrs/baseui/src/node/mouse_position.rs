@@ -6,7 +6,7 @@ use eframe::egui;
 use pubsub::PubSub;
 use serde::Deserialize;
 pub struct MousePosition {}
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct MousePositionNodeConfig {}
 
 impl NodeConfig for MousePositionNodeConfig {
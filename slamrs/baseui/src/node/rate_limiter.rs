@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use common::{
+    node::{Node, NodeConfig, TopicDecl},
+    robot::Command,
+};
+use pubsub::{Publisher, Subscription};
+use serde::Deserialize;
+use web_time::Instant;
+
+/// Republishes motion commands at a limited rate, optionally smoothing the wheel speeds, so that
+/// sources publishing at the UI frame rate do not flood the connection to the robot.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct CommandRateLimiterNodeConfig {
+    topic_input: String,
+    topic_output: String,
+    /// The maximum number of commands published per second.
+    #[serde(default = "default_max_rate")]
+    max_rate: f32,
+    /// How much of the previous wheel speeds is kept in every published command, from 0 (no
+    /// smoothing) towards 1 (very slow changes). Stop commands are always passed on directly.
+    #[serde(default)]
+    smoothing: f32,
+}
+
+fn default_max_rate() -> f32 {
+    10.0
+}
+
+/// Commands closer than this (m/s) to the target are considered to have reached it.
+const SETTLED_SPEED: f32 = 1e-3;
+
+pub struct CommandRateLimiterNode {
+    sub_command: Subscription<Command>,
+    pub_command: Publisher<Command>,
+    min_period: f32,
+    smoothing: f32,
+    /// The latest received command.
+    target: Option<Command>,
+    /// The last published command and when it was published.
+    published: Option<(Instant, Command)>,
+}
+
+impl NodeConfig for CommandRateLimiterNodeConfig {
+    fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
+        Box::new(CommandRateLimiterNode {
+            sub_command: pubsub.subscribe(&self.topic_input),
+            pub_command: pubsub.publish_registered(&self.topic_output),
+            min_period: 1.0 / self.max_rate.max(f32::EPSILON),
+            smoothing: self.smoothing.clamp(0.0, 0.99),
+            target: None,
+            published: None,
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![
+            TopicDecl::subscribe::<Command>(&self.topic_input),
+            TopicDecl::publish::<Command>(&self.topic_output),
+        ]
+    }
+}
+
+impl CommandRateLimiterNode {
+    /// The command to publish next, moving from the last published command towards the target.
+    fn next_command(&self, target: Command) -> Command {
+        let Some((_, last)) = self.published else {
+            return target;
+        };
+
+        let stop = Command::default();
+        if target == stop {
+            return stop;
+        }
+
+        let blend =
+            |last: f32, target: f32| self.smoothing * last + (1.0 - self.smoothing) * target;
+        let command = Command {
+            speed_left: blend(last.speed_left, target.speed_left),
+            speed_right: blend(last.speed_right, target.speed_right),
+        };
+
+        // snap to the target instead of approaching it forever
+        let settled = (command.speed_left - target.speed_left).abs() < SETTLED_SPEED
+            && (command.speed_right - target.speed_right).abs() < SETTLED_SPEED;
+        if settled {
+            target
+        } else {
+            command
+        }
+    }
+}
+
+impl Node for CommandRateLimiterNode {
+    fn update(&mut self) {
+        while let Some(command) = self.sub_command.try_recv() {
+            self.target = Some(*command);
+        }
+
+        let Some(target) = self.target else {
+            return;
+        };
+
+        let (due, reached) = match self.published {
+            Some((at, last)) => (
+                at.elapsed().as_secs_f32() >= self.min_period,
+                last == target,
+            ),
+            None => (true, false),
+        };
+
+        // stopping can not wait
+        let stop = target == Command::default();
+        if reached || !(due || stop) {
+            return;
+        }
+
+        let command = self.next_command(target);
+        self.pub_command.publish(Arc::new(command));
+        self.published = Some((Instant::now(), command));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pubsub::PubSub;
+
+    use super::*;
+
+    fn command(speed_left: f32, speed_right: f32) -> Command {
+        Command {
+            speed_left,
+            speed_right,
+        }
+    }
+
+    /// A limiter with the topics to feed it and to read what it publishes.
+    struct Harness {
+        pubsub: PubSub,
+        input: Publisher<Command>,
+        node: CommandRateLimiterNode,
+        output: Subscription<Command>,
+    }
+
+    impl Harness {
+        /// Publishing at most once every `min_period` seconds.
+        fn new(min_period: f32, smoothing: f32) -> Self {
+            let mut pubsub = PubSub::new();
+            let input = pubsub.publish("input");
+            let output = pubsub.subscribe("output");
+            let node = CommandRateLimiterNode {
+                sub_command: pubsub.subscribe("input"),
+                pub_command: pubsub.publish("output"),
+                min_period,
+                smoothing,
+                target: None,
+                published: None,
+            };
+            Self {
+                pubsub,
+                input,
+                node,
+                output,
+            }
+        }
+
+        /// Sends `command` (if any) to the limiter and returns what it published in response.
+        fn step(&mut self, command: Option<Command>) -> Option<Command> {
+            if let Some(command) = command {
+                self.input.publish(Arc::new(command));
+            }
+            self.pubsub.tick();
+            self.node.update();
+            self.pubsub.tick();
+            self.output.try_recv().map(|c| *c)
+        }
+    }
+
+    #[test]
+    fn next_command_moves_towards_the_target() {
+        let mut node = Harness::new(0.1, 0.5).node;
+        let target = command(0.2, -0.2);
+
+        // nothing to smooth from yet
+        assert_eq!(node.next_command(target), target);
+
+        node.published = Some((Instant::now(), command(0.1, 0.1)));
+        let next = node.next_command(target);
+        assert!((next.speed_left - 0.15).abs() < 1e-6);
+        assert!((next.speed_right + 0.05).abs() < 1e-6);
+
+        // stopping is not smoothed
+        assert_eq!(node.next_command(Command::default()), Command::default());
+
+        // close enough snaps to the target
+        node.published = Some((Instant::now(), command(0.2005, -0.2005)));
+        assert_eq!(node.next_command(target), target);
+    }
+
+    #[test]
+    fn commands_are_only_published_when_due() {
+        let mut limiter = Harness::new(60.0, 0.0);
+
+        // the first command is always due
+        assert_eq!(
+            limiter.step(Some(command(0.1, 0.1))),
+            Some(command(0.1, 0.1))
+        );
+
+        // the next one has to wait for the period to pass
+        assert_eq!(limiter.step(Some(command(0.2, 0.2))), None);
+
+        // but stopping does not
+        assert_eq!(
+            limiter.step(Some(Command::default())),
+            Some(Command::default())
+        );
+    }
+
+    #[test]
+    fn reached_targets_are_not_republished() {
+        let mut limiter = Harness::new(0.0, 0.0);
+
+        assert_eq!(
+            limiter.step(Some(command(0.1, 0.1))),
+            Some(command(0.1, 0.1))
+        );
+
+        // due, but the target was already published
+        assert_eq!(limiter.step(None), None);
+
+        // a new target is published as soon as it is due
+        assert_eq!(
+            limiter.step(Some(command(0.2, 0.2))),
+            Some(command(0.2, 0.2))
+        );
+    }
+}
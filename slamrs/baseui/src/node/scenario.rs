@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use common::{
+    node::{Node, NodeConfig, TopicDecl},
+    robot::Command,
+    world::WorldObj,
+};
+use eframe::egui;
+use egui::RichText;
+use pubsub::{PubSub, Publisher};
+use serde::Deserialize;
+use web_time::Instant;
+
+/// Drives the robot by publishing a scripted sequence of commands, to get repeatable runs
+/// without manual control.
+pub struct ScenarioNode {
+    pub_cmd: Publisher<Command>,
+    steps: Vec<ScenarioStep>,
+    looping: bool,
+    start: Instant,
+    /// The step currently being published, `None` once the scenario has finished.
+    current: Option<usize>,
+    last_command: Command,
+}
+
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct ScenarioNodeConfig {
+    topic_command: String,
+    steps: Vec<ScenarioStep>,
+    /// Starts over from the first step when the last one has finished, otherwise the robot is
+    /// stopped.
+    #[serde(default)]
+    looping: bool,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+struct ScenarioStep {
+    /// How long to keep sending this command, in seconds.
+    duration: f32,
+    speed_left: f32,
+    speed_right: f32,
+}
+
+impl NodeConfig for ScenarioNodeConfig {
+    fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
+        Box::new(ScenarioNode {
+            pub_cmd: pubsub.publish_registered(&self.topic_command),
+            steps: self.steps.clone(),
+            looping: self.looping,
+            start: Instant::now(),
+            current: None,
+            last_command: Default::default(),
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![TopicDecl::publish::<Command>(&self.topic_command)]
+    }
+}
+
+impl ScenarioNode {
+    fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    /// Returns the index of the step that should be active `elapsed` seconds into the scenario.
+    fn step_at(&self, elapsed: f32) -> Option<usize> {
+        let total: f32 = self.steps.iter().map(|s| s.duration).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut t = if self.looping {
+            elapsed % total
+        } else {
+            elapsed
+        };
+
+        for (i, step) in self.steps.iter().enumerate() {
+            if t < step.duration {
+                return Some(i);
+            }
+            t -= step.duration;
+        }
+
+        None
+    }
+}
+
+impl Node for ScenarioNode {
+    fn update(&mut self) {
+        self.current = self.step_at(self.start.elapsed().as_secs_f32());
+
+        // stop the robot when the scenario is over
+        let cmd = match self.current {
+            Some(i) => Command {
+                speed_left: self.steps[i].speed_left,
+                speed_right: self.steps[i].speed_right,
+            },
+            None => Command::default(),
+        };
+
+        if cmd != self.last_command {
+            self.pub_cmd.publish(Arc::new(cmd));
+            self.last_command = cmd;
+        }
+    }
+
+    fn draw(&mut self, ui: &egui::Ui, _world: &mut WorldObj<'_>) {
+        egui::Window::new("Scenario").show(ui.ctx(), |ui| {
+            match self.current {
+                Some(i) => ui.label(format!("Step: {}/{}", i + 1, self.steps.len())),
+                None => ui.label("Finished"),
+            };
+
+            ui.label(
+                RichText::new(format!(
+                    "Elapsed: {:.1} s\nLeft: {:+.3} | Right: {:+.3}",
+                    self.start.elapsed().as_secs_f32(),
+                    self.last_command.speed_left,
+                    self.last_command.speed_right
+                ))
+                .text_style(egui::TextStyle::Monospace),
+            );
+
+            ui.checkbox(&mut self.looping, "Loop");
+
+            if ui.button("Restart Scenario").clicked() {
+                self.restart();
+            }
+        });
+    }
+}
@@ -11,7 +11,7 @@ use pubsub::PubSub;
 use serde::Deserialize;
 pub struct ShapeRendering {}
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct ShapeRenderingNodeConfig {}
 
 impl NodeConfig for ShapeRenderingNodeConfig {
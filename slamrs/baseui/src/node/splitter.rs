@@ -1,13 +1,13 @@
 use std::sync::Arc;
 
 use common::{
-    node::{Node, NodeConfig},
+    node::{Node, NodeConfig, TopicDecl},
     robot::{LandmarkObservations, Observation, Odometry},
 };
 use pubsub::{Publisher, Subscription};
 use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct SplitterNodeConfig {
     splits: Vec<Split>,
 }
@@ -34,7 +34,7 @@ impl<S: Send + Sync + 'static + Clone, T: Send + Sync + 'static + Clone> Splitte
         }
     }
 }
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
 enum Split {
     ScannerOdometry {
         input: String,
@@ -71,6 +71,29 @@ impl Split {
             }),
         }
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        match self {
+            Split::ScannerOdometry {
+                input,
+                scanner,
+                odometry,
+            } => vec![
+                TopicDecl::subscribe::<(Observation, Odometry)>(input),
+                TopicDecl::publish::<Observation>(scanner),
+                TopicDecl::publish::<Odometry>(odometry),
+            ],
+            Split::LandmarkOdometry {
+                input,
+                landmark,
+                odometry,
+            } => vec![
+                TopicDecl::subscribe::<(LandmarkObservations, Odometry)>(input),
+                TopicDecl::publish::<LandmarkObservations>(landmark),
+                TopicDecl::publish::<Odometry>(odometry),
+            ],
+        }
+    }
 }
 
 impl NodeConfig for SplitterNodeConfig {
@@ -79,6 +102,10 @@ impl NodeConfig for SplitterNodeConfig {
             splitters: self.splits.iter().map(|s| s.instantiate(pubsub)).collect(),
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        self.splits.iter().flat_map(|s| s.topics()).collect()
+    }
 }
 
 pub struct SplitterNode {
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use common::{
+    node::{Node, NodeConfig, TopicDecl},
+    robot::{FrameTransform, Observation, Pose},
+};
+use nalgebra::Vector2;
+use pubsub::{Publisher, Subscription};
+use serde::Deserialize;
+
+/// Converts data from another source into the conventions of this crate (meters and radians)
+/// by republishing it with a scale, rotation and translation applied.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct FrameTransformNodeConfig {
+    topic: TransformTopic,
+    #[serde(default = "default_scale")]
+    scale: f32,
+    /// Counter-clockwise rotation in degrees.
+    #[serde(default)]
+    rotation_deg: f32,
+    #[serde(default)]
+    translation: [f32; 2],
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+enum TransformTopic {
+    Observation { input: String, output: String },
+    Pose { input: String, output: String },
+}
+
+trait Transformer {
+    fn update(&mut self, transform: &FrameTransform);
+}
+
+struct TopicTransformer<T: Send + Sync + 'static> {
+    input: Subscription<T>,
+    output: Publisher<T>,
+    apply: fn(&FrameTransform, &T) -> T,
+}
+
+impl<T: Send + Sync + 'static> Transformer for TopicTransformer<T> {
+    fn update(&mut self, transform: &FrameTransform) {
+        while let Some(data) = self.input.try_recv() {
+            self.output
+                .publish(Arc::new((self.apply)(transform, &data)));
+        }
+    }
+}
+
+impl NodeConfig for FrameTransformNodeConfig {
+    fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
+        let transformer: Box<dyn Transformer> = match &self.topic {
+            TransformTopic::Observation { input, output } => Box::new(TopicTransformer {
+                input: pubsub.subscribe::<Observation>(input),
+                output: pubsub.publish(output),
+                apply: |t, o| t.transform_observation(o),
+            }),
+            TransformTopic::Pose { input, output } => Box::new(TopicTransformer {
+                input: pubsub.subscribe::<Pose>(input),
+                output: pubsub.publish(output),
+                apply: |t, p| t.transform_pose(*p),
+            }),
+        };
+
+        Box::new(FrameTransformNode {
+            transform: FrameTransform {
+                scale: self.scale,
+                rotation: self.rotation_deg.to_radians(),
+                translation: Vector2::from(self.translation),
+            },
+            transformer,
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        match &self.topic {
+            TransformTopic::Observation { input, output } => vec![
+                TopicDecl::subscribe::<Observation>(input),
+                TopicDecl::publish::<Observation>(output),
+            ],
+            TransformTopic::Pose { input, output } => vec![
+                TopicDecl::subscribe::<Pose>(input),
+                TopicDecl::publish::<Pose>(output),
+            ],
+        }
+    }
+}
+
+pub struct FrameTransformNode {
+    transform: FrameTransform,
+    transformer: Box<dyn Transformer>,
+}
+
+impl Node for FrameTransformNode {
+    fn update(&mut self) {
+        self.transformer.update(&self.transform);
+    }
+}
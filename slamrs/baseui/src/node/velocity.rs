@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use common::{
+    node::{Node, NodeConfig, TopicDecl},
+    robot::{Command, VelocityCommand},
+};
+use pubsub::{Publisher, Subscription};
+use serde::Deserialize;
+
+/// Converts velocity commands (forward and turning speed) into the wheel speeds the robot and
+/// the simulator understand, see [`VelocityCommand::to_command`].
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct VelocityCommandNodeConfig {
+    topic_input: String,
+    topic_output: String,
+    /// The distance between the wheels of the robot (meters), must match the robot or the
+    /// simulator for the turning rate to come out right.
+    wheel_base: f32,
+}
+
+pub struct VelocityCommandNode {
+    sub_velocity: Subscription<VelocityCommand>,
+    pub_command: Publisher<Command>,
+    wheel_base: f32,
+}
+
+impl NodeConfig for VelocityCommandNodeConfig {
+    fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
+        Box::new(VelocityCommandNode {
+            sub_velocity: pubsub.subscribe(&self.topic_input),
+            pub_command: pubsub.publish_registered(&self.topic_output),
+            wheel_base: self.wheel_base,
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![
+            TopicDecl::subscribe::<VelocityCommand>(&self.topic_input),
+            TopicDecl::publish::<Command>(&self.topic_output),
+        ]
+    }
+}
+
+impl Node for VelocityCommandNode {
+    fn update(&mut self) {
+        while let Some(velocity) = self.sub_velocity.try_recv() {
+            self.pub_command
+                .publish(Arc::new(velocity.to_command(self.wheel_base)));
+        }
+    }
+}
@@ -1,12 +1,17 @@
-use common::robot::{LandmarkObservations, Observation, Pose};
+use common::{
+    robot::{BumpEvent, LandmarkObservations, Observation, Pose},
+    world::{DrawMode, WorldObj},
+};
 use eframe::egui;
-use egui::Slider;
+use egui::{Align2, Color32, FontId, Rgba, Slider, Stroke};
 use graphics::{
-    primitiverenderer::{Color, PrimitiveType},
+    pointcloudrenderer::PointCloudId,
+    primitiverenderer::{hsv_to_color, Color, PrimitiveType},
     shaperenderer::ShapeRenderer,
 };
+use nalgebra::Point2;
 use serde::Deserialize;
-use slam::{GridMapMessage, LandmarkMapMessage, PointMap};
+use slam::{GridMapMessage, HitCountMapMessage, LandmarkMapMessage, PointMap};
 
 pub trait Visualize {
     type Parameters;
@@ -17,24 +22,56 @@ pub trait Visualize {
         config: &Self::Parameters,
         secondary: &Option<Self::Secondary>,
     );
+
+    /// Draw screen space overlays, such as text labels, on top of the world.
+    fn overlay(
+        &self,
+        _painter: &egui::Painter,
+        _world: &WorldObj<'_>,
+        _config: &Self::Parameters,
+        _secondary: &Option<Self::Secondary>,
+    ) {
+    }
 }
 
 pub trait VisualizeParametersUi {
     fn ui(&mut self, ui: &mut egui::Ui);
 }
 
+/// Edits the RGB components of `color` with a color picker, keeping its alpha.
+fn color_edit_button(ui: &mut egui::Ui, color: &mut Color) {
+    let [r, g, b, a] = color.to_rgba();
+    let mut rgb = [r, g, b];
+    if ui.color_edit_button_rgb(&mut rgb).changed() {
+        *color = Color::rgba(rgb[0], rgb[1], rgb[2], a);
+    }
+}
+
 //////////////// Implementation for Pose /////////////////
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct PoseVisualizeConfig {
-    color: [f32; 3],
+    color: Color,
     radius: f32,
+
+    /// Keep moving the arrow with the latest velocity between the pose updates, for at most
+    /// `max_extrapolation` seconds. Only affects the visualization.
+    #[serde(default)]
+    pub extrapolate: bool,
+    #[serde(default = "default_max_extrapolation")]
+    pub max_extrapolation: f32,
+}
+
+fn default_max_extrapolation() -> f32 {
+    0.5
 }
 
 impl Default for PoseVisualizeConfig {
     fn default() -> Self {
         Self {
-            color: [0.0, 1.0, 0.0],
+            color: Color::GREEN,
             radius: 0.1,
+            extrapolate: false,
+            max_extrapolation: default_max_extrapolation(),
         }
     }
 }
@@ -43,7 +80,7 @@ impl VisualizeParametersUi for PoseVisualizeConfig {
     fn ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label("Color: ");
-            ui.color_edit_button_rgb(&mut self.color);
+            color_edit_button(ui, &mut self.color);
         });
 
         ui.horizontal(|ui| {
@@ -54,6 +91,15 @@ impl VisualizeParametersUi for PoseVisualizeConfig {
                     .fixed_decimals(2),
             );
         });
+
+        ui.checkbox(&mut self.extrapolate, "Extrapolate");
+        if self.extrapolate {
+            ui.add(
+                Slider::new(&mut self.max_extrapolation, 0.1..=2.0)
+                    .step_by(0.1)
+                    .text("Max Extrapolation [s]"),
+            );
+        }
     }
 }
 
@@ -63,17 +109,33 @@ impl Visualize for Pose {
 
     fn visualize(&self, sr: &mut ShapeRenderer, c: &Self::Parameters, _: &Option<Self::Secondary>) {
         sr.begin(PrimitiveType::Filled);
-        sr.arrow(self.x, self.y, self.theta, c.radius, Color::from(c.color));
+        sr.arrow(self.x, self.y, self.theta, c.radius, c.color);
         sr.end()
     }
 }
 
 //////////////// Implementation for Observation /////////////////
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct ObservationVisualizeConfig {
     draw_lines: bool,
     size: f32,
-    point_color: [f32; 3],
+    point_color: Color,
+    /// Color each point by its angle instead of `point_color`, to make the orientation obvious.
+    #[serde(default)]
+    color_by_angle: bool,
+    #[serde(default)]
+    frame: Frame,
+}
+
+/// The frame observations are drawn in.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Frame {
+    /// Around the origin with the robot facing +x, ignoring the pose. Useful for checking the
+    /// geometry of the sensor itself.
+    Robot,
+    /// At the pose of the robot.
+    #[default]
+    World,
 }
 
 impl Default for ObservationVisualizeConfig {
@@ -81,7 +143,9 @@ impl Default for ObservationVisualizeConfig {
         Self {
             draw_lines: true,
             size: 0.01,
-            point_color: [0.0, 0.0, 0.0],
+            point_color: Color::BLACK,
+            color_by_angle: false,
+            frame: Frame::World,
         }
     }
 }
@@ -104,7 +168,16 @@ impl VisualizeParametersUi for ObservationVisualizeConfig {
 
         ui.horizontal(|ui| {
             ui.label("Point Color: ");
-            ui.color_edit_button_rgb(&mut self.point_color);
+            ui.add_enabled_ui(!self.color_by_angle, |ui| {
+                color_edit_button(ui, &mut self.point_color);
+            });
+            ui.checkbox(&mut self.color_by_angle, "By Angle");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Frame: ");
+            ui.radio_value(&mut self.frame, Frame::World, "World");
+            ui.radio_value(&mut self.frame, Frame::Robot, "Robot");
         });
     }
 }
@@ -119,25 +192,18 @@ impl Visualize for Observation {
         c: &Self::Parameters,
         pose: &Option<Self::Secondary>,
     ) {
-        let (ox, oy, otheta) = if let Some(p) = pose {
-            (p.x, p.y, p.theta)
-        } else {
-            (0.0, 0.0, 0.0)
-        };
+        let pose = pose.as_ref().filter(|_| c.frame == Frame::World);
+        let (ox, oy) = pose.map_or((0.0, 0.0), |p| (p.x, p.y));
+        let points = self.to_points(pose, true);
 
         if c.draw_lines {
             sr.begin(PrimitiveType::Line);
 
-            for m in self.measurements.iter() {
-                let (s, c) = (m.angle as f32 + otheta).sin_cos();
-                let d = m.distance as f32;
-                let x = c * d;
-                let y = s * d;
-
+            for (m, p) in self.measurements.iter().zip(points.column_iter()) {
                 let color = if m.valid { Color::BLACK } else { Color::RED };
                 // let color = Color::BLACK;
 
-                sr.line(ox, oy, ox + x, oy + y, color);
+                sr.line(ox, oy, p.x, p.y, color);
             }
 
             sr.end();
@@ -146,17 +212,18 @@ impl Visualize for Observation {
         sr.begin(PrimitiveType::Filled);
 
         let map_point_size = c.size;
-        let color = Color::from(c.point_color);
-        for m in self.measurements.iter() {
-            let (s, c) = (m.angle as f32 + otheta).sin_cos();
-            let d = m.distance as f32;
-            let x = c * d;
-            let y = s * d;
+        let point_color = c.point_color;
+        for (m, p) in self.measurements.iter().zip(points.column_iter()) {
+            let color = if c.color_by_angle {
+                hsv_to_color((m.angle as f32).to_degrees(), 1.0, 1.0)
+            } else {
+                point_color
+            };
 
             // let color = Color::rgb(m.strength as f32 / 2000.0, 0.0, 0.0);
             sr.rect(
-                ox + x - map_point_size / 2.0,
-                oy + y - map_point_size / 2.0,
+                p.x - map_point_size / 2.0,
+                p.y - map_point_size / 2.0,
                 map_point_size,
                 map_point_size,
                 color,
@@ -168,35 +235,68 @@ impl Visualize for Observation {
 
 //////////////// Implementation for PointMap /////////////////
 
-#[derive(Deserialize, Debug, Clone)]
+/// How the points of a [`PointMap`] are drawn.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PointMapBackend {
+    /// A filled square per point, sized in meters. Generated again every frame.
+    #[default]
+    Shapes,
+    /// Points sized in pixels, kept on the GPU and only uploaded when the map changes. Much
+    /// faster for maps with many points.
+    GpuPoints,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct PointMapVisualizeConfig {
     size: f32,
-    point_color: [f32; 3],
+    point_color: Color,
+    #[serde(default)]
+    pub backend: PointMapBackend,
+    /// The size of the points in pixels when using [`PointMapBackend::GpuPoints`].
+    #[serde(default = "default_point_pixels")]
+    point_pixels: f32,
+}
+
+fn default_point_pixels() -> f32 {
+    2.0
 }
 
 impl Default for PointMapVisualizeConfig {
     fn default() -> Self {
         Self {
             size: 0.01,
-            point_color: [0.0, 0.0, 0.0],
+            point_color: Color::BLACK,
+            backend: PointMapBackend::default(),
+            point_pixels: default_point_pixels(),
         }
     }
 }
 
 impl VisualizeParametersUi for PointMapVisualizeConfig {
     fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Backend: ");
+            ui.radio_value(&mut self.backend, PointMapBackend::Shapes, "Shapes");
+            ui.radio_value(&mut self.backend, PointMapBackend::GpuPoints, "GPU Points");
+        });
+
         ui.horizontal(|ui| {
             ui.label("Point Size: ");
-            ui.add(
-                Slider::new(&mut self.size, 0.001..=0.02)
-                    .step_by(0.001)
-                    .fixed_decimals(3),
-            );
+            match self.backend {
+                PointMapBackend::Shapes => ui.add(
+                    Slider::new(&mut self.size, 0.001..=0.02)
+                        .step_by(0.001)
+                        .fixed_decimals(3),
+                ),
+                PointMapBackend::GpuPoints => {
+                    ui.add(Slider::new(&mut self.point_pixels, 1.0..=10.0).suffix(" px"))
+                }
+            };
         });
 
         ui.horizontal(|ui| {
             ui.label("Point Color: ");
-            ui.color_edit_button_rgb(&mut self.point_color);
+            color_edit_button(ui, &mut self.point_color);
         });
     }
 }
@@ -209,7 +309,7 @@ impl Visualize for PointMap {
         sr.begin(PrimitiveType::Filled);
 
         let map_point_size = c.size;
-        let color = Color::from(c.point_color);
+        let color = c.point_color;
 
         for p in self.0.column_iter() {
             sr.rect(
@@ -225,32 +325,114 @@ impl Visualize for PointMap {
     }
 }
 
+/// Draws the map using [`PointMapBackend::GpuPoints`]. Only the points added since the last
+/// frame are uploaded, `generation` must change whenever the map changes in any other way.
+pub fn visualize_point_cloud(
+    map: &PointMap,
+    sr: &mut ShapeRenderer,
+    c: &PointMapVisualizeConfig,
+    cloud: PointCloudId,
+    generation: u64,
+) {
+    // the columns of the matrix are already laid out as x, y pairs
+    sr.point_cloud(
+        cloud,
+        generation,
+        c.point_pixels,
+        c.point_color,
+        map.0.as_slice(),
+    );
+}
+
 //////////////// Implementation for GridMap /////////////////
 
-#[derive(Deserialize, Debug, Clone, Default)]
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct GridMapVisualizeConfig {
     gridlines: bool,
+
+    /// Combine blocks of cells (keeping the highest occupancy) until at most this many are drawn.
+    max_display_cells: Option<usize>,
+
+    /// In `Wireframe` mode only the outlines of the occupied cells are drawn.
+    #[serde(default)]
+    draw_mode: DrawMode,
 }
 
 impl VisualizeParametersUi for GridMapVisualizeConfig {
     fn ui(&mut self, ui: &mut egui::Ui) {
         ui.checkbox(&mut self.gridlines, "Draw Grid Lines");
+        self.draw_mode.ui(ui);
+
+        let mut limit = self.max_display_cells.is_some();
+        ui.checkbox(&mut limit, "Limit Displayed Cells");
+        if limit {
+            let max = self.max_display_cells.get_or_insert(100_000);
+            ui.add(
+                Slider::new(max, 1_000..=1_000_000)
+                    .logarithmic(true)
+                    .text("Max Cells"),
+            );
+        } else {
+            self.max_display_cells = None;
+        }
     }
 }
 
+/// The smallest stride that downscales a grid of the given size to at most `max_cells` cells.
+fn display_stride(columns: usize, rows: usize, max_cells: usize) -> usize {
+    let max_cells = max_cells.max(1);
+    (1..)
+        .find(|&stride| {
+            (columns + stride - 1) / stride * ((rows + stride - 1) / stride) <= max_cells
+        })
+        .unwrap_or(1)
+}
+
 impl Visualize for GridMapMessage {
     type Parameters = GridMapVisualizeConfig;
     type Secondary = ();
 
     fn visualize(&self, sr: &mut ShapeRenderer, c: &Self::Parameters, _: &Option<Self::Secondary>) {
-        sr.begin(PrimitiveType::Filled);
+        sr.begin(c.draw_mode.primitive_type());
+
+        // the free cells would only clutter the outlines of the occupied ones
+        let visible = |occupancy: f64| c.draw_mode == DrawMode::Filled || occupancy > 0.5;
 
-        for (c, v) in self.data.iter_cells() {
-            let color = Color::grayscale(1.0 - v.value() as f32);
+        let size = self.data.size();
+        let stride = c
+            .max_display_cells
+            .map_or(1, |max| display_stride(size.x, size.y, max));
 
-            let x = self.position.x + c.column as f32 * self.resolution;
-            let y = self.position.y + c.row as f32 * self.resolution;
-            sr.rect(x, y, self.resolution, self.resolution, color)
+        if stride == 1 {
+            for (c, v) in self.data.iter_cells().filter(|(_, v)| visible(v.value())) {
+                let color = Color::grayscale(1.0 - v.value() as f32);
+
+                let x = self.position.x + c.column as f32 * self.resolution;
+                let y = self.position.y + c.row as f32 * self.resolution;
+                sr.rect(x, y, self.resolution, self.resolution, color)
+            }
+        } else {
+            let blocks = self.data.downscale(stride, |block| {
+                block.iter().map(|p| p.value()).fold(0.0, f64::max)
+            });
+
+            let width = size.x as f32 * self.resolution;
+            let height = size.y as f32 * self.resolution;
+            let block_size = stride as f32 * self.resolution;
+            for (c, v) in blocks.iter_cells().filter(|(_, v)| visible(**v)) {
+                let color = Color::grayscale(1.0 - *v as f32);
+
+                // the blocks along the far edges may cover fewer cells
+                let x = c.column as f32 * block_size;
+                let y = c.row as f32 * block_size;
+                sr.rect(
+                    self.position.x + x,
+                    self.position.y + y,
+                    block_size.min(width - x),
+                    block_size.min(height - y),
+                    color,
+                )
+            }
         }
 
         sr.end();
@@ -283,12 +465,65 @@ impl Visualize for GridMapMessage {
     }
 }
 
+//////////////// Implementation for HitCountMap /////////////////
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct HitCountMapVisualizeConfig {
+    /// Use a logarithmic scale so that cells with few hits are still visible.
+    logarithmic: bool,
+}
+
+impl VisualizeParametersUi for HitCountMapVisualizeConfig {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.logarithmic, "Logarithmic Scale");
+    }
+}
+
+impl Visualize for HitCountMapMessage {
+    type Parameters = HitCountMapVisualizeConfig;
+    type Secondary = ();
+
+    fn visualize(&self, sr: &mut ShapeRenderer, c: &Self::Parameters, _: &Option<Self::Secondary>) {
+        let scale = |count: u32| {
+            if c.logarithmic {
+                (count as f32).ln_1p()
+            } else {
+                count as f32
+            }
+        };
+
+        let max = self.data.iter_cells().map(|(_, &v)| v).max().unwrap_or(0);
+        if max == 0 {
+            return;
+        }
+        let max = scale(max);
+
+        sr.begin(PrimitiveType::Filled);
+
+        // only draw the cells that have been hit so that the occupancy map stays visible below
+        for (cell, &v) in self.data.iter_cells().filter(|(_, &v)| v > 0) {
+            let x = self.position.x + cell.column as f32 * self.resolution;
+            let y = self.position.y + cell.row as f32 * self.resolution;
+            sr.rect(
+                x,
+                y,
+                self.resolution,
+                self.resolution,
+                Color::heat(scale(v) / max),
+            )
+        }
+
+        sr.end();
+    }
+}
+
 //////////////// Implementation for LandmarkObsercations /////////////////
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct LandmarkObservationVisualizeConfig {
-    color: [f32; 3],
+    color: Color,
     radius: f32,
 }
 
@@ -296,7 +531,7 @@ impl Default for LandmarkObservationVisualizeConfig {
     fn default() -> Self {
         Self {
             radius: 0.02,
-            color: Default::default(),
+            color: Color::BLACK,
         }
     }
 }
@@ -314,7 +549,7 @@ impl VisualizeParametersUi for LandmarkObservationVisualizeConfig {
 
         ui.horizontal(|ui| {
             ui.label("Color: ");
-            ui.color_edit_button_rgb(&mut self.color);
+            color_edit_button(ui, &mut self.color);
         });
     }
 }
@@ -332,7 +567,7 @@ impl Visualize for LandmarkObservations {
         if let Some(pose) = pose {
             sr.begin(PrimitiveType::Filled);
 
-            let color = Color::from(c.color);
+            let color = c.color;
             for l in &self.landmarks {
                 let angle = pose.theta + l.angle;
                 let x = pose.x + l.distance * angle.cos();
@@ -348,15 +583,19 @@ impl Visualize for LandmarkObservations {
 
 //////////////// Implementation for LandmarkMapMessage /////////////////
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct LandmarkMapMessageVisualizeConfig {
     p: f32,
+    show_labels: bool,
 }
 
 impl Default for LandmarkMapMessageVisualizeConfig {
     fn default() -> Self {
-        Self { p: 0.95 }
+        Self {
+            p: 0.95,
+            show_labels: false,
+        }
     }
 }
 
@@ -370,6 +609,8 @@ impl VisualizeParametersUi for LandmarkMapMessageVisualizeConfig {
                     .fixed_decimals(3),
             );
         });
+
+        ui.checkbox(&mut self.show_labels, "Show Labels");
     }
 }
 
@@ -378,8 +619,173 @@ impl Visualize for LandmarkMapMessage {
     type Secondary = ();
 
     fn visualize(&self, sr: &mut ShapeRenderer, c: &Self::Parameters, _: &Option<Self::Secondary>) {
-        for l in &self.landmarks {
-            sr.gaussian2d(&l.mean, &l.covariance, c.p);
+        sr.gaussians2d(self.landmarks.iter().map(|l| (&l.mean, &l.covariance)), c.p);
+    }
+
+    fn overlay(
+        &self,
+        painter: &egui::Painter,
+        world: &WorldObj<'_>,
+        c: &Self::Parameters,
+        _: &Option<Self::Secondary>,
+    ) {
+        if !c.show_labels {
+            return;
+        }
+
+        for (i, l) in self.landmarks.iter().enumerate() {
+            let pos = world.project(Point2::new(l.mean.x, l.mean.y));
+            if painter.clip_rect().contains(pos) {
+                painter.text(
+                    pos,
+                    Align2::LEFT_BOTTOM,
+                    i.to_string(),
+                    FontId::monospace(12.0),
+                    painter.ctx().style().visuals.strong_text_color(),
+                );
+            }
+        }
+    }
+}
+
+//////////////// Implementation for Footprint /////////////////
+
+/// The outline of the robot, expressed in the robot frame (x forward, y left).
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum Footprint {
+    Circle { radius: f32 },
+    Polygon { points: Vec<[f32; 2]> },
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct FootprintVisualizeConfig {
+    pub shape: Footprint,
+    color: Color,
+}
+
+impl Default for FootprintVisualizeConfig {
+    fn default() -> Self {
+        Self {
+            shape: Footprint::Circle { radius: 0.1 },
+            color: Color::BLUE,
+        }
+    }
+}
+
+impl VisualizeParametersUi for FootprintVisualizeConfig {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Color: ");
+            color_edit_button(ui, &mut self.color);
+        });
+    }
+}
+
+impl Visualize for Footprint {
+    type Parameters = FootprintVisualizeConfig;
+    type Secondary = Pose;
+
+    fn visualize(
+        &self,
+        sr: &mut ShapeRenderer,
+        c: &Self::Parameters,
+        pose: &Option<Self::Secondary>,
+    ) {
+        let Some(pose) = pose else {
+            return;
+        };
+
+        let color = c.color;
+
+        sr.begin(PrimitiveType::Line);
+        match self {
+            Footprint::Circle { radius } => sr.circle(pose.x, pose.y, *radius, color),
+            Footprint::Polygon { points } => {
+                let (s, c) = pose.theta.sin_cos();
+                let transform =
+                    |p: &[f32; 2]| (pose.x + c * p[0] - s * p[1], pose.y + s * p[0] + c * p[1]);
+
+                // draw each edge, closing the polygon back to the first point
+                for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+                    let (x1, y1) = transform(a);
+                    let (x2, y2) = transform(b);
+                    sr.line(x1, y1, x2, y2, color);
+                }
+            }
+        }
+        sr.end();
+    }
+}
+
+//////////////// Implementation for BumpEvent /////////////////
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct BumpEventVisualizeConfig {
+    color: Color,
+    /// How long (in seconds) the contact point stays visible after a bump.
+    duration: f32,
+}
+
+impl Default for BumpEventVisualizeConfig {
+    fn default() -> Self {
+        Self {
+            color: Color::RED,
+            duration: 0.5,
+        }
+    }
+}
+
+impl VisualizeParametersUi for BumpEventVisualizeConfig {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Color: ");
+            color_edit_button(ui, &mut self.color);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Duration: ");
+            ui.add(
+                Slider::new(&mut self.duration, 0.1..=2.0)
+                    .step_by(0.1)
+                    .fixed_decimals(1),
+            );
+        });
+    }
+}
+
+impl Visualize for BumpEvent {
+    type Parameters = BumpEventVisualizeConfig;
+    type Secondary = ();
+
+    fn visualize(&self, _: &mut ShapeRenderer, _: &Self::Parameters, _: &Option<Self::Secondary>) {}
+
+    fn overlay(
+        &self,
+        painter: &egui::Painter,
+        world: &WorldObj<'_>,
+        c: &Self::Parameters,
+        _: &Option<Self::Secondary>,
+    ) {
+        // remember when each bump was first shown so it can fade out over time
+        let ctx = painter.ctx();
+        let now = ctx.input(|i| i.time);
+        let shown_at = ctx
+            .data_mut(|d| *d.get_temp_mut_or_insert_with(egui::Id::new(("bump", self.id)), || now));
+
+        let fade = 1.0 - ((now - shown_at) as f32 / c.duration).clamp(0.0, 1.0);
+        if fade <= 0.0 {
+            return;
         }
+
+        let [r, g, b, _] = c.color.to_rgba();
+        let color: Color32 = (Rgba::from_rgb(r, g, b) * fade).into();
+        let point = world.project(Point2::new(self.point[0], self.point[1]));
+        let normal = egui::vec2(self.normal[0], -self.normal[1]);
+
+        painter.circle_stroke(point, 4.0 + 8.0 * (1.0 - fade), Stroke::new(2.0_f32, color));
+        painter.line_segment([point, point + 20.0 * normal], Stroke::new(2.0_f32, color));
+
+        // keep repainting until the flash has faded out
+        ctx.request_repaint();
     }
 }
@@ -132,6 +132,14 @@ impl SubAssign<LogOdds> for LogOdds {
 }
 
 impl LogOdds {
+    pub const fn new(value: f64) -> LogOdds {
+        LogOdds(value)
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+
     pub fn probability(&self) -> Probability {
         Probability(1.0 - 1.0 / (1.0 + self.0.exp()))
     }
@@ -1,3 +1,5 @@
+use std::any::type_name;
+
 use crate::world::WorldObj;
 use eframe::egui;
 use pubsub::PubSub;
@@ -16,6 +18,10 @@ pub trait Node {
     /// Note: No logic update should happen here since it might not be called if running in headless state.
     fn draw(&mut self, _ui: &egui::Ui, _world: &mut WorldObj<'_>) {}
 
+    /// Called when the whole pipeline is paused or resumed. Nodes that produce data on their own
+    /// (e.g. simulators) should stop doing so while paused.
+    fn set_paused(&mut self, _paused: bool) {}
+
     /// Called when the Node should terminate. Terminate background threads etc. here.
     fn terminate(&mut self) {}
 }
@@ -24,4 +30,49 @@ pub trait NodeConfig {
     /// Constructs a new Node object. This should also subscribe or request permission to
     /// publish via the Publish/Subscribe mechanism.
     fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node>;
+
+    /// Lists the topics the Node will publish and subscribe to once instantiated. This allows
+    /// checking how nodes are wired together without instantiating anything.
+    fn topics(&self) -> Vec<TopicDecl> {
+        Vec::new()
+    }
+}
+
+/// Typed widgets for editing a config while the app is running, as an alternative to editing
+/// the raw text. The widgets should keep the values within sensible ranges.
+pub trait ConfigUi {
+    /// Draws the widgets, returns true if any value was changed.
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicDirection {
+    Pub,
+    Sub,
+}
+
+/// Declares the use of a topic by a Node, see [`NodeConfig::topics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicDecl {
+    pub name: String,
+    pub type_name: &'static str,
+    pub direction: TopicDirection,
+}
+
+impl TopicDecl {
+    pub fn publish<T: 'static>(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            type_name: type_name::<T>(),
+            direction: TopicDirection::Pub,
+        }
+    }
+
+    pub fn subscribe<T: 'static>(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            type_name: type_name::<T>(),
+            direction: TopicDirection::Sub,
+        }
+    }
 }
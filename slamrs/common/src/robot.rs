@@ -1,7 +1,15 @@
-use nalgebra::{Matrix2xX, Vector2, Vector3};
+//! Types describing the robot and what it senses.
+//!
+//! All of them use the same convention: x points forward (right in the world view), y points left
+//! (up in the world view) and angles are measured in radians counter-clockwise from the x-axis.
+//! Data following other conventions is converted when it enters the system, see
+//! [`AxisConvention`].
+
+use nalgebra::{Matrix2xX, Rotation2, Vector2, Vector3};
 
 use crate::math::{self, LogProbability};
 use rand::distributions::Distribution;
+use serde::Deserialize;
 use statrs::distribution::{Continuous, Normal};
 
 /// The pose of a robot in the 2D plane.
@@ -51,29 +59,190 @@ impl Pose {
 pub struct Observation {
     pub id: usize,
     pub measurements: Vec<Measurement>,
+    /// The rotation speed of the scanner while the observation was taken, if known.
+    pub rpm: Option<f32>,
+    /// The angle (radians) of the first measurement slot of the scanner. Slots without a
+    /// return may be missing from `measurements`.
+    pub angle_min: f64,
+    /// The angle (radians) of the last measurement slot of the scanner.
+    pub angle_max: f64,
+    /// The angle (radians) between two consecutive measurement slots.
+    pub angle_increment: f64,
+    /// The time (seconds) the scan was completed, on the clock of whatever produced it.
+    pub timestamp: f64,
 }
 
 impl Observation {
-    pub fn to_points(&self, origin: Pose) -> Vec<Vector2<f32>> {
-        self.measurements
+    /// Converts the measurements to cartesian points, one per column. The points are in the robot
+    /// frame, or in the world frame if the `pose` of the robot is given. Invalid measurements are
+    /// left out unless `include_invalid` is set, then the columns match `measurements`.
+    pub fn to_points(&self, pose: Option<&Pose>, include_invalid: bool) -> Matrix2xX<f32> {
+        let origin = pose.copied().unwrap_or_default();
+
+        let coordinates: Vec<f32> = self
+            .measurements
             .iter()
-            .filter(|&m| m.valid)
-            .map(|m| {
-                Vector2::new(
-                    origin.x + (origin.theta + m.angle as f32).cos() * m.distance as f32,
-                    origin.y + (origin.theta + m.angle as f32).sin() * m.distance as f32,
-                )
+            .filter(|m| include_invalid || m.valid)
+            .flat_map(|m| {
+                let (sin, cos) = (origin.theta + m.angle as f32).sin_cos();
+                let distance = m.distance as f32;
+                [origin.x + cos * distance, origin.y + sin * distance]
             })
-            .collect()
+            .collect();
+
+        Matrix2xX::from_vec(coordinates)
+    }
+
+    /// Returns true if the measurement slots of the scanner cover a complete revolution.
+    pub fn is_full_revolution(&self) -> bool {
+        self.angle_max - self.angle_min + self.angle_increment
+            >= std::f64::consts::TAU - self.angle_increment / 2.0
     }
 
-    pub fn to_matrix(&self, origin: Pose) -> Matrix2xX<f32> {
-        // Not the most efficient implementation (since it creates two Vec's, but it works)
-        let vectors: Vec<Vector2<f32>> = self.to_points(origin);
-        if !vectors.is_empty() {
-            Matrix2xX::from_columns(&vectors)
+    /// Summarizes how much of the scan has valid returns, to spot a sensor that is not seeing
+    /// anything or only sees in some directions.
+    pub fn quality(&self) -> ScanQuality {
+        use std::f64::consts::TAU;
+
+        let valid = self.measurements.iter().filter(|m| m.valid).count();
+
+        // the field of view is divided into bins, that are never narrower than a slot
+        let fov = if self.angle_increment > 0.0 {
+            (self.angle_max - self.angle_min + self.angle_increment).min(TAU)
         } else {
-            Matrix2xX::zeros(0)
+            TAU
+        };
+        let bins = (fov / COVERAGE_BIN_WIDTH.max(self.angle_increment))
+            .round()
+            .max(1.0) as usize;
+        let mut occupied = vec![false; bins];
+        for m in self.measurements.iter().filter(|m| m.valid) {
+            let offset = (m.angle - self.angle_min).rem_euclid(TAU);
+            if let Some(bin) = occupied.get_mut((offset / fov * bins as f64) as usize) {
+                *bin = true;
+            }
+        }
+
+        let ratio = |count: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                (count as f32 / total as f32).min(1.0)
+            }
+        };
+
+        ScanQuality {
+            valid_ratio: ratio(valid, self.measurements.len()),
+            coverage: ratio(occupied.iter().filter(|&&o| o).count(), bins),
+        }
+    }
+
+    /// Returns true if the scanner speed is within `band` (`[min, max]` in rpm) or is not known.
+    pub fn rpm_within(&self, band: [f32; 2]) -> bool {
+        !matches!(self.rpm, Some(rpm) if rpm < band[0] || rpm > band[1])
+    }
+
+    /// Returns a copy of this observation where all measurements weaker than `min_strength`
+    /// are marked as invalid.
+    pub fn filter_by_strength(&self, min_strength: f64) -> Observation {
+        Observation {
+            id: self.id,
+            rpm: self.rpm,
+            angle_min: self.angle_min,
+            angle_max: self.angle_max,
+            angle_increment: self.angle_increment,
+            timestamp: self.timestamp,
+            measurements: self
+                .measurements
+                .iter()
+                .map(|m| Measurement {
+                    valid: m.valid && m.strength >= min_strength,
+                    ..*m
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns a copy of this observation where all measurements closer than `min` or further
+    /// away than `max` (meters) are marked as invalid. A distance of zero is what many sensors
+    /// report when there was no return, so those are always marked as invalid.
+    pub fn clamp_range(&self, min: f64, max: f64) -> Observation {
+        Observation {
+            measurements: self
+                .measurements
+                .iter()
+                .map(|m| Measurement {
+                    valid: m.valid && m.distance > 0.0 && m.distance >= min && m.distance <= max,
+                    ..*m
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// A similarity transform (uniform scale, rotation and translation) between two frames, used
+/// to bring data from other sources into the meter and radian conventions of this crate.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTransform {
+    pub scale: f32,
+
+    /// The rotation in radians counter-clockwise.
+    pub rotation: f32,
+
+    /// The translation applied after scaling and rotating.
+    pub translation: Vector2<f32>,
+}
+
+impl Default for FrameTransform {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            rotation: 0.0,
+            translation: Vector2::zeros(),
+        }
+    }
+}
+
+impl FrameTransform {
+    /// Returns the transform that undoes this one.
+    pub fn inverse(&self) -> Self {
+        let rotation = Rotation2::new(-self.rotation);
+        Self {
+            scale: 1.0 / self.scale,
+            rotation: -self.rotation,
+            translation: -(rotation * self.translation) / self.scale,
+        }
+    }
+
+    pub fn transform_pose(&self, pose: Pose) -> Pose {
+        let xy = self.scale * (Rotation2::new(self.rotation) * pose.xy()) + self.translation;
+        Pose {
+            x: xy.x,
+            y: xy.y,
+            theta: pose.theta + self.rotation,
+        }
+    }
+
+    /// Since the measurements are relative to the sensor only the scale and the rotation apply,
+    /// the translation is ignored.
+    pub fn transform_observation(&self, observation: &Observation) -> Observation {
+        Observation {
+            id: observation.id,
+            rpm: observation.rpm,
+            angle_min: observation.angle_min + self.rotation as f64,
+            angle_max: observation.angle_max + self.rotation as f64,
+            angle_increment: observation.angle_increment,
+            timestamp: observation.timestamp,
+            measurements: observation
+                .measurements
+                .iter()
+                .map(|m| Measurement {
+                    angle: m.angle + self.rotation as f64,
+                    distance: m.distance * self.scale as f64,
+                    ..*m
+                })
+                .collect(),
         }
     }
 }
@@ -91,6 +260,24 @@ pub struct Measurement {
 
     /// If this measurement is valid (information provided by the sensor itself)
     pub valid: bool,
+
+    /// When during the revolution this measurement was taken, from 0 (start of the scan) to 1
+    /// (end of the scan). Used to compensate for the motion of the robot during a scan.
+    pub phase: f32,
+}
+
+/// The width (radians) of the angular bins of [`ScanQuality::coverage`].
+const COVERAGE_BIN_WIDTH: f64 = 5.0 * std::f64::consts::PI / 180.0;
+
+/// How healthy a scan is, see [`Observation::quality`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanQuality {
+    /// The fraction of the measurements that are valid.
+    pub valid_ratio: f32,
+    /// The fraction of the field of view of the scanner with a valid return, counted in angular
+    /// bins of 5 degrees (or one slot if wider). Low when the valid returns all lie in a few
+    /// directions, also if most of the measurements are valid.
+    pub coverage: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -149,6 +336,21 @@ impl Odometry {
         }
     }
 
+    /// The average distance in meters travelled by the wheels, which also counts turning on the
+    /// spot as motion.
+    pub fn travel(&self) -> f32 {
+        (self.distance_left.abs() + self.distance_right.abs()) / 2.0
+    }
+
+    /// Combines two consecutive odometry readings into one covering the motion of both.
+    pub fn combine(&self, next: &Odometry) -> Self {
+        Self::new(
+            self.distance_left + next.distance_left,
+            self.distance_right + next.distance_right,
+            next.wheel_distance,
+        )
+    }
+
     pub fn probabiliy_of(&self, initial_pose: Pose, new_pose: Pose) -> LogProbability {
         // hard code the parameters here for now. Future improvements should add
         // a MotionModel to handle the forward and inverse case.
@@ -183,6 +385,56 @@ impl Odometry {
     }
 }
 
+/// The axis convention of a data source, used to convert its observations and odometry into the
+/// convention used internally (see the module documentation) in a single place.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AxisConvention {
+    /// The same as the internal convention, nothing is changed.
+    #[default]
+    Standard,
+
+    /// Angles increase clockwise, as with the Neato XV-11 LIDAR mounted upside down. Data is
+    /// mirrored around the x-axis.
+    NeatoXV11,
+}
+
+impl AxisConvention {
+    /// Converts an observation from this convention into the internal one.
+    pub fn observation(&self, observation: Observation) -> Observation {
+        match self {
+            AxisConvention::Standard => observation,
+            AxisConvention::NeatoXV11 => Observation {
+                // keep the measurements sorted by increasing angle
+                measurements: observation
+                    .measurements
+                    .iter()
+                    .rev()
+                    .map(|m| Measurement {
+                        angle: -m.angle,
+                        ..*m
+                    })
+                    .collect(),
+                angle_min: -observation.angle_max,
+                angle_max: -observation.angle_min,
+                ..observation
+            },
+        }
+    }
+
+    /// Converts wheel odometry from this convention into the internal one.
+    pub fn odometry(&self, odometry: Odometry) -> Odometry {
+        match self {
+            AxisConvention::Standard => odometry,
+            // mirroring the robot swaps its left and right wheels
+            AxisConvention::NeatoXV11 => Odometry::new(
+                odometry.distance_right,
+                odometry.distance_left,
+                odometry.wheel_distance,
+            ),
+        }
+    }
+}
+
 /// A Command to move the robot by setting the desired left and right wheel speed.
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Command {
@@ -192,3 +444,405 @@ pub struct Command {
     /// The target speed in meters/second that the right wheel of the robot should move.
     pub speed_right: f32,
 }
+
+/// A Command to move the robot by setting the desired forward and turning speed, for controllers
+/// that do not want to deal with the individual wheels.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VelocityCommand {
+    /// The target speed in meters/second along the heading of the robot.
+    pub linear: f32,
+
+    /// The target turning rate in radians/second, positive is counter-clockwise.
+    pub angular: f32,
+}
+
+impl VelocityCommand {
+    /// Converts to the wheel speeds of a differential drive with the wheels `wheel_base` meters
+    /// apart. The wheels move at `linear ∓ angular * wheel_base / 2`, the inverse of how
+    /// [`Odometry`] turns the wheel distances into a change of the pose.
+    pub fn to_command(&self, wheel_base: f32) -> Command {
+        let turn = self.angular * wheel_base / 2.0;
+        Command {
+            speed_left: self.linear - turn,
+            speed_right: self.linear + turn,
+        }
+    }
+}
+
+/// Emitted when the body of the robot bumps into an obstacle.
+#[derive(Debug, Clone, Copy)]
+pub struct BumpEvent {
+    /// Increases by one for every new bump, to tell consecutive events apart.
+    pub id: usize,
+
+    /// The point of contact in world coordinates.
+    pub point: [f32; 2],
+
+    /// The surface normal at the point of contact, pointing away from the obstacle.
+    pub normal: [f32; 2],
+}
+
+#[cfg(test)]
+mod test {
+
+    use approx::assert_relative_eq;
+
+    use super::*;
+
+    #[test]
+    fn to_points_in_robot_and_world_frame() {
+        let observation = Observation {
+            id: 0,
+            measurements: [(0.0, 1.0, true), (90.0, 2.0, true), (180.0, 0.5, false)]
+                .into_iter()
+                .map(|(angle, distance, valid)| Measurement {
+                    angle: f64::to_radians(angle),
+                    distance,
+                    strength: 1.0,
+                    valid,
+                    phase: 0.0,
+                })
+                .collect(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 180f64.to_radians(),
+            angle_increment: 90f64.to_radians(),
+            timestamp: 0.0,
+        };
+
+        let points = observation.to_points(None, false);
+        assert_eq!(points.ncols(), 2);
+        assert_relative_eq!(points.column(0).x, 1.0);
+        assert_relative_eq!(points.column(0).y, 0.0);
+        assert_relative_eq!(points.column(1).x, 0.0, epsilon = 1e-6);
+        assert_relative_eq!(points.column(1).y, 2.0);
+
+        // facing up from (1, 2), the first measurement points up and the second left
+        let pose = Pose {
+            x: 1.0,
+            y: 2.0,
+            theta: std::f32::consts::FRAC_PI_2,
+        };
+        let points = observation.to_points(Some(&pose), true);
+        assert_eq!(points.ncols(), 3);
+        assert_relative_eq!(points.column(0).x, 1.0, epsilon = 1e-6);
+        assert_relative_eq!(points.column(0).y, 3.0);
+        assert_relative_eq!(points.column(1).x, -1.0);
+        assert_relative_eq!(points.column(1).y, 2.0, epsilon = 1e-6);
+        assert_relative_eq!(points.column(2).x, 1.0, epsilon = 1e-6);
+        assert_relative_eq!(points.column(2).y, 1.5);
+
+        let empty = Observation {
+            measurements: Vec::new(),
+            ..observation
+        };
+        assert_eq!(empty.to_points(None, true).ncols(), 0);
+    }
+
+    #[test]
+    fn filter_by_strength_drops_weak_measurements() {
+        let observation = Observation {
+            id: 3,
+            measurements: [(0.5, true), (2.0, true), (1.0, true), (5.0, false)]
+                .into_iter()
+                .enumerate()
+                .map(|(i, (strength, valid))| Measurement {
+                    angle: i as f64,
+                    distance: 1.0,
+                    strength,
+                    valid,
+                    phase: 0.0,
+                })
+                .collect(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 3.0,
+            angle_increment: 1.0,
+            timestamp: 0.0,
+        };
+
+        let filtered = observation.filter_by_strength(1.0);
+
+        assert_eq!(filtered.id, 3);
+        assert_eq!(filtered.measurements.len(), 4);
+        assert_eq!(
+            filtered
+                .measurements
+                .iter()
+                .map(|m| m.valid)
+                .collect::<Vec<_>>(),
+            vec![false, true, true, false]
+        );
+        assert_eq!(filtered.to_points(None, false).ncols(), 2);
+        assert_eq!(filtered.to_points(None, true).ncols(), 4);
+    }
+
+    #[test]
+    fn clamp_range_drops_zero_and_out_of_range() {
+        let observation = Observation {
+            id: 0,
+            measurements: [
+                (0.0, true),
+                (0.1, true),
+                (1.0, true),
+                (7.0, true),
+                (1.0, false),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (distance, valid))| Measurement {
+                angle: i as f64,
+                distance,
+                strength: 1.0,
+                valid,
+                phase: 0.0,
+            })
+            .collect(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 4.0,
+            angle_increment: 1.0,
+            timestamp: 0.0,
+        };
+
+        let clamped = observation.clamp_range(0.15, 6.0);
+        assert_eq!(
+            clamped
+                .measurements
+                .iter()
+                .map(|m| m.valid)
+                .collect::<Vec<_>>(),
+            vec![false, false, true, false, false]
+        );
+
+        // zero is never a valid distance, even without a lower limit
+        assert!(!observation.clamp_range(0.0, 6.0).measurements[0].valid);
+    }
+
+    #[test]
+    fn rpm_within_band() {
+        let mut observation = Observation {
+            id: 0,
+            measurements: Vec::new(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 359f64.to_radians(),
+            angle_increment: 1f64.to_radians(),
+            timestamp: 0.0,
+        };
+        assert!(observation.rpm_within([280.0, 320.0]));
+
+        observation.rpm = Some(300.0);
+        assert!(observation.rpm_within([280.0, 320.0]));
+        assert!(observation.rpm_within([300.0, 300.0]));
+
+        observation.rpm = Some(250.0);
+        assert!(!observation.rpm_within([280.0, 320.0]));
+
+        observation.rpm = Some(330.0);
+        assert!(!observation.rpm_within([280.0, 320.0]));
+    }
+
+    #[test]
+    fn full_revolution() {
+        let mut observation = Observation {
+            id: 0,
+            measurements: Vec::new(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 359f64.to_radians(),
+            angle_increment: 1f64.to_radians(),
+            timestamp: 0.0,
+        };
+        assert!(observation.is_full_revolution());
+
+        observation.angle_max = 180f64.to_radians();
+        assert!(!observation.is_full_revolution());
+    }
+
+    #[test]
+    fn neato_convention_mirrors() {
+        let observation = Observation {
+            id: 0,
+            measurements: [10f64, 90.0]
+                .into_iter()
+                .map(|angle| Measurement {
+                    angle: angle.to_radians(),
+                    distance: 1.0,
+                    strength: 1.0,
+                    valid: true,
+                    phase: 0.0,
+                })
+                .collect(),
+            rpm: None,
+            angle_min: 10f64.to_radians(),
+            angle_max: 90f64.to_radians(),
+            angle_increment: 80f64.to_radians(),
+            timestamp: 0.0,
+        };
+
+        let mirrored = AxisConvention::NeatoXV11.observation(observation.clone());
+        assert_relative_eq!(mirrored.angle_min, -90f64.to_radians());
+        assert_relative_eq!(mirrored.angle_max, -10f64.to_radians());
+        let mirrored_points = mirrored.to_points(None, false);
+        let points = observation.to_points(None, false);
+        for (m, o) in mirrored_points
+            .column_iter()
+            .rev()
+            .zip(points.column_iter())
+        {
+            assert_relative_eq!(m.x, o.x);
+            assert_relative_eq!(m.y, -o.y);
+        }
+
+        let odometry = AxisConvention::NeatoXV11.odometry(Odometry::new(0.1, 0.3, 0.2));
+        assert_eq!(odometry.distance_left, 0.3);
+        assert_eq!(odometry.distance_right, 0.1);
+
+        assert_eq!(
+            AxisConvention::Standard.observation(observation).angle_min,
+            10f64.to_radians()
+        );
+    }
+
+    #[test]
+    fn frame_transform_round_trip() {
+        let transform = FrameTransform {
+            scale: 0.001,
+            rotation: 30f32.to_radians(),
+            translation: Vector2::new(1.5, -2.0),
+        };
+        let inverse = transform.inverse();
+
+        let pose = Pose {
+            x: 1200.0,
+            y: -350.0,
+            theta: 0.4,
+        };
+        let transformed = transform.transform_pose(pose);
+        assert_relative_eq!(transformed.theta, 0.4 + 30f32.to_radians());
+
+        let back = inverse.transform_pose(transformed);
+        assert_relative_eq!(back.x, pose.x, epsilon = 1e-3);
+        assert_relative_eq!(back.y, pose.y, epsilon = 1e-3);
+        assert_relative_eq!(back.theta, pose.theta, epsilon = 1e-6);
+
+        let observation = Observation {
+            id: 0,
+            measurements: vec![Measurement {
+                angle: 1.0,
+                distance: 2500.0,
+                strength: 1.0,
+                valid: true,
+                phase: 0.0,
+            }],
+            rpm: None,
+            angle_min: 1.0,
+            angle_max: 1.0,
+            angle_increment: 0.0,
+            timestamp: 0.0,
+        };
+        let transformed = transform.transform_observation(&observation);
+        assert_relative_eq!(transformed.measurements[0].distance, 2.5, epsilon = 1e-6);
+
+        let back = inverse.transform_observation(&transformed);
+        assert_relative_eq!(back.measurements[0].angle, 1.0, epsilon = 1e-6);
+        assert_relative_eq!(back.measurements[0].distance, 2500.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn scan_quality() {
+        // four slots, the one at 270 degrees had no return at all
+        let observation = Observation {
+            id: 0,
+            measurements: [(0.0, true), (90.0, false), (180.0, true)]
+                .into_iter()
+                .map(|(angle, valid)| Measurement {
+                    angle: f64::to_radians(angle),
+                    distance: 1.0,
+                    strength: 1.0,
+                    valid,
+                    phase: 0.0,
+                })
+                .collect(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 270f64.to_radians(),
+            angle_increment: 90f64.to_radians(),
+            timestamp: 0.0,
+        };
+
+        let quality = observation.quality();
+        assert_relative_eq!(quality.valid_ratio, 2.0 / 3.0);
+        assert_relative_eq!(quality.coverage, 0.5);
+
+        // one degree slots, but all the returns are in the first quarter of the revolution
+        let sector = Observation {
+            measurements: (0..360)
+                .map(|i| Measurement {
+                    angle: f64::to_radians(i as f64 * 0.25),
+                    distance: 1.0,
+                    strength: 1.0,
+                    valid: true,
+                    phase: 0.0,
+                })
+                .collect(),
+            angle_max: 359f64.to_radians(),
+            angle_increment: 1f64.to_radians(),
+            ..observation.clone()
+        };
+        let quality = sector.quality();
+        assert_relative_eq!(quality.valid_ratio, 1.0);
+        assert_relative_eq!(quality.coverage, 0.25);
+
+        let empty = Observation {
+            measurements: Vec::new(),
+            ..observation
+        };
+        assert_eq!(
+            empty.quality(),
+            ScanQuality {
+                valid_ratio: 0.0,
+                coverage: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn velocity_to_wheel_speeds() {
+        let wheel_base = 0.2;
+
+        let straight = VelocityCommand {
+            linear: 0.3,
+            angular: 0.0,
+        }
+        .to_command(wheel_base);
+        assert_relative_eq!(straight.speed_left, 0.3);
+        assert_relative_eq!(straight.speed_right, 0.3);
+
+        // turning counter-clockwise on the spot moves the right wheel forward
+        let spin = VelocityCommand {
+            linear: 0.0,
+            angular: 1.0,
+        }
+        .to_command(wheel_base);
+        assert_relative_eq!(spin.speed_left, -0.1);
+        assert_relative_eq!(spin.speed_right, 0.1);
+
+        // driving one second with the wheel speeds gives back the commanded motion
+        let twist = VelocityCommand {
+            linear: 0.25,
+            angular: -0.5,
+        };
+        let command = twist.to_command(wheel_base);
+        let odometry = Odometry::new(command.speed_left, command.speed_right, wheel_base);
+        assert_relative_eq!(
+            (odometry.distance_left + odometry.distance_right) / 2.0,
+            twist.linear
+        );
+        assert_relative_eq!(
+            (odometry.distance_right - odometry.distance_left) / odometry.wheel_distance,
+            twist.angular
+        );
+    }
+}
@@ -1,7 +1,48 @@
-use graphics::shaperenderer::ShapeRenderer;
+use eframe::egui;
+use graphics::{camera::Camera, primitiverenderer::PrimitiveType, shaperenderer::ShapeRenderer};
 use nalgebra::Point2;
+use serde::Deserialize;
 
 pub struct WorldObj<'a> {
     pub sr: &'a mut ShapeRenderer,
     pub last_mouse_pos: Point2<f32>,
+    /// If the world view was clicked (without dragging) this frame, at `last_mouse_pos`.
+    pub clicked: bool,
+    pub camera: &'a Camera,
+    /// The position of the top left corner of the world view on the screen.
+    pub screen_offset: egui::Vec2,
+}
+
+impl WorldObj<'_> {
+    /// Projects a world position to screen coordinates that can be used with an
+    /// [`egui::Painter`] to draw on top of the world.
+    pub fn project(&self, world: Point2<f32>) -> egui::Pos2 {
+        self.camera.project(world) + self.screen_offset
+    }
+}
+
+/// Whether the shapes of a layer are drawn as outlines or filled.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DrawMode {
+    Wireframe,
+    #[default]
+    Filled,
+}
+
+impl DrawMode {
+    /// The primitive type to `begin()` drawing with.
+    pub fn primitive_type(self) -> PrimitiveType {
+        match self {
+            DrawMode::Wireframe => PrimitiveType::Line,
+            DrawMode::Filled => PrimitiveType::Filled,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Draw Mode: ");
+            ui.radio_value(self, DrawMode::Wireframe, "Wireframe");
+            ui.radio_value(self, DrawMode::Filled, "Filled");
+        });
+    }
 }
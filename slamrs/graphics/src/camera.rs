@@ -71,6 +71,17 @@ impl Camera {
         self.has_changed = true;
     }
 
+    /// The position and zoom level of the camera, as restored by [`Camera::set_view`].
+    pub fn view(&self) -> (Vector2<f32>, f32) {
+        (self.position, self.zoom)
+    }
+
+    pub fn set_view(&mut self, position: Vector2<f32>, zoom: f32) {
+        self.position = position;
+        self.zoom = zoom.max(0.1);
+        self.has_changed = true;
+    }
+
     pub fn unproject(&self, screen_coord: egui::Pos2) -> Point2<f32> {
         // let r = self
         //     .combined
@@ -100,6 +111,27 @@ impl Camera {
         Point2::new(v.x, v.y)
     }
 
+    /// The inverse of [`Camera::unproject`], mapping a world position to screen pixel
+    /// coordinates. Positions outside of the screen are returned as well so that the
+    /// caller can decide what to cull.
+    pub fn project(&self, world: Point2<f32>) -> egui::Pos2 {
+        let mut v = world.coords;
+
+        // move from world coordinates to be relative to the lower left corner of the viewport
+        v += self.position;
+        v += Vector2::new(
+            self.viewport_width * self.zoom / 2.0,
+            self.viewport_height * self.zoom / 2.0,
+        );
+
+        egui::Pos2::new(
+            v.x / (self.viewport_width * self.zoom) * self.current_screen_size.x,
+            self.current_screen_size.y
+                - v.y / (self.viewport_height * self.zoom) * self.current_screen_size.y
+                - 1.0,
+        )
+    }
+
     pub fn update(&mut self) {
         if !self.has_changed {
             return;
@@ -136,3 +168,27 @@ impl Default for Camera {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn project_unproject_roundtrip() {
+        let mut camera = Camera::new();
+        camera.resize(egui::Vec2::new(800.0, 600.0));
+        camera.pan(egui::Vec2::new(13.0, -42.0));
+        camera.zoom(1.7);
+        camera.update();
+
+        for p in [
+            Point2::new(0.0, 0.0),
+            Point2::new(1.5, -2.0),
+            Point2::new(-3.25, 4.0),
+            Point2::new(100.0, 100.0), // far outside the screen
+        ] {
+            let q = camera.unproject(camera.project(p));
+            assert!((p - q).norm() < 1e-3, "{p} != {q}");
+        }
+    }
+}
@@ -128,7 +128,22 @@ impl VertexArray {
 pub struct VertexBuffer {
     id: glow::Buffer,
     is_bound: bool,
+    /// The number of floats written to the buffer.
+    len: usize,
+    /// The number of floats the buffer has storage for.
+    capacity: usize,
 }
+
+/// Reinterprets the vertex data as pure bytes
+fn as_bytes(vertices: &[f32]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            vertices.as_ptr() as *const u8,
+            std::mem::size_of_val(vertices),
+        )
+    }
+}
+
 impl VertexBuffer {
     pub fn new(gl: &glow::Context) -> Self {
         use glow::HasContext as _;
@@ -138,9 +153,26 @@ impl VertexBuffer {
         Self {
             id: buffer,
             is_bound: false,
+            len: 0,
+            capacity: 0,
         }
     }
 
+    /// The number of floats written to the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of floats that fit in the buffer without allocating new storage.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Replaces the storage of the buffer with exactly `vertices`.
     pub fn set_vertices(&mut self, gl: &glow::Context, vertices: &[f32]) {
         use glow::HasContext as _;
 
@@ -148,15 +180,61 @@ impl VertexBuffer {
             self.bind(gl);
         }
 
-        // reinterpret the data as pure bytes
-        let data = unsafe {
-            std::slice::from_raw_parts(
-                vertices.as_ptr() as *const u8,
-                std::mem::size_of_val(vertices),
-            )
-        };
         // upload the data
-        unsafe { gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, data, glow::DYNAMIC_DRAW) }
+        unsafe {
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, as_bytes(vertices), glow::DYNAMIC_DRAW)
+        }
+        self.len = vertices.len();
+        self.capacity = vertices.len();
+    }
+
+    /// Replaces the storage of the buffer with room for `capacity` floats and writes `vertices`
+    /// at the start, leaving space for [`VertexBuffer::set_vertices_range`] to append to.
+    pub fn set_vertices_reserve(&mut self, gl: &glow::Context, vertices: &[f32], capacity: usize) {
+        use glow::HasContext as _;
+
+        if !self.is_bound {
+            self.bind(gl);
+        }
+
+        let capacity = capacity.max(vertices.len());
+        unsafe {
+            gl.buffer_data_size(
+                glow::ARRAY_BUFFER,
+                (capacity * std::mem::size_of::<f32>()) as i32,
+                glow::DYNAMIC_DRAW,
+            );
+        }
+        self.capacity = capacity;
+        self.len = 0;
+        self.set_vertices_range(gl, 0, vertices);
+    }
+
+    /// Overwrites the floats starting at `offset` with `vertices` without touching the rest of
+    /// the buffer, typically to append after [`VertexBuffer::len`]. Panics if the buffer has no
+    /// room for them.
+    pub fn set_vertices_range(&mut self, gl: &glow::Context, offset: usize, vertices: &[f32]) {
+        use glow::HasContext as _;
+
+        assert!(
+            offset + vertices.len() <= self.capacity,
+            "no space for {} floats at {offset} in a buffer of {}",
+            vertices.len(),
+            self.capacity
+        );
+
+        if !self.is_bound {
+            self.bind(gl);
+        }
+
+        unsafe {
+            gl.buffer_sub_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                (offset * std::mem::size_of::<f32>()) as i32,
+                as_bytes(vertices),
+            )
+        }
+        self.len = self.len.max(offset + vertices.len());
     }
 
     pub fn bind(&mut self, gl: &glow::Context) {
@@ -3,6 +3,7 @@
 pub mod camera;
 
 mod gl;
+pub mod pointcloudrenderer;
 pub mod primitiverenderer;
 pub mod shader;
 pub mod shaperenderer;
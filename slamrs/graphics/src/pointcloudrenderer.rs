@@ -0,0 +1,253 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use eframe::glow;
+
+use super::{gl, primitiverenderer::Color, shader};
+
+/// Identifies a point cloud kept on the GPU by the [`PointCloudRenderer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PointCloudId(u64);
+
+impl PointCloudId {
+    /// Creates a new unique id, does not need a GL context.
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for PointCloudId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The smallest buffer (in floats) allocated for a cloud, so that small clouds can grow for a
+/// while before their buffer has to be replaced.
+const MIN_CAPACITY: usize = 2 * 1024;
+
+/// Draws large numbers of points as `GL_POINTS` from vertex buffers that persist between frames.
+/// Within a generation the points of a cloud are treated as append-only, so only the points that
+/// were added since the last frame are uploaded and drawing a static cloud costs nothing but the
+/// draw call.
+pub struct PointCloudRenderer {
+    /// `None` for a headless renderer, see [`PointCloudRenderer::headless`].
+    program: Option<shader::Program>,
+    proj_model_view: nalgebra::Matrix4<f32>,
+    clouds: HashMap<PointCloudId, PointCloud>,
+    /// The clouds to draw on the next flush, in order.
+    draws: Vec<PointCloudDraw>,
+}
+
+struct PointCloud {
+    vertex_array: gl::VertexArray,
+    vertex_buffer: gl::VertexBuffer,
+    /// The generation of the uploaded points.
+    generation: u64,
+}
+
+struct PointCloudDraw {
+    id: PointCloudId,
+    generation: u64,
+    /// In pixels
+    size: f32,
+    color: Color,
+    /// How the uploaded points have to change, `None` if they are up to date.
+    update: Option<PointCloudUpdate>,
+}
+
+enum PointCloudUpdate {
+    /// Upload all points (as x, y pairs) into a new buffer.
+    Replace(Vec<f32>),
+    /// Write the points added to the end of the cloud into the existing buffer.
+    Append { offset: usize, points: Vec<f32> },
+}
+
+impl PointCloud {
+    fn new(gl: &glow::Context) -> Self {
+        let mut layout = gl::VertexBufferLayout::new();
+        layout.push(gl::GLType::Float, 2);
+
+        let mut vertex_buffer = gl::VertexBuffer::new(gl);
+        let mut vertex_array = gl::VertexArray::new(gl);
+        vertex_array.add_buffer(gl, &mut vertex_buffer, &layout);
+
+        Self {
+            vertex_array,
+            vertex_buffer,
+            generation: 0,
+        }
+    }
+
+    fn destroy(&self, gl: &glow::Context) {
+        self.vertex_array.destroy(gl);
+        self.vertex_buffer.destroy(gl);
+    }
+}
+
+impl PointCloudRenderer {
+    pub fn new(gl: &glow::Context) -> Self {
+        let program = shader::Program::new(
+            gl,
+            r#"
+            layout(location = 0) in vec2 position;
+
+            uniform mat4 u_projModelView;
+            uniform float u_pointSize;
+
+            void main(){
+                gl_Position = u_projModelView * vec4(position, 0.0, 1.0);
+                gl_PointSize = u_pointSize;
+            }
+        "#,
+            r#"
+            precision mediump float;
+            layout(location = 0) out vec4 color;
+
+            uniform vec4 u_color;
+            void main(){
+                color = u_color;
+            }
+            "#,
+        );
+
+        Self {
+            program: Some(program),
+            ..Self::headless()
+        }
+    }
+
+    /// A renderer without a GL context, that queues clouds like any other but can only
+    /// [`discard`](PointCloudRenderer::discard) them.
+    pub fn headless() -> Self {
+        Self {
+            program: None,
+            proj_model_view: nalgebra::Matrix4::identity(),
+            clouds: HashMap::new(),
+            draws: Vec::new(),
+        }
+    }
+
+    pub fn set_mvp(&mut self, mvp: nalgebra::Matrix4<f32>) {
+        self.proj_model_view = mvp;
+    }
+
+    /// Draws the cloud `id` on the next flush, with points `size` pixels large. `points` are the
+    /// positions as x, y pairs. While the `generation` stays the same, points may only be added
+    /// to the end and only those are uploaded. The `generation` must change whenever any other
+    /// point changes or is removed, e.g. when the map is reset, which uploads all points again.
+    pub fn draw(
+        &mut self,
+        id: PointCloudId,
+        generation: u64,
+        size: f32,
+        color: Color,
+        points: &[f32],
+    ) {
+        let update = match self.clouds.get(&id) {
+            Some(cloud)
+                if cloud.generation == generation
+                    && points.len() >= cloud.vertex_buffer.len()
+                    && points.len() <= cloud.vertex_buffer.capacity() =>
+            {
+                let offset = cloud.vertex_buffer.len();
+                (points.len() > offset).then(|| PointCloudUpdate::Append {
+                    offset,
+                    points: points[offset..].to_vec(),
+                })
+            }
+            // a new generation, or out of space in the buffer
+            _ => Some(PointCloudUpdate::Replace(points.to_vec())),
+        };
+
+        self.draws.push(PointCloudDraw {
+            id,
+            generation,
+            size,
+            color,
+            update,
+        });
+    }
+
+    /// Uploads the changed clouds and draws all clouds queued since the last flush. The buffers
+    /// of clouds that were not drawn are freed, they are uploaded again if drawn later.
+    pub fn flush(&mut self, gl: &glow::Context) {
+        use glow::HasContext as _;
+
+        if self.draws.is_empty() && self.clouds.is_empty() {
+            return;
+        }
+
+        let program = self
+            .program
+            .as_ref()
+            .expect("a headless renderer can not draw");
+        program.bind(gl);
+        program.set_uniform_matrix_4_f32(gl, "u_projModelView", self.proj_model_view);
+
+        // always enabled in WebGL, but not in desktop OpenGL
+        #[cfg(not(target_arch = "wasm32"))]
+        unsafe {
+            gl.enable(glow::PROGRAM_POINT_SIZE);
+        }
+
+        let mut drawn = HashSet::new();
+        for draw in self.draws.drain(..) {
+            let cloud = self
+                .clouds
+                .entry(draw.id)
+                .or_insert_with(|| PointCloud::new(gl));
+
+            match draw.update {
+                Some(PointCloudUpdate::Replace(points)) => {
+                    // leave room for the cloud to grow
+                    let capacity = (2 * points.len()).max(MIN_CAPACITY);
+                    cloud.vertex_buffer.bind(gl);
+                    cloud
+                        .vertex_buffer
+                        .set_vertices_reserve(gl, &points, capacity);
+                    cloud.generation = draw.generation;
+                }
+                Some(PointCloudUpdate::Append { offset, points }) => {
+                    cloud.vertex_buffer.bind(gl);
+                    cloud.vertex_buffer.set_vertices_range(gl, offset, &points);
+                }
+                None => {}
+            }
+
+            program.set_uniform_1_f32(gl, "u_pointSize", draw.size);
+            program.set_uniform_4_f32(gl, "u_color", draw.color.to_rgba());
+
+            cloud.vertex_array.bind(gl);
+            unsafe {
+                gl.draw_arrays(glow::POINTS, 0, (cloud.vertex_buffer.len() / 2) as i32);
+            }
+            drawn.insert(draw.id);
+        }
+
+        self.clouds.retain(|id, cloud| {
+            let keep = drawn.contains(id);
+            if !keep {
+                cloud.destroy(gl);
+            }
+            keep
+        });
+    }
+
+    /// Forgets the clouds queued since the last flush without drawing them.
+    pub fn discard(&mut self) {
+        self.draws.clear();
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        for cloud in self.clouds.values() {
+            cloud.destroy(gl);
+        }
+        if let Some(program) = &self.program {
+            program.destroy(gl);
+        }
+    }
+}
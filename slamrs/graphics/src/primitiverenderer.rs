@@ -1,5 +1,6 @@
 use super::{gl, shader};
 use eframe::glow;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u32)]
@@ -7,12 +8,15 @@ pub enum PrimitiveType {
     Point = glow::POINTS,
     Line = glow::LINES,
     Filled = glow::TRIANGLES,
+    /// Each vertex after the first two forms a triangle with the first and the previous vertex.
+    TriangleFan = glow::TRIANGLE_FAN,
+    /// Each vertex after the first two forms a triangle with the two previous vertices.
+    TriangleStrip = glow::TRIANGLE_STRIP,
 }
 
 pub struct PrimitiveRenderer {
-    program: shader::Program,
-    vertex_array: gl::VertexArray,
-    vertex_buffer: gl::VertexBuffer,
+    /// `None` for a headless renderer, see [`PrimitiveRenderer::headless`].
+    gpu: Option<PrimitiveGpu>,
 
     proj_model_view: nalgebra::Matrix4<f32>,
     vertices: Vec<f32>,
@@ -21,15 +25,50 @@ pub struct PrimitiveRenderer {
     index: usize,
     active_drawcall: Option<DrawCall>,
     draw_calls: Vec<DrawCall>,
+    /// The next vertex is added twice, to join a triangle strip to the previous one.
+    join_strip: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// The GL objects of a [`PrimitiveRenderer`].
+struct PrimitiveGpu {
+    program: shader::Program,
+    vertex_array: gl::VertexArray,
+    vertex_buffer: gl::VertexBuffer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct DrawCall {
     pt: PrimitiveType,
     start_index: usize,
     vertex_count: usize,
 }
 
+impl DrawCall {
+    /// The index after the last vertex of this draw call.
+    fn end_index(&self) -> usize {
+        self.start_index + self.vertex_count
+    }
+}
+
+/// Adds `dc` to the `draw_calls`, extending the last one instead if it draws the same separate
+/// primitives (points, lines or triangles) right before it. Empty draw calls are dropped.
+fn push_draw_call(draw_calls: &mut Vec<DrawCall>, dc: DrawCall) {
+    if dc.vertex_count == 0 {
+        return;
+    }
+
+    let separate = matches!(
+        dc.pt,
+        PrimitiveType::Point | PrimitiveType::Line | PrimitiveType::Filled
+    );
+    match draw_calls.last_mut() {
+        Some(last) if separate && last.pt == dc.pt && last.end_index() == dc.start_index => {
+            last.vertex_count += dc.vertex_count;
+        }
+        _ => draw_calls.push(dc),
+    }
+}
+
 /* /// Test for using a "RenderGuard" to make sure state of the renderer is correctly managed
 pub struct RenderGuard<'a> {
     pr: &'a mut PrimitiveRenderer,
@@ -157,24 +196,34 @@ impl PrimitiveRenderer {
 
         let mut vb = gl::VertexBuffer::new(gl);
 
-        // allocate storage for our vertices (3 position + 1 color) floats
-        let vertices = vec![0f32; max_vertices as usize * 4];
-
         // create vertex array and combine our vertex buffer with the layout
         let mut va = gl::VertexArray::new(gl);
         va.add_buffer(gl, &mut vb, &layout);
 
         Self {
-            program: shader,
-            vertex_array: va,
-            vertex_buffer: vb,
-            vertices,
+            gpu: Some(PrimitiveGpu {
+                program: shader,
+                vertex_array: va,
+                vertex_buffer: vb,
+            }),
+            ..Self::headless(max_vertices)
+        }
+    }
+
+    /// A renderer without a GL context, that collects vertices like any other but can only
+    /// [`discard`](PrimitiveRenderer::discard) them.
+    pub fn headless(max_vertices: u32) -> Self {
+        Self {
+            gpu: None,
+            // allocate storage for our vertices (3 position + 1 color) floats
+            vertices: vec![0f32; max_vertices as usize * 4],
             max_vertices: max_vertices as usize,
             proj_model_view: nalgebra::Matrix4::identity(),
             vertex_count: 0,
             index: 0,
             active_drawcall: None,
             draw_calls: Vec::new(),
+            join_strip: false,
         }
     }
 
@@ -188,6 +237,20 @@ impl PrimitiveRenderer {
             "begin cannot be called twice in a row"
         );
 
+        // a strip right after another continues it, the two are joined by degenerate triangles
+        // (repeating the last vertex of the first and the first vertex of the second)
+        let continues_strip = self.draw_calls.last().is_some_and(|last| {
+            last.pt == PrimitiveType::TriangleStrip && last.end_index() == self.vertex_count
+        });
+        if primitive_type == PrimitiveType::TriangleStrip && continues_strip {
+            self.active_drawcall = self.draw_calls.pop();
+            let last = &self.vertices[self.index - 4..self.index];
+            let (x, y, z, bits) = (last[0], last[1], last[2], last[3]);
+            self.xyzc(x, y, z, Color { bits });
+            self.join_strip = true;
+            return;
+        }
+
         self.active_drawcall = Some(DrawCall {
             pt: primitive_type,
             start_index: self.vertex_count,
@@ -215,12 +278,13 @@ impl PrimitiveRenderer {
         // mark the current position in the buffer
         if let Some(mut dc) = self.active_drawcall {
             dc.vertex_count = self.vertex_count - dc.start_index;
-            self.draw_calls.push(dc);
+            push_draw_call(&mut self.draw_calls, dc);
         } else {
             panic!("end() cannot be called before a call to begin() was made");
         }
 
         self.active_drawcall = None;
+        self.join_strip = false;
     }
 
     // TODO: add function for ensuring space for X more vertices. That could actually take in the GL context and perform a `draw` if necessary...
@@ -241,20 +305,20 @@ impl PrimitiveRenderer {
         //     self.vertices.capacity(),
         //     (self.vertices.capacity() * std::mem::size_of::<f32>()) / 1024 / 1024
         // );
+        let gpu = self.gpu.as_mut().expect("a headless renderer can not draw");
+
         // use the shader
-        self.program.bind(gl);
-        self.program
+        gpu.program.bind(gl);
+        gpu.program
             .set_uniform_matrix_4_f32(gl, "u_projModelView", self.proj_model_view);
 
         // upload all our data
-        self.vertex_buffer.bind(gl);
-        self.vertex_buffer
+        gpu.vertex_buffer.bind(gl);
+        gpu.vertex_buffer
             .set_vertices(gl, &self.vertices[..self.index]);
 
         // do the actual drawing using multiple draw calls
-        self.vertex_array.bind(gl);
-
-        // TODO: go through and "optimize" the drawcalls if possible, i.e. by combining "adjacent" calls with the same primitive type
+        gpu.vertex_array.bind(gl);
 
         for dc in self.draw_calls.iter() {
             unsafe {
@@ -262,16 +326,27 @@ impl PrimitiveRenderer {
             }
         }
 
-        // reset state
+        self.discard();
+    }
+
+    /// Throws away the vertices added since the last flush without drawing them.
+    pub fn discard(&mut self) {
+        assert!(
+            self.active_drawcall.is_none(),
+            "end() must be called before discard()"
+        );
+
         self.vertex_count = 0;
         self.index = 0;
         self.draw_calls.clear();
     }
 
     pub fn destroy(&self, gl: &glow::Context) {
-        self.vertex_array.destroy(gl);
-        self.vertex_buffer.destroy(gl);
-        self.program.destroy(gl);
+        if let Some(gpu) = &self.gpu {
+            gpu.vertex_array.destroy(gl);
+            gpu.vertex_buffer.destroy(gl);
+            gpu.program.destroy(gl);
+        }
     }
 }
 
@@ -302,12 +377,21 @@ impl Vertex3C for PrimitiveRenderer {
 
         self.index += 4; // 3 position + 1 u32 for color
         self.vertex_count += 1;
+
+        if std::mem::take(&mut self.join_strip) {
+            self.xyzc(x, y, z, color);
+        }
     }
 }
 
 /// An RGBA color.
 /// Internally, the color is packed into 4 bytes, one for each of RGBA, instead of as 4 floats to save memory
-#[derive(Clone, Copy)]
+///
+/// Serializes as an `#rrggbbaa` hex string, and deserializes from either a hex string
+/// (`#rrggbb` or `#rrggbbaa`) or an `[r, g, b]` / `[r, g, b, a]` array with components in
+/// `[0, 1]`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "ColorRepr", into = "String")]
 pub struct Color {
     bits: f32,
 }
@@ -323,12 +407,8 @@ impl Color {
         Self::rgba(r, g, b, 1.0)
     }
     pub fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
-        Self::rgba_u8(
-            (255.0 * r) as u8,
-            (255.0 * g) as u8,
-            (255.0 * b) as u8,
-            (255.0 * a) as u8,
-        )
+        let to_u8 = |c: f32| (255.0 * c).round() as u8;
+        Self::rgba_u8(to_u8(r), to_u8(g), to_u8(b), to_u8(a))
     }
 
     pub const fn rgba_u8(r: u8, g: u8, b: u8, a: u8) -> Self {
@@ -338,9 +418,77 @@ impl Color {
         }
     }
 
+    /// The red, green, blue and alpha components in `[0, 1]`.
+    pub fn to_rgba(self) -> [f32; 4] {
+        self.to_rgba_u8().map(|c| c as f32 / 255.0)
+    }
+
+    /// The red, green, blue and alpha components.
+    pub fn to_rgba_u8(self) -> [u8; 4] {
+        let bits = self.bits.to_bits();
+        [0, 8, 16, 24].map(|shift| ((bits >> shift) & 0xff) as u8)
+    }
+
+    /// Parses a color written as `#rrggbb` or `#rrggbbaa`, the alpha defaults to opaque.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix('#')?;
+        if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+            return None;
+        }
+
+        let component = |i: usize| u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok();
+        let a = if digits.len() == 8 {
+            component(3)?
+        } else {
+            0xff
+        };
+        Some(Self::rgba_u8(
+            component(0)?,
+            component(1)?,
+            component(2)?,
+            a,
+        ))
+    }
+
+    /// Formats the color as `#rrggbbaa`.
+    pub fn to_hex(self) -> String {
+        let [r, g, b, a] = self.to_rgba_u8();
+        format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+    }
+
     pub fn grayscale(gray: f32) -> Self {
         Self::rgb(gray, gray, gray)
     }
+
+    /// Maps a value in `[0, 1]` onto a black-red-yellow-white heat colormap.
+    pub fn heat(value: f32) -> Self {
+        let v = 3.0 * value.clamp(0.0, 1.0);
+        Self::rgb(
+            v.min(1.0),
+            (v - 1.0).clamp(0.0, 1.0),
+            (v - 2.0).clamp(0.0, 1.0),
+        )
+    }
+}
+
+/// Converts a color given as hue (degrees), saturation and value (both in `[0, 1]`) to a
+/// [`Color`]. Hues outside of `[0, 360)` wrap around.
+pub fn hsv_to_color(h: f32, s: f32, v: f32) -> Color {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let c = v * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = v - c;
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+
+    Color::rgb(r + m, g + m, b + m)
 }
 
 impl From<[f32; 3]> for Color {
@@ -354,3 +502,165 @@ impl From<[f32; 4]> for Color {
         Color::rgba(value[0], value[1], value[2], value[3])
     }
 }
+
+impl PartialEq for Color {
+    fn eq(&self, other: &Self) -> bool {
+        // the packed bits are not necessarily a valid float, so compare them directly
+        self.bits.to_bits() == other.bits.to_bits()
+    }
+}
+
+impl Eq for Color {}
+
+impl std::fmt::Debug for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Color({})", self.to_hex())
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_hex()
+    }
+}
+
+/// The forms a [`Color`] can be written in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Hex(String),
+    Rgb([f32; 3]),
+    Rgba([f32; 4]),
+}
+
+impl TryFrom<ColorRepr> for Color {
+    type Error = String;
+
+    fn try_from(repr: ColorRepr) -> Result<Self, Self::Error> {
+        match repr {
+            ColorRepr::Hex(hex) => Color::from_hex(&hex)
+                .ok_or_else(|| format!("invalid color {hex:?}, expected #rrggbb or #rrggbbaa")),
+            ColorRepr::Rgb(rgb) => Ok(rgb.into()),
+            ColorRepr::Rgba(rgba) => Ok(rgba.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hsv_primary_colors() {
+        assert_eq!(
+            hsv_to_color(0.0, 1.0, 1.0).bits.to_bits(),
+            Color::RED.bits.to_bits()
+        );
+        assert_eq!(
+            hsv_to_color(120.0, 1.0, 1.0).bits.to_bits(),
+            Color::GREEN.bits.to_bits()
+        );
+        assert_eq!(
+            hsv_to_color(240.0, 1.0, 1.0).bits.to_bits(),
+            Color::BLUE.bits.to_bits()
+        );
+        assert_eq!(
+            hsv_to_color(360.0, 1.0, 1.0).bits.to_bits(),
+            Color::RED.bits.to_bits()
+        );
+        assert_eq!(
+            hsv_to_color(42.0, 0.0, 1.0).bits.to_bits(),
+            Color::WHITE.bits.to_bits()
+        );
+    }
+
+    #[test]
+    fn rgba_components() {
+        assert_eq!(Color::RED.to_rgba(), [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            Color::rgba_u8(0, 51, 255, 0).to_rgba(),
+            [0.0, 0.2, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(Color::RED.to_hex(), "#ff0000ff");
+        assert_eq!(
+            Color::from_hex("#0033ff80"),
+            Some(Color::rgba_u8(0x00, 0x33, 0xff, 0x80))
+        );
+        assert_eq!(Color::from_hex("#00FF00"), Some(Color::GREEN));
+        for invalid in ["00ff00", "#00ff0", "#00ff00f", "#gg0000", "#ääää"] {
+            assert_eq!(Color::from_hex(invalid), None, "{invalid}");
+        }
+
+        let color = Color::rgba_u8(1, 2, 3, 4);
+        assert_eq!(Color::from_hex(&color.to_hex()), Some(color));
+    }
+
+    #[test]
+    fn deserialize_hex_and_arrays() {
+        let parse = |yaml: &str| serde_yaml::from_str::<Color>(yaml);
+        assert_eq!(parse("'#0000ff'").unwrap(), Color::BLUE);
+        assert_eq!(parse("[0.0, 1.0, 0.0]").unwrap(), Color::GREEN);
+        assert_eq!(
+            parse("[1.0, 0.0, 0.0, 0.2]").unwrap(),
+            Color::rgba_u8(0xff, 0x00, 0x00, 51)
+        );
+        assert!(parse("'blue'").is_err());
+
+        let yaml = serde_yaml::to_string(&Color::rgb(0.0, 0.2, 1.0)).unwrap();
+        assert_eq!(yaml.trim(), "'#0033ffff'");
+        assert_eq!(parse(&yaml).unwrap(), Color::rgb(0.0, 0.2, 1.0));
+    }
+
+    #[test]
+    fn headless_renderer_discards_vertices() {
+        let mut pr = PrimitiveRenderer::headless(16);
+        for _ in 0..3 {
+            pr.begin(PrimitiveType::Filled);
+            for _ in 0..6 {
+                pr.xy(1.0, 2.0);
+            }
+            pr.end();
+            assert_eq!(pr.vertex_count, 6);
+
+            // without this the buffer would overflow on the second round
+            pr.discard();
+            assert_eq!((pr.vertex_count, pr.index), (0, 0));
+            assert!(pr.draw_calls.is_empty());
+        }
+    }
+
+    #[test]
+    fn adjacent_draw_calls_are_merged() {
+        let dc = |pt, start_index, vertex_count| DrawCall {
+            pt,
+            start_index,
+            vertex_count,
+        };
+
+        let mut draw_calls = Vec::new();
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::Filled, 0, 6));
+        // nothing was drawn in between
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::TriangleStrip, 6, 0));
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::Filled, 6, 3));
+        assert_eq!(draw_calls, vec![dc(PrimitiveType::Filled, 0, 9)]);
+
+        // fans can not be continued, each one is a draw call of its own
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::TriangleFan, 9, 5));
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::TriangleFan, 14, 5));
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::Line, 19, 2));
+        push_draw_call(&mut draw_calls, dc(PrimitiveType::Line, 21, 2));
+        assert_eq!(
+            draw_calls,
+            vec![
+                dc(PrimitiveType::Filled, 0, 9),
+                dc(PrimitiveType::TriangleFan, 9, 5),
+                dc(PrimitiveType::TriangleFan, 14, 5),
+                dc(PrimitiveType::Line, 19, 4),
+            ]
+        );
+    }
+}
@@ -5,10 +5,14 @@ use nalgebra::{Matrix2, Vector2};
 
 use crate::primitiverenderer::Color;
 
-use super::primitiverenderer::{PrimitiveRenderer, PrimitiveType, Vertex2C};
+use super::{
+    pointcloudrenderer::{PointCloudId, PointCloudRenderer},
+    primitiverenderer::{PrimitiveRenderer, PrimitiveType, Vertex2C},
+};
 
 pub struct ShapeRenderer {
     pr: PrimitiveRenderer,
+    pcr: PointCloudRenderer,
     current_shape_type: Option<PrimitiveType>,
 }
 
@@ -18,12 +22,24 @@ impl ShapeRenderer {
     pub fn new(gl: &glow::Context) -> Self {
         Self {
             pr: PrimitiveRenderer::new(gl, 1000000),
+            pcr: PointCloudRenderer::new(gl),
+            current_shape_type: None,
+        }
+    }
+
+    /// A renderer without a GL context, for running the nodes when there is nothing to draw on.
+    /// The shapes can not be flushed, only [`discard`](ShapeRenderer::discard)ed.
+    pub fn headless() -> Self {
+        Self {
+            pr: PrimitiveRenderer::headless(1000000),
+            pcr: PointCloudRenderer::headless(),
             current_shape_type: None,
         }
     }
 
     pub fn set_mvp(&mut self, mvp: nalgebra::Matrix4<f32>) {
         self.pr.set_mvp(mvp);
+        self.pcr.set_mvp(mvp);
     }
 
     pub fn begin(&mut self, pt: PrimitiveType) {
@@ -38,6 +54,13 @@ impl ShapeRenderer {
 
     pub fn flush(&mut self, gl: &glow::Context) {
         self.pr.flush(gl);
+        self.pcr.flush(gl);
+    }
+
+    /// Throws away everything drawn since the last flush.
+    pub fn discard(&mut self) {
+        self.pr.discard();
+        self.pcr.discard();
     }
 
     fn check(&mut self, desired_type: PrimitiveType, other: PrimitiveType, _n_vertices: usize) {
@@ -57,6 +80,34 @@ impl ShapeRenderer {
         }
     }
 
+    /// Draws using a different primitive type, then continues with the current primitive type.
+    /// Used for shapes that need fewer vertices as a strip. Consecutive strips are joined into a
+    /// single draw call by the [`PrimitiveRenderer`].
+    fn separate(&mut self, pt: PrimitiveType, f: impl FnOnce(&mut PrimitiveRenderer)) {
+        let current = self
+            .current_shape_type
+            .expect("begin() must be called first");
+
+        self.end();
+        self.begin(pt);
+        f(&mut self.pr);
+        self.end();
+        self.begin(current);
+    }
+
+    /// Draws points that are kept on the GPU between frames, on top of all shapes. See
+    /// [`PointCloudRenderer::draw`] for how `generation` and `points` are used.
+    pub fn point_cloud(
+        &mut self,
+        id: PointCloudId,
+        generation: u64,
+        size: f32,
+        color: Color,
+        points: &[f32],
+    ) {
+        self.pcr.draw(id, generation, size, color, points);
+    }
+
     pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, color: Color) {
         self.check(PrimitiveType::Line, PrimitiveType::Point, 2);
 
@@ -96,7 +147,7 @@ impl ShapeRenderer {
 
     pub fn circle(&mut self, x: f32, y: f32, radius: f32, color: Color) {
         // calculate the number of segments needed for a "good" circle
-        let number_of_segments = 1.max((4.0 * 12.0 * radius.cbrt()) as usize);
+        let number_of_segments = 3.max((4.0 * 12.0 * radius.cbrt()) as usize);
         self._circle(x, y, radius, color, number_of_segments);
     }
 
@@ -126,18 +177,25 @@ impl ShapeRenderer {
                 }
             }
             Some(PrimitiveType::Filled) => {
-                // check(ShapeType.LINE, ShapeType.FILLED, numberOfSegments * 3 + 3);
-
-                // place one vertex for each segment
-                for _ in 0..number_of_segments {
-                    self.pr.xyc(x, y, color);
-                    self.pr.xyc(x + px, y + py, color);
-
-                    // rotate point using the "rotation matrix" multiplication to get to the next
-                    (px, py) = (c * px - s * py, s * px + c * py);
-
-                    self.pr.xyc(x + px, y + py, color);
-                }
+                // check(ShapeType.LINE, ShapeType.FILLED, numberOfSegments + 2);
+
+                // as a strip zig-zagging between the two sides, each segment only needs one
+                // vertex instead of a full triangle
+                self.separate(PrimitiveType::TriangleStrip, |pr| {
+                    pr.xyc(x + px, y + py, color);
+
+                    // walk around the circle in both directions until the two sides meet
+                    let (mut qx, mut qy) = (px, py);
+                    for i in 1..number_of_segments {
+                        if i % 2 == 1 {
+                            (px, py) = (c * px - s * py, s * px + c * py);
+                            pr.xyc(x + px, y + py, color);
+                        } else {
+                            (qx, qy) = (c * qx + s * qy, -s * qx + c * qy);
+                            pr.xyc(x + qx, y + qy, color);
+                        }
+                    }
+                });
             }
             _ => {}
         }
@@ -227,10 +285,33 @@ impl ShapeRenderer {
 
     /// Use the information in the Gaussian2D component to draw the correct ellipse around the uncertainty as well as a center piece
     pub fn gaussian2d(&mut self, mean: &Vector2<f32>, covariance: &Matrix2<f32>, p: f32) {
+        self.gaussians2d([(mean, covariance)], p);
+    }
+
+    /// Draws several Gaussians like [`ShapeRenderer::gaussian2d`], but all the ellipses and all
+    /// the center pieces in one batch each instead of two draw calls per Gaussian.
+    pub fn gaussians2d<'a>(
+        &mut self,
+        gaussians: impl IntoIterator<Item = (&'a Vector2<f32>, &'a Matrix2<f32>)> + Clone,
+        p: f32,
+    ) {
+        self.begin(PrimitiveType::Line);
+        for (mean, covariance) in gaussians.clone() {
+            self.ellipse(mean, covariance, p);
+        }
+        self.end();
+
+        // on top of the ellipses
         self.begin(PrimitiveType::Filled);
-        self.circle(mean.x, mean.y, 0.01, Color::BLUE);
+        for (mean, _) in gaussians {
+            self.circle(mean.x, mean.y, 0.01, Color::BLUE);
+        }
         self.end();
+    }
 
+    /// The outline of the ellipse containing the probability mass `p` of a Gaussian, drawn as
+    /// lines.
+    fn ellipse(&mut self, mean: &Vector2<f32>, covariance: &Matrix2<f32>, p: f32) {
         // Matlab reference (Source: https://www.xarg.org/2018/04/how-to-plot-a-covariance-error-ellipse/)
         // s = -2 * log(1 - p);
         // [V, D] = eig(Sigma * s);
@@ -247,7 +328,6 @@ impl ShapeRenderer {
         let d = Matrix2::from_diagonal(&eigen.eigenvalues.map(|v| v.sqrt()));
         let v = eigen.eigenvectors;
 
-        self.begin(PrimitiveType::Line);
         let steps = 25;
         for i in 0..steps {
             let angle = i as f32 * PI * 2.0 / steps as f32;
@@ -257,11 +337,10 @@ impl ShapeRenderer {
             let end = mean + (v * d) * Vector2::new(angle.cos(), angle.sin());
             self.line(start.x, start.y, end.x, end.y, Color::BLACK);
         }
-
-        self.end();
     }
 
     pub fn destroy(&self, gl: &glow::Context) {
         self.pr.destroy(gl);
+        self.pcr.destroy(gl);
     }
 }
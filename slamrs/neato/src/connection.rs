@@ -1,38 +1,85 @@
 use common::{
-    node::{Node, NodeConfig},
-    robot::{Command, Observation, Odometry},
+    node::{Node, NodeConfig, TopicDecl},
+    robot::{AxisConvention, Command, Observation, Odometry},
     world::WorldObj,
 };
 use eframe::egui;
 use pubsub::{PubSub, Publisher, Subscription};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use slamrs_message::{bincode, CommandMessage, RobotMessage};
 use std::{
     net::TcpStream,
     path::PathBuf,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
     thread::{self, JoinHandle},
 };
 use tracing::{error, info};
 
-use serial2::SerialPort;
+use serial2::{CharSize, Parity, SerialPort, Settings, StopBits};
+use thiserror::Error;
 
-use crate::frame;
+use crate::frame::{self, FrameError};
+
+/// Errors that end the streaming of data from the robot.
+#[derive(Debug, Error)]
+pub enum ConnectionError {
+    #[error("the connection timed out")]
+    Timeout,
+
+    #[error("connection failed: {0}")]
+    Io(std::io::Error),
+
+    #[error("failed to send command: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+
+    #[error("failed to receive message: {0}")]
+    Decode(#[from] bincode::error::DecodeError),
+
+    #[error("received a corrupt scan frame: {0}")]
+    Frame(#[from] FrameError),
+}
+
+impl From<std::io::Error> for ConnectionError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::TimedOut => ConnectionError::Timeout,
+            _ => ConnectionError::Io(e),
+        }
+    }
+}
 
 pub struct RobotConnection {
     state: State,
     serial_port_sected: bool,
     selected_port: usize,
     host: String,
+    serial: SerialSettings,
+    downsampling: u8,
+    /// Shared with the connection thread so that it can be changed while connected.
+    decimation: Arc<AtomicUsize>,
+    range: [f64; 2],
+    read_buffer_size: usize,
+    convention: AxisConvention,
+    odometry: OdometryParams,
+    wheel_base: f32,
+    /// Latched by the emergency stop, no motion is sent to the robot until re-armed.
+    stopped: bool,
     pub_obs: Publisher<(Observation, Odometry)>,
+    pub_stopped: Option<Publisher<bool>>,
     sub_command: Subscription<Command>,
 }
 
-/// The distance between the wheels of the robot
-static WHEEL_BASE: f32 = 0.2;
+/// How often to ping the robot so its command watchdog does not stop it
+const KEEPALIVE_PERIOD: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// The speed the Neato spins at after the robot has booted
+const DEFAULT_NEATO_RPM: u16 = 300;
+
+/// How often to request the diagnostics of the robot
+const DIAGNOSTICS_PERIOD: std::time::Duration = std::time::Duration::from_secs(1);
 
 enum State {
     Idle,
@@ -40,16 +87,288 @@ enum State {
         handle: JoinHandle<()>,
         running: Arc<AtomicBool>,
         sender: std::sync::mpsc::Sender<CommandMessage>,
+        packet_stats: Arc<PacketStats>,
+        reports: Arc<Mutex<Reports>>,
+        downsampling: u8,
+        neato_rpm: u16,
         speed: f32,
         kp: f32,
         ki: f32,
     },
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct RobotConnectionNodeConfig {
     topic_observation: String,
     topic_command: String,
+    /// Publishes whether the emergency stop is engaged, command sources should not send
+    /// any motion while it is.
+    topic_stopped: Option<String>,
+    #[serde(default)]
+    serial: SerialSettings,
+    /// Only every n-th scan frame is sent by the robot, lower values give denser scans
+    /// at the cost of bandwidth.
+    #[serde(default = "default_downsampling")]
+    downsampling: u8,
+    /// Only every n-th measurement of each scan is kept. Unlike `downsampling` this is done on
+    /// the host and can be changed without reconnecting.
+    #[serde(default = "default_decimation")]
+    decimation: usize,
+    /// Measurements closer than this (meters) are marked as invalid.
+    #[serde(default = "frame::default_range_min")]
+    range_min: f64,
+    /// Measurements further away than this (meters) are marked as invalid.
+    #[serde(default = "frame::default_range_max")]
+    range_max: f64,
+    /// The number of bytes requested from the connection per read. Messages are decoded from
+    /// the buffered bytes, so larger reads mean fewer system calls at high data rates.
+    #[serde(default = "default_read_buffer_size")]
+    read_buffer_size: usize,
+    /// Sent to the robot when connecting, so that a robot with different gearing or wheels can
+    /// be calibrated without changing the firmware.
+    #[serde(default)]
+    odometry: OdometryParams,
+    /// The distance between the wheels of the robot (meters), used to compute the rotation
+    /// from the wheel odometry.
+    #[serde(default = "default_wheel_base")]
+    wheel_base: f32,
+    /// Set from the global settings, see [`RobotConnectionNodeConfig::with_convention`].
+    #[serde(skip)]
+    convention: AxisConvention,
+}
+
+impl RobotConnectionNodeConfig {
+    /// Returns a copy of the config with the axis convention of the robot set.
+    pub fn with_convention(&self, convention: AxisConvention) -> Self {
+        Self {
+            convention,
+            ..self.clone()
+        }
+    }
+}
+
+fn default_downsampling() -> u8 {
+    2
+}
+
+fn default_decimation() -> usize {
+    1
+}
+
+fn default_read_buffer_size() -> usize {
+    4096
+}
+
+fn default_wheel_base() -> f32 {
+    0.2
+}
+
+/// The drivetrain geometry the robot uses to convert between meters and encoder steps.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+struct OdometryParams {
+    steps_per_rev: u32,
+    wheel_diameter_mm: u16,
+}
+
+/// The values the firmware starts with.
+impl Default for OdometryParams {
+    fn default() -> Self {
+        Self {
+            steps_per_rev: 2000,
+            wheel_diameter_mm: 60,
+        }
+    }
+}
+
+impl OdometryParams {
+    fn command(&self) -> CommandMessage {
+        CommandMessage::SetOdometryParams {
+            steps_per_rev: self.steps_per_rev,
+            wheel_diameter_mm: self.wheel_diameter_mm,
+        }
+    }
+}
+
+/// The motor PI parameters reported by the robot.
+#[derive(Clone, Copy, Debug)]
+struct PiParams {
+    kp: f32,
+    ki: f32,
+}
+
+/// The uptime and error counters reported by the robot.
+#[derive(Clone, Copy, Debug)]
+struct Diagnostics {
+    uptime_ms: u32,
+    uart_overruns: u32,
+    channel_full_drops: u32,
+    neato_bad_packets: u32,
+}
+
+/// Replies from the robot, handed from the connection thread to the UI.
+#[derive(Default)]
+struct Reports {
+    /// Taken by the UI once it has updated the sliders.
+    pi_params: Option<PiParams>,
+    diagnostics: Option<Diagnostics>,
+    /// The speed of the Neato in the latest scan frame.
+    rpm: Option<u16>,
+}
+
+/// Keeps count of the Neato packets and the bytes received over the connection.
+#[derive(Default)]
+struct PacketStats {
+    good: AtomicUsize,
+    bad: AtomicUsize,
+    bytes: AtomicUsize,
+    reads: AtomicUsize,
+}
+
+/// Holds the bytes read from the connection until they make up complete messages, so that a
+/// single read can give many messages and a message can span many reads. Nothing is lost when
+/// a read times out in the middle of a message.
+struct ReadBuffer {
+    data: Vec<u8>,
+    read_size: usize,
+}
+
+impl ReadBuffer {
+    fn new(read_size: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(read_size),
+            read_size: read_size.max(1),
+        }
+    }
+
+    /// Reads up to `read_size` more bytes from the connection with a single read call.
+    fn fill(&mut self, reader: &mut impl std::io::Read) -> std::io::Result<usize> {
+        let len = self.data.len();
+        self.data.resize(len + self.read_size, 0);
+        let result = reader.read(&mut self.data[len..]);
+        self.data.truncate(len + *result.as_ref().unwrap_or(&0));
+        result
+    }
+
+    /// Decodes the first message in the buffer, or returns `None` if it is not complete yet.
+    fn decode<D: bincode::Decode>(&mut self) -> Result<Option<D>, bincode::error::DecodeError> {
+        match bincode::decode_from_slice(&self.data, bincode::config::standard()) {
+            Ok((message, length)) => {
+                self.data.drain(..length);
+                Ok(Some(message))
+            }
+            Err(bincode::error::DecodeError::UnexpectedEnd { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Baud rates supported by most serial adapters.
+const COMMON_BAUD_RATES: &[u32] = &[9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+/// Settings used when opening a serial port, defaults to 115200 8N1.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+struct SerialSettings {
+    #[serde(deserialize_with = "deserialize_baud_rate")]
+    baud_rate: u32,
+    data_bits: DataBits,
+    parity: SerialParity,
+    stop_bits: SerialStopBits,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            parity: SerialParity::None,
+            stop_bits: SerialStopBits::One,
+        }
+    }
+}
+
+impl SerialSettings {
+    fn apply(&self, mut settings: Settings) -> std::io::Result<Settings> {
+        settings.set_raw();
+        settings.set_baud_rate(self.baud_rate)?;
+        settings.set_char_size(match self.data_bits {
+            DataBits::Seven => CharSize::Bits7,
+            DataBits::Eight => CharSize::Bits8,
+        });
+        settings.set_parity(match self.parity {
+            SerialParity::None => Parity::None,
+            SerialParity::Odd => Parity::Odd,
+            SerialParity::Even => Parity::Even,
+        });
+        settings.set_stop_bits(match self.stop_bits {
+            SerialStopBits::One => StopBits::One,
+            SerialStopBits::Two => StopBits::Two,
+        });
+        Ok(settings)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Baud")
+            .selected_text(self.baud_rate.to_string())
+            .show_ui(ui, |ui| {
+                for &baud_rate in COMMON_BAUD_RATES {
+                    ui.selectable_value(&mut self.baud_rate, baud_rate, baud_rate.to_string());
+                }
+            });
+
+        egui::ComboBox::from_label("Data Bits")
+            .selected_text(format!("{:?}", self.data_bits))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.data_bits, DataBits::Seven, "Seven");
+                ui.selectable_value(&mut self.data_bits, DataBits::Eight, "Eight");
+            });
+
+        egui::ComboBox::from_label("Parity")
+            .selected_text(format!("{:?}", self.parity))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.parity, SerialParity::None, "None");
+                ui.selectable_value(&mut self.parity, SerialParity::Odd, "Odd");
+                ui.selectable_value(&mut self.parity, SerialParity::Even, "Even");
+            });
+
+        egui::ComboBox::from_label("Stop Bits")
+            .selected_text(format!("{:?}", self.stop_bits))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.stop_bits, SerialStopBits::One, "One");
+                ui.selectable_value(&mut self.stop_bits, SerialStopBits::Two, "Two");
+            });
+    }
+}
+
+fn deserialize_baud_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let baud_rate = u32::deserialize(deserializer)?;
+    if COMMON_BAUD_RATES.contains(&baud_rate) {
+        Ok(baud_rate)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "unsupported baud rate {baud_rate}, expected one of {COMMON_BAUD_RATES:?}"
+        )))
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+enum DataBits {
+    Seven,
+    Eight,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+enum SerialParity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+enum SerialStopBits {
+    One,
+    Two,
 }
 
 impl NodeConfig for RobotConnectionNodeConfig {
@@ -59,10 +378,35 @@ impl NodeConfig for RobotConnectionNodeConfig {
             serial_port_sected: false,
             selected_port: 0,
             host: "robot:8080".into(),
-            pub_obs: pubsub.publish(&self.topic_observation),
+            serial: self.serial,
+            downsampling: self.downsampling,
+            decimation: Arc::new(AtomicUsize::new(self.decimation)),
+            range: [self.range_min, self.range_max],
+            read_buffer_size: self.read_buffer_size,
+            convention: self.convention,
+            odometry: self.odometry,
+            wheel_base: self.wheel_base,
+            stopped: false,
+            pub_obs: pubsub.publish_registered(&self.topic_observation),
+            pub_stopped: self
+                .topic_stopped
+                .as_ref()
+                .map(|topic| pubsub.publish_registered(topic)),
             sub_command: pubsub.subscribe(&self.topic_command),
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = vec![
+            TopicDecl::publish::<(Observation, Odometry)>(&self.topic_observation),
+            TopicDecl::subscribe::<Command>(&self.topic_command),
+        ];
+        if let Some(topic) = &self.topic_stopped {
+            topics.push(TopicDecl::publish::<bool>(topic));
+        }
+
+        topics
+    }
 }
 
 impl Node for RobotConnection {
@@ -80,15 +424,22 @@ impl Node for RobotConnection {
                         });
 
                         if self.serial_port_sected {
-                            if !ports.is_empty() {
-                                egui::ComboBox::from_label("Port")
-                                    .selected_text(format!("{:?}", self.selected_port))
-                                    .show_index(ui, &mut self.selected_port, ports.len(), |i| {
-                                        ports[i].display().to_string()
-                                    });
-                            } else {
-                                ui.label("No ports available!");
-                            }
+                            ui.vertical(|ui| {
+                                if !ports.is_empty() {
+                                    egui::ComboBox::from_label("Port")
+                                        .selected_text(format!("{:?}", self.selected_port))
+                                        .show_index(
+                                            ui,
+                                            &mut self.selected_port,
+                                            ports.len(),
+                                            |i| ports[i].display().to_string(),
+                                        );
+                                } else {
+                                    ui.label("No ports available!");
+                                }
+
+                                self.serial.ui(ui);
+                            });
                         } else {
                             ui.label("Host");
                             ui.text_edit_singleline(&mut self.host);
@@ -98,18 +449,49 @@ impl Node for RobotConnection {
                     if ui.button("Open").clicked() {
                         // start a thread
                         let connection_type = if self.serial_port_sected {
-                            ConnectionType::Serial(ports[self.selected_port].to_owned())
+                            ConnectionType::Serial(
+                                ports[self.selected_port].to_owned(),
+                                self.serial,
+                            )
                         } else {
                             ConnectionType::Tcp(self.host.to_owned())
                         };
 
                         let running = Arc::new(AtomicBool::new(true));
+                        let packet_stats = Arc::new(PacketStats::default());
+                        let reports = Arc::new(Mutex::new(Reports::default()));
                         let (sender, receiver) = std::sync::mpsc::channel();
+
+                        // the robot keeps its speed between connections, make sure it matches the slider
+                        sender
+                            .send(CommandMessage::SetNeatoRpm {
+                                rpm: DEFAULT_NEATO_RPM,
+                            })
+                            .ok();
+                        sender.send(self.odometry.command()).ok();
                         let handle = thread::spawn({
                             let running = running.clone();
+                            let packet_stats = packet_stats.clone();
+                            let reports = reports.clone();
                             let pub_obs = self.pub_obs.clone();
+                            let settings = StreamSettings {
+                                downsampling: self.downsampling,
+                                decimation: self.decimation.clone(),
+                                range: self.range,
+                                read_buffer_size: self.read_buffer_size,
+                                convention: self.convention,
+                                wheel_base: self.wheel_base,
+                            };
                             move || {
-                                connection_thread(connection_type, running, pub_obs, receiver);
+                                connection_thread(
+                                    connection_type,
+                                    running,
+                                    pub_obs,
+                                    receiver,
+                                    packet_stats,
+                                    reports,
+                                    settings,
+                                );
                             }
                         });
 
@@ -117,6 +499,10 @@ impl Node for RobotConnection {
                             handle,
                             running,
                             sender,
+                            packet_stats,
+                            reports,
+                            downsampling: self.downsampling,
+                            neato_rpm: DEFAULT_NEATO_RPM,
                             speed: 0.0,
                             kp: 0.5,
                             ki: 2.0,
@@ -127,6 +513,10 @@ impl Node for RobotConnection {
                     handle,
                     running,
                     sender,
+                    packet_stats,
+                    reports,
+                    downsampling,
+                    neato_rpm,
                     speed,
                     kp,
                     ki,
@@ -139,7 +529,52 @@ impl Node for RobotConnection {
                         new_state = Some(Idle);
                     }
 
-                    if let Some(cmd) = self.sub_command.try_recv() {
+                    // the spacebar works from anywhere as long as no text field has focus
+                    let space = ui.ctx().memory(|m| m.focused().is_none())
+                        && ui.input(|i| i.key_pressed(egui::Key::Space));
+
+                    if self.stopped {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(egui::Color32::RED, "STOPPED");
+                            if ui.button("Re-arm").clicked() {
+                                self.stopped = false;
+                                if let Some(p) = &mut self.pub_stopped {
+                                    p.publish(Arc::new(false));
+                                }
+                            }
+                        });
+                    } else if ui
+                        .add(
+                            egui::Button::new(
+                                egui::RichText::new("EMERGENCY STOP (Space)")
+                                    .strong()
+                                    .color(egui::Color32::WHITE),
+                            )
+                            .fill(egui::Color32::DARK_RED)
+                            .min_size(egui::vec2(200.0, 40.0)),
+                        )
+                        .clicked()
+                        || space
+                    {
+                        for cmd in [
+                            CommandMessage::Stop,
+                            CommandMessage::Drive {
+                                left: 0.0,
+                                right: 0.0,
+                            },
+                            CommandMessage::NeatoOff,
+                        ] {
+                            sender.send(cmd).ok();
+                        }
+                        *speed = 0.0;
+                        self.stopped = true;
+                        if let Some(p) = &mut self.pub_stopped {
+                            p.publish(Arc::new(true));
+                        }
+                    }
+
+                    // drop any commands while stopped so they are not replayed when re-armed
+                    if let Some(cmd) = self.sub_command.try_recv().filter(|_| !self.stopped) {
                         sender
                             .send(CommandMessage::Drive {
                                 left: cmd.speed_left,
@@ -148,13 +583,77 @@ impl Node for RobotConnection {
                             .ok();
                     }
 
-                    ui.vertical(|ui| {
+                    // show the values actually in use once the robot has reported them
+                    if let Some(reported) = reports.lock().unwrap().pi_params.take() {
+                        *kp = reported.kp;
+                        *ki = reported.ki;
+                    }
+
+                    let diagnostics = reports.lock().unwrap().diagnostics;
+                    if let Some(d) = diagnostics {
+                        egui::Window::new("Robot Diagnostics").show(ui.ctx(), |ui| {
+                            ui.label(format!("Uptime: {:.1} s", d.uptime_ms as f32 / 1000.0));
+                            ui.label(format!("UART errors: {}", d.uart_overruns));
+                            ui.label(format!("Dropped messages: {}", d.channel_full_drops));
+                            ui.label(format!("Bad Neato packets: {}", d.neato_bad_packets));
+                        });
+                    }
+
+                    let good = packet_stats.good.load(Ordering::Relaxed);
+                    let bad = packet_stats.bad.load(Ordering::Relaxed);
+                    let bad_rate = if good + bad > 0 {
+                        100.0 * bad as f32 / (good + bad) as f32
+                    } else {
+                        0.0
+                    };
+                    ui.label(format!(
+                        "Packets: {good} ok, {bad} bad ({bad_rate:.1}% bad)"
+                    ));
+
+                    let bytes = packet_stats.bytes.load(Ordering::Relaxed);
+                    let reads = packet_stats.reads.load(Ordering::Relaxed);
+                    ui.label(format!(
+                        "Received: {:.1} kB in {reads} reads ({:.0} B/read)",
+                        bytes as f32 / 1000.0,
+                        bytes as f32 / reads.max(1) as f32
+                    ));
+
+                    ui.add_enabled_ui(!self.stopped, |ui| {
                         if ui.button("Start Neato").clicked() {
                             sender.send(CommandMessage::NeatoOn).ok();
                         }
                         if ui.button("Stop Neato").clicked() {
                             sender.send(CommandMessage::NeatoOff).ok();
                         }
+                        if ui
+                            .add(egui::Slider::new(neato_rpm, 180..=350).text("Neato RPM"))
+                            .changed()
+                        {
+                            sender
+                                .send(CommandMessage::SetNeatoRpm { rpm: *neato_rpm })
+                                .ok();
+                        }
+                        match reports.lock().unwrap().rpm {
+                            Some(rpm) => ui.label(format!("Measured: {rpm} rpm")),
+                            None => ui.label("Measured: -"),
+                        };
+                        if ui
+                            .add(egui::Slider::new(downsampling, 1..=10).text("Downsampling"))
+                            .changed()
+                        {
+                            sender
+                                .send(CommandMessage::SetDownsampling {
+                                    every: *downsampling,
+                                })
+                                .ok();
+                        }
+                        let mut decimation = self.decimation.load(Ordering::Relaxed);
+                        if ui
+                            .add(egui::Slider::new(&mut decimation, 1..=10).text("Decimation"))
+                            .changed()
+                        {
+                            self.decimation.store(decimation, Ordering::Relaxed);
+                        }
                         if ui
                             .add(egui::Slider::new(speed, -1.0..=1.0).text("Speed"))
                             .changed()
@@ -177,6 +676,33 @@ impl Node for RobotConnection {
                                 .send(CommandMessage::SetMotorPiParams { kp: *kp, ki: *ki })
                                 .ok();
                         }
+                        ui.horizontal(|ui| {
+                            ui.label("Odometry:");
+                            let changed = ui
+                                .add(
+                                    egui::DragValue::new(&mut self.odometry.steps_per_rev)
+                                        .range(1..=100_000)
+                                        .suffix(" steps/rev"),
+                                )
+                                .changed()
+                                | ui.add(
+                                    egui::DragValue::new(&mut self.odometry.wheel_diameter_mm)
+                                        .range(1..=1000)
+                                        .suffix(" mm"),
+                                )
+                                .changed();
+                            if changed {
+                                sender.send(self.odometry.command()).ok();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Read PI").clicked() {
+                                sender.send(CommandMessage::GetMotorPiParams).ok();
+                            }
+                            if ui.button("Save to flash").clicked() {
+                                sender.send(CommandMessage::SaveMotorPiParams).ok();
+                            }
+                        });
                     });
                 }
             }
@@ -200,22 +726,45 @@ impl Drop for RobotConnection {
 }
 
 enum ConnectionType {
-    Serial(PathBuf),
+    Serial(PathBuf, SerialSettings),
     Tcp(String),
 }
+
+/// How the scans are requested from the robot and turned into observations.
+struct StreamSettings {
+    downsampling: u8,
+    /// Shared with the UI, so that it can be changed while streaming.
+    decimation: Arc<AtomicUsize>,
+    range: [f64; 2],
+    read_buffer_size: usize,
+    convention: AxisConvention,
+    wheel_base: f32,
+}
+
 fn connection_thread(
     connection_type: ConnectionType,
     running: Arc<AtomicBool>,
     pub_obs: Publisher<(Observation, Odometry)>,
     receiver: std::sync::mpsc::Receiver<CommandMessage>,
+    packet_stats: Arc<PacketStats>,
+    reports: Arc<Mutex<Reports>>,
+    settings: StreamSettings,
 ) {
     match connection_type {
-        ConnectionType::Serial(path) => {
-            info!("Opening {path:?}");
+        ConnectionType::Serial(path, serial) => {
+            info!("Opening {path:?} with {serial:?}");
 
-            match SerialPort::open(path, 115200) {
+            match SerialPort::open(path, |s: Settings| serial.apply(s)) {
                 Ok(port) => {
-                    if let Err(e) = stream(port, running, pub_obs, receiver) {
+                    if let Err(e) = stream(
+                        port,
+                        running,
+                        pub_obs,
+                        receiver,
+                        packet_stats,
+                        reports,
+                        settings,
+                    ) {
                         error!("Error while streaming serial port:\n{:#}", e);
                     }
                 }
@@ -229,7 +778,15 @@ fn connection_thread(
 
             match TcpStream::connect(host) {
                 Ok(port) => {
-                    if let Err(e) = stream(port, running, pub_obs, receiver) {
+                    if let Err(e) = stream(
+                        port,
+                        running,
+                        pub_obs,
+                        receiver,
+                        packet_stats,
+                        reports,
+                        settings,
+                    ) {
                         error!("Error while streaming network connection:\n{:#}", e);
                     }
                 }
@@ -246,11 +803,25 @@ fn stream<C: ConnectionMedium>(
     running: Arc<AtomicBool>,
     mut pub_obs: Publisher<(Observation, Odometry)>,
     receiver: std::sync::mpsc::Receiver<CommandMessage>,
-) -> anyhow::Result<()> {
+    packet_stats: Arc<PacketStats>,
+    reports: Arc<Mutex<Reports>>,
+    settings: StreamSettings,
+) -> Result<(), ConnectionError> {
+    let StreamSettings {
+        downsampling,
+        decimation,
+        range,
+        read_buffer_size,
+        convention,
+        wheel_base,
+    } = settings;
+
     connection.set_timeout_read(std::time::Duration::from_millis(200))?;
 
     bincode::encode_into_std_write(
-        CommandMessage::SetDownsampling { every: 2 },
+        CommandMessage::SetDownsampling {
+            every: downsampling,
+        },
         &mut connection,
         bincode::config::standard(),
     )?;
@@ -261,38 +832,111 @@ fn stream<C: ConnectionMedium>(
         bincode::config::standard(),
     )?;
 
+    bincode::encode_into_std_write(
+        CommandMessage::GetMotorPiParams,
+        &mut connection,
+        bincode::config::standard(),
+    )?;
+
+    // the robot has no clock of its own, so scans are stamped with the time since connecting
+    let start = std::time::Instant::now();
+    let mut last_sent = std::time::Instant::now();
+    let mut last_diagnostics = std::time::Instant::now();
+    let mut buffer = ReadBuffer::new(read_buffer_size);
+
     while running.load(Ordering::Relaxed) {
         while let Ok(cmd) = receiver.try_recv() {
             info!("Sending: {:?}", cmd);
             bincode::encode_into_std_write(cmd, &mut connection, bincode::config::standard())?;
+            last_sent = std::time::Instant::now();
         }
 
-        match bincode::decode_from_std_read(&mut connection, bincode::config::standard()) {
-            Ok(data) => match data {
-                RobotMessage::ScanFrame(scan_frame) => {
-                    let parsed = frame::parse_frame(&scan_frame.scan_data)?;
-                    println!("Received: {:?}", &scan_frame.rpm);
-                    let odometry =
-                        Odometry::new(scan_frame.odometry[0], scan_frame.odometry[1], WHEEL_BASE);
-                    pub_obs.publish(Arc::new((parsed.into(), odometry)));
-                }
-                RobotMessage::Pong => {
-                    println!("Received: Pong");
-
-                    // send ping
-                    bincode::encode_into_std_write(
-                        CommandMessage::Ping,
-                        &mut connection,
-                        bincode::config::standard(),
-                    )?;
+        if last_sent.elapsed() > KEEPALIVE_PERIOD {
+            bincode::encode_into_std_write(
+                CommandMessage::Ping,
+                &mut connection,
+                bincode::config::standard(),
+            )?;
+            last_sent = std::time::Instant::now();
+        }
+
+        if last_diagnostics.elapsed() > DIAGNOSTICS_PERIOD {
+            bincode::encode_into_std_write(
+                CommandMessage::GetDiagnostics,
+                &mut connection,
+                bincode::config::standard(),
+            )?;
+            last_diagnostics = std::time::Instant::now();
+        }
+
+        // only read more once all complete messages have been handled
+        let Some(message) = buffer.decode::<RobotMessage>()? else {
+            match buffer.fill(&mut connection) {
+                Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                Ok(n) => {
+                    packet_stats.bytes.fetch_add(n, Ordering::Relaxed);
+                    packet_stats.reads.fetch_add(1, Ordering::Relaxed);
                 }
-            },
-            // skip TimedOut errors
-            Err(bincode::error::DecodeError::Io { inner, .. })
-                if inner.kind() == std::io::ErrorKind::TimedOut
-                    || inner.kind() == std::io::ErrorKind::WouldBlock => {}
-            Err(e) => {
-                return Err(e.into());
+                // skip TimedOut errors
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::TimedOut
+                        || e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+            continue;
+        };
+
+        match message {
+            RobotMessage::ScanFrame(scan_frame) => {
+                let parsed = frame::parse_frame(&scan_frame.scan_data)?;
+                packet_stats
+                    .good
+                    .fetch_add(90 - parsed.bad_packets, Ordering::Relaxed);
+                packet_stats
+                    .bad
+                    .fetch_add(parsed.bad_packets, Ordering::Relaxed);
+                println!("Received: {:?}", &scan_frame.rpm);
+                reports.lock().unwrap().rpm = Some(scan_frame.rpm);
+                let odometry =
+                    Odometry::new(scan_frame.odometry[0], scan_frame.odometry[1], wheel_base);
+                let observation = Observation {
+                    rpm: Some(scan_frame.rpm as f32),
+                    timestamp: start.elapsed().as_secs_f64(),
+                    ..parsed
+                        .to_observation(decimation.load(Ordering::Relaxed))
+                        .clamp_range(range[0], range[1])
+                };
+                pub_obs.publish(Arc::new((
+                    convention.observation(observation),
+                    convention.odometry(odometry),
+                )));
+            }
+            RobotMessage::MotorPiParams { kp, ki } => {
+                info!("Robot uses motor PI parameters kp={kp}, ki={ki}");
+                reports.lock().unwrap().pi_params = Some(PiParams { kp, ki });
+            }
+            RobotMessage::Diagnostics {
+                uptime_ms,
+                uart_overruns,
+                channel_full_drops,
+                neato_bad_packets,
+            } => {
+                reports.lock().unwrap().diagnostics = Some(Diagnostics {
+                    uptime_ms,
+                    uart_overruns,
+                    channel_full_drops,
+                    neato_bad_packets,
+                });
+            }
+            RobotMessage::Pong => {
+                println!("Received: Pong");
+
+                // send ping
+                bincode::encode_into_std_write(
+                    CommandMessage::Ping,
+                    &mut connection,
+                    bincode::config::standard(),
+                )?;
             }
         }
     }
@@ -336,3 +980,76 @@ impl ConnectionMedium for std::net::TcpStream {
         self.set_read_timeout(Some(timeout))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gives out at most `chunk` bytes per read, like a slow serial port.
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        reads: usize,
+    }
+
+    impl std::io::Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads += 1;
+            let n = buf.len().min(self.chunk).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    /// Decodes all messages in `data`, returning the parameters of the `MotorPiParams` messages
+    /// and the number of reads it took.
+    fn decode_all(data: &[u8], chunk: usize, read_size: usize) -> (Vec<(f32, f32)>, usize) {
+        let mut reader = Chunked {
+            data,
+            chunk,
+            reads: 0,
+        };
+        let mut buffer = ReadBuffer::new(read_size);
+        let mut params = Vec::new();
+        loop {
+            match buffer.decode::<RobotMessage>().unwrap() {
+                Some(RobotMessage::MotorPiParams { kp, ki }) => params.push((kp, ki)),
+                Some(_) => {}
+                None => {
+                    if buffer.fill(&mut reader).unwrap() == 0 {
+                        return (params, reader.reads);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn read_buffer_decodes_messages_split_across_reads() {
+        let mut data = Vec::new();
+        for i in 0..100 {
+            for message in [
+                RobotMessage::Pong,
+                RobotMessage::MotorPiParams {
+                    kp: i as f32,
+                    ki: 0.5,
+                },
+            ] {
+                bincode::encode_into_std_write(message, &mut data, bincode::config::standard())
+                    .unwrap();
+            }
+        }
+        let expected: Vec<(f32, f32)> = (0..100).map(|i| (i as f32, 0.5)).collect();
+
+        // messages span many reads
+        for chunk in [1, 3, 7] {
+            assert_eq!(decode_all(&data, chunk, 4096).0, expected, "chunk {chunk}");
+        }
+
+        // a single read gives many messages, plus the read that finds the end
+        let (params, reads) = decode_all(&data, usize::MAX, 4096);
+        assert_eq!(params, expected);
+        assert_eq!(reads, 2);
+    }
+}
@@ -1,40 +1,80 @@
 use common::{
-    node::{Node, NodeConfig},
-    robot::{Observation, Pose},
+    node::{Node, NodeConfig, TopicDecl},
+    robot::{AxisConvention, Observation, Pose},
     world::WorldObj,
 };
 use pubsub::{PubSub, Publisher};
 use serde::Deserialize;
 use std::sync::Arc;
+use tracing::error;
 
-use super::frame;
+use super::frame::{self, NeatoFrame};
 use eframe::egui;
 
 pub struct FileLoader {
     picked_path: Option<String>,
-    data: Option<Vec<Observation>>,
+    data: Option<Vec<NeatoFrame>>,
     selected_frame: usize,
+    decimation: usize,
+    range: [f64; 2],
+    convention: AxisConvention,
     pub_frame: Publisher<Observation>,
     pub_pose: Publisher<Pose>,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct FileLoaderNodeConfig {
     topic_observation: String,
     topic_pose: String,
+    /// Only every n-th measurement of each scan is published.
+    #[serde(default = "default_decimation")]
+    decimation: usize,
+    /// Measurements closer than this (meters) are marked as invalid.
+    #[serde(default = "frame::default_range_min")]
+    range_min: f64,
+    /// Measurements further away than this (meters) are marked as invalid.
+    #[serde(default = "frame::default_range_max")]
+    range_max: f64,
+    /// Set from the global settings, see [`FileLoaderNodeConfig::with_convention`].
+    #[serde(skip)]
+    convention: AxisConvention,
     // TODO: make it possible to specify a path to load automatically here
 }
 
+fn default_decimation() -> usize {
+    1
+}
+
+impl FileLoaderNodeConfig {
+    /// Returns a copy of the config with the axis convention of the loaded files set.
+    pub fn with_convention(&self, convention: AxisConvention) -> Self {
+        Self {
+            convention,
+            ..self.clone()
+        }
+    }
+}
+
 impl NodeConfig for FileLoaderNodeConfig {
     fn instantiate(&self, pubsub: &mut PubSub) -> Box<dyn Node> {
         Box::new(FileLoader {
             picked_path: None,
             data: None,
             selected_frame: 0,
-            pub_frame: pubsub.publish(&self.topic_observation),
-            pub_pose: pubsub.publish(&self.topic_pose),
+            decimation: self.decimation,
+            range: [self.range_min, self.range_max],
+            convention: self.convention,
+            pub_frame: pubsub.publish_registered(&self.topic_observation),
+            pub_pose: pubsub.publish_registered(&self.topic_pose),
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![
+            TopicDecl::publish::<Observation>(&self.topic_observation),
+            TopicDecl::publish::<Pose>(&self.topic_pose),
+        ]
+    }
 }
 
 impl Node for FileLoader {
@@ -48,9 +88,13 @@ impl Node for FileLoader {
                     self.picked_path = Some(path.display().to_string());
 
                     // do stuff here!
-                    self.data = frame::load_neato_binary(&path)
-                        .ok()
-                        .map(|n| n.iter().map(|&o| o.into()).collect())
+                    self.data = match frame::load_neato_binary(&path) {
+                        Ok(n) => Some(n),
+                        Err(e) => {
+                            error!("Could not load {}: {:#}", path.display(), e);
+                            None
+                        }
+                    }
                 }
             }
 
@@ -73,9 +117,13 @@ impl Node for FileLoader {
                         .integer()
                         .text("Scan"),
                 );
-                if r.changed() {
+                let d = ui.add(egui::Slider::new(&mut self.decimation, 1..=10).text("Decimation"));
+                if r.changed() || d.changed() {
+                    let observation = data[self.selected_frame]
+                        .to_observation(self.decimation)
+                        .clamp_range(self.range[0], self.range[1]);
                     self.pub_frame
-                        .publish(Arc::new(data[self.selected_frame].clone()));
+                        .publish(Arc::new(self.convention.observation(observation)));
                     self.pub_pose.publish(Arc::new(Pose::default()));
                 }
             }
@@ -3,12 +3,32 @@
 use std::{fs::File, io::Read, path::PathBuf};
 
 use common::robot::{Measurement, Observation};
+use thiserror::Error;
+
+/// The number of bytes in a single packet from the sensor.
+const PACKET_LEN: usize = 22;
+
+/// The number of packets in a complete revolution.
+const PACKETS_PER_FRAME: usize = 90;
+
+/// Errors that can occur while reading or parsing Neato sensor data.
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("expected {expected} bytes of frame data, got {actual}")]
+    InvalidLength { expected: usize, actual: usize },
+
+    #[error("failed to read frame data: {0}")]
+    Io(#[from] std::io::Error),
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct NeatoFrame {
     pub distance: [u16; 360],
     pub strength: [u16; 360],
     pub valid: [u8; 360],
+    /// The number of packets in this frame that failed the checksum validation. Their
+    /// measurements are all marked as invalid.
+    pub bad_packets: usize,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -65,25 +85,26 @@ impl Revolution {
             distance,
             strength,
             valid,
+            bad_packets: 0,
         }
 
         // println!("],");
     }
 }
 
-fn parse_data(b: &[u8]) -> anyhow::Result<Data> {
+fn parse_data(b: &[u8]) -> Data {
     assert!(b.len() == 4);
 
-    Ok(Data {
+    Data {
         valid: (b[1] & (1 << 7)) == 0,
-        strength_warning: (b[1] & (1 << 6)) == 0,
+        strength_warning: (b[1] & (1 << 6)) != 0,
         distance: b[0] as u16 | (((b[1] as u16) & 0x3F) << 8),
         strength: ((b[3] as u16) << 8) | b[2] as u16,
-    })
+    }
 }
 
-fn calculate_checksum_and_validate(b: &[u8]) -> anyhow::Result<bool> {
-    assert!(b.len() == 22);
+fn calculate_checksum_and_validate(b: &[u8]) -> bool {
+    assert!(b.len() == PACKET_LEN);
 
     // convert data to words, little-endian
     let mut words = Vec::with_capacity(b.len() / 2);
@@ -102,38 +123,54 @@ fn calculate_checksum_and_validate(b: &[u8]) -> anyhow::Result<bool> {
     let cs = ((b[21] as u16) << 8) | b[20] as u16;
     // println!("{checksum} == {cs}");
 
-    Ok(checksum == cs)
+    checksum == cs
 }
 
-fn parse_packet(b: &[u8]) -> anyhow::Result<Packet> {
-    assert!(b.len() == 22);
+fn parse_packet(b: &[u8]) -> Packet {
+    assert!(b.len() == PACKET_LEN);
 
-    Ok(Packet {
+    Packet {
         index: b[1],
         speed: ((b[3] as u16) << 8) | b[2] as u16,
         data: [
-            parse_data(&b[4..8])?,
-            parse_data(&b[8..12])?,
-            parse_data(&b[12..16])?,
-            parse_data(&b[16..20])?,
+            parse_data(&b[4..8]),
+            parse_data(&b[8..12]),
+            parse_data(&b[12..16]),
+            parse_data(&b[16..20]),
         ],
-        checksum: calculate_checksum_and_validate(b)?,
-    })
+        checksum: calculate_checksum_and_validate(b),
+    }
 }
 
-pub fn parse_frame(buf: &[u8]) -> anyhow::Result<NeatoFrame> {
-    assert!(buf.len() == 22 * 90);
+/// Parses a complete revolution of packets as sent by the robot. Packets that fail the checksum
+/// validation are counted in [`NeatoFrame::bad_packets`] rather than failing the whole frame.
+pub fn parse_frame(buf: &[u8]) -> Result<NeatoFrame, FrameError> {
+    if buf.len() != PACKET_LEN * PACKETS_PER_FRAME {
+        return Err(FrameError::InvalidLength {
+            expected: PACKET_LEN * PACKETS_PER_FRAME,
+            actual: buf.len(),
+        });
+    }
 
     let mut r = Revolution::default();
+    let mut bad_packets = 0;
+
+    for i in 0..PACKETS_PER_FRAME {
+        // drop any packets that were corrupted on the way
+        r.packets[i] =
+            Some(parse_packet(&buf[i * PACKET_LEN..(i + 1) * PACKET_LEN])).filter(|p| p.checksum);
 
-    for i in 0..90 {
-        r.packets[i] = parse_packet(&buf[i * 22..(i + 1) * 22]).ok();
+        if r.packets[i].is_none() {
+            bad_packets += 1;
+        }
     }
 
-    Ok(r.as_readings())
+    let mut frame = r.as_readings();
+    frame.bad_packets = bad_packets;
+    Ok(frame)
 }
 
-fn parse_packets<R: Read>(reader: &mut R) -> anyhow::Result<Vec<NeatoFrame>> {
+fn parse_packets<R: Read>(reader: &mut R) -> Result<Vec<NeatoFrame>, FrameError> {
     // read all the bytes into a buffer for now
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf)?;
@@ -151,10 +188,10 @@ fn parse_packets<R: Read>(reader: &mut R) -> anyhow::Result<Vec<NeatoFrame>> {
     let mut last_index = 0;
 
     while i < buf.len() {
-        if buf[i] == 0xFA && (buf.len() - i) >= 22 {
+        if buf[i] == 0xFA && (buf.len() - i) >= PACKET_LEN {
             // parse a packet!
 
-            let packet = &buf[i..(i + 22)];
+            let packet = &buf[i..(i + PACKET_LEN)];
 
             // print!("Found 0xFA: ");
 
@@ -163,7 +200,7 @@ fn parse_packets<R: Read>(reader: &mut R) -> anyhow::Result<Vec<NeatoFrame>> {
             // }
             // println!();
 
-            let p = parse_packet(packet)?;
+            let p = parse_packet(packet);
             // println!("{p:?}");
 
             // insert into the current revolution
@@ -181,6 +218,11 @@ fn parse_packets<R: Read>(reader: &mut R) -> anyhow::Result<Vec<NeatoFrame>> {
             }
 
             let index = p.index - 0xA0;
+            if index as usize >= PACKETS_PER_FRAME {
+                i += 1;
+                continue;
+            }
+
             if index < last_index {
                 // wrapped around to new revolution, print and instantiate new one
                 // print!("Revolution: ");
@@ -200,7 +242,7 @@ fn parse_packets<R: Read>(reader: &mut R) -> anyhow::Result<Vec<NeatoFrame>> {
     Ok(frames)
 }
 
-pub fn load_neato_binary(path: &PathBuf) -> anyhow::Result<Vec<NeatoFrame>> {
+pub fn load_neato_binary(path: &PathBuf) -> Result<Vec<NeatoFrame>, FrameError> {
     // load all contents of the file
     // let contents = fs::read(path)?;
     let mut file = File::open(path)?;
@@ -209,22 +251,253 @@ pub fn load_neato_binary(path: &PathBuf) -> anyhow::Result<Vec<NeatoFrame>> {
     Ok(p)
 }
 
-impl From<NeatoFrame> for Observation {
-    fn from(value: NeatoFrame) -> Self {
-        let mut m: Vec<Measurement> = Vec::new();
+/// The closest distance (meters) the Neato lidar measures reliably.
+pub fn default_range_min() -> f64 {
+    0.15
+}
 
-        for i in 0..value.distance.len() {
-            m.push(Measurement {
+/// The furthest distance (meters) the Neato lidar measures reliably.
+pub fn default_range_max() -> f64 {
+    6.0
+}
+
+impl NeatoFrame {
+    /// Converts the frame to an observation, keeping only every `decimation`-th measurement
+    /// (starting with the first one) to save processing further down the line.
+    pub fn to_observation(self, decimation: usize) -> Observation {
+        let step = decimation.max(1);
+
+        let measurements = (0..self.distance.len())
+            .step_by(step)
+            .map(|i| Measurement {
                 angle: (i as f64).to_radians(),
-                distance: value.distance[i] as f64 / 1000.0,
-                strength: value.strength[i] as f64,
-                valid: value.valid[i] != 0,
+                distance: self.distance[i] as f64 / 1000.0,
+                strength: self.strength[i] as f64,
+                valid: self.valid[i] != 0,
+                // the measurements are taken in order while the scanner spins
+                phase: i as f32 / self.distance.len() as f32,
             })
-        }
+            .collect();
+
+        // the last slot that is kept
+        let last = (self.distance.len() - 1) / step * step;
 
         Observation {
             id: 0,
-            measurements: m,
+            measurements,
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: (last as f64).to_radians(),
+            angle_increment: (step as f64).to_radians(),
+            timestamp: 0.0,
+        }
+    }
+}
+
+impl From<NeatoFrame> for Observation {
+    fn from(value: NeatoFrame) -> Self {
+        value.to_observation(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single reading as encoded by the sensor.
+    #[derive(Clone, Copy)]
+    struct Reading {
+        /// Millimeters
+        distance: u16,
+        strength: u16,
+        invalid: bool,
+        strength_warning: bool,
+    }
+
+    impl Reading {
+        fn encode(&self) -> [u8; 4] {
+            let flags = (self.invalid as u8) << 7 | (self.strength_warning as u8) << 6;
+            [
+                (self.distance & 0xFF) as u8,
+                (self.distance >> 8) as u8 & 0x3F | flags,
+                (self.strength & 0xFF) as u8,
+                (self.strength >> 8) as u8,
+            ]
         }
     }
+
+    /// Builds a packet with a valid checksum, `speed` is in 64ths of an rpm.
+    fn packet(index: u8, speed: u16, readings: [Reading; 4]) -> [u8; PACKET_LEN] {
+        let mut b = [0u8; PACKET_LEN];
+        b[0] = 0xFA;
+        b[1] = 0xA0 + index;
+        b[2..4].copy_from_slice(&speed.to_le_bytes());
+        for (i, reading) in readings.iter().enumerate() {
+            b[4 + 4 * i..8 + 4 * i].copy_from_slice(&reading.encode());
+        }
+
+        let chk32 = b[..20]
+            .chunks(2)
+            .map(|w| u16::from_le_bytes([w[0], w[1]]) as u32)
+            .fold(0u32, |chk, word| (chk << 1) + word);
+        let checksum = (((chk32 & 0x7FFF) + (chk32 >> 15)) & 0x7FFF) as u16;
+        b[20..].copy_from_slice(&checksum.to_le_bytes());
+        b
+    }
+
+    /// The reading at `angle` degrees in the test frame.
+    fn reading(angle: usize) -> Reading {
+        Reading {
+            distance: 200 + 10 * angle as u16,
+            strength: 1000 + angle as u16,
+            invalid: angle % 7 == 0,
+            strength_warning: angle % 5 == 0,
+        }
+    }
+
+    fn frame_bytes() -> Vec<u8> {
+        (0..PACKETS_PER_FRAME)
+            .flat_map(|p| {
+                let readings = std::array::from_fn(|j| reading(4 * p + j));
+                packet(p as u8, 300 * 64, readings)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn data_flags_and_fields() {
+        let data = parse_data(&[0x34, 0x12, 0x78, 0x01]);
+        assert!(data.valid);
+        assert!(!data.strength_warning);
+        assert_eq!(data.distance, 0x1234);
+        assert_eq!(data.strength, 0x0178);
+
+        // the flags are not part of the distance
+        let data = parse_data(&[0x34, 0x12 | 0x80, 0x00, 0x00]);
+        assert!(!data.valid);
+        assert!(!data.strength_warning);
+        assert_eq!(data.distance, 0x1234);
+
+        let data = parse_data(&[0x34, 0x12 | 0x40, 0x00, 0x00]);
+        assert!(data.valid);
+        assert!(data.strength_warning);
+        assert_eq!(data.distance, 0x1234);
+    }
+
+    #[test]
+    fn packet_fields_and_checksum() {
+        let mut b = packet(
+            5,
+            0x1234,
+            [
+                reading(20),
+                reading(21),
+                reading(22),
+                Reading {
+                    distance: 0x3FFF,
+                    strength: 0xFFFF,
+                    invalid: true,
+                    strength_warning: true,
+                },
+            ],
+        );
+
+        let p = parse_packet(&b);
+        assert!(p.checksum);
+        assert_eq!(p.index, 0xA5);
+        assert_eq!(p.speed, 0x1234);
+        assert_eq!(p.data[0].distance, 400);
+        assert_eq!(p.data[1].strength, 1021);
+        assert_eq!(p.data[3].distance, 0x3FFF);
+        assert_eq!(p.data[3].strength, 0xFFFF);
+        assert!(!p.data[3].valid && p.data[3].strength_warning);
+
+        b[10] ^= 0x01;
+        assert!(!parse_packet(&b).checksum);
+    }
+
+    #[test]
+    fn frame_to_observation() {
+        let frame = parse_frame(&frame_bytes()).unwrap();
+        assert_eq!(frame.bad_packets, 0);
+
+        let observation = Observation::from(frame);
+        assert_eq!(observation.measurements.len(), 360);
+
+        for (angle, m) in observation.measurements.iter().enumerate() {
+            let expected = reading(angle);
+            assert!((m.angle - (angle as f64).to_radians()).abs() < 1e-12);
+            assert!((m.distance - expected.distance as f64 / 1000.0).abs() < 1e-12);
+            assert_eq!(m.strength, expected.strength as f64);
+            assert_eq!(m.valid, !expected.invalid, "angle {angle}");
+        }
+
+        assert_eq!(observation.angle_min, 0.0);
+        assert!((observation.angle_max - 359f64.to_radians()).abs() < 1e-12);
+        assert!((observation.angle_increment - 1f64.to_radians()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn decimated_observation() {
+        let frame = parse_frame(&frame_bytes()).unwrap();
+
+        // (decimation, measurements, last angle in degrees)
+        for (decimation, count, last) in [(4, 90, 356), (7, 52, 357), (0, 360, 359)] {
+            let observation = frame.to_observation(decimation);
+            let step = decimation.max(1);
+            assert_eq!(
+                observation.measurements.len(),
+                count,
+                "decimation {decimation}"
+            );
+
+            for (i, m) in observation.measurements.iter().enumerate() {
+                let angle = i * step;
+                assert!((m.angle - (angle as f64).to_radians()).abs() < 1e-12);
+                assert!((m.distance - reading(angle).distance as f64 / 1000.0).abs() < 1e-12);
+            }
+
+            assert_eq!(observation.angle_min, 0.0);
+            assert!((observation.angle_max - (last as f64).to_radians()).abs() < 1e-12);
+            assert!((observation.angle_increment - (step as f64).to_radians()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn corrupted_packets_are_invalid() {
+        let mut bytes = frame_bytes();
+        // flip a distance bit in the third packet
+        bytes[2 * PACKET_LEN + 4] ^= 0x10;
+
+        let frame = parse_frame(&bytes).unwrap();
+        assert_eq!(frame.bad_packets, 1);
+        assert_eq!(frame.valid[8..12], [0, 0, 0, 0]);
+        // the neighbours are unaffected
+        assert_eq!(frame.valid[12], !reading(12).invalid as u8);
+        assert_eq!(frame.distance[7], reading(7).distance);
+    }
+
+    #[test]
+    fn frame_of_wrong_length() {
+        let bytes = frame_bytes();
+        assert!(matches!(
+            parse_frame(&bytes[1..]),
+            Err(FrameError::InvalidLength {
+                expected: 1980,
+                actual: 1979
+            })
+        ));
+    }
+
+    #[test]
+    fn stream_with_bad_index() {
+        let mut bytes = frame_bytes();
+        // a packet claiming to be past the end of the revolution
+        bytes.extend(packet(95, 0, [reading(0); 4]));
+        bytes.extend(frame_bytes());
+
+        let frames = parse_packets(&mut bytes.as_slice()).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].distance[359], reading(359).distance);
+    }
 }
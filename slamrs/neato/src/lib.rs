@@ -1,8 +1,9 @@
 mod connection;
 
-pub use connection::{RobotConnection, RobotConnectionNodeConfig};
+pub use connection::{ConnectionError, RobotConnection, RobotConnectionNodeConfig};
 
 mod frame;
+pub use frame::FrameError;
 
 mod fileloader;
 pub use fileloader::{FileLoader, FileLoaderNodeConfig};
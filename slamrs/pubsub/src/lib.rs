@@ -1,20 +1,32 @@
 use std::{
     any::{type_name, Any, TypeId},
     collections::HashMap,
+    fmt::Debug,
     marker::PhantomData,
     sync::{
         mpsc::{self, channel, Receiver, Sender},
-        Arc,
+        Arc, Mutex,
     },
 };
 
 /// A simple publish/subscribe system that allows sending and subscribing to values on different topics.
 /// Each topic name is allocated to a single type, attempts to subscribe and publish to the same topic with
 /// different types will panic!
+///
+/// Published values are queued until [`tick`](PubSub::tick) distributes them. The delivery guarantees are:
+/// * Within a topic, each subscriber receives the values in the order they were published, also
+///   across several ticks and several publishers.
+/// * A subscriber receives all values distributed after it subscribed, including those published
+///   before but not yet distributed. Values distributed earlier are not replayed.
+/// * There is no ordering between different topics.
 pub struct PubSub {
     topics: HashMap<String, Topic>,
     signal: Receiver<Signal>,
     signal_source: Sender<Signal>,
+    /// Keep the [`SharedLatest`] cells up to date, run at the end of every tick
+    mirrors: Vec<Box<dyn FnMut() + Send>>,
+    /// The types registered for dynamic access, see [`PubSub::register`]
+    types: HashMap<TypeId, TypeVtable>,
 }
 
 pub struct Signal {}
@@ -80,6 +92,247 @@ impl<T: Any + Send + Sync + 'static> Subscription<T> {
     pub fn topic(&self) -> &str {
         &self.topic
     }
+
+    /// Transforms every received value with `f`. The mapping happens when receiving, so the
+    /// type of the topic itself is unchanged.
+    pub fn map<U: Any + Send + Sync + 'static>(
+        self,
+        f: impl FnMut(Arc<T>) -> Arc<U> + Send + 'static,
+    ) -> MappedSubscription<U> {
+        MappedSubscription::from(self).map(f)
+    }
+
+    /// Only receives the values for which `predicate` returns true, all other values are dropped.
+    pub fn filter(
+        self,
+        predicate: impl FnMut(&T) -> bool + Send + 'static,
+    ) -> MappedSubscription<T> {
+        MappedSubscription::from(self).filter(predicate)
+    }
+}
+
+/// Anything that values can be received from, used to chain combinators on subscriptions.
+trait Receive<T>: Send {
+    fn try_recv(&mut self) -> Option<Arc<T>>;
+    fn recv(&mut self) -> Arc<T>;
+    fn topic(&self) -> &str;
+}
+
+impl<T: Any + Send + Sync + 'static> Receive<T> for Subscription<T> {
+    fn try_recv(&mut self) -> Option<Arc<T>> {
+        Subscription::try_recv(self)
+    }
+
+    fn recv(&mut self) -> Arc<T> {
+        Subscription::recv(self)
+    }
+
+    fn topic(&self) -> &str {
+        Subscription::topic(self)
+    }
+}
+
+struct Map<T, F> {
+    inner: Box<dyn Receive<T>>,
+    f: F,
+}
+
+impl<T, U, F> Receive<U> for Map<T, F>
+where
+    T: 'static,
+    F: FnMut(Arc<T>) -> Arc<U> + Send,
+{
+    fn try_recv(&mut self) -> Option<Arc<U>> {
+        self.inner.try_recv().map(&mut self.f)
+    }
+
+    fn recv(&mut self) -> Arc<U> {
+        (self.f)(self.inner.recv())
+    }
+
+    fn topic(&self) -> &str {
+        self.inner.topic()
+    }
+}
+
+struct Filter<T, P> {
+    inner: Box<dyn Receive<T>>,
+    predicate: P,
+}
+
+impl<T, P> Receive<T> for Filter<T, P>
+where
+    T: 'static,
+    P: FnMut(&T) -> bool + Send,
+{
+    fn try_recv(&mut self) -> Option<Arc<T>> {
+        // skip past any values that do not match, without blocking
+        while let Some(value) = self.inner.try_recv() {
+            if (self.predicate)(&value) {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn recv(&mut self) -> Arc<T> {
+        loop {
+            let value = self.inner.recv();
+            if (self.predicate)(&value) {
+                return value;
+            }
+        }
+    }
+
+    fn topic(&self) -> &str {
+        self.inner.topic()
+    }
+}
+
+/// A [`Subscription`] with one or more [`map`](Subscription::map) or
+/// [`filter`](Subscription::filter) combinators applied to it.
+pub struct MappedSubscription<T: Any + Send + Sync + 'static> {
+    inner: Box<dyn Receive<T>>,
+}
+
+impl<T: Any + Send + Sync + 'static> From<Subscription<T>> for MappedSubscription<T> {
+    fn from(subscription: Subscription<T>) -> Self {
+        Self {
+            inner: Box::new(subscription),
+        }
+    }
+}
+
+impl<T: Any + Send + Sync + 'static> MappedSubscription<T> {
+    /// Tries to receive a value from the subscribed topic, but will not block if no data is available.
+    pub fn try_recv(&mut self) -> Option<Arc<T>> {
+        self.inner.try_recv()
+    }
+
+    /// Receives a value from the subscribed topic, and will block if no data is available.
+    pub fn recv(&mut self) -> Arc<T> {
+        self.inner.recv()
+    }
+
+    pub fn topic(&self) -> &str {
+        self.inner.topic()
+    }
+
+    /// See [`Subscription::map`].
+    pub fn map<U: Any + Send + Sync + 'static>(
+        self,
+        f: impl FnMut(Arc<T>) -> Arc<U> + Send + 'static,
+    ) -> MappedSubscription<U> {
+        MappedSubscription {
+            inner: Box::new(Map {
+                inner: self.inner,
+                f,
+            }),
+        }
+    }
+
+    /// See [`Subscription::filter`].
+    pub fn filter(
+        self,
+        predicate: impl FnMut(&T) -> bool + Send + 'static,
+    ) -> MappedSubscription<T> {
+        MappedSubscription {
+            inner: Box::new(Filter {
+                inner: self.inner,
+                predicate,
+            }),
+        }
+    }
+}
+
+/// Holds the latest value published to a topic, created with [`PubSub::latest_cell`].
+///
+/// The cell is only updated when the [`PubSub`] is ticked, so just like a [`Subscription`] it
+/// lags behind the publisher by (at most) one tick.
+pub struct SharedLatest<T> {
+    value: Arc<Mutex<Option<Arc<T>>>>,
+}
+
+impl<T> Clone for SharedLatest<T> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl<T> SharedLatest<T> {
+    /// Returns the latest value, or `None` if nothing has been received yet.
+    pub fn get(&self) -> Option<Arc<T>> {
+        self.value.lock().unwrap().clone()
+    }
+}
+
+/// Types that can be serialized to bytes without knowing the type at compile time, e.g. by a
+/// recorder, see [`PubSub::register_encodable`].
+pub trait Encode {
+    fn encode(&self) -> Vec<u8>;
+}
+
+/// Functions for working with the values of a registered type through `&dyn Any`, used by
+/// generic tools that can not know the type of a topic at compile time.
+#[derive(Clone, Copy)]
+pub struct TypeVtable {
+    pub name: &'static str,
+    pub debug_fmt: fn(&dyn Any) -> String,
+    /// `None` unless registered with [`PubSub::register_encodable`]
+    pub encode: Option<fn(&dyn Any) -> Vec<u8>>,
+}
+
+impl TypeVtable {
+    fn new<T: Debug + Any>() -> Self {
+        Self {
+            name: type_name::<T>(),
+            debug_fmt: debug_fmt_any::<T>,
+            encode: None,
+        }
+    }
+}
+
+fn debug_fmt_any<T: Debug + Any>(value: &dyn Any) -> String {
+    format!("{:?}", downcast_any::<T>(value))
+}
+
+fn encode_any<T: Encode + Any>(value: &dyn Any) -> Vec<u8> {
+    downcast_any::<T>(value).encode()
+}
+
+fn downcast_any<T: Any>(value: &dyn Any) -> &T {
+    value.downcast_ref::<T>().unwrap_or_else(|| {
+        panic!(
+            "Value passed to the vtable of '{}' has a different type",
+            type_name::<T>()
+        )
+    })
+}
+
+/// A subscription that receives the values of a topic without knowing their type, created with
+/// [`PubSub::subscribe_any`].
+pub struct AnySubscription {
+    topic: String,
+    reciever: Receiver<Arc<dyn Any + Send + Sync + 'static>>,
+    vtable: Option<TypeVtable>,
+}
+
+impl AnySubscription {
+    /// Tries to receive a value from the subscribed topic, but will not block if no data is available.
+    pub fn try_recv(&mut self) -> Option<Arc<dyn Any + Send + Sync + 'static>> {
+        self.reciever.try_recv().ok()
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// The vtable of the topic's type, `None` if the type has not been registered (yet).
+    pub fn vtable(&self) -> Option<&TypeVtable> {
+        self.vtable.as_ref()
+    }
 }
 
 #[derive(Clone)]
@@ -110,6 +363,8 @@ impl PubSub {
             topics: HashMap::new(),
             signal: receive,
             signal_source: send,
+            mirrors: Vec::new(),
+            types: HashMap::new(),
         }
     }
 
@@ -142,6 +397,56 @@ impl PubSub {
         }
     }
 
+    /// Like [`publish`](Self::publish), but also [`register`](Self::register)s the type so that
+    /// the topic can be inspected at runtime.
+    pub fn publish_registered<T: Debug + Any + Send + Sync + 'static>(
+        &mut self,
+        topic: &str,
+    ) -> Publisher<T> {
+        self.register::<T>();
+        self.publish(topic)
+    }
+
+    /// Registers the type for dynamic access, giving tools that receive the values through
+    /// [`subscribe_any`](Self::subscribe_any) a way to display them.
+    pub fn register<T: Debug + Any + Send + Sync + 'static>(&mut self) {
+        self.types
+            .entry(TypeId::of::<T>())
+            .or_insert_with(TypeVtable::new::<T>);
+    }
+
+    /// Like [`register`](Self::register), but the values can also be serialized.
+    pub fn register_encodable<T: Debug + Encode + Any + Send + Sync + 'static>(&mut self) {
+        self.types
+            .entry(TypeId::of::<T>())
+            .or_insert_with(TypeVtable::new::<T>)
+            .encode = Some(encode_any::<T>);
+    }
+
+    /// Returns the vtable of the type of the topic, if the topic is in use and its type has been
+    /// registered.
+    pub fn vtable(&self, topic: &str) -> Option<&TypeVtable> {
+        self.topics
+            .get(topic)
+            .and_then(|t| self.types.get(&t.value_type))
+    }
+
+    /// Subscribe to the values of a topic without knowing their type. Returns `None` if nobody
+    /// has used the topic yet, since it has no type then.
+    pub fn subscribe_any(&mut self, topic: &str) -> Option<AnySubscription> {
+        let vtable = self.vtable(topic).copied();
+        let t = self.topics.get_mut(topic)?;
+
+        let (send, recv) = channel();
+        t.outgoing.push(send);
+
+        Some(AnySubscription {
+            topic: topic.to_owned(),
+            reciever: recv,
+            vtable,
+        })
+    }
+
     /// Subscribe to messages of the specific type on the topic name. Panics if the topic has already been allocated to values of a different type.
     pub fn subscribe<T: Any + Send + Sync + 'static>(&mut self, topic: &str) -> Subscription<T> {
         let t = self.get_topic_by_name_or_insert::<T>(topic);
@@ -158,6 +463,36 @@ impl PubSub {
         }
     }
 
+    /// Returns a cell that always holds the latest value published to the topic, for nodes that
+    /// only need to poll the current value. Panics if the topic has already been allocated to
+    /// values of a different type.
+    pub fn latest_cell<T: Any + Send + Sync + 'static>(&mut self, topic: &str) -> SharedLatest<T> {
+        let mut subscription = self.subscribe::<T>(topic);
+        let cell = SharedLatest {
+            value: Arc::new(Mutex::new(None)),
+        };
+
+        let value = cell.value.clone();
+        self.mirrors.push(Box::new(move || {
+            // only keep the last of the values distributed this tick
+            if let Some(latest) = std::iter::from_fn(|| subscription.try_recv()).last() {
+                *value.lock().unwrap() = Some(latest);
+            }
+        }));
+
+        cell
+    }
+
+    /// Returns the names of all topics that have been used so far, in no particular order.
+    pub fn topics(&self) -> impl Iterator<Item = &str> {
+        self.topics.keys().map(String::as_str)
+    }
+
+    /// Returns the name of the type the topic has been allocated to, if anyone has used it yet.
+    pub fn topic_type(&self, topic: &str) -> Option<&'static str> {
+        self.topics.get(topic).map(|t| t.value_name)
+    }
+
     /// Proceses and distributes messages to all subscribers.
     pub fn tick(&mut self) {
         for (_topic, t) in self.topics.iter_mut() {
@@ -171,6 +506,10 @@ impl PubSub {
             // empty all signals as well
         }
         while self.signal.try_recv().is_ok() {}
+
+        for mirror in self.mirrors.iter_mut() {
+            mirror();
+        }
     }
 
     /// Creates a ticker that calls tick() continously when updated.
@@ -179,6 +518,12 @@ impl PubSub {
     pub fn to_ticker(self, waker: impl FnMut() + Send + 'static) -> ticker::PubSubTicker {
         ticker::PubSubTicker::new(self, waker)
     }
+
+    /// Creates a ticker that only processes messages when its tick() method is called, on all
+    /// platforms. Gives the same single threaded behavior on desktop as on wasm32.
+    pub fn into_manual(self) -> ticker::PubSubTicker {
+        ticker::PubSubTicker::manual(self)
+    }
 }
 
 impl Default for PubSub {
@@ -193,47 +538,144 @@ pub mod ticker {
 
     pub struct PubSubTicker {
         pubsub: PubSub,
+        paused: bool,
     }
 
     impl PubSubTicker {
         pub fn new(pubsub: PubSub, _waker: impl FnMut() + Send + 'static) -> Self {
             // waker is intentionally unused since we will not "wake up" to do
             // repaint on publish either way
-            Self { pubsub }
+            Self {
+                pubsub,
+                paused: false,
+            }
+        }
+
+        pub fn manual(pubsub: PubSub) -> Self {
+            // always ticked manually on wasm
+            Self::new(pubsub, || {})
+        }
+
+        pub fn is_manual(&self) -> bool {
+            true
         }
 
         pub fn tick(&mut self) {
+            if !self.paused {
+                self.pubsub.tick()
+            }
+        }
+
+        /// Stops distributing messages until [`resume`](Self::resume) is called. Published
+        /// messages are queued up in the meantime.
+        pub fn pause(&mut self) {
+            self.paused = true;
+        }
+
+        pub fn resume(&mut self) {
+            self.paused = false;
+        }
+
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Distributes the queued messages once, regardless of being paused or not.
+        pub fn step(&mut self) {
             self.pubsub.tick()
         }
+
+        /// Gives access to the running [`PubSub`], e.g. to create nodes while it is running.
+        pub fn with_pubsub<R>(&mut self, f: impl FnOnce(&mut PubSub) -> R) -> R {
+            f(&mut self.pubsub)
+        }
+
         pub fn stop(self) {}
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod ticker {
-    use crate::PubSub;
+    use crate::{PubSub, Signal};
     use std::sync::atomic::{AtomicBool, Ordering};
-    use std::sync::Arc;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::{Arc, Mutex};
     use std::thread::{self, JoinHandle};
     use std::time::Duration;
 
     pub struct PubSubTicker {
-        thread_handle: PubSubThreadHandle,
+        pubsub: Arc<Mutex<PubSub>>,
+        paused: Arc<AtomicBool>,
+        /// `None` when ticked manually
+        thread_handle: Option<PubSubThreadHandle>,
     }
 
     impl PubSubTicker {
         pub fn new(pubsub: PubSub, waker: impl FnMut() + Send + 'static) -> Self {
+            let pubsub = Arc::new(Mutex::new(pubsub));
+            let paused = Arc::new(AtomicBool::new(false));
             Self {
-                thread_handle: PubSubThreadHandle::new(pubsub, waker),
+                thread_handle: Some(PubSubThreadHandle::new(
+                    pubsub.clone(),
+                    paused.clone(),
+                    waker,
+                )),
+                pubsub,
+                paused,
             }
         }
 
+        pub fn manual(pubsub: PubSub) -> Self {
+            Self {
+                pubsub: Arc::new(Mutex::new(pubsub)),
+                paused: Arc::new(AtomicBool::new(false)),
+                thread_handle: None,
+            }
+        }
+
+        /// True if messages are only distributed when calling [`tick`](Self::tick).
+        pub fn is_manual(&self) -> bool {
+            self.thread_handle.is_none()
+        }
+
         pub fn tick(&mut self) {
-            // do nothing on desktop
+            // the background thread does the work unless ticked manually
+            if self.is_manual() && !self.is_paused() {
+                self.step();
+            }
+        }
+
+        /// Stops distributing messages until [`resume`](Self::resume) is called. Published
+        /// messages are queued up in the meantime.
+        pub fn pause(&mut self) {
+            self.paused.store(true, Ordering::Relaxed);
+        }
+
+        pub fn resume(&mut self) {
+            self.paused.store(false, Ordering::Relaxed);
+
+            // the background thread only wakes up on new messages, so deliver the queued ones
+            self.step();
+        }
+
+        pub fn is_paused(&self) -> bool {
+            self.paused.load(Ordering::Relaxed)
+        }
+
+        /// Distributes the queued messages once, regardless of being paused or not.
+        pub fn step(&mut self) {
+            self.pubsub.lock().unwrap().tick();
+        }
+
+        /// Gives access to the running [`PubSub`], e.g. to create nodes while it is running.
+        pub fn with_pubsub<R>(&mut self, f: impl FnOnce(&mut PubSub) -> R) -> R {
+            f(&mut self.pubsub.lock().unwrap())
         }
 
         pub fn stop(self) {
-            self.thread_handle.stop();
+            if let Some(thread_handle) = self.thread_handle {
+                thread_handle.stop();
+            }
         }
     }
 
@@ -243,12 +685,19 @@ pub mod ticker {
     }
 
     impl PubSubThreadHandle {
-        fn new(pubsub: PubSub, waker: impl FnMut() + Send + 'static) -> Self {
+        fn new(
+            pubsub: Arc<Mutex<PubSub>>,
+            paused: Arc<AtomicBool>,
+            waker: impl FnMut() + Send + 'static,
+        ) -> Self {
             let running = Arc::new(AtomicBool::new(true));
 
+            // take the signal out of the pubsub so we do not hold the lock while waiting on it
+            let signal = std::mem::replace(&mut pubsub.lock().unwrap().signal, channel().1);
+
             let handle = thread::spawn({
                 let running = running.clone();
-                move || Self::tick_thread(pubsub, running, waker)
+                move || Self::tick_thread(pubsub, signal, running, paused, waker)
             });
 
             Self { handle, running }
@@ -260,15 +709,17 @@ pub mod ticker {
         }
 
         fn tick_thread(
-            mut pubsub: PubSub,
+            pubsub: Arc<Mutex<PubSub>>,
+            signal: Receiver<Signal>,
             running: Arc<AtomicBool>,
+            paused: Arc<AtomicBool>,
             mut waker: impl FnMut() + Send + 'static,
         ) -> anyhow::Result<()> {
             'outer: loop {
                 // block on the signal
 
                 loop {
-                    let result = pubsub.signal.recv_timeout(Duration::from_millis(500));
+                    let result = signal.recv_timeout(Duration::from_millis(500));
                     if !running.load(Ordering::Relaxed) {
                         println!("Stopping Tick Thread");
                         break 'outer;
@@ -281,8 +732,18 @@ pub mod ticker {
                     };
                 }
 
+                // leave the messages queued up until resumed or stepped
+                if paused.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                // the tick handles all messages published so far, so the signals queued up for
+                // them can be dropped. Signals arriving from here on are for messages the tick
+                // might miss and lead to another tick.
+                while signal.try_recv().is_ok() {}
+
                 // process messages
-                pubsub.tick();
+                pubsub.lock().unwrap().tick();
 
                 // call the waker to notify anyone listening about the newly available messages
                 waker();
@@ -293,6 +754,210 @@ pub mod ticker {
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latest_cell_follows_topic() {
+        let mut pubsub = PubSub::new();
+        let mut publisher = pubsub.publish::<u32>("numbers");
+        let cell = pubsub.latest_cell::<u32>("numbers");
+        assert!(cell.get().is_none());
+
+        publisher.publish(Arc::new(1));
+        publisher.publish(Arc::new(2));
+
+        // nothing is received until the next tick
+        assert!(cell.get().is_none());
+        pubsub.tick();
+        assert_eq!(*cell.get().unwrap(), 2);
+
+        // the value is kept when nothing new is published
+        pubsub.tick();
+        assert_eq!(*cell.clone().get().unwrap(), 2);
+    }
+
+    #[test]
+    fn map_transforms_values() {
+        let mut pubsub = PubSub::new();
+        let mut publisher = pubsub.publish::<u32>("numbers");
+        let mut sub = pubsub
+            .subscribe::<u32>("numbers")
+            .map(|v| Arc::new(format!("#{v}")));
+
+        assert_eq!(sub.topic(), "numbers");
+        assert!(sub.try_recv().is_none());
+
+        publisher.publish(Arc::new(1));
+        publisher.publish(Arc::new(2));
+        pubsub.tick();
+
+        assert_eq!(*sub.try_recv().unwrap(), "#1");
+        assert_eq!(*sub.recv(), "#2");
+        assert!(sub.try_recv().is_none());
+    }
+
+    #[test]
+    fn filter_drops_values() {
+        let mut pubsub = PubSub::new();
+        let mut publisher = pubsub.publish::<u32>("numbers");
+        let mut even = pubsub.subscribe::<u32>("numbers").filter(|v| v % 2 == 0);
+        let mut all = pubsub.subscribe::<u32>("numbers");
+
+        for v in 1..=5 {
+            publisher.publish(Arc::new(v));
+        }
+        pubsub.tick();
+
+        assert_eq!(*even.try_recv().unwrap(), 2);
+        assert_eq!(*even.recv(), 4);
+        assert!(even.try_recv().is_none());
+
+        // other subscribers still get everything
+        assert_eq!((0..5).map(|_| *all.recv()).sum::<u32>(), 15);
+    }
+
+    #[test]
+    fn combinators_chain() {
+        let mut pubsub = PubSub::new();
+        let mut publisher = pubsub.publish::<u32>("numbers");
+        let mut sub = pubsub
+            .subscribe::<u32>("numbers")
+            .filter(|v| *v > 1)
+            .map(|v| Arc::new(*v * 10))
+            .filter(|v| *v != 30);
+
+        for v in 1..=4 {
+            publisher.publish(Arc::new(v));
+        }
+        pubsub.tick();
+
+        assert_eq!(*sub.try_recv().unwrap(), 20);
+        assert_eq!(*sub.try_recv().unwrap(), 40);
+        assert!(sub.try_recv().is_none());
+    }
+
+    #[test]
+    fn delivery_is_in_publish_order() {
+        let mut pubsub = PubSub::new();
+        let mut first = pubsub.publish::<u32>("numbers");
+        let mut second = pubsub.publish::<u32>("numbers");
+        let mut early = pubsub.subscribe::<u32>("numbers");
+
+        first.publish(Arc::new(0));
+        second.publish(Arc::new(1));
+        pubsub.tick();
+
+        // published before subscribing, but not yet distributed
+        first.publish(Arc::new(2));
+        let mut late = pubsub.subscribe::<u32>("numbers");
+        second.publish(Arc::new(3));
+        pubsub.tick();
+
+        for v in 4..10 {
+            if v % 2 == 0 {
+                first.publish(Arc::new(v));
+            } else {
+                second.publish(Arc::new(v));
+            }
+            pubsub.tick();
+        }
+
+        let received = |sub: &mut Subscription<u32>| {
+            std::iter::from_fn(|| sub.try_recv().map(|v| *v)).collect::<Vec<_>>()
+        };
+        assert_eq!(received(&mut early), (0..10).collect::<Vec<_>>());
+        assert_eq!(received(&mut late), (2..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn topic_type_of_used_topics() {
+        let mut pubsub = PubSub::new();
+        assert_eq!(pubsub.topic_type("numbers"), None);
+
+        let _sub = pubsub.subscribe::<u32>("numbers");
+        assert_eq!(pubsub.topic_type("numbers"), Some(type_name::<u32>()));
+        assert_eq!(pubsub.topics().collect::<Vec<_>>(), vec!["numbers"]);
+    }
+
+    #[derive(Debug)]
+    struct Bytes(Vec<u8>);
+
+    impl Encode for Bytes {
+        fn encode(&self) -> Vec<u8> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn registered_types_are_inspectable() {
+        let mut pubsub = PubSub::new();
+        assert!(pubsub.subscribe_any("numbers").is_none());
+
+        let mut numbers = pubsub.publish_registered::<u32>("numbers");
+        let mut sub = pubsub.subscribe_any("numbers").unwrap();
+        let vtable = *sub.vtable().unwrap();
+        assert_eq!(vtable.name, type_name::<u32>());
+        assert!(vtable.encode.is_none());
+
+        numbers.publish(Arc::new(5));
+        pubsub.tick();
+        assert_eq!((vtable.debug_fmt)(sub.try_recv().unwrap().as_ref()), "5");
+
+        pubsub.register_encodable::<Bytes>();
+        let mut bytes = pubsub.publish::<Bytes>("bytes");
+        let mut sub = pubsub.subscribe_any("bytes").unwrap();
+        let encode = sub.vtable().unwrap().encode.unwrap();
+
+        bytes.publish(Arc::new(Bytes(vec![1, 2])));
+        pubsub.tick();
+        assert_eq!(encode(sub.try_recv().unwrap().as_ref()), vec![1, 2]);
+
+        // unregistered types can still be received, but not displayed
+        let _other = pubsub.publish::<i64>("other");
+        assert!(pubsub.subscribe_any("other").unwrap().vtable().is_none());
+    }
+
+    #[test]
+    fn paused_ticker_only_steps() {
+        let mut pubsub = PubSub::new();
+        let mut publisher = pubsub.publish::<u32>("numbers");
+        let mut sub = pubsub.subscribe::<u32>("numbers");
+        let mut ticker = pubsub.to_ticker(|| {});
+
+        ticker.pause();
+        assert!(ticker.is_paused());
+        publisher.publish(Arc::new(1));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(sub.try_recv().is_none());
+
+        ticker.step();
+        assert_eq!(*sub.try_recv().unwrap(), 1);
+
+        ticker.resume();
+        publisher.publish(Arc::new(2));
+        assert_eq!(*sub.recv(), 2);
+
+        ticker.stop();
+    }
+
+    #[test]
+    fn manual_ticker_waits_for_tick() {
+        let mut pubsub = PubSub::new();
+        let mut publisher = pubsub.publish::<u32>("numbers");
+        let mut sub = pubsub.subscribe::<u32>("numbers");
+        let mut ticker = pubsub.into_manual();
+        assert!(ticker.is_manual());
+
+        publisher.publish(Arc::new(1));
+        assert!(sub.try_recv().is_none());
+
+        ticker.tick();
+        assert_eq!(*sub.try_recv().unwrap(), 1);
+    }
+}
+
 // #[derive(Debug)]
 // struct Data {
 //     d: Vec<u32>,
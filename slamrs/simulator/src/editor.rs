@@ -0,0 +1,257 @@
+use std::fmt::Write as _;
+
+use eframe::egui;
+use egui::RichText;
+use graphics::{
+    primitiverenderer::{Color, PrimitiveType},
+    shaperenderer::ShapeRenderer,
+};
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    scene::{
+        landmark::Landmark,
+        ray::{LineSegment, Scene},
+    },
+    SceneObject,
+};
+
+/// What clicking in the world places while editing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EditTool {
+    /// Two clicks, one for each endpoint.
+    Line,
+    /// Two clicks, one for each of two opposite corners.
+    Rectangle,
+    Landmark,
+}
+
+/// Builds the scene of the simulator by clicking in the world. Keeps the objects of the scene in
+/// the same form as the config, so that they can be exported as config text.
+pub struct SceneEditor {
+    pub enabled: bool,
+    tool: EditTool,
+    /// The first point of the line or rectangle being placed.
+    first: Option<Point2<f32>>,
+    /// In the order they were placed.
+    objects: Vec<SceneObject>,
+    /// The exported config text, shown until the scene changes.
+    export: Option<String>,
+}
+
+impl SceneEditor {
+    pub fn new(objects: Vec<SceneObject>) -> Self {
+        Self {
+            enabled: false,
+            tool: EditTool::Line,
+            first: None,
+            objects,
+            export: None,
+        }
+    }
+
+    /// Creates the scene for the simulator from the current objects.
+    pub fn build_scene(&self) -> Scene {
+        let mut scene = Scene::new();
+
+        for o in &self.objects {
+            match *o {
+                SceneObject::Line { x1, y1, x2, y2 } => {
+                    scene.add(Box::new(LineSegment::new(x1, y1, x2, y2)));
+                }
+                SceneObject::Rectangle {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => {
+                    scene.add_rect(Point2::new(x, y), Vector2::new(width, height));
+                }
+                SceneObject::Landmark { x, y } => {
+                    scene.add_landmarks(&[Landmark { x, y }]);
+                }
+            }
+        }
+
+        scene
+    }
+
+    /// Handles a click in the world at `pos`. Returns true if the scene changed.
+    pub fn click(&mut self, pos: Point2<f32>) -> bool {
+        let object = match (self.tool, self.first.take()) {
+            (EditTool::Landmark, _) => SceneObject::Landmark { x: pos.x, y: pos.y },
+            (_, None) => {
+                self.first = Some(pos);
+                return false;
+            }
+            (EditTool::Line, Some(p1)) => SceneObject::Line {
+                x1: p1.x,
+                y1: p1.y,
+                x2: pos.x,
+                y2: pos.y,
+            },
+            (EditTool::Rectangle, Some(p1)) => SceneObject::Rectangle {
+                x: p1.x.min(pos.x),
+                y: p1.y.min(pos.y),
+                width: (pos.x - p1.x).abs(),
+                height: (pos.y - p1.y).abs(),
+            },
+        };
+
+        self.objects.push(object);
+        self.export = None;
+        true
+    }
+
+    /// Removes the most recently placed object. Returns true if the scene changed.
+    pub fn delete_last(&mut self) -> bool {
+        self.export = None;
+        self.objects.pop().is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.objects.clear();
+        self.first = None;
+        self.export = None;
+    }
+
+    /// The `scene` of the simulator config.
+    pub fn export(&self) -> String {
+        let mut text = String::from("scene:\n");
+        for o in &self.objects {
+            let _ = match *o {
+                SceneObject::Line { x1, y1, x2, y2 } => writeln!(
+                    text,
+                    "- !Line {{x1: {x1:.3}, y1: {y1:.3}, x2: {x2:.3}, y2: {y2:.3}}}"
+                ),
+                SceneObject::Rectangle {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => writeln!(
+                    text,
+                    "- !Rectangle {{x: {x:.3}, y: {y:.3}, width: {width:.3}, height: {height:.3}}}"
+                ),
+                SceneObject::Landmark { x, y } => {
+                    writeln!(text, "- !Landmark {{x: {x:.3}, y: {y:.3}}}")
+                }
+            };
+        }
+
+        text
+    }
+
+    /// Draws the line or rectangle being placed, from its first point to the cursor.
+    pub fn draw_pending(&self, sr: &mut ShapeRenderer, cursor: Point2<f32>) {
+        let Some(first) = self.first else {
+            return;
+        };
+
+        sr.begin(PrimitiveType::Line);
+        match self.tool {
+            EditTool::Line => sr.line(first.x, first.y, cursor.x, cursor.y, Color::RED),
+            EditTool::Rectangle => sr.rect(
+                first.x.min(cursor.x),
+                first.y.min(cursor.y),
+                (cursor.x - first.x).abs(),
+                (cursor.y - first.y).abs(),
+                Color::RED,
+            ),
+            EditTool::Landmark => {}
+        }
+        sr.end();
+    }
+
+    /// Returns true if the scene changed.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.checkbox(&mut self.enabled, "Edit Scene");
+        if !self.enabled {
+            self.first = None;
+            return false;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Place: ");
+            for (tool, name) in [
+                (EditTool::Line, "Line"),
+                (EditTool::Rectangle, "Rectangle"),
+                (EditTool::Landmark, "Landmark"),
+            ] {
+                if ui.selectable_label(self.tool == tool, name).clicked() {
+                    self.tool = tool;
+                    self.first = None;
+                }
+            }
+        });
+
+        ui.label(match (self.tool, self.first) {
+            (EditTool::Landmark, _) => "Click in the world to place a landmark.",
+            (EditTool::Line, None) => "Click to place the start of the line.",
+            (EditTool::Line, Some(_)) => "Click to place the end of the line.",
+            (EditTool::Rectangle, None) => "Click to place a corner of the rectangle.",
+            (EditTool::Rectangle, Some(_)) => "Click to place the opposite corner.",
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Delete Last").clicked() {
+                changed |= self.delete_last();
+            }
+            if ui.button("Clear").clicked() {
+                self.clear();
+                changed = true;
+            }
+            if ui.button("Export Scene").clicked() {
+                self.export = Some(self.export());
+            }
+        });
+
+        if let Some(export) = &self.export {
+            ui.label(RichText::new(export).monospace());
+            if ui.button("Copy").clicked() {
+                ui.ctx().copy_text(export.clone());
+            }
+        }
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn place_and_delete_objects() {
+        let mut editor = SceneEditor::new(vec![SceneObject::Line {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 0.0,
+        }]);
+
+        editor.tool = EditTool::Rectangle;
+        assert!(!editor.click(Point2::new(1.0, 2.0)));
+        assert!(editor.click(Point2::new(0.5, 1.0)));
+        editor.tool = EditTool::Landmark;
+        assert!(editor.click(Point2::new(-1.0, 0.25)));
+
+        assert_eq!(
+            editor.export(),
+            "scene:\n\
+             - !Line {x1: 0.000, y1: 0.000, x2: 1.000, y2: 0.000}\n\
+             - !Rectangle {x: 0.500, y: 1.000, width: 0.500, height: 1.000}\n\
+             - !Landmark {x: -1.000, y: 0.250}\n"
+        );
+
+        assert!(editor.delete_last());
+        assert!(editor.delete_last());
+        assert_eq!(editor.objects.len(), 1);
+
+        editor.clear();
+        assert!(!editor.delete_last());
+        assert_eq!(editor.export(), "scene:\n");
+    }
+}
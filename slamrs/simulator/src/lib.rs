@@ -1,45 +1,57 @@
-use common::node::{Node, NodeConfig};
-use eframe::egui;
-use egui::{
-    mutex::{Mutex, RwLock},
-    Slider,
+use common::{
+    node::{ConfigUi, Node, NodeConfig, TopicDecl},
+    robot::{BumpEvent, Command, LandmarkObservations, Observation, Odometry, Pose},
+    world::DrawMode,
 };
+use eframe::egui;
+use egui::mutex::{Mutex, RwLock};
 
+use editor::SceneEditor;
 use graphics::primitiverenderer::{Color, PrimitiveType};
-use nalgebra::{Point2, Vector2};
 use simulator_loop::SimulatorLoop;
 use std::sync::Arc;
 
 use scene::{
     landmark::Landmark,
-    ray::{Draw, LineSegment, Scene},
+    ray::{Draw, Scene},
 };
 use serde::Deserialize;
-use sim::{SimParameters, Simulator};
+use sim::Simulator;
 
+mod editor;
 mod scene;
 mod sim;
+
+pub use sim::{SensorModel, SimParameters, SimParametersBuilder};
 pub struct SimulatorNode {
     scene: Arc<RwLock<Scene>>,
     simulator: Arc<Mutex<Simulator>>,
     simulator_loop: simulator_loop::SimulatorLoop,
     running: bool,
+    paused: bool,
     draw_scene: bool,
     draw_pose: bool,
+    scene_draw_mode: DrawMode,
+    /// Paused from the UI, independent of the global pause.
+    stepping: bool,
+    editor: SceneEditor,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct SimulatorNodeConfig {
     topic_observation_scanner: Option<String>,
     topic_observation_landmarks: Option<String>,
     topic_pose: Option<String>,
+    topic_collision: Option<String>,
+    topic_bump: Option<String>,
     topic_command: String,
     running: bool,
 
     #[serde(default)]
     scene: Vec<SceneObject>,
 
+    /// Landmarks can also be placed in the `scene`, this list is kept for older configs.
     #[serde(default)]
     landmarks: Vec<Landmark>,
 
@@ -47,6 +59,9 @@ pub struct SimulatorNodeConfig {
     draw_scene: bool,
     #[serde(default = "_default_true")]
     draw_pose: bool,
+    /// The scene is drawn as outlines unless set to `Filled`.
+    #[serde(default = "_default_scene_draw_mode")]
+    scene_draw_mode: DrawMode,
 
     parameters: SimParameters,
 }
@@ -55,7 +70,11 @@ const fn _default_true() -> bool {
     true
 }
 
-#[derive(Clone, Deserialize)]
+const fn _default_scene_draw_mode() -> DrawMode {
+    DrawMode::Wireframe
+}
+
+#[derive(Clone, Deserialize, PartialEq)]
 enum SceneObject {
     Line {
         x1: f32,
@@ -69,39 +88,44 @@ enum SceneObject {
         width: f32,
         height: f32,
     },
+    /// A point landmark, seen by the landmark sensor but not by the LIDAR.
+    Landmark {
+        x: f32,
+        y: f32,
+    },
 }
 
 impl NodeConfig for SimulatorNodeConfig {
     fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
-        let mut scene = Scene::new();
-
-        scene.add_landmarks(&self.landmarks);
-
-        for o in &self.scene {
-            match *o {
-                SceneObject::Line { x1, y1, x2, y2 } => {
-                    scene.add(Box::new(LineSegment::new(x1, y1, x2, y2)));
-                }
-                SceneObject::Rectangle {
-                    x,
-                    y,
-                    width,
-                    height,
-                } => {
-                    scene.add_rect(Point2::new(x, y), Vector2::new(width, height));
-                }
-            }
-        }
-
-        let scene = Arc::new(RwLock::new(scene));
+        let objects = self
+            .scene
+            .iter()
+            .cloned()
+            .chain(
+                self.landmarks
+                    .iter()
+                    .map(|&Landmark { x, y }| SceneObject::Landmark { x, y }),
+            )
+            .collect();
+        let editor = SceneEditor::new(objects);
+
+        let scene = Arc::new(RwLock::new(editor.build_scene()));
         let simulator = Arc::new(Mutex::new(Simulator::new(
             self.topic_observation_scanner
                 .as_ref()
-                .map(|topic| pubsub.publish(topic)),
+                .map(|topic| pubsub.publish_registered(topic)),
             self.topic_observation_landmarks
                 .as_ref()
-                .map(|topic| pubsub.publish(topic)),
-            self.topic_pose.as_ref().map(|topic| pubsub.publish(topic)),
+                .map(|topic| pubsub.publish_registered(topic)),
+            self.topic_pose
+                .as_ref()
+                .map(|topic| pubsub.publish_registered(topic)),
+            self.topic_collision
+                .as_ref()
+                .map(|topic| pubsub.publish_registered(topic)),
+            self.topic_bump
+                .as_ref()
+                .map(|topic| pubsub.publish_registered(topic)),
             pubsub.subscribe(&self.topic_command),
             scene.clone(),
             self.parameters,
@@ -110,37 +134,83 @@ impl NodeConfig for SimulatorNodeConfig {
         Box::new(SimulatorNode {
             scene,
             running: self.running,
+            paused: false,
             simulator: simulator.clone(),
             simulator_loop: SimulatorLoop::new(simulator),
             draw_scene: self.draw_scene,
             draw_pose: self.draw_pose,
+            scene_draw_mode: self.scene_draw_mode,
+            stepping: false,
+            editor,
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = vec![TopicDecl::subscribe::<Command>(&self.topic_command)];
+
+        if let Some(topic) = &self.topic_observation_scanner {
+            topics.push(TopicDecl::publish::<(Observation, Odometry)>(topic));
+        }
+        if let Some(topic) = &self.topic_observation_landmarks {
+            topics.push(TopicDecl::publish::<(LandmarkObservations, Odometry)>(
+                topic,
+            ));
+        }
+        if let Some(topic) = &self.topic_pose {
+            topics.push(TopicDecl::publish::<Pose>(topic));
+        }
+        if let Some(topic) = &self.topic_collision {
+            topics.push(TopicDecl::publish::<bool>(topic));
+        }
+        if let Some(topic) = &self.topic_bump {
+            topics.push(TopicDecl::publish::<BumpEvent>(topic));
+        }
+
+        topics
+    }
 }
 
 impl Node for SimulatorNode {
     fn draw(&mut self, ui: &egui::Ui, world: &mut common::world::WorldObj<'_>) {
         self.simulator_loop.tick(self.running);
+        self.simulator_loop.set_paused(self.paused || self.stepping);
 
         egui::Window::new("Simulator").show(ui.ctx(), |ui| {
             ui.label("Used to simulate different LIDAR sensors and environment shapes.");
 
             ui.checkbox(&mut self.running, "Running");
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.stepping, "Paused");
+                if ui
+                    .add_enabled(self.running && self.stepping, egui::Button::new("Step"))
+                    .clicked()
+                {
+                    self.simulator_loop.step();
+                }
+            });
 
             ui.checkbox(&mut self.draw_scene, "Draw Scene");
             ui.checkbox(&mut self.draw_pose, "Draw Pose");
+            self.scene_draw_mode.ui(ui);
+
+            // lock the scene to make UI controls for the parameters
+            self.simulator.lock().parameters_mut().ui(ui);
 
-            // lock the scene to make UI controls for some of the parameters
-            {
-                let mut simulator = self.simulator.lock();
-                let params = simulator.parameters_mut();
-                ui.add(Slider::new(&mut params.wheel_base, 0.05..=0.4).text("Wheel Base (m)"));
-                ui.add(Slider::new(&mut params.update_period, 0.1..=2.0).text("Update Period (s)"));
-                ui.add(Slider::new(&mut params.scanner_range, 0.1..=10.0).text("Scanner Range(m)"));
+            ui.separator();
+            if self.editor.ui(ui) {
+                *self.scene.write() = self.editor.build_scene();
             }
         });
+
+        if self.editor.enabled {
+            if world.clicked && self.editor.click(world.last_mouse_pos) {
+                *self.scene.write() = self.editor.build_scene();
+            }
+            self.editor.draw_pending(world.sr, world.last_mouse_pos);
+        }
+
         if self.draw_scene {
-            world.sr.begin(PrimitiveType::Line);
+            world.sr.begin(self.scene_draw_mode.primitive_type());
             self.scene.read().draw(world.sr, Color::BLACK);
             world.sr.end();
         }
@@ -153,6 +223,10 @@ impl Node for SimulatorNode {
         }
     }
 
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
     fn terminate(&mut self) {
         self.simulator_loop.tick(false);
     }
@@ -169,12 +243,13 @@ mod simulator_loop {
     use eframe::egui::mutex::{Mutex, MutexGuard};
     use web_time::Instant;
 
-    use crate::sim::Simulator;
+    use crate::sim::{Simulator, MIN_SPEED};
 
     pub struct SimulatorLoop {
         simulator: Arc<Mutex<Simulator>>,
         accumulator: f64,
         current_time: Instant,
+        paused: bool,
     }
 
     impl SimulatorLoop {
@@ -183,18 +258,40 @@ mod simulator_loop {
                 simulator,
                 accumulator: 0.0,
                 current_time: Instant::now(),
+                paused: false,
             }
         }
 
         pub fn tick(&mut self, running: bool) {
             if running {
-                let dt = 1.0 / 30.0;
+                let (dt, realtime, speed) = {
+                    let sim = self.simulator.lock();
+                    let params = sim.parameters();
+                    (
+                        params.timestep as f64,
+                        params.realtime,
+                        params.speed.max(MIN_SPEED) as f64,
+                    )
+                };
 
                 let new_time = Instant::now();
                 let frame_time = new_time - self.current_time;
                 self.current_time = new_time;
 
-                self.accumulator += frame_time.as_secs_f64();
+                // do not catch up on the time spent paused
+                if self.paused {
+                    self.accumulator = 0.0;
+                    return;
+                }
+
+                // without a thread of its own, "as fast as possible" means one step every frame
+                if !realtime {
+                    self.accumulator = 0.0;
+                    self.simulator.lock().tick(dt as f32);
+                    return;
+                }
+
+                self.accumulator += frame_time.as_secs_f64() * speed;
 
                 while self.accumulator >= dt {
                     self.simulator.lock().tick(dt as f32);
@@ -203,6 +300,18 @@ mod simulator_loop {
             }
         }
 
+        /// Stops advancing the simulation without stopping the loop.
+        pub fn set_paused(&mut self, paused: bool) {
+            self.paused = paused;
+        }
+
+        /// Advances the simulation by a single timestep, intended for use while paused.
+        pub fn step(&mut self) {
+            let mut sim = self.simulator.lock();
+            let dt = sim.parameters().timestep;
+            sim.tick(dt);
+        }
+
         pub fn lock(&mut self) -> MutexGuard<'_, Simulator> {
             self.simulator.lock()
         }
@@ -213,7 +322,7 @@ mod simulator_loop {
 mod simulator_loop {
     // On desktop targets we run the simulator in a separate background thread,
     // while the actual game loop is the same.
-    use crate::Simulator;
+    use crate::{sim::MIN_SPEED, Simulator};
     use eframe::egui::mutex::{Mutex, MutexGuard};
     use std::{
         sync::{
@@ -226,9 +335,14 @@ mod simulator_loop {
     use tracing::info;
     use web_time::Instant;
 
+    /// How often the simulator thread checks if it has been resumed.
+    const PAUSED_POLL_PERIOD: Duration = Duration::from_millis(50);
+
     pub struct SimulatorLoop {
         simulator: Arc<Mutex<Simulator>>,
         handle: Option<SimulatorThreadHandle>,
+        /// Shared with the thread, which keeps running but does not advance the simulation
+        paused: Arc<AtomicBool>,
     }
 
     impl SimulatorLoop {
@@ -236,11 +350,15 @@ mod simulator_loop {
             Self {
                 simulator,
                 handle: None,
+                paused: Arc::new(AtomicBool::new(false)),
             }
         }
         pub fn tick(&mut self, running: bool) {
             if running && self.handle.is_none() {
-                self.handle = Some(SimulatorThreadHandle::new(self.simulator.clone()))
+                self.handle = Some(SimulatorThreadHandle::new(
+                    self.simulator.clone(),
+                    self.paused.clone(),
+                ))
             }
 
             if !running {
@@ -249,6 +367,18 @@ mod simulator_loop {
                 }
             }
         }
+        /// Stops advancing the simulation without stopping the thread.
+        pub fn set_paused(&mut self, paused: bool) {
+            self.paused.store(paused, Ordering::Relaxed);
+        }
+
+        /// Advances the simulation by a single timestep, intended for use while paused.
+        pub fn step(&mut self) {
+            let mut sim = self.simulator.lock();
+            let dt = sim.parameters().timestep;
+            sim.tick(dt);
+        }
+
         pub fn lock(&mut self) -> MutexGuard<'_, Simulator> {
             self.simulator.lock()
         }
@@ -260,23 +390,21 @@ mod simulator_loop {
     }
 
     impl SimulatorThreadHandle {
-        pub fn new(sim: Arc<Mutex<Simulator>>) -> Self {
+        pub fn new(sim: Arc<Mutex<Simulator>>, paused: Arc<AtomicBool>) -> Self {
             let running = Arc::new(AtomicBool::new(true));
 
             let handle = thread::spawn({
                 let running = running.clone();
-                move || Self::thread(running, sim)
+                move || Self::thread(running, paused, sim)
             });
 
             SimulatorThreadHandle { handle, running }
         }
 
-        fn thread(running: Arc<AtomicBool>, sim: Arc<Mutex<Simulator>>) {
+        fn thread(running: Arc<AtomicBool>, paused: Arc<AtomicBool>, sim: Arc<Mutex<Simulator>>) {
             info!("Simulator Thread Started");
 
             // loop taken from : https://www.gafferongames.com/post/fix_your_timestep/
-            let dt = 1.0 / 30.0;
-
             let mut current_time = Instant::now();
             let mut accumulator = 0.0;
 
@@ -285,14 +413,36 @@ mod simulator_loop {
                 let frame_time = new_time - current_time;
                 current_time = new_time;
 
-                accumulator += frame_time.as_secs_f64();
+                // do not catch up on the time spent paused
+                if paused.load(Ordering::Relaxed) {
+                    accumulator = 0.0;
+                    thread::sleep(PAUSED_POLL_PERIOD);
+                    continue;
+                }
+
+                let mut sim = sim.lock();
+                let dt = sim.parameters().timestep as f64;
+                let speed = sim.parameters().speed.max(MIN_SPEED) as f64;
+
+                // advance by a fixed step without waiting for the wall clock, but let go of the
+                // lock and the core between steps so the UI can still get to the simulator
+                if !sim.parameters().realtime {
+                    accumulator = 0.0;
+                    sim.tick(dt as f32);
+                    drop(sim);
+                    thread::yield_now();
+                    continue;
+                }
+
+                accumulator += frame_time.as_secs_f64() * speed;
 
                 while accumulator >= dt {
-                    sim.lock().tick(dt as f32);
+                    sim.tick(dt as f32);
                     accumulator -= dt;
                 }
 
-                thread::sleep(Duration::from_secs_f64(dt));
+                drop(sim);
+                thread::sleep(Duration::from_secs_f64(dt / speed));
             }
 
             info!("Simulator Thread Ended");
@@ -2,7 +2,7 @@ use serde::Deserialize;
 
 use super::ray::Draw;
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
 pub struct Landmark {
     pub x: f32,
     pub y: f32,
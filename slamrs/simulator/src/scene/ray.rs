@@ -32,6 +32,22 @@ pub trait Intersect {
     /// intersection point can be described by `ray.origin + u*ray.direction`,
     /// or `None` if no intersection occurs.
     fn intersect(&self, ray: &Ray) -> Option<f32>;
+
+    /// Returns the first contact between the object and a circle of `radius` whose center moves
+    /// from `origin` along the unit vector `direction`, as the distance `u` moved before touching
+    /// together with the touched point on the object, or `None` if the circle never touches it.
+    /// A circle that already overlaps the object only collides if it moves further into it.
+    fn sweep_circle(
+        &self,
+        origin: Point2<f32>,
+        direction: Vector2<f32>,
+        radius: f32,
+    ) -> Option<(f32, Point2<f32>)>;
+}
+
+/// Picks the contact closest to the start of the sweep.
+fn first_contact(contacts: impl Iterator<Item = (f32, Point2<f32>)>) -> Option<(f32, Point2<f32>)> {
+    contacts.min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Less))
 }
 
 pub struct LineSegment {
@@ -50,6 +66,17 @@ impl LineSegment {
             p2: Point2::new(x2, y2),
         }
     }
+
+    /// The point on the segment closest to `p`.
+    pub(crate) fn closest_point(&self, p: Point2<f32>) -> Point2<f32> {
+        let along = self.p2 - self.p1;
+        let length2 = along.norm_squared();
+        if length2 == 0.0 {
+            return self.p1;
+        }
+        let t = ((p - self.p1).dot(&along) / length2).clamp(0.0, 1.0);
+        self.p1 + t * along
+    }
 }
 
 impl Intersect for LineSegment {
@@ -80,8 +107,113 @@ impl Intersect for LineSegment {
         }
         None
     }
+
+    fn sweep_circle(
+        &self,
+        origin: Point2<f32>,
+        direction: Vector2<f32>,
+        radius: f32,
+    ) -> Option<(f32, Point2<f32>)> {
+        // already touching (up to rounding): only block movement towards the segment
+        const TOUCHING: f32 = 1e-5;
+        let closest = self.closest_point(origin);
+        let offset = closest - origin;
+        if offset.norm() < radius + TOUCHING {
+            return (offset.dot(&direction) > 0.0).then_some((0.0, closest));
+        }
+
+        // the circle reaching the inside of the segment, i.e. the center reaching the line moved
+        // `radius` towards the circle
+        let along = self.p2 - self.p1;
+        let side = Vector2::new(-along.y, along.x).try_normalize(f32::EPSILON);
+        let side_contact = side.and_then(|normal| {
+            let distance = normal.dot(&(origin - self.p1));
+            let approach = -normal.dot(&direction) * distance.signum();
+            if approach <= 0.0 {
+                return None;
+            }
+            let u = (distance.abs() - radius) / approach;
+            if u < 0.0 {
+                return None;
+            }
+            let point = origin + u * direction - distance.signum() * radius * normal;
+            let t = (point - self.p1).dot(&along) / along.norm_squared();
+            (0.0..=1.0).contains(&t).then_some((u, point))
+        });
+
+        // the circle reaching one of the end points, i.e. a ray hitting a circle around the point
+        let end_contact = |p: Point2<f32>| {
+            let m = origin - p;
+            let b = m.dot(&direction);
+            let discriminant = b * b - (m.norm_squared() - radius * radius);
+            if discriminant < 0.0 {
+                return None;
+            }
+            let u = -b - discriminant.sqrt();
+            (u >= 0.0).then_some((u, p))
+        };
+
+        first_contact(
+            side_contact
+                .into_iter()
+                .chain(end_contact(self.p1))
+                .chain(end_contact(self.p2)),
+        )
+    }
+}
+
+/// An axis aligned rectangle. Kept as a whole, rather than as four separate line segments, so
+/// that it can also be drawn filled.
+pub struct Rectangle {
+    origin: Point2<f32>,
+    size: Vector2<f32>,
+    sides: [LineSegment; 4],
+}
+
+impl Rectangle {
+    pub fn new(origin: Point2<f32>, size: Vector2<f32>) -> Self {
+        let corners = [
+            origin,
+            origin + Vector2::new(size.x, 0.0),
+            origin + size,
+            origin + Vector2::new(0.0, size.y),
+        ];
+
+        Self {
+            origin,
+            size,
+            sides: std::array::from_fn(|i| LineSegment {
+                p1: corners[i],
+                p2: corners[(i + 1) % 4],
+            }),
+        }
+    }
+}
+
+impl Intersect for Rectangle {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        self.sides
+            .iter()
+            .filter_map(|s| s.intersect(ray))
+            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less))
+    }
+
+    fn sweep_circle(
+        &self,
+        origin: Point2<f32>,
+        direction: Vector2<f32>,
+        radius: f32,
+    ) -> Option<(f32, Point2<f32>)> {
+        first_contact(
+            self.sides
+                .iter()
+                .filter_map(|s| s.sweep_circle(origin, direction, radius)),
+        )
+    }
 }
 
+/// Shapes are drawn using the primitive type passed to `begin()`, i.e. as outlines for
+/// `PrimitiveType::Line` and filled for `PrimitiveType::Filled`. Lines are always lines.
 pub trait Draw {
     fn draw(&self, r: &mut ShapeRenderer, color: Color);
 }
@@ -92,10 +224,23 @@ impl Draw for LineSegment {
     }
 }
 
+impl Draw for Rectangle {
+    fn draw(&self, r: &mut ShapeRenderer, color: Color) {
+        r.rect(
+            self.origin.x,
+            self.origin.y,
+            self.size.x,
+            self.size.y,
+            color,
+        );
+    }
+}
+
 pub trait SceneObject: Intersect + Draw {}
 impl<T: Intersect + Draw> SceneObject for T {}
 pub struct Scene {
     objects: Vec<Box<dyn SceneObject + Send + Sync>>,
+    rectangles: Vec<Rectangle>,
     landmarks: Vec<Landmark>,
 }
 
@@ -103,6 +248,7 @@ impl Scene {
     pub fn new() -> Self {
         Self {
             objects: Vec::new(),
+            rectangles: Vec::new(),
             landmarks: Vec::new(),
         }
     }
@@ -122,42 +268,25 @@ impl Scene {
     }
 
     pub fn add_rect(&mut self, origin: Point2<f32>, size: Vector2<f32>) -> &mut Self {
-        self.add(Box::new(LineSegment::new(
-            origin.x,
-            origin.y,
-            origin.x + size.x,
-            origin.y,
-        )))
-        .add(Box::new(LineSegment::new(
-            origin.x + size.x,
-            origin.y,
-            origin.x + size.x,
-            origin.y + size.y,
-        )))
-        .add(Box::new(LineSegment::new(
-            origin.x + size.x,
-            origin.y + size.y,
-            origin.x,
-            origin.y + size.y,
-        )))
-        .add(Box::new(LineSegment::new(
-            origin.x,
-            origin.y + size.y,
-            origin.x,
-            origin.y,
-        )))
+        self.rectangles.push(Rectangle::new(origin, size));
+        self
     }
 }
 
 impl Draw for Scene {
     fn draw(&self, r: &mut ShapeRenderer, color: Color) {
-        for o in &self.objects {
-            o.draw(r, color);
+        // the shapes that can be filled go first, since drawing a line switches to drawing lines
+        for rect in &self.rectangles {
+            rect.draw(r, color);
         }
 
         for l in &self.landmarks {
             l.draw(r, color);
         }
+
+        for o in &self.objects {
+            o.draw(r, color);
+        }
     }
 }
 
@@ -167,8 +296,27 @@ impl Intersect for Scene {
         self.objects
             .iter()
             .filter_map(|o| o.intersect(ray))
+            .chain(self.rectangles.iter().filter_map(|r| r.intersect(ray)))
             .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Less))
     }
+
+    fn sweep_circle(
+        &self,
+        origin: Point2<f32>,
+        direction: Vector2<f32>,
+        radius: f32,
+    ) -> Option<(f32, Point2<f32>)> {
+        first_contact(
+            self.objects
+                .iter()
+                .filter_map(|o| o.sweep_circle(origin, direction, radius))
+                .chain(
+                    self.rectangles
+                        .iter()
+                        .filter_map(|r| r.sweep_circle(origin, direction, radius)),
+                ),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -213,6 +361,9 @@ mod test {
                 p2: Point2::new(2.0, -2.0),
             }));
 
+        scene.add_rect(Point2::new(1.5, -1.0), Vector2::new(0.2, 2.0));
+        assert_eq!(scene.intersect(&ray), Some(1.5));
+
         if let Some(u) = scene.intersect(&ray) {
             let p = ray.origin + u * ray.direction;
             println!("Point of intersection: {p} (u={u})")
@@ -220,4 +371,31 @@ mod test {
             println!("No intersection!");
         }
     }
+
+    #[test]
+    fn circle_sweep() {
+        let wall = LineSegment::new(1.0, -1.0, 1.0, 1.0);
+        let right = Vector2::new(1.0, 0.0);
+
+        // the side of the circle touches the wall
+        let (u, p) = wall.sweep_circle(Point2::origin(), right, 0.1).unwrap();
+        assert!((u - 0.9).abs() < 1e-6);
+        assert!((p - Point2::new(1.0, 0.0)).norm() < 1e-6);
+
+        // passing just beside the end of the wall still touches the end point
+        let (u, p) = wall
+            .sweep_circle(Point2::new(0.0, 1.06), right, 0.1)
+            .unwrap();
+        assert!((u - 0.92).abs() < 1e-5);
+        assert_eq!(p, Point2::new(1.0, 1.0));
+        assert_eq!(wall.sweep_circle(Point2::new(0.0, 1.2), right, 0.1), None);
+
+        // touching the wall only blocks moving further into it
+        let touching = Point2::new(0.9, 0.0);
+        assert_eq!(
+            wall.sweep_circle(touching, right, 0.1).map(|c| c.0),
+            Some(0.0)
+        );
+        assert_eq!(wall.sweep_circle(touching, -right, 0.1), None);
+    }
 }
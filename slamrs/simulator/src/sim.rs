@@ -1,13 +1,17 @@
 use std::sync::Arc;
 
-use common::robot::{
-    Command, LandmarkObservation, LandmarkObservations, Measurement, Observation, Odometry, Pose,
+use common::{
+    node::ConfigUi,
+    robot::{
+        BumpEvent, Command, LandmarkObservation, LandmarkObservations, Measurement, Observation,
+        Odometry, Pose,
+    },
 };
 use eframe::egui;
-use egui::mutex::RwLock;
+use egui::{mutex::RwLock, DragValue, Slider};
 use nalgebra::{Point2, Vector2};
 use pubsub::{Publisher, Subscription};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 
 use crate::scene::ray::{Intersect, Ray, Scene};
 use rand::distributions::Distribution;
@@ -17,6 +21,8 @@ pub struct Simulator {
     pub_obs_scanner: Option<Publisher<(Observation, Odometry)>>,
     pub_obs_landmarks: Option<Publisher<(LandmarkObservations, Odometry)>>,
     pub_pose: Option<Publisher<Pose>>,
+    pub_collision: Option<Publisher<bool>>,
+    pub_bump: Option<Publisher<BumpEvent>>,
     sub_cmd: Subscription<Command>,
     scene: Arc<RwLock<Scene>>,
     parameters: SimParameters,
@@ -24,11 +30,120 @@ pub struct Simulator {
     wheel_velocity: Vector2<f32>,
     active: bool,
     scan_update_timer: f32,
+    /// The simulated time (seconds) since the start.
+    time: f64,
     scan_counter: usize,
     wheel_motion_accumulator: (f32, f32),
+    colliding: bool,
+    bump_counter: usize,
 }
 
-#[derive(Clone, Copy, Deserialize)]
+/// The beams a simulated laser range scanner measures along, for prototyping with other scanners
+/// than the Neato. Angles are relative to the front of the scanner.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Default)]
+pub enum SensorModel {
+    /// One beam per degree around the full revolution, like the Neato.
+    #[default]
+    Uniform360,
+    /// One beam per degree over a field of view (degrees) centered on the front, the sector
+    /// behind is blind, e.g. when the robot body covers it.
+    LimitedFov { fov: f32 },
+    /// A forward facing single line profiler with `beams` beams spread over a narrow field of
+    /// view (degrees), measuring between `min_range` and `max_range` (meters).
+    Profiler {
+        fov: f32,
+        beams: usize,
+        min_range: f32,
+        max_range: f32,
+    },
+}
+
+impl SensorModel {
+    const LIMITED_FOV: SensorModel = SensorModel::LimitedFov { fov: 270.0 };
+    const PROFILER: SensorModel = SensorModel::Profiler {
+        fov: 30.0,
+        beams: 64,
+        min_range: 0.05,
+        max_range: 0.5,
+    };
+
+    /// The angles (radians) of the beams, in the order they are measured.
+    pub fn beam_angles(&self) -> Vec<f32> {
+        let spread = |fov: f32, beams: usize| {
+            let fov = fov.to_radians();
+            let step = fov / (beams.max(2) - 1) as f32;
+            (0..beams).map(|i| -fov / 2.0 + i as f32 * step).collect()
+        };
+
+        match *self {
+            SensorModel::Uniform360 => (0..360).map(|i| (i as f32).to_radians()).collect(),
+            SensorModel::LimitedFov { fov } => spread(fov, fov.max(0.0) as usize + 1),
+            SensorModel::Profiler { fov, beams, .. } => spread(fov, beams),
+        }
+    }
+
+    /// The `[min, max]` distance (meters) that can be measured, with `scanner_range` as the
+    /// maximum unless the model has its own.
+    pub fn range(&self, scanner_range: f32) -> [f32; 2] {
+        match *self {
+            SensorModel::Uniform360 | SensorModel::LimitedFov { .. } => [0.0, scanner_range],
+            SensorModel::Profiler {
+                min_range,
+                max_range,
+                ..
+            } => [min_range, max_range],
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Sensor Model: ");
+            for (model, name) in [
+                (SensorModel::Uniform360, "360°"),
+                (Self::LIMITED_FOV, "Limited FOV"),
+                (Self::PROFILER, "Profiler"),
+            ] {
+                let selected = std::mem::discriminant(self) == std::mem::discriminant(&model);
+                if ui.selectable_label(selected, name).clicked() && !selected {
+                    *self = model;
+                    changed = true;
+                }
+            }
+        });
+
+        match self {
+            SensorModel::Uniform360 => {}
+            SensorModel::LimitedFov { fov } => {
+                changed |= ui
+                    .add(Slider::new(fov, 10.0..=350.0).text("Field of View (°)"))
+                    .changed();
+            }
+            SensorModel::Profiler {
+                fov,
+                beams,
+                min_range,
+                max_range,
+            } => {
+                changed |= ui
+                    .add(Slider::new(fov, 1.0..=90.0).text("Field of View (°)"))
+                    .changed();
+                changed |= ui.add(Slider::new(beams, 2..=512).text("Beams")).changed();
+                changed |= ui
+                    .add(Slider::new(min_range, 0.0..=1.0).text("Min Range (m)"))
+                    .changed();
+                changed |= ui
+                    .add(Slider::new(max_range, 0.1..=10.0).text("Max Range (m)"))
+                    .changed();
+            }
+        }
+
+        changed
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
 #[serde(default)]
 pub struct SimParameters {
     /// The wheel base (in meters) of the differential robot used in the simulator, i.e,
@@ -41,11 +156,55 @@ pub struct SimParameters {
     /// Laser range scanner maximum distance in meters.
     pub(crate) scanner_range: f32,
 
+    /// The beams and range limits of the laser range scanner.
+    pub(crate) sensor_model: SensorModel,
+
     /// The uncertainty for the sensor in the angle direction (radians)
     pub(crate) angle_uncertainty: f32,
 
     /// The uncertainty for the sensor in the distance measurement (meters)
     pub(crate) distance_uncertainty: f32,
+
+    /// The radius (in meters) of the circular robot body, used for collision checks.
+    pub(crate) robot_radius: f32,
+
+    /// If enabled, the robot stops when it hits objects in the scene instead of
+    /// driving through them.
+    pub(crate) collisions_enabled: bool,
+
+    /// The mounting position of the laser range scanner in the robot frame as
+    /// `[forward, lateral]` (meters) relative to the center of rotation.
+    pub(crate) scanner_offset: [f32; 2],
+
+    /// The fixed amount of time (in seconds) the simulation is advanced by in every step, at
+    /// least [`MIN_TIMESTEP`].
+    #[serde(deserialize_with = "deserialize_timestep")]
+    pub(crate) timestep: f32,
+
+    /// If enabled, the simulation is paced to follow the wall clock. Otherwise it is advanced
+    /// as fast as possible, which is useful for generating datasets in batch runs.
+    pub(crate) realtime: bool,
+
+    /// How fast the simulation runs compared to the wall clock when `realtime` is enabled,
+    /// e.g. 2.0 for twice as fast or 0.5 for slow motion. Paced at [`MIN_SPEED`] at the least.
+    pub(crate) speed: f32,
+}
+
+/// The shortest timestep, smaller ones (or zero) would keep the simulation busy stepping.
+pub(crate) const MIN_TIMESTEP: f32 = 1e-4;
+
+/// The slowest the simulation is paced at, it would never advance at a speed of zero.
+pub(crate) const MIN_SPEED: f32 = 0.01;
+
+fn deserialize_timestep<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let timestep = f32::deserialize(deserializer)?;
+    if timestep.is_finite() {
+        Ok(timestep.max(MIN_TIMESTEP))
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "invalid timestep {timestep}, expected a number of seconds"
+        )))
+    }
 }
 
 impl Default for SimParameters {
@@ -54,17 +213,168 @@ impl Default for SimParameters {
             wheel_base: 0.1,
             update_period: 0.2,
             scanner_range: 1.0,
+            sensor_model: SensorModel::default(),
             angle_uncertainty: 0.03,
             distance_uncertainty: 0.02,
+            robot_radius: 0.05,
+            collisions_enabled: false,
+            scanner_offset: [0.0, 0.0],
+            timestep: 1.0 / 30.0,
+            realtime: true,
+            speed: 1.0,
         }
     }
 }
 
+impl SimParameters {
+    /// Starts from the defaults, for setting up a simulator without a config.
+    pub fn builder() -> SimParametersBuilder {
+        SimParametersBuilder::default()
+    }
+}
+
+/// Builds [`SimParameters`], every parameter that is not set keeps its default.
+#[derive(Clone, Copy, Default)]
+pub struct SimParametersBuilder {
+    parameters: SimParameters,
+}
+
+impl SimParametersBuilder {
+    pub fn wheel_base(mut self, wheel_base: f32) -> Self {
+        self.parameters.wheel_base = wheel_base;
+        self
+    }
+
+    pub fn update_period(mut self, update_period: f32) -> Self {
+        self.parameters.update_period = update_period;
+        self
+    }
+
+    pub fn scanner_range(mut self, scanner_range: f32) -> Self {
+        self.parameters.scanner_range = scanner_range;
+        self
+    }
+
+    pub fn sensor_model(mut self, sensor_model: SensorModel) -> Self {
+        self.parameters.sensor_model = sensor_model;
+        self
+    }
+
+    pub fn angle_uncertainty(mut self, angle_uncertainty: f32) -> Self {
+        self.parameters.angle_uncertainty = angle_uncertainty;
+        self
+    }
+
+    pub fn distance_uncertainty(mut self, distance_uncertainty: f32) -> Self {
+        self.parameters.distance_uncertainty = distance_uncertainty;
+        self
+    }
+
+    pub fn robot_radius(mut self, robot_radius: f32) -> Self {
+        self.parameters.robot_radius = robot_radius;
+        self
+    }
+
+    pub fn collisions_enabled(mut self, collisions_enabled: bool) -> Self {
+        self.parameters.collisions_enabled = collisions_enabled;
+        self
+    }
+
+    pub fn scanner_offset(mut self, scanner_offset: [f32; 2]) -> Self {
+        self.parameters.scanner_offset = scanner_offset;
+        self
+    }
+
+    pub fn timestep(mut self, timestep: f32) -> Self {
+        self.parameters.timestep = timestep.max(MIN_TIMESTEP);
+        self
+    }
+
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.parameters.realtime = realtime;
+        self
+    }
+
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.parameters.speed = speed;
+        self
+    }
+
+    pub fn build(self) -> SimParameters {
+        self.parameters
+    }
+}
+
+impl ConfigUi for SimParameters {
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        changed |= ui
+            .add(Slider::new(&mut self.wheel_base, 0.05..=0.4).text("Wheel Base (m)"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.update_period, 0.1..=2.0).text("Update Period (s)"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.scanner_range, 0.1..=10.0).text("Scanner Range (m)"))
+            .changed();
+        changed |= self.sensor_model.ui(ui);
+        changed |= ui
+            .add(
+                Slider::new(&mut self.angle_uncertainty, 0.0..=0.2).text("Angle Uncertainty (rad)"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut self.distance_uncertainty, 0.0..=0.2)
+                    .text("Distance Uncertainty (m)"),
+            )
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.robot_radius, 0.01..=0.5).text("Robot Radius (m)"))
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.collisions_enabled, "Collisions")
+            .changed();
+
+        ui.horizontal(|ui| {
+            ui.label("Scanner Offset (m): ");
+            for value in &mut self.scanner_offset {
+                changed |= ui
+                    .add(DragValue::new(value).speed(0.01).range(-0.5..=0.5))
+                    .changed();
+            }
+        });
+
+        changed |= ui
+            .add(
+                Slider::new(&mut self.timestep, 0.001..=0.2)
+                    .logarithmic(true)
+                    .text("Timestep (s)"),
+            )
+            .changed();
+        changed |= ui.checkbox(&mut self.realtime, "Real-time").changed();
+        changed |= ui
+            .add_enabled(
+                self.realtime,
+                Slider::new(&mut self.speed, 0.1..=10.0)
+                    .logarithmic(true)
+                    .text("Speed (x real-time)"),
+            )
+            .changed();
+
+        changed
+    }
+}
+
 impl Simulator {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pub_obs_scanner: Option<Publisher<(Observation, Odometry)>>,
         pub_obs_landmarks: Option<Publisher<(LandmarkObservations, Odometry)>>,
         pub_pose: Option<Publisher<Pose>>,
+        pub_collision: Option<Publisher<bool>>,
+        pub_bump: Option<Publisher<BumpEvent>>,
         sub_cmd: Subscription<Command>,
         scene: Arc<RwLock<Scene>>,
         parameters: SimParameters,
@@ -73,6 +383,8 @@ impl Simulator {
             pub_obs_scanner,
             pub_obs_landmarks,
             pub_pose,
+            pub_collision,
+            pub_bump,
             sub_cmd,
             scene,
             parameters,
@@ -80,11 +392,18 @@ impl Simulator {
             wheel_velocity: Vector2::zeros(),
             active: true,
             scan_update_timer: 0.0,
+            time: 0.0,
             scan_counter: 0,
             wheel_motion_accumulator: (0.0, 0.0),
+            colliding: false,
+            bump_counter: 0,
         }
     }
 
+    pub fn parameters(&self) -> &SimParameters {
+        &self.parameters
+    }
+
     pub fn parameters_mut(&mut self) -> &mut SimParameters {
         &mut self.parameters
     }
@@ -100,6 +419,8 @@ impl Simulator {
         }
 
         if self.active {
+            self.time += dt as f64;
+
             // make the robot move
             self.motion_model(self.wheel_velocity.x * dt, self.wheel_velocity.y * dt);
 
@@ -128,11 +449,24 @@ impl Simulator {
                 // if the laser scanner is enabled, perform a scan
                 if let Some(pub_obs) = &mut self.pub_obs_scanner {
                     // take a reading and send it to the drawing node
-                    let mut meas: Vec<Measurement> = Vec::with_capacity(360);
-                    let origin = Point2::new(self.pose.x, self.pose.y);
-
-                    for angle in 0..360 {
-                        let angle = (angle as f32).to_radians();
+                    let angles = self.parameters.sensor_model.beam_angles();
+                    let [min_range, max_range] = self
+                        .parameters
+                        .sensor_model
+                        .range(self.parameters.scanner_range);
+                    let mut meas: Vec<Measurement> = Vec::with_capacity(angles.len());
+                    // the scanner is mounted with an offset from the center of the robot
+                    let (s, c) = self.pose.theta.sin_cos();
+                    let [forward, lateral] = self.parameters.scanner_offset;
+                    let origin = Point2::new(
+                        self.pose.x + c * forward - s * lateral,
+                        self.pose.y + s * forward + c * lateral,
+                    );
+
+                    for (i, &angle) in angles.iter().enumerate() {
+                        // all measurements are taken at the same time, but spread them out
+                        // like a real scanner would
+                        let phase = i as f32 / angles.len() as f32;
 
                         // let angle = 0.0;
                         if let Some(v) = self
@@ -140,19 +474,30 @@ impl Simulator {
                             .read()
                             .intersect(&Ray::from_origin_angle(origin, angle + self.pose.theta))
                         {
-                            if v < self.parameters.scanner_range {
+                            if v < min_range {
+                                // too close to be measured
+                                meas.push(Measurement {
+                                    angle: angle as f64,
+                                    distance: v as f64,
+                                    strength: 1.0,
+                                    valid: false,
+                                    phase,
+                                });
+                            } else if v < max_range {
                                 meas.push(Measurement {
                                     angle: angle as f64,
                                     distance: v as f64,
                                     strength: 1.0,
                                     valid: true,
+                                    phase,
                                 });
                             } else {
                                 meas.push(Measurement {
                                     angle: angle as f64,
-                                    distance: self.parameters.scanner_range as f64,
+                                    distance: max_range as f64,
                                     strength: 1.0,
                                     valid: false, // Treat the valid flag as a hit/no hit for now
+                                    phase,
                                 });
                             }
                         }
@@ -162,6 +507,11 @@ impl Simulator {
                         Observation {
                             id: self.scan_counter,
                             measurements: meas,
+                            rpm: None,
+                            angle_min: angles.first().copied().unwrap_or_default() as f64,
+                            angle_max: angles.last().copied().unwrap_or_default() as f64,
+                            angle_increment: angles.get(1).map_or(0.0, |a| a - angles[0]) as f64,
+                            timestamp: self.time,
                         },
                         odometry,
                     )));
@@ -215,7 +565,165 @@ impl Simulator {
         // from https://rossum.sourceforge.net/papers/DiffSteer/DiffSteer.html
         let sbar = (sr + sl) / 2.0;
         self.pose.theta += (sr - sl) / self.parameters.wheel_base;
-        self.pose.x += sbar * self.pose.theta.cos();
-        self.pose.y += sbar * self.pose.theta.sin();
+
+        let mut distance = sbar.abs();
+        let direction = Vector2::new(self.pose.theta.cos(), self.pose.theta.sin()) * sbar.signum();
+
+        let mut colliding = false;
+        let mut contact = None;
+        if self.parameters.collisions_enabled && distance > 0.0 {
+            // sweep the circular footprint of the robot along the direction of motion and stop
+            // it where it first touches the scene
+            let origin = Point2::new(self.pose.x, self.pose.y);
+            let scene = self.scene.read();
+            if let Some((u, point)) =
+                scene.sweep_circle(origin, direction, self.parameters.robot_radius)
+            {
+                if distance >= u {
+                    distance = u;
+                    colliding = true;
+                    // the normal of the touched surface points back to the center of the robot
+                    let normal = (origin + u * direction - point)
+                        .try_normalize(f32::EPSILON)
+                        .unwrap_or(-direction);
+                    contact = Some((point, normal));
+                }
+            }
+        }
+
+        self.pose.x += distance * direction.x;
+        self.pose.y += distance * direction.y;
+
+        // only notify about changes in the collision state while the robot is moving
+        if sbar != 0.0 && colliding != self.colliding {
+            self.colliding = colliding;
+            if let Some(pub_collision) = &mut self.pub_collision {
+                pub_collision.publish(Arc::new(colliding));
+            }
+
+            if let (Some(pub_bump), Some((point, normal))) = (&mut self.pub_bump, contact) {
+                self.bump_counter += 1;
+                pub_bump.publish(Arc::new(BumpEvent {
+                    id: self.bump_counter,
+                    point: [point.x, point.y],
+                    normal: [normal.x, normal.y],
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::ray::LineSegment;
+
+    #[test]
+    fn sensor_model_beams() {
+        let angles = SensorModel::Uniform360.beam_angles();
+        assert_eq!(angles.len(), 360);
+        assert_eq!(angles[0], 0.0);
+
+        // centered on the front, one beam per degree
+        let angles = SensorModel::LimitedFov { fov: 90.0 }.beam_angles();
+        assert_eq!(angles.len(), 91);
+        assert!((angles[0] + 45f32.to_radians()).abs() < 1e-6);
+        assert!((angles[90] - 45f32.to_radians()).abs() < 1e-6);
+
+        let profiler = SensorModel::Profiler {
+            fov: 20.0,
+            beams: 5,
+            min_range: 0.1,
+            max_range: 0.5,
+        };
+        let angles = profiler.beam_angles();
+        assert_eq!(angles.len(), 5);
+        assert!((angles[4] - angles[0] - 20f32.to_radians()).abs() < 1e-6);
+        assert_eq!(profiler.range(2.0), [0.1, 0.5]);
+        assert_eq!(SensorModel::Uniform360.range(2.0), [0.0, 2.0]);
+    }
+
+    #[test]
+    fn partial_parameters_use_defaults() {
+        let parameters: SimParameters =
+            serde_yaml::from_str("wheel_base: 0.12\nrealtime: false").unwrap();
+        assert_eq!(
+            parameters,
+            SimParameters::builder()
+                .wheel_base(0.12)
+                .realtime(false)
+                .build()
+        );
+        assert_eq!(parameters.speed, SimParameters::default().speed);
+    }
+
+    #[test]
+    fn timestep_is_validated() {
+        let parse = |yaml: &str| serde_yaml::from_str::<SimParameters>(yaml);
+        assert_eq!(parse("timestep: 0.0").unwrap().timestep, MIN_TIMESTEP);
+        assert_eq!(parse("timestep: -1.0").unwrap().timestep, MIN_TIMESTEP);
+        assert_eq!(parse("timestep: 0.01").unwrap().timestep, 0.01);
+        assert!(parse("timestep: .nan").is_err());
+        assert!(parse("timestep: .inf").is_err());
+    }
+
+    #[test]
+    fn footprint_does_not_clip_walls() {
+        let radius = 0.05;
+        // walls that the center of the robot never points at, but its sides run into
+        let walls = [(0.3, 0.04, 0.3, 1.0), (-1.0, 0.2, 5.0, 0.2)];
+        let mut scene = Scene::new();
+        for &(x1, y1, x2, y2) in &walls {
+            scene.add(Box::new(LineSegment::new(x1, y1, x2, y2)));
+        }
+
+        let mut pubsub = pubsub::PubSub::new();
+        let mut sim = Simulator::new(
+            None,
+            None,
+            None,
+            None,
+            None,
+            pubsub.subscribe("robot/command"),
+            Arc::new(RwLock::new(scene)),
+            SimParameters::builder()
+                .robot_radius(radius)
+                .collisions_enabled(true)
+                .build(),
+        );
+
+        let clearance = |pose: Pose| {
+            walls
+                .iter()
+                .map(|&(x1, y1, x2, y2)| {
+                    let center = Point2::new(pose.x, pose.y);
+                    (LineSegment::new(x1, y1, x2, y2).closest_point(center) - center).norm()
+                })
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        for theta in [0.0, 0.3, 0.08] {
+            sim.pose = Pose {
+                x: 0.0,
+                y: 0.0,
+                theta,
+            };
+            for _ in 0..100 {
+                sim.motion_model(0.01, 0.01);
+                assert!(
+                    clearance(sim.pose) >= radius - 1e-5,
+                    "robot at {:?} clips a wall",
+                    sim.pose
+                );
+            }
+            // it ends up touching a wall instead of stopping early
+            assert!((clearance(sim.pose) - radius).abs() < 1e-4);
+            assert!(sim.colliding);
+        }
+
+        // straight ahead the wall is only touched by the side of the footprint
+        sim.pose = Pose::default();
+        sim.motion_model(1.0, 1.0);
+        assert!((sim.pose.x - (0.3 - (radius * radius - 0.04f32 * 0.04).sqrt())).abs() < 1e-5);
     }
 }
@@ -0,0 +1,149 @@
+//! Benchmarks of the hot paths of the scan matching and the grid map integration, run with
+//! `cargo bench -p slam`. All inputs are synthetic and deterministic so results can be compared
+//! between runs.
+
+use std::f32::consts::PI;
+
+use common::robot::{Measurement, Observation, Pose};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra::{Matrix2xX, Vector2, Vector3};
+use slam::{icp_point_to_normal, CorrespondenceBackend, IcpParameters, Map, Scalar};
+
+/// Half the width and height of the rectangular room the synthetic scans are taken in.
+const ROOM: Vector2<f32> = Vector2::new(2.0, 1.5);
+
+/// Distance from `pose` to the walls of the room along the ray with angle `angle` (in the robot
+/// frame).
+fn distance_to_wall(pose: Pose, angle: f32) -> f32 {
+    let direction = Vector2::new((pose.theta + angle).cos(), (pose.theta + angle).sin());
+
+    // the closest of the two walls in the direction of the ray along each axis
+    let along_x = (direction.x.signum() * ROOM.x - pose.x) / direction.x;
+    let along_y = (direction.y.signum() * ROOM.y - pose.y) / direction.y;
+    along_x.min(along_y)
+}
+
+/// A full revolution of a scanner with 1 degree resolution, like the Neato.
+fn scan(pose: Pose) -> Observation {
+    let measurements = (0..360)
+        .map(|i| {
+            let angle = (i as f32).to_radians();
+            Measurement {
+                angle: angle as f64,
+                distance: distance_to_wall(pose, angle) as f64,
+                strength: 1000.0,
+                valid: true,
+                phase: i as f32 / 360.0,
+            }
+        })
+        .collect();
+
+    Observation {
+        id: 0,
+        measurements,
+        rpm: None,
+        angle_min: 0.0,
+        angle_max: 359f64.to_radians(),
+        angle_increment: 1f64.to_radians(),
+        timestamp: 0.0,
+    }
+}
+
+/// `n` points evenly spread along the walls of the room.
+fn room_outline(n: usize) -> Matrix2xX<Scalar> {
+    let perimeter = 4.0 * (ROOM.x + ROOM.y);
+    let points: Vec<Vector2<f32>> = (0..n)
+        .map(|i| {
+            let mut s = i as f32 / n as f32 * perimeter;
+            for (start, direction, length) in [
+                (Vector2::new(-ROOM.x, -ROOM.y), Vector2::x(), 2.0 * ROOM.x),
+                (Vector2::new(ROOM.x, -ROOM.y), Vector2::y(), 2.0 * ROOM.y),
+                (Vector2::new(ROOM.x, ROOM.y), -Vector2::x(), 2.0 * ROOM.x),
+                (Vector2::new(-ROOM.x, ROOM.y), -Vector2::y(), 2.0 * ROOM.y),
+            ] {
+                if s <= length {
+                    return start + direction * s;
+                }
+                s -= length;
+            }
+            unreachable!()
+        })
+        .collect();
+    Matrix2xX::from_columns(&points).cast()
+}
+
+fn icp(c: &mut Criterion) {
+    let reference = room_outline(400);
+
+    // the scan is taken slightly off from where the matching starts
+    let pose = Pose {
+        x: 0.1,
+        y: -0.05,
+        theta: PI / 36.0,
+    };
+    let points = scan(pose).to_points(None, false).cast();
+
+    c.bench_function("icp_point_to_normal 360 points", |b| {
+        b.iter(|| {
+            icp_point_to_normal(
+                black_box(&points),
+                black_box(&reference),
+                Vector3::zeros(),
+                IcpParameters::default(),
+            )
+        })
+    });
+}
+
+/// Compares the correspondence search backends for growing reference sets, to see up to which
+/// size brute force is the faster choice.
+fn correspondences(c: &mut Criterion) {
+    let points = room_outline(90);
+
+    let mut group = c.benchmark_group("correspondences");
+    for n in [16, 32, 64, 128, 256] {
+        let reference = room_outline(n);
+
+        for backend in [
+            CorrespondenceBackend::BruteForce,
+            CorrespondenceBackend::KdTree,
+        ] {
+            let params = IcpParameters {
+                correspondence_backend: backend,
+                ..Default::default()
+            };
+            group.bench_with_input(BenchmarkId::new(format!("{backend:?}"), n), &n, |b, _| {
+                b.iter(|| {
+                    icp_point_to_normal(
+                        black_box(&points),
+                        black_box(&reference),
+                        Vector3::zeros(),
+                        params,
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn grid_integrate(c: &mut Criterion) {
+    let pose = Pose {
+        x: 0.3,
+        y: 0.2,
+        theta: 0.5,
+    };
+    let observation = scan(pose);
+    let map = Map::new(Vector2::new(-2.5, -2.0), 5.0, 4.0, 0.02, 0.04);
+
+    c.bench_function("grid integrate 360 rays", |b| {
+        b.iter_batched_ref(
+            || map.clone(),
+            |map| map.integrate(black_box(&observation), pose),
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, icp, correspondences, grid_integrate);
+criterion_main!(benches);
@@ -0,0 +1,253 @@
+//! Finds the frontiers of a grid map, the borders between the explored free space and the
+//! unexplored space. Driving to a frontier reveals more of the map, which makes them the goals
+//! of an autonomous exploration.
+
+use std::sync::Arc;
+
+use common::node::{Node, NodeConfig, TopicDecl};
+use graphics::primitiverenderer::{Color, PrimitiveType};
+use nalgebra::Vector2;
+use pubsub::{Publisher, Subscription};
+use serde::Deserialize;
+
+use super::{
+    map::{Cell, GridData},
+    node::GridMapMessage,
+};
+
+/// A connected group of frontier cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frontier {
+    /// The center of the cells in world coordinates, the goal to drive towards.
+    pub centroid: Vector2<f32>,
+    /// The number of cells, larger frontiers promise more unexplored space.
+    pub cells: usize,
+}
+
+/// Cells this close to the prior probability of 0.5 have not been observed yet.
+const UNKNOWN_MARGIN: f64 = 1e-3;
+
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct FrontierNodeConfig {
+    topic_map: String,
+    /// The frontiers found in every map, largest first.
+    topic_frontiers: String,
+    /// Cells with an occupancy probability below this are considered free.
+    #[serde(default = "default_free_threshold")]
+    free_threshold: f64,
+    /// Frontiers with fewer cells than this are ignored, they are mostly noise.
+    #[serde(default = "default_min_cluster_size")]
+    min_cluster_size: usize,
+}
+
+fn default_free_threshold() -> f64 {
+    0.3
+}
+
+fn default_min_cluster_size() -> usize {
+    5
+}
+
+pub struct FrontierNode {
+    sub_map: Subscription<GridMapMessage>,
+    pub_frontiers: Publisher<Vec<Frontier>>,
+    free_threshold: f64,
+    min_cluster_size: usize,
+    /// The frontiers of the last map, for drawing.
+    frontiers: Vec<Frontier>,
+    /// The cell size of the last map, the frontiers are drawn that large.
+    resolution: f32,
+}
+
+impl NodeConfig for FrontierNodeConfig {
+    fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
+        Box::new(FrontierNode {
+            sub_map: pubsub.subscribe(&self.topic_map),
+            pub_frontiers: pubsub.publish_registered(&self.topic_frontiers),
+            free_threshold: self.free_threshold,
+            min_cluster_size: self.min_cluster_size,
+            frontiers: Vec::new(),
+            resolution: 0.0,
+        })
+    }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![
+            TopicDecl::subscribe::<GridMapMessage>(&self.topic_map),
+            TopicDecl::publish::<Vec<Frontier>>(&self.topic_frontiers),
+        ]
+    }
+}
+
+/// The cells next to `cell` within a grid of `size`, including the diagonal ones if `diagonal`.
+fn neighbours(cell: Cell, size: Vector2<usize>, diagonal: bool) -> impl Iterator<Item = Cell> {
+    (-1isize..=1)
+        .flat_map(|dr| (-1isize..=1).map(move |dc| (dr, dc)))
+        .filter(move |&(dr, dc)| (dr, dc) != (0, 0) && (diagonal || dr == 0 || dc == 0))
+        .filter_map(move |(dr, dc)| {
+            let row = cell.row.checked_add_signed(dr).filter(|&r| r < size.y)?;
+            let column = cell.column.checked_add_signed(dc).filter(|&c| c < size.x)?;
+            Some(Cell::new(column, row))
+        })
+}
+
+/// Finds the free cells of the map next to an unknown cell and groups the touching ones into
+/// frontiers. Frontiers smaller than `min_cluster_size` cells are dropped, the rest are returned
+/// largest first.
+pub fn find_frontiers(
+    map: &GridMapMessage,
+    free_threshold: f64,
+    min_cluster_size: usize,
+) -> Vec<Frontier> {
+    let data = &map.data;
+    let size = data.size();
+    let unknown = |cell: Cell| (data.get(cell).value() - 0.5).abs() < UNKNOWN_MARGIN;
+
+    let mut is_frontier = GridData::new_fill(size, false);
+    for (cell, p) in data.iter_cells() {
+        if p.value() < free_threshold && neighbours(cell, size, false).any(unknown) {
+            *is_frontier.get_mut(cell) = true;
+        }
+    }
+
+    // connected components of the frontier cells, also connected diagonally
+    let mut frontiers = Vec::new();
+    let mut stack = Vec::new();
+    for row in 0..size.y {
+        for column in 0..size.x {
+            let start = Cell::new(column, row);
+            if !*is_frontier.get(start) {
+                continue;
+            }
+
+            // clear the cells as they are visited so that each is only counted once
+            *is_frontier.get_mut(start) = false;
+            stack.push(start);
+
+            let mut cells = 0;
+            let mut sum = Vector2::<f32>::zeros();
+            while let Some(cell) = stack.pop() {
+                cells += 1;
+                sum += Vector2::new(cell.column as f32, cell.row as f32);
+
+                for n in neighbours(cell, size, true) {
+                    if *is_frontier.get(n) {
+                        *is_frontier.get_mut(n) = false;
+                        stack.push(n);
+                    }
+                }
+            }
+
+            if cells >= min_cluster_size {
+                let center = sum / cells as f32 + Vector2::repeat(0.5);
+                frontiers.push(Frontier {
+                    centroid: map.position + center * map.resolution,
+                    cells,
+                });
+            }
+        }
+    }
+
+    frontiers.sort_by_key(|f| std::cmp::Reverse(f.cells));
+    frontiers
+}
+
+impl Node for FrontierNode {
+    fn update(&mut self) {
+        // only the latest map matters, finding the frontiers of the others is wasted work
+        let mut latest = None;
+        while let Some(map) = self.sub_map.try_recv() {
+            latest = Some(map);
+        }
+
+        if let Some(map) = latest {
+            self.frontiers = find_frontiers(&map, self.free_threshold, self.min_cluster_size);
+            self.resolution = map.resolution;
+            self.pub_frontiers.publish(Arc::new(self.frontiers.clone()));
+        }
+    }
+
+    fn draw(&mut self, _ui: &eframe::egui::Ui, world: &mut common::world::WorldObj<'_>) {
+        if self.frontiers.is_empty() {
+            return;
+        }
+
+        world.sr.begin(PrimitiveType::Filled);
+        for f in &self.frontiers {
+            world.sr.circle(
+                f.centroid.x,
+                f.centroid.y,
+                2.0 * self.resolution,
+                Color::rgba_u8(0xff, 0x00, 0xff, 0xc0),
+            );
+        }
+        world.sr.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::math::Probability;
+
+    use super::*;
+
+    /// A map of `rows`, where `.` is free, `#` is occupied and `?` is unknown. The first row is
+    /// the top of the map.
+    fn map_from_rows(rows: &[&str]) -> GridMapMessage {
+        let size = Vector2::new(rows[0].len(), rows.len());
+        let data = rows
+            .iter()
+            .rev()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '.' => Probability::new(0.1),
+                '#' => Probability::new(0.9),
+                _ => Probability::new(0.5),
+            })
+            .collect();
+
+        GridMapMessage {
+            position: Vector2::new(-1.0, 2.0),
+            resolution: 0.5,
+            data: GridData::from_vec(size, data).unwrap(),
+        }
+    }
+
+    #[test]
+    fn finds_clusters_of_frontier_cells() {
+        let map = map_from_rows(&[
+            "??????", //
+            "......", //
+            "######", //
+            "......", //
+            "..?...", //
+            "......", //
+        ]);
+
+        let frontiers = find_frontiers(&map, 0.3, 1);
+        assert_eq!(frontiers.len(), 2);
+
+        // the free row below the unknown space
+        assert_eq!(frontiers[0].cells, 6);
+        assert_eq!(frontiers[0].centroid, Vector2::new(0.5, 4.25));
+
+        // the four free cells around the unknown cell, connected diagonally
+        assert_eq!(frontiers[1].cells, 4);
+        assert_eq!(frontiers[1].centroid, Vector2::new(0.25, 2.75));
+    }
+
+    #[test]
+    fn drops_small_clusters() {
+        let map = map_from_rows(&[
+            "......", //
+            "..?...", //
+            "......", //
+        ]);
+
+        assert_eq!(find_frontiers(&map, 0.3, 4).len(), 1);
+        assert!(find_frontiers(&map, 0.3, 5).is_empty());
+
+        // nothing is free
+        assert!(find_frontiers(&map, 0.05, 1).is_empty());
+    }
+}
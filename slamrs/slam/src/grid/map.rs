@@ -1,29 +1,46 @@
 use common::robot::{Observation, Pose};
-use nalgebra::{DMatrix, EuclideanNorm, Matrix2, Vector2};
+use nalgebra::{DMatrix, EuclideanNorm, Matrix2, Matrix2xX, Vector2};
 
 use super::ray::GridRayIterator;
+use crate::Scalar;
 use common::math::{LogOdds, LogProbability, Probability};
 
 #[derive(Clone)]
 pub struct Map {
     /// the position of this map in the world (lower left corner)
-    position: Vector2<f32>,
+    position: Vector2<Scalar>,
 
     /// the size of the map in world coordinates
-    world_size: Vector2<f32>,
+    world_size: Vector2<Scalar>,
 
     /// the size of the map in cells
     grid_size: Vector2<usize>,
 
     /// the resolution of this GridMap, given in meters per cell
-    resolution: f32,
+    resolution: Scalar,
+
+    /// How thick (in cells) the band of cells around a hit is that is marked as occupied.
+    wall_thickness: Scalar,
+
+    /// The range the log odds of a cell are kept within, so that a cell can still change its mind
+    /// after many consistent observations.
+    log_odds_min: f64,
+    log_odds_max: f64,
 
     /// Data vectors
     odds: GridData<LogOdds>,
 }
 
 impl Map {
-    pub fn new(position: Vector2<f32>, width: f32, height: f32, resolution: f32) -> Self {
+    /// Creates an unknown map covering `width` x `height` meters, where hits are marked as walls
+    /// `wall_thickness` meters thick.
+    pub fn new(
+        position: Vector2<Scalar>,
+        width: Scalar,
+        height: Scalar,
+        resolution: Scalar,
+        wall_thickness: Scalar,
+    ) -> Self {
         // calculate the required size in cells to fill the desired area based on the resolution
         let grid_size = Vector2::new(
             (width / resolution).ceil() as usize,
@@ -32,8 +49,8 @@ impl Map {
 
         // calculate the "real" size of this grid map (potentially caused by ceil() above)
         let world_size = Vector2::new(
-            grid_size.x as f32 * resolution,
-            grid_size.y as f32 * resolution,
+            grid_size.x as Scalar * resolution,
+            grid_size.y as Scalar * resolution,
         );
 
         let vec_len = grid_size.x * grid_size.y;
@@ -43,25 +60,93 @@ impl Map {
             world_size,
             grid_size,
             resolution,
+            wall_thickness: wall_thickness / resolution,
+            log_odds_min: f64::NEG_INFINITY,
+            log_odds_max: f64::INFINITY,
             odds: GridData::new_fill(grid_size, Probability::new(0.5).log_odds()),
         }
     }
 
+    /// Creates a map from previously accumulated log odds, with its size given by the data.
+    pub fn from_log_odds(
+        position: Vector2<Scalar>,
+        resolution: Scalar,
+        wall_thickness: Scalar,
+        odds: GridData<LogOdds>,
+    ) -> Self {
+        let grid_size = odds.size();
+
+        Self {
+            position,
+            world_size: Vector2::new(
+                grid_size.x as Scalar * resolution,
+                grid_size.y as Scalar * resolution,
+            ),
+            grid_size,
+            resolution,
+            wall_thickness: wall_thickness / resolution,
+            log_odds_min: f64::NEG_INFINITY,
+            log_odds_max: f64::INFINITY,
+            odds,
+        }
+    }
+
+    /// Keeps the log odds of every cell updated by a measurement between `min` and `max`, which
+    /// are swapped if given the wrong way around. The map is unbounded by default.
+    pub fn with_log_odds_limits(mut self, min: f64, max: f64) -> Self {
+        self.log_odds_min = min.min(max);
+        self.log_odds_max = max.max(min);
+        self
+    }
+
     pub fn likelihood(&self) -> GridData<Probability> {
         self.odds.transform()
     }
 
-    pub fn position(&self) -> Vector2<f32> {
+    pub fn log_odds(&self) -> &GridData<LogOdds> {
+        &self.odds
+    }
+
+    pub fn resolution(&self) -> Scalar {
+        self.resolution
+    }
+
+    pub fn position(&self) -> Vector2<Scalar> {
         self.position
     }
 
     /// Converts a position in the world into a grid-relative position. Note that the returned
     /// value is not guaranteed to lie _within_ the bounds of this Map.
-    pub fn world_to_grid(&self, world: Vector2<f32>) -> Vector2<f32> {
+    pub fn world_to_grid(&self, world: Vector2<Scalar>) -> Vector2<Scalar> {
         (world - self.position) / self.resolution
     }
 
-    pub fn is_valid(&self, grid: Vector2<f32>) -> bool {
+    /// The log odds of the cell containing a position in the world, `None` if the position is
+    /// outside of this Map.
+    pub fn log_odds_at(&self, world: Vector2<Scalar>) -> Option<LogOdds> {
+        let grid = self.world_to_grid(world);
+        self.is_valid(grid)
+            .then(|| *self.odds.get(Cell::new(grid.x as usize, grid.y as usize)))
+    }
+
+    /// Returns the world positions of the centers of all cells with an occupancy probability
+    /// above `threshold`, for example to use as reference points for scan matching.
+    pub fn occupied_points(&self, threshold: Probability) -> Matrix2xX<Scalar> {
+        let points: Vec<Vector2<Scalar>> = self
+            .odds
+            .iter_cells()
+            .filter(|(_, odds)| odds.probability().value() > threshold.value())
+            .map(|(cell, _)| {
+                self.position
+                    + Vector2::new(cell.column as Scalar + 0.5, cell.row as Scalar + 0.5)
+                        * self.resolution
+            })
+            .collect();
+
+        Matrix2xX::from_iterator(points.len(), points.iter().flat_map(|p| [p.x, p.y]))
+    }
+
+    pub fn is_valid(&self, grid: Vector2<Scalar>) -> bool {
         !((grid.x < 0.0)
             || (grid.y < 0.0)
             || (grid.x as usize >= self.grid_size.x)
@@ -69,39 +154,55 @@ impl Map {
     }
 
     pub fn integrate(&mut self, observation: &Observation, pose: Pose) {
-        let start = self.world_to_grid(pose.xy());
-
-        for m in &observation.measurements {
-            let end = Vector2::new(
-                pose.x + (pose.theta + m.angle as f32).cos() * m.distance as f32,
-                pose.y + (pose.theta + m.angle as f32).sin() * m.distance as f32,
-            );
+        let start = self.world_to_grid(pose.xy().cast());
 
-            let end = self.world_to_grid(end);
+        let ends = observation.to_points(Some(&pose), true);
+        for (m, end) in observation.measurements.iter().zip(ends.column_iter()) {
+            let end = self.world_to_grid(end.into_owned().cast());
 
             // println!("{} -> {}", start, end);
 
-            self.apply_measurement(start, end, m.distance as f32 / self.resolution, m.valid);
+            self.apply_measurement(start, end, m.distance as Scalar / self.resolution, m.valid);
+        }
+    }
+
+    /// Moves the log odds of every cell towards the prior (0.5) by `rate` (0 to 1), so that
+    /// obstacles that are not observed again fade away.
+    pub fn decay(&mut self, rate: Scalar) {
+        let factor: f64 = nalgebra::convert((1.0 - rate).clamp(0.0, 1.0));
+        for odds in self.odds.values_mut() {
+            *odds = LogOdds::new(odds.value() * factor);
         }
     }
 
     fn apply_measurement(
         &mut self,
-        start: Vector2<f32>,
-        end: Vector2<f32>,
-        measured_distance: f32,
+        start: Vector2<Scalar>,
+        end: Vector2<Scalar>,
+        measured_distance: Scalar,
         was_hit: bool,
     ) {
-        // TODO: additional_steps below need to coincide with the threshold in the inverse sensor model (so that we correctly take the model into account)
-        for (cell, center) in
-            GridRayIterator::new(start.x, start.y, end.x, end.y, self.grid_size, 2)
-        {
+        // continue the ray past the end point to cover the far half of the wall, plus a cell
+        // for the end point not being at the center of its cell
+        let additional_steps = (self.wall_thickness / 2.0).ceil() as usize + 1;
+
+        for (cell, center) in GridRayIterator::new(
+            start.x,
+            start.y,
+            end.x,
+            end.y,
+            self.grid_size,
+            additional_steps,
+        ) {
             // calculate the distance from the start to the center of this visited cell
             let distance = start.apply_metric_distance(&center, &EuclideanNorm);
 
             // update the log odds based on the inverse sensor model
-            *self.odds.get_mut(cell) +=
-                inverse_sensor_model(distance, measured_distance, was_hit, 2.0).log_odds();
+            let odds = self.odds.get_mut(cell);
+            *odds +=
+                inverse_sensor_model(distance, measured_distance, was_hit, self.wall_thickness)
+                    .log_odds();
+            *odds = LogOdds::new(odds.value().clamp(self.log_odds_min, self.log_odds_max));
         }
     }
     /// Probability to assign when hit, random is the complement (1-Z_HIT)
@@ -113,16 +214,8 @@ impl Map {
     pub(crate) fn probability_of(&self, z: &Observation, pose: Pose) -> LogProbability {
         let mut product = LogProbability::new(1.0);
 
-        for m in &z.measurements {
-            if !m.valid {
-                continue;
-            }
-            let end = Vector2::new(
-                pose.x + (pose.theta + m.angle as f32).cos() * m.distance as f32,
-                pose.y + (pose.theta + m.angle as f32).sin() * m.distance as f32,
-            );
-
-            let end = self.world_to_grid(end);
+        for end in z.to_points(Some(&pose), false).column_iter() {
+            let end = self.world_to_grid(end.into_owned().cast());
 
             if self.is_valid(end) {
                 let gridx = end.x as usize;
@@ -146,10 +239,10 @@ impl Map {
 }
 
 fn inverse_sensor_model(
-    distance: f32,
-    measured_distance: f32,
+    distance: Scalar,
+    measured_distance: Scalar,
     was_hit: bool,
-    tolerance: f32,
+    tolerance: Scalar,
 ) -> Probability {
     const P_FREE: Probability = Probability::new_unchecked(0.30);
     const P_OCCUPPIED: Probability = Probability::new_unchecked(0.9);
@@ -177,6 +270,61 @@ struct MapData {
     likelihood: Vec<Probability>,
 }
 
+/// Counts how many scan returns landed in each cell, using the same layout as a [`Map`].
+#[derive(Clone)]
+pub struct HitCountMap {
+    /// the position of this map in the world (lower left corner)
+    position: Vector2<Scalar>,
+
+    /// the resolution of this map, given in meters per cell
+    resolution: Scalar,
+
+    counts: GridData<u32>,
+}
+
+impl HitCountMap {
+    pub fn new(
+        position: Vector2<Scalar>,
+        width: Scalar,
+        height: Scalar,
+        resolution: Scalar,
+    ) -> Self {
+        let grid_size = Vector2::new(
+            (width / resolution).ceil() as usize,
+            (height / resolution).ceil() as usize,
+        );
+
+        Self {
+            position,
+            resolution,
+            counts: GridData::new_fill(grid_size, 0),
+        }
+    }
+
+    /// Adds the end point of every valid measurement in the observation to the counts.
+    pub fn integrate(&mut self, observation: &Observation, pose: Pose) {
+        let size = self.counts.size();
+
+        for end in observation.to_points(Some(&pose), false).column_iter() {
+            let grid = (end.into_owned().cast() - self.position) / self.resolution;
+
+            if grid.x >= 0.0
+                && grid.y >= 0.0
+                && (grid.x as usize) < size.x
+                && (grid.y as usize) < size.y
+            {
+                *self
+                    .counts
+                    .get_mut(Cell::new(grid.x as usize, grid.y as usize)) += 1;
+            }
+        }
+    }
+
+    pub fn counts(&self) -> &GridData<u32> {
+        &self.counts
+    }
+}
+
 #[derive(Clone)]
 pub struct GridData<T> {
     /** the size of the grid in cells */
@@ -186,6 +334,7 @@ pub struct GridData<T> {
     data: Vec<T>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Cell {
     pub row: usize,
     pub column: usize,
@@ -199,8 +348,9 @@ impl Cell {
 
 impl<T> GridData<T> {
     fn index(&self, cell: Cell) -> usize {
-        // Row-major order
-        cell.row * self.size.y + cell.column
+        // Row-major order, with a stride of the number of columns. Non-square maps used to be
+        // stored with a stride of the number of rows, see `GridMapSnapshot::version`.
+        cell.row * self.size.x + cell.column
     }
 
     fn cell(&self, index: usize) -> Cell {
@@ -208,8 +358,8 @@ impl<T> GridData<T> {
         assert!(index < self.size.x * self.size.y);
 
         Cell {
-            row: index / self.size.y,
-            column: index % self.size.y,
+            row: index / self.size.x,
+            column: index % self.size.x,
         }
     }
 
@@ -252,9 +402,50 @@ impl<T> GridData<T> {
     pub fn size(&self) -> Vector2<usize> {
         self.size
     }
+
+    /// Combines each block of `stride` x `stride` cells into a single cell using `f`, e.g. to
+    /// draw a large grid with fewer cells. The blocks along the far edges are smaller if the
+    /// size is not a multiple of `stride`.
+    pub fn downscale<S>(&self, stride: usize, f: impl Fn(&[&T]) -> S) -> GridData<S> {
+        let stride = stride.max(1);
+        let size = self.size.map(|n| (n + stride - 1) / stride);
+
+        let mut data = Vec::with_capacity(size.x * size.y);
+        let mut block = Vec::with_capacity(stride * stride);
+        for block_row in 0..size.y {
+            for block_column in 0..size.x {
+                block.clear();
+                for row in block_row * stride..((block_row + 1) * stride).min(self.size.y) {
+                    for column in
+                        block_column * stride..((block_column + 1) * stride).min(self.size.x)
+                    {
+                        block.push(self.get(Cell::new(column, row)));
+                    }
+                }
+
+                data.push(f(&block));
+            }
+        }
+
+        GridData { size, data }
+    }
 }
 
 impl<T: Clone> GridData<T> {
+    /// Creates a grid from data in row-major order. Returns `None` if the length of the data
+    /// does not match the size.
+    pub fn from_vec(size: Vector2<usize>, data: Vec<T>) -> Option<Self> {
+        (data.len() == size.x * size.y).then_some(Self { size, data })
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
     pub fn new_fill(size: Vector2<usize>, initial_value: T) -> Self {
         Self {
             size,
@@ -262,3 +453,219 @@ impl<T: Clone> GridData<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use common::robot::Measurement;
+
+    use super::*;
+
+    #[test]
+    fn non_square_grid_is_row_major() {
+        // wider than tall, indexing rows by the height would overlap the rows
+        let size = Vector2::new(4, 2);
+        let data = GridData::from_vec(size, (0..8).collect()).unwrap();
+
+        assert_eq!(*data.get(Cell::new(3, 0)), 3);
+        assert_eq!(*data.get(Cell::new(0, 1)), 4);
+        assert_eq!(*data.get(Cell::new(3, 1)), 7);
+
+        // every cell maps to its own value, and back
+        for (cell, &value) in data.iter_cells() {
+            assert_eq!(value, cell.row * size.x + cell.column);
+            assert_eq!(data.index(cell), value);
+            assert_eq!(data.cell(value), cell);
+        }
+
+        // and taller than wide
+        let mut data = GridData::new_fill(Vector2::new(2, 3), 0);
+        *data.get_mut(Cell::new(1, 2)) = 1;
+        assert_eq!(data.values(), &[0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn occupied_points_are_cell_centers() {
+        let mut map = Map::new(Vector2::new(-1.0, -1.0), 1.0, 1.0, 0.25, 0.5);
+        *map.odds.get_mut(Cell::new(1, 2)) = Probability::new(0.9).log_odds();
+        *map.odds.get_mut(Cell::new(3, 0)) = Probability::new(0.6).log_odds();
+        *map.odds.get_mut(Cell::new(0, 3)) = Probability::new(0.2).log_odds();
+
+        let points = map.occupied_points(Probability::new(0.7));
+        assert_eq!(points.ncols(), 1);
+        assert_relative_eq!(points.column(0).into_owned(), Vector2::new(-0.625, -0.375));
+
+        let points = map.occupied_points(Probability::new(0.55));
+        assert_eq!(points.ncols(), 2);
+        assert_relative_eq!(points.column(0).into_owned(), Vector2::new(-0.125, -0.875));
+        assert_relative_eq!(points.column(1).into_owned(), Vector2::new(-0.625, -0.375));
+
+        // unknown cells are never occupied
+        let points =
+            Map::new(Vector2::zeros(), 1.0, 1.0, 0.25, 0.5).occupied_points(Probability::new(0.5));
+        assert_eq!(points.ncols(), 0);
+    }
+
+    #[test]
+    fn log_odds_at_world_position() {
+        let mut map = Map::new(Vector2::new(-1.0, -1.0), 1.0, 1.0, 0.25, 0.5);
+        *map.odds.get_mut(Cell::new(1, 2)) = LogOdds::new(2.0);
+
+        let odds = map.log_odds_at(Vector2::new(-0.6, -0.4)).unwrap();
+        assert_eq!(odds.value(), 2.0);
+        assert_eq!(
+            map.log_odds_at(Vector2::new(-0.9, -0.9)).unwrap().value(),
+            0.0
+        );
+
+        // outside on every side
+        for outside in [[-1.1, -0.5], [0.1, -0.5], [-0.5, -1.1], [-0.5, 0.1]] {
+            assert!(map.log_odds_at(Vector2::from(outside)).is_none());
+        }
+    }
+
+    #[test]
+    fn decay_relaxes_towards_prior() {
+        let mut map = Map::new(Vector2::zeros(), 1.0, 1.0, 0.25, 0.5);
+        *map.odds.get_mut(Cell::new(1, 1)) = Probability::new(0.95).log_odds();
+        *map.odds.get_mut(Cell::new(2, 2)) = Probability::new(0.1).log_odds();
+
+        let probability = |map: &Map, cell| map.odds.get(cell).probability().value();
+
+        let mut last = probability(&map, Cell::new(1, 1));
+        for _ in 0..50 {
+            map.decay(0.1);
+            let p = probability(&map, Cell::new(1, 1));
+            assert!(p < last && p > 0.5);
+            last = p;
+        }
+        assert_relative_eq!(last, 0.5, epsilon = 0.01);
+        assert_relative_eq!(probability(&map, Cell::new(2, 2)), 0.5, epsilon = 0.01);
+
+        // no decay keeps the map as it is
+        let before = map
+            .odds
+            .values()
+            .iter()
+            .map(|o| o.value())
+            .collect::<Vec<_>>();
+        map.decay(0.0);
+        let after = map
+            .odds
+            .values()
+            .iter()
+            .map(|o| o.value())
+            .collect::<Vec<_>>();
+        assert_eq!(before, after);
+    }
+
+    /// A single measurement straight ahead, one meter from a pose in the center of the first
+    /// cell of row 5. In a map with 0.1 m cells the hit is 10 cells away.
+    fn single_hit() -> (Observation, Pose) {
+        let observation = Observation {
+            id: 0,
+            measurements: vec![Measurement {
+                angle: 0.0,
+                distance: 1.0,
+                strength: 1.0,
+                valid: true,
+                phase: 0.0,
+            }],
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 0.0,
+            angle_increment: 0.0,
+            timestamp: 0.0,
+        };
+        let pose = Pose {
+            x: 0.05,
+            y: 0.55,
+            theta: 0.0,
+        };
+
+        (observation, pose)
+    }
+
+    #[test]
+    fn hits_are_as_thick_as_the_walls() {
+        let (observation, pose) = single_hit();
+
+        for (wall_thickness, occupied) in [(0.1, vec![10]), (0.3, vec![9, 10, 11])] {
+            let mut map = Map::new(Vector2::zeros(), 2.0, 1.0, 0.1, wall_thickness);
+            map.integrate(&observation, pose);
+
+            let row: Vec<Scalar> = (0..20)
+                .map(|column| map.odds.get(Cell::new(column, 5)).value() as Scalar)
+                .collect();
+            let marked: Vec<usize> = (0..20).filter(|&column| row[column] > 0.0).collect();
+            assert_eq!(marked, occupied, "wall thickness {wall_thickness}");
+
+            // free up to the wall and unknown behind it
+            assert!(row[..occupied[0]].iter().all(|&odds| odds < 0.0));
+            assert!(row[occupied[occupied.len() - 1] + 1..]
+                .iter()
+                .all(|&odds| odds == 0.0));
+        }
+    }
+
+    #[test]
+    fn log_odds_saturate_at_the_limits() {
+        let (observation, pose) = single_hit();
+
+        let mut map =
+            Map::new(Vector2::zeros(), 2.0, 1.0, 0.1, 0.1).with_log_odds_limits(-2.0, 3.5);
+        for _ in 0..100 {
+            map.integrate(&observation, pose);
+        }
+
+        assert_eq!(map.odds.get(Cell::new(10, 5)).value(), 3.5);
+        assert_eq!(map.odds.get(Cell::new(5, 5)).value(), -2.0);
+
+        // a few observations of the cell being free are enough to clear it again
+        let free = Observation {
+            measurements: vec![Measurement {
+                distance: 1.5,
+                ..observation.measurements[0]
+            }],
+            ..observation
+        };
+        for _ in 0..10 {
+            map.integrate(&free, pose);
+        }
+        assert!(map.odds.get(Cell::new(10, 5)).value() < 0.0);
+    }
+
+    #[test]
+    fn swapped_log_odds_limits_are_reordered() {
+        let (observation, pose) = single_hit();
+
+        let mut map =
+            Map::new(Vector2::zeros(), 2.0, 1.0, 0.1, 0.1).with_log_odds_limits(3.5, -2.0);
+        for _ in 0..100 {
+            map.integrate(&observation, pose);
+        }
+
+        assert_eq!(map.odds.get(Cell::new(10, 5)).value(), 3.5);
+        assert_eq!(map.odds.get(Cell::new(5, 5)).value(), -2.0);
+    }
+
+    #[test]
+    fn downscale_combines_blocks() {
+        let grid = GridData::from_vec(Vector2::new(5, 5), (0..25).collect()).unwrap();
+        let max = |block: &[&u32]| **block.iter().max().unwrap();
+
+        let downscaled = grid.downscale(2, max);
+        assert_eq!(downscaled.size(), Vector2::new(3, 3));
+        assert_eq!(*downscaled.get(Cell::new(0, 0)), 6);
+        assert_eq!(*downscaled.get(Cell::new(1, 0)), 8);
+        // the last block only covers a single cell
+        assert_eq!(*downscaled.get(Cell::new(2, 2)), 24);
+
+        // a stride of one keeps the grid as it is
+        assert_eq!(grid.downscale(1, max).values(), grid.values());
+
+        let grid = GridData::new_fill(Vector2::new(10, 7), 0u32);
+        assert_eq!(grid.downscale(3, max).size(), Vector2::new(4, 3));
+        assert_eq!(grid.downscale(20, max).size(), Vector2::new(1, 1));
+    }
+}
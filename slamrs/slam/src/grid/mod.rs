@@ -1,5 +1,6 @@
 #![allow(unused)]
 
+pub(crate) mod frontier;
 pub(crate) mod map;
 pub(crate) mod node;
 pub(crate) mod particle;
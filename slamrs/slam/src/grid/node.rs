@@ -1,17 +1,26 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use common::{
-    node::{Node, NodeConfig},
+    node::{ConfigUi, Node, NodeConfig, TopicDecl},
     robot::{Observation, Odometry, Pose},
 };
 use eframe::egui;
+use egui::{DragValue, Slider};
 use nalgebra::Vector2;
 use pubsub::{Publisher, Subscription};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use web_time::Instant;
 
 use super::{
-    map::GridData,
-    slam::{GridMapSlam, GridMapSlamConfig},
+    map::{GridData, HitCountMap},
+    slam::{GridMapSlam, GridMapSlamConfig, GridMapSnapshot},
+};
+use crate::{
+    publish::{MapPublishGate, MapPublishMode},
+    snapshot::{self, SnapshotError},
 };
 use common::math::Probability;
 
@@ -19,49 +28,401 @@ pub struct GridMapSlamNode {
     sub_obs_odom: Subscription<(Observation, Odometry)>,
     pub_pose: Publisher<Pose>,
     pub_map: Publisher<GridMapMessage>,
+    pub_hit_count: Option<Publisher<HitCountMapMessage>>,
     slam: GridMapSlam,
+    /// Only kept when there is someone to publish it to.
+    hit_count: Option<HitCountMap>,
     config: GridMapSlamConfig,
+    max_update_hz: Option<f32>,
+    last_update: Option<Instant>,
+    /// The motion of scans dropped by the throttling, carried over to the next update.
+    pending_odometry: Option<Odometry>,
+    min_strength: Option<f64>,
+    stable_rpm: Option<[f32; 2]>,
+    /// The number of scans dropped because the scanner speed was outside `stable_rpm`.
+    unstable_scans: usize,
+    min_motion_to_map: Option<f32>,
+    /// The distance travelled since the map was last updated, `None` before the first update.
+    motion_since_map: Option<f32>,
+    /// The number of scans not integrated into the map because the robot was not moving.
+    stationary_scans: usize,
+    map_publish: MapPublishGate,
+    snapshot_file: String,
+    /// The outcome of the last save or load, shown in the window.
+    snapshot_status: Option<String>,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct GridMapSlamNodeConfig {
     topic_pose: String,
     topic_observation_odometry: String,
     topic_map: String,
+    /// If set, the number of scan returns that landed in each cell is published here.
+    topic_hit_count: Option<String>,
     config: GridMapSlamConfig,
+    /// Limits how often scans are integrated into the map, any scans arriving in between
+    /// are dropped. Unlimited if not set.
+    #[serde(default, deserialize_with = "deserialize_max_update_hz")]
+    max_update_hz: Option<f32>,
+    /// Measurements weaker than this are ignored. All measurements are used if not set.
+    min_strength: Option<f64>,
+    /// Scans taken while the scanner speed is outside this `[min, max]` rpm band are dropped,
+    /// they are warped while the scanner spins up or down. Scans of unknown speed are kept.
+    stable_rpm: Option<[f32; 2]>,
+    /// Scans are only integrated into the map once the wheels travelled this far (meters) since
+    /// the last map update, the pose is still updated in between. This avoids reinforcing the
+    /// same cells while standing still. Every scan is integrated if not set.
+    min_motion_to_map: Option<f32>,
+    /// When the map (and hit count map) is published, the pose is published after every scan.
+    #[serde(default)]
+    publish_mode: MapPublishMode,
+    /// The file the map of the strongest particle (and its pose) is saved to and loaded from.
+    #[serde(default = "default_snapshot_file")]
+    snapshot_file: PathBuf,
+}
+
+fn default_snapshot_file() -> PathBuf {
+    PathBuf::from("grid_map.yaml")
+}
+
+/// A rate that is not positive would drop every scan after the first, so it is rejected rather
+/// than silently meaning something else.
+fn deserialize_max_update_hz<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f32>, D::Error> {
+    match Option::<f32>::deserialize(deserializer)? {
+        Some(hz) if hz.is_nan() || hz <= 0.0 => Err(serde::de::Error::custom(format!(
+            "invalid max_update_hz {hz}, expected a positive rate in Hz (leave it out for no limit)"
+        ))),
+        hz => Ok(hz),
+    }
 }
 
 impl NodeConfig for GridMapSlamNodeConfig {
     fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
         Box::new(GridMapSlamNode {
             sub_obs_odom: pubsub.subscribe(&self.topic_observation_odometry),
-            pub_pose: pubsub.publish(&self.topic_pose),
+            pub_pose: pubsub.publish_registered(&self.topic_pose),
             pub_map: pubsub.publish(&self.topic_map),
+            pub_hit_count: self
+                .topic_hit_count
+                .as_ref()
+                .map(|topic| pubsub.publish(topic)),
             slam: GridMapSlam::new(&self.config),
+            hit_count: self
+                .topic_hit_count
+                .as_ref()
+                .map(|_| new_hit_count_map(&self.config)),
             config: self.config.clone(),
+            max_update_hz: self.max_update_hz,
+            last_update: None,
+            pending_odometry: None,
+            min_strength: self.min_strength,
+            stable_rpm: self.stable_rpm,
+            unstable_scans: 0,
+            min_motion_to_map: self.min_motion_to_map,
+            motion_since_map: None,
+            stationary_scans: 0,
+            map_publish: MapPublishGate::new(self.publish_mode),
+            snapshot_file: self.snapshot_file.display().to_string(),
+            snapshot_status: None,
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = vec![
+            TopicDecl::subscribe::<(Observation, Odometry)>(&self.topic_observation_odometry),
+            TopicDecl::publish::<Pose>(&self.topic_pose),
+            TopicDecl::publish::<GridMapMessage>(&self.topic_map),
+        ];
+        if let Some(topic) = &self.topic_hit_count {
+            topics.push(TopicDecl::publish::<HitCountMapMessage>(topic));
+        }
+
+        topics
+    }
 }
 
-impl Node for GridMapSlamNode {
-    fn update(&mut self) {
-        if let Some(o) = self.sub_obs_odom.try_recv() {
-            self.slam.update(&o.0, o.1);
+fn new_hit_count_map(config: &GridMapSlamConfig) -> HitCountMap {
+    HitCountMap::new(
+        config.position,
+        config.width,
+        config.height,
+        config.resolution,
+    )
+}
+
+impl GridMapSlamNode {
+    /// Throws away all accumulated state and starts over with a map of the configured size.
+    fn reset(&mut self) {
+        self.slam = GridMapSlam::new(&self.config);
+        if let Some(hit_count) = &mut self.hit_count {
+            *hit_count = new_hit_count_map(&self.config);
+        }
+        self.last_update = None;
+        self.pending_odometry = None;
+        self.motion_since_map = None;
+        self.stationary_scans = 0;
+        // let everyone know about the new map right away
+        self.map_publish = MapPublishGate::new(self.map_publish.mode);
+        self.publish_pose();
+        self.publish_map();
+    }
+
+    fn save(&self) -> Result<(), SnapshotError> {
+        snapshot::save(&self.slam.snapshot(), Path::new(&self.snapshot_file))
+    }
 
-            self.pub_pose.publish(Arc::new(self.slam.estimated_pose()));
+    /// Replaces the map and the pose of all particles with the saved ones, to continue mapping
+    /// where an earlier session stopped.
+    fn load(&mut self) -> Result<(), SnapshotError> {
+        let snapshot: GridMapSnapshot = snapshot::load(Path::new(&self.snapshot_file))?;
+        let config = snapshot.map_config(&self.config);
+        self.slam.restore(snapshot)?;
 
-            self.pub_map.publish(Arc::new(GridMapMessage {
-                position: self.config.position,
-                resolution: self.config.resolution,
-                data: self.slam.estimated_likelihood(),
+        // the maps are published with the extent of the loaded one
+        self.config = config;
+        if let Some(hit_count) = &mut self.hit_count {
+            *hit_count = new_hit_count_map(&self.config);
+        }
+        self.map_publish = MapPublishGate::new(self.map_publish.mode);
+        self.publish_pose();
+        self.publish_map();
+        Ok(())
+    }
+
+    /// Returns true if not enough time has passed since the last update.
+    fn throttled(&self) -> bool {
+        match (self.max_update_hz, self.last_update) {
+            (Some(hz), Some(last)) => last.elapsed().as_secs_f32() < 1.0 / hz,
+            _ => false,
+        }
+    }
+
+    /// Adds the motion to the distance travelled since the last map update and returns true if
+    /// it is enough to integrate the scan into the map.
+    fn moved_enough(&mut self, odometry: &Odometry) -> bool {
+        let Some(min_motion) = self.min_motion_to_map else {
+            return true;
+        };
+
+        let motion = self
+            .motion_since_map
+            .map_or(f32::INFINITY, |m| m + odometry.travel());
+        if motion < min_motion {
+            self.motion_since_map = Some(motion);
+            false
+        } else {
+            self.motion_since_map = Some(0.0);
+            true
+        }
+    }
+
+    fn publish_pose(&mut self) {
+        self.pub_pose.publish(Arc::new(self.slam.estimated_pose()));
+    }
+
+    /// Publishes the maps if the publish mode allows it.
+    fn publish_map(&mut self) {
+        if !self.map_publish.ready() {
+            return;
+        }
+
+        self.pub_map.publish(Arc::new(GridMapMessage {
+            position: self.config.position.cast(),
+            resolution: nalgebra::convert(self.config.resolution),
+            data: self.slam.estimated_likelihood(),
+        }));
+
+        if let (Some(pub_hit_count), Some(hit_count)) = (&mut self.pub_hit_count, &self.hit_count) {
+            pub_hit_count.publish(Arc::new(HitCountMapMessage {
+                position: self.config.position.cast(),
+                resolution: nalgebra::convert(self.config.resolution),
+                data: hit_count.counts().clone(),
             }));
         }
     }
 
+    /// Updates the pose, and the map unless the scan is dropped or the robot stands still.
+    fn process(&mut self, o: &(Observation, Odometry)) {
+        // do not lose the motion of any dropped scans
+        let odometry = match self.pending_odometry.take() {
+            Some(pending) => pending.combine(&o.1),
+            None => o.1,
+        };
+
+        if self.throttled() {
+            self.pending_odometry = Some(odometry);
+            return;
+        }
+
+        if let Some(band) = self.stable_rpm {
+            if !o.0.rpm_within(band) {
+                self.unstable_scans += 1;
+                self.pending_odometry = Some(odometry);
+                return;
+            }
+        }
+
+        let observation = match self.min_strength {
+            Some(min_strength) => o.0.filter_by_strength(min_strength),
+            None => o.0.clone(),
+        };
+
+        let update_map = self.moved_enough(&odometry);
+        if !update_map {
+            self.stationary_scans += 1;
+        }
+
+        self.last_update = Some(Instant::now());
+        self.slam.update(&observation, odometry, update_map);
+        if update_map {
+            if let Some(hit_count) = &mut self.hit_count {
+                hit_count.integrate(&observation, self.slam.estimated_pose());
+            }
+            self.map_publish.changed();
+        }
+        self.map_publish.updated();
+        self.publish_pose();
+    }
+}
+
+impl Node for GridMapSlamNode {
+    fn update(&mut self) {
+        if let Some(o) = self.sub_obs_odom.try_recv() {
+            self.process(&o);
+        }
+
+        // also without a new scan, the rate limit may have expired since the map changed
+        self.publish_map();
+    }
+
     fn draw(&mut self, ui: &egui::Ui, world: &mut common::world::WorldObj<'_>) {
         egui::Window::new("Slam").show(ui.ctx(), |ui| {
             ui.label("[WIP]");
+
+            match self.max_update_hz {
+                Some(hz) => ui.label(format!("Max update rate: {hz:.1} Hz")),
+                None => ui.label("Max update rate: unlimited"),
+            };
+
+            if let Some([min, max]) = self.stable_rpm {
+                ui.label(format!(
+                    "Dropped {} scans outside {min:.0}-{max:.0} rpm",
+                    self.unstable_scans
+                ));
+            }
+
+            if self.min_motion_to_map.is_some() {
+                ui.label(format!(
+                    "Skipped mapping {} scans while stationary",
+                    self.stationary_scans
+                ));
+            }
+
+            self.map_publish.mode.ui(ui);
+
+            // the map value under the cursor, for debugging
+            let mouse = world.last_mouse_pos;
+            match self.slam.log_odds_at(mouse.coords.cast()) {
+                Some(odds) => ui.label(format!(
+                    "Cursor [{:.2}, {:.2}]: p = {:.3}, log odds = {:.2}",
+                    mouse.x,
+                    mouse.y,
+                    odds.probability().value(),
+                    odds.value()
+                )),
+                None => ui.label(format!(
+                    "Cursor [{:.2}, {:.2}]: outside the map",
+                    mouse.x, mouse.y
+                )),
+            };
+
+            ui.collapsing("Map", |ui| {
+                ui.label("Changing the map resets it.");
+                if self.config.ui(ui) {
+                    self.reset();
+                }
+            });
+
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Map File: ");
+                ui.text_edit_singleline(&mut self.snapshot_file);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.snapshot_status = Some(match self.save() {
+                        Ok(()) => format!("Saved to {}", self.snapshot_file),
+                        Err(e) => format!("Save failed: {e}"),
+                    });
+                }
+                if ui.button("Load").clicked() {
+                    self.snapshot_status = Some(match self.load() {
+                        Ok(()) => format!("Loaded {}", self.snapshot_file),
+                        Err(e) => format!("Load failed: {e}"),
+                    });
+                }
+            });
+            if let Some(status) = &self.snapshot_status {
+                ui.label(status);
+            }
+        });
+    }
+}
+
+impl ConfigUi for GridMapSlamConfig {
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Position (m): ");
+            changed |= ui
+                .add(DragValue::new(&mut self.position.x).speed(0.1))
+                .changed();
+            changed |= ui
+                .add(DragValue::new(&mut self.position.y).speed(0.1))
+                .changed();
         });
+
+        changed |= ui
+            .add(Slider::new(&mut self.width, 0.5..=50.0).text("Width (m)"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.height, 0.5..=50.0).text("Height (m)"))
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut self.resolution, 0.01..=0.5)
+                    .logarithmic(true)
+                    .text("Resolution (m)"),
+            )
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.n_particles, 1..=200).text("Particles"))
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut self.wall_thickness, 0.01..=0.5)
+                    .logarithmic(true)
+                    .text("Wall Thickness (m)"),
+            )
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.decay_rate, 0.0..=0.1).text("Decay Rate"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.log_odds_min, -10.0..=0.0).text("Min Log Odds"))
+            .changed();
+        changed |= ui
+            .add(Slider::new(&mut self.log_odds_max, 0.0..=10.0).text("Max Log Odds"))
+            .changed();
+
+        changed
     }
 }
 
@@ -70,3 +431,37 @@ pub struct GridMapMessage {
     pub resolution: f32,
     pub data: GridData<Probability>,
 }
+
+pub struct HitCountMapMessage {
+    pub position: Vector2<f32>,
+    pub resolution: f32,
+    pub data: GridData<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_update_hz_is_validated() {
+        let parse = |max_update_hz: &str| {
+            serde_yaml::from_str::<GridMapSlamNodeConfig>(&format!(
+                "topic_pose: pose
+topic_observation_odometry: scan
+topic_map: map
+config: {{position: [0.0, 0.0], width: 1.0, height: 1.0, resolution: 0.1, n_particles: 1}}
+{max_update_hz}"
+            ))
+            .map(|c| c.max_update_hz)
+        };
+
+        assert_eq!(parse("").unwrap(), None);
+        assert_eq!(parse("max_update_hz: 5.0").unwrap(), Some(5.0));
+        for invalid in ["0.0", "-1.0", ".nan"] {
+            assert!(
+                parse(&format!("max_update_hz: {invalid}")).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
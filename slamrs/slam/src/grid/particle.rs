@@ -71,6 +71,11 @@ impl<T: Clone> ParticleFilter<T> {
         &mut self.particles[index].value
     }
 
+    /// The number of particles in the filter.
+    pub fn number_of_particles(&self) -> usize {
+        self.particles.len()
+    }
+
     pub fn strongest_particle_idx(&self) -> usize {
         self.max_particle
     }
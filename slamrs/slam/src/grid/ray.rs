@@ -1,14 +1,15 @@
 use nalgebra::Vector2;
 
 use super::map::Cell;
+use crate::Scalar;
 
 pub struct GridRayIterator {
     /// Number of rows and columns in the grid to generate `Cell`s for.
     size: Vector2<usize>,
 
-    delta: Vector2<f32>,
+    delta: Vector2<Scalar>,
     increment: Vector2<isize>,
-    error: f32,
+    error: Scalar,
     x: isize,
     y: isize,
     remaining_cells: usize,
@@ -19,10 +20,10 @@ impl GridRayIterator {
     /// with the origin at the bottom left. The iterator values correspond to the center of each cell that is visited
     /// along the ray from start to end point.
     pub fn new(
-        x0: f32,
-        y0: f32,
-        x1: f32,
-        y1: f32,
+        x0: Scalar,
+        y0: Scalar,
+        x1: Scalar,
+        y1: Scalar,
         size: Vector2<usize>,
         additional_steps: usize,
     ) -> Self {
@@ -41,10 +42,10 @@ impl GridRayIterator {
         // decide based on case
         if delta.x == 0.0 {
             x_inc = 0;
-            error = f32::INFINITY;
+            error = Scalar::INFINITY;
         } else if x1 > x0 {
             x_inc = 1;
-            n += (x1.floor() - x as f32) as isize;
+            n += (x1.floor() - x as Scalar) as isize;
             error = ((x0.floor() + 1.0 - x0) * delta.y);
         } else {
             x_inc = -1;
@@ -54,7 +55,7 @@ impl GridRayIterator {
 
         if delta.y == 0.0 {
             y_inc = 0;
-            error -= f32::INFINITY;
+            error -= Scalar::INFINITY;
         } else if y1 > y0 {
             y_inc = 1;
             n += y1.floor() as isize - y;
@@ -65,6 +66,11 @@ impl GridRayIterator {
             error -= (y0 - y0.floor()) * delta.x;
         }
 
+        // a ray without length has no direction to continue in, only visit the start cell
+        if delta.x == 0.0 && delta.y == 0.0 {
+            n = 1;
+        }
+
         Self {
             size,
             delta,
@@ -78,7 +84,7 @@ impl GridRayIterator {
 }
 
 impl Iterator for GridRayIterator {
-    type Item = (Cell, Vector2<f32>);
+    type Item = (Cell, Vector2<Scalar>);
 
     fn next(&mut self) -> Option<Self::Item> {
         // make sure we do not leave the allowed range of x,y values
@@ -90,7 +96,7 @@ impl Iterator for GridRayIterator {
 
         if one_more {
             let cell = Cell::new(self.x as usize, self.y as usize);
-            let cell_center = Vector2::new(self.x as f32 + 0.5, self.y as f32 + 0.5);
+            let cell_center = Vector2::new(self.x as Scalar + 0.5, self.y as Scalar + 0.5);
 
             // move to next position
             if self.error > 0.0 {
@@ -109,3 +115,100 @@ impl Iterator for GridRayIterator {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SIZE: Vector2<usize> = Vector2::new(10, 10);
+
+    fn visited(
+        x0: Scalar,
+        y0: Scalar,
+        x1: Scalar,
+        y1: Scalar,
+        additional_steps: usize,
+    ) -> Vec<(Cell, Vector2<Scalar>)> {
+        GridRayIterator::new(x0, y0, x1, y1, SIZE, additional_steps).collect()
+    }
+
+    /// Builds the expected `(Cell, center)` pairs from a list of `(column, row)`.
+    fn expected(cells: &[(usize, usize)]) -> Vec<(Cell, Vector2<Scalar>)> {
+        cells
+            .iter()
+            .map(|&(x, y)| {
+                (
+                    Cell::new(x, y),
+                    Vector2::new(x as Scalar + 0.5, y as Scalar + 0.5),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn horizontal() {
+        assert_eq!(
+            visited(0.5, 0.5, 3.5, 0.5, 0),
+            expected(&[(0, 0), (1, 0), (2, 0), (3, 0)])
+        );
+        assert_eq!(
+            visited(3.5, 0.5, 1.0, 0.5, 0),
+            expected(&[(3, 0), (2, 0), (1, 0)])
+        );
+    }
+
+    #[test]
+    fn vertical() {
+        assert_eq!(
+            visited(2.5, 0.5, 2.5, 3.5, 0),
+            expected(&[(2, 0), (2, 1), (2, 2), (2, 3)])
+        );
+        assert_eq!(
+            visited(2.5, 3.5, 2.5, 1.2, 0),
+            expected(&[(2, 3), (2, 2), (2, 1)])
+        );
+    }
+
+    #[test]
+    fn diagonal() {
+        assert_eq!(
+            visited(0.2, 0.7, 3.6, 2.1, 0),
+            expected(&[(0, 0), (0, 1), (1, 1), (2, 1), (3, 1), (3, 2)])
+        );
+        assert_eq!(
+            visited(3.6, 2.1, 0.2, 0.7, 0),
+            expected(&[(3, 2), (3, 1), (2, 1), (1, 1), (0, 1), (0, 0)])
+        );
+    }
+
+    #[test]
+    fn endpoint_is_visited() {
+        for (x1, y1) in [(4.0, 0.5), (4.0, 4.0), (0.5, 4.0), (6.3, 2.9), (1.0, 7.99)] {
+            let last = visited(0.5, 0.5, x1, y1, 0).pop().unwrap().0;
+            assert_eq!(last, Cell::new(x1 as usize, y1 as usize));
+        }
+    }
+
+    #[test]
+    fn additional_steps() {
+        assert_eq!(
+            visited(0.5, 0.5, 2.5, 0.5, 2),
+            expected(&[(0, 0), (1, 0), (2, 0), (3, 0), (4, 0)])
+        );
+    }
+
+    #[test]
+    fn zero_length() {
+        assert_eq!(visited(1.5, 1.5, 1.5, 1.5, 2), expected(&[(1, 1)]));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        // stops when leaving the grid
+        assert_eq!(visited(8.5, 0.5, 12.5, 0.5, 0), expected(&[(8, 0), (9, 0)]));
+        assert_eq!(visited(1.5, 1.5, 1.5, -3.0, 0), expected(&[(1, 1), (1, 0)]));
+
+        // starting outside of the grid does not visit anything
+        assert_eq!(visited(-1.5, 0.5, 2.5, 0.5, 0), expected(&[]));
+    }
+}
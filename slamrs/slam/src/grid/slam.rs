@@ -1,6 +1,11 @@
-use common::robot::{Observation, Odometry, Pose};
-use nalgebra::Vector2;
-use serde::Deserialize;
+use common::{
+    math::LogOdds,
+    robot::{Observation, Odometry, Pose},
+};
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{snapshot::SnapshotError, Scalar};
 
 use super::{
     map::{GridData, Map},
@@ -13,15 +18,46 @@ type PoseMap = (Pose, Map);
 pub struct GridMapSlam {
     // map: Map,
     filter: ParticleFilter<PoseMap>,
+    wall_thickness: Scalar,
+    decay_rate: Scalar,
+    log_odds_limits: (f64, f64),
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct GridMapSlamConfig {
-    pub position: Vector2<f32>,
-    pub width: f32,
-    pub height: f32,
-    pub resolution: f32,
-    n_particles: usize,
+    pub position: Vector2<Scalar>,
+    pub width: Scalar,
+    pub height: Scalar,
+    pub resolution: Scalar,
+    pub n_particles: usize,
+    /// How thick (meters) the walls seen by the scanner are drawn into the map, the cells within
+    /// half of this from a hit are marked as occupied.
+    #[serde(default = "default_wall_thickness")]
+    pub wall_thickness: Scalar,
+    /// Every map update moves all cells this fraction (0 to 1) of the way back to unknown, so that
+    /// obstacles that moved fade away if they are not seen again. No decay if 0.
+    #[serde(default)]
+    pub decay_rate: Scalar,
+    /// The log odds of the cells are kept between these, so that an obstacle that is removed
+    /// can be cleared again after having been seen many times.
+    #[serde(default = "default_log_odds_min")]
+    pub log_odds_min: f64,
+    #[serde(default = "default_log_odds_max")]
+    pub log_odds_max: f64,
+}
+
+fn default_wall_thickness() -> Scalar {
+    0.04
+}
+
+/// A free probability of about 0.12
+fn default_log_odds_min() -> f64 {
+    -2.0
+}
+
+/// An occupied probability of about 0.97
+fn default_log_odds_max() -> f64 {
+    3.5
 }
 
 impl GridMapSlam {
@@ -36,18 +72,24 @@ impl GridMapSlam {
                         config.width,
                         config.height,
                         config.resolution,
-                    ),
+                        config.wall_thickness,
+                    )
+                    .with_log_odds_limits(config.log_odds_min, config.log_odds_max),
                 ),
             ),
+            wall_thickness: config.wall_thickness,
+            decay_rate: config.decay_rate,
+            log_odds_limits: (config.log_odds_min, config.log_odds_max),
         }
     }
 
+    /// Moves the particles according to the odometry and weighs them by the observation. The
+    /// observation is only integrated into the maps if `update_map` is set.
     #[tracing::instrument(skip_all)]
-    pub fn update(&mut self, z: &Observation, u: Odometry) {
+    pub fn update(&mut self, z: &Observation, u: Odometry, update_map: bool) {
         // self.map.integrate(observation, Pose::default());
 
-        let update_map = true;
-
+        let decay_rate = self.decay_rate;
         self.filter.update(|(pose, map)| {
             let initial_pose = *pose;
 
@@ -63,6 +105,10 @@ impl GridMapSlam {
             // * SensorModel.probHeading(z.getHeading(), pose.theta); // Compass measurements are not included
 
             if update_map {
+                if decay_rate > 0.0 {
+                    map.decay(decay_rate);
+                }
+
                 // integrate the measurement into the particles map
                 map.integrate(z, new_pose);
             }
@@ -87,7 +133,66 @@ impl GridMapSlam {
             .likelihood()
     }
 
-    pub fn map_position(&self) -> Vector2<f32> {
+    /// Captures the pose and the map of the strongest particle.
+    pub fn snapshot(&self) -> GridMapSnapshot {
+        let (pose, map) = self
+            .filter
+            .particle_value(self.filter.strongest_particle_idx());
+
+        let odds = map.log_odds();
+        GridMapSnapshot {
+            version: SNAPSHOT_VERSION,
+            pose: (*pose).into(),
+            position: map.position(),
+            resolution: map.resolution(),
+            size: odds.size(),
+            log_odds: odds.values().iter().map(|o| o.value()).collect(),
+        }
+    }
+
+    /// Replaces the state of all particles with the one in the snapshot. Fails if the snapshot
+    /// is inconsistent, in which case the current state is kept.
+    pub fn restore(&mut self, snapshot: GridMapSnapshot) -> Result<(), SnapshotError> {
+        if snapshot.version == 0 && snapshot.size.x != snapshot.size.y {
+            return Err(SnapshotError::OldCellOrder);
+        }
+
+        let (expected, actual) = (snapshot.size.x * snapshot.size.y, snapshot.log_odds.len());
+        let odds = GridData::from_vec(
+            snapshot.size,
+            snapshot.log_odds.into_iter().map(LogOdds::new).collect(),
+        )
+        .ok_or(SnapshotError::SizeMismatch {
+            field: "log_odds",
+            expected,
+            actual,
+        })?;
+
+        let value = (
+            Pose::from(snapshot.pose),
+            Map::from_log_odds(
+                snapshot.position,
+                snapshot.resolution,
+                self.wall_thickness,
+                odds,
+            )
+            .with_log_odds_limits(self.log_odds_limits.0, self.log_odds_limits.1),
+        );
+        self.filter = ParticleFilter::new(self.filter.number_of_particles(), value);
+
+        Ok(())
+    }
+
+    /// The log odds at a position in the world in the map of the strongest particle, `None`
+    /// outside of the map.
+    pub fn log_odds_at(&self, world: Vector2<Scalar>) -> Option<LogOdds> {
+        self.filter
+            .particle_value(self.filter.strongest_particle_idx())
+            .1
+            .log_odds_at(world)
+    }
+
+    pub fn map_position(&self) -> Vector2<Scalar> {
         // TODO: the position never changes for the particles...
         self.filter
             .particle_value(self.filter.strongest_particle_idx())
@@ -95,3 +200,167 @@ impl GridMapSlam {
             .position()
     }
 }
+
+/// The current [`GridMapSnapshot::version`].
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// The state of a [`GridMapSlam`], to save and later restore a session.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GridMapSnapshot {
+    /// Missing (0) in snapshots saved before the cells of non-square maps were stored row by
+    /// row. Those can not be restored, square maps are stored the same way in both.
+    #[serde(default)]
+    version: u32,
+    /// The estimated pose as `[x, y, theta]`.
+    pose: Vector3<f32>,
+    /// The position of the lower left corner of the map.
+    position: Vector2<Scalar>,
+    resolution: Scalar,
+    /// The size of the map in cells.
+    size: Vector2<usize>,
+    /// The log odds of each cell, in row-major order.
+    log_odds: Vec<f64>,
+}
+
+impl GridMapSnapshot {
+    /// A copy of `config` with the extent and resolution of the saved map, which can differ from
+    /// the configured ones.
+    pub fn map_config(&self, config: &GridMapSlamConfig) -> GridMapSlamConfig {
+        GridMapSlamConfig {
+            position: self.position,
+            width: self.size.x as Scalar * self.resolution,
+            height: self.size.y as Scalar * self.resolution,
+            resolution: self.resolution,
+            ..config.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::robot::Measurement;
+
+    use super::*;
+
+    #[test]
+    fn snapshot_round_trip() {
+        let config = GridMapSlamConfig {
+            position: Vector2::new(-1.0, -1.0),
+            width: 2.0,
+            height: 2.0,
+            resolution: 0.1,
+            n_particles: 3,
+            wall_thickness: 0.2,
+            decay_rate: 0.0,
+            log_odds_min: -2.0,
+            log_odds_max: 3.5,
+        };
+        let mut slam = GridMapSlam::new(&config);
+
+        let observation = Observation {
+            id: 0,
+            measurements: (0..36)
+                .map(|i| Measurement {
+                    angle: (i as f64 * 10.0).to_radians(),
+                    distance: 0.5,
+                    strength: 1.0,
+                    valid: true,
+                    phase: i as f32 / 36.0,
+                })
+                .collect(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 350f64.to_radians(),
+            angle_increment: 10f64.to_radians(),
+            timestamp: 0.0,
+        };
+        slam.update(&observation, Odometry::new(0.01, 0.02, 0.1), true);
+
+        let snapshot = slam.snapshot();
+        let yaml = serde_yaml::to_string(&snapshot).unwrap();
+
+        let mut restored = GridMapSlam::new(&config);
+        restored
+            .restore(serde_yaml::from_str(&yaml).unwrap())
+            .unwrap();
+
+        let (pose, expected) = (slam.estimated_pose(), slam.estimated_likelihood());
+        let (restored_pose, actual) = (restored.estimated_pose(), restored.estimated_likelihood());
+        assert_eq!(restored_pose.x, pose.x);
+        assert_eq!(restored_pose.y, pose.y);
+        assert_eq!(restored_pose.theta, pose.theta);
+        assert_eq!(actual.size(), expected.size());
+        assert_eq!(actual.values(), expected.values());
+        assert_eq!(restored.map_position(), slam.map_position());
+
+        // a map loaded into a node with a different configuration keeps its own extent
+        let other = GridMapSlamConfig {
+            position: Vector2::zeros(),
+            width: 5.0,
+            resolution: 0.5,
+            ..config.clone()
+        };
+        let map_config = snapshot.map_config(&other);
+        assert_eq!(map_config.position, config.position);
+        assert_eq!(map_config.resolution, config.resolution);
+        assert!((map_config.width - config.width).abs() < 1e-6);
+        assert!((map_config.height - config.height).abs() < 1e-6);
+        assert_eq!(map_config.n_particles, other.n_particles);
+    }
+
+    #[test]
+    fn restore_rejects_inconsistent_size() {
+        let config = GridMapSlamConfig {
+            position: Vector2::zeros(),
+            width: 1.0,
+            height: 1.0,
+            resolution: 0.5,
+            n_particles: 1,
+            wall_thickness: 1.0,
+            decay_rate: 0.0,
+            log_odds_min: -2.0,
+            log_odds_max: 3.5,
+        };
+        let mut slam = GridMapSlam::new(&config);
+
+        let mut snapshot = slam.snapshot();
+        snapshot.log_odds.pop();
+
+        assert!(slam.restore(snapshot).is_err());
+    }
+
+    #[test]
+    fn restore_rejects_old_non_square_maps() {
+        let config = GridMapSlamConfig {
+            position: Vector2::zeros(),
+            width: 1.0,
+            height: 1.0,
+            resolution: 0.5,
+            n_particles: 1,
+            wall_thickness: 1.0,
+            decay_rate: 0.0,
+            log_odds_min: -2.0,
+            log_odds_max: 3.5,
+        };
+        let mut slam = GridMapSlam::new(&config);
+        let old = |size: &str, cells: usize| {
+            let yaml = format!(
+                "pose: [0.0, 0.0, 0.0]\nposition: [0.0, 0.0]\nresolution: 0.5\nsize: {size}\n\
+                 log_odds: {:?}",
+                vec![0.5; cells]
+            );
+            serde_yaml::from_str::<GridMapSnapshot>(&yaml).unwrap()
+        };
+
+        // the cells of square maps were already stored in the same order
+        assert!(slam.restore(old("[2, 2]", 4)).is_ok());
+        assert!(matches!(
+            slam.restore(old("[3, 2]", 6)),
+            Err(SnapshotError::OldCellOrder)
+        ));
+
+        let mut current = old("[3, 2]", 6);
+        current.version = SNAPSHOT_VERSION;
+        assert!(slam.restore(current).is_ok());
+    }
+}
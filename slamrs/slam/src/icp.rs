@@ -10,11 +10,16 @@ use kd_tree::KdMap;
 use nalgebra::{Matrix1, Matrix2, Matrix2x3, Matrix2xX, Matrix3, Vector2, Vector3};
 use serde::Deserialize;
 
-/// Specifies parameters to use during the ICP computation.
-#[derive(Deserialize, Clone, Copy)]
+use crate::Scalar;
+
+/// Specifies parameters to use during the ICP computation. Parameters left out of a config keep
+/// their default.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
 pub struct IcpParameters {
     pub correspondence_weights: CorrespondenceWeight,
     pub iterations: usize,
+    pub correspondence_backend: CorrespondenceBackend,
 }
 
 impl Default for IcpParameters {
@@ -22,21 +27,41 @@ impl Default for IcpParameters {
         Self {
             correspondence_weights: CorrespondenceWeight::Uniform,
             iterations: 10,
+            correspondence_backend: CorrespondenceBackend::default(),
         }
     }
 }
 
-#[derive(Deserialize, Clone, Copy)]
+/// How to search for the closest reference point of each point.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum CorrespondenceBackend {
+    /// Builds a k-d tree of the reference points once per scan match.
+    #[default]
+    KdTree,
+
+    /// Compares each point to all reference points, O(n·m) but without the cost of building a
+    /// tree. Can be faster for sparse scans matched against few reference points, compare the
+    /// two with the `correspondences` benchmark (`cargo bench -p slam`).
+    BruteForce,
+}
+
+impl CorrespondenceBackend {
+    fn use_kd_tree(&self) -> bool {
+        *self == CorrespondenceBackend::KdTree
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
 pub enum CorrespondenceWeight {
     /// All weights are 1.0
     Uniform,
 
     /// Weight is a step function. Below the threshold (in error norm terms) the weight is 1.0. Above it is 0.0.
-    Step { threshold: f32 },
+    Step { threshold: Scalar },
 }
 
 impl CorrespondenceWeight {
-    fn weight(&self, error: Matrix1<f32>) -> f32 {
+    fn weight(&self, error: Matrix1<Scalar>) -> Scalar {
         match self {
             CorrespondenceWeight::Uniform => 1.0,
             CorrespondenceWeight::Step { threshold } => {
@@ -52,14 +77,16 @@ impl CorrespondenceWeight {
 
 #[derive(Debug)]
 pub struct IcpResult {
-    pub transformation: Vector3<f32>,
-    pub transformed_points: Matrix2xX<f32>,
-    pub chi_values: Vec<f32>,
+    pub transformation: Vector3<Scalar>,
+    pub transformed_points: Matrix2xX<Scalar>,
+    pub chi_values: Vec<Scalar>,
+    /// Number of correspondences used in the last iteration.
+    pub correspondences: usize,
     pub execution_time: Duration,
 }
 
-fn matrix_to_kdmap(matrix: &Matrix2xX<f32>) -> KdMap<[f32; 2], usize> {
-    let s: Vec<([f32; 2], usize)> = matrix
+pub(crate) fn matrix_to_kdmap(matrix: &Matrix2xX<Scalar>) -> KdMap<[Scalar; 2], usize> {
+    let s: Vec<([Scalar; 2], usize)> = matrix
         .column_iter()
         .enumerate()
         .map(|(i, c)| ([c.x, c.y], i))
@@ -67,7 +94,10 @@ fn matrix_to_kdmap(matrix: &Matrix2xX<f32>) -> KdMap<[f32; 2], usize> {
     KdMap::build_by_ordered_float(s)
 }
 
-fn transform_points(points: &Matrix2xX<f32>, x: Vector3<f32>) -> Matrix2xX<f32> {
+pub(crate) fn transform_points(
+    points: &Matrix2xX<Scalar>,
+    x: Vector3<Scalar>,
+) -> Matrix2xX<Scalar> {
     // perform rotation
     let mut p = R(x[2]) * points;
 
@@ -78,57 +108,183 @@ fn transform_points(points: &Matrix2xX<f32>, x: Vector3<f32>) -> Matrix2xX<f32>
     p
 }
 
+/// The reference points of a scan match together with the normals and the search structure
+/// computed from them, so that many scans can be matched against the same reference without
+/// recomputing everything each time.
+pub struct IcpReference {
+    points: Matrix2xX<Scalar>,
+    normals: Matrix2xX<Scalar>,
+    /// `None` when searching by brute force
+    tree: Option<KdMap<[Scalar; 2], usize>>,
+}
+
+impl IcpReference {
+    pub fn new(points: Matrix2xX<Scalar>, backend: CorrespondenceBackend) -> Self {
+        Self {
+            normals: compute_normals(&points),
+            tree: backend.use_kd_tree().then(|| matrix_to_kdmap(&points)),
+            points,
+        }
+    }
+
+    pub fn points(&self) -> &Matrix2xX<Scalar> {
+        &self.points
+    }
+
+    /// True if this was built the way `backend` would build it for the same points.
+    pub fn built_for(&self, backend: CorrespondenceBackend) -> bool {
+        backend.use_kd_tree() == self.tree.is_some()
+    }
+
+    /// The squared distance from `point` to the closest reference point, `None` if there are no
+    /// reference points.
+    pub fn nearest_squared_distance(&self, point: [Scalar; 2]) -> Option<Scalar> {
+        match &self.tree {
+            Some(tree) => tree.nearest(&point).map(|n| n.squared_distance),
+            None => self
+                .points
+                .column_iter()
+                .map(|q| (q.x - point[0]).powi(2) + (q.y - point[1]).powi(2))
+                .min_by(Scalar::total_cmp),
+        }
+    }
+}
+
 /// Returns the pose required to translate points to be as close to the reference points as possible.
 pub fn icp_point_to_normal(
-    points: &Matrix2xX<f32>,
-    reference_points: &Matrix2xX<f32>,
-    initial_pose: Vector3<f32>,
+    points: &Matrix2xX<Scalar>,
+    reference_points: &Matrix2xX<Scalar>,
+    initial_pose: Vector3<Scalar>,
+    params: IcpParameters,
+) -> IcpResult {
+    let normals = compute_normals(reference_points);
+    let tree = params
+        .correspondence_backend
+        .use_kd_tree()
+        .then(|| matrix_to_kdmap(reference_points));
+
+    match_to_reference(
+        points,
+        reference_points,
+        &normals,
+        tree.as_ref(),
+        initial_pose,
+        params,
+    )
+}
+
+/// Same as [`icp_point_to_normal`] but with the reference already prepared. Note that the search
+/// structure of the reference is used regardless of `params.correspondence_backend`.
+pub fn icp_point_to_normal_prebuilt(
+    points: &Matrix2xX<Scalar>,
+    reference: &IcpReference,
+    initial_pose: Vector3<Scalar>,
+    params: IcpParameters,
+) -> IcpResult {
+    match_to_reference(
+        points,
+        &reference.points,
+        &reference.normals,
+        reference.tree.as_ref(),
+        initial_pose,
+        params,
+    )
+}
+
+/// The scan match shared by [`icp_point_to_normal`] and [`icp_point_to_normal_prebuilt`], with
+/// the parts of the reference borrowed separately so that the points are never copied.
+fn match_to_reference(
+    points: &Matrix2xX<Scalar>,
+    reference_points: &Matrix2xX<Scalar>,
+    q_normals: &Matrix2xX<Scalar>,
+    tree: Option<&KdMap<[Scalar; 2], usize>>,
+    initial_pose: Vector3<Scalar>,
     params: IcpParameters,
 ) -> IcpResult {
     let start_time = Instant::now();
 
     let mut x = initial_pose;
 
-    let q_normals = compute_normals(reference_points);
-    let q_tree = matrix_to_kdmap(reference_points);
-
-    let mut chi_values: Vec<f32> = Vec::with_capacity(params.iterations);
+    let mut chi_values: Vec<Scalar> = Vec::with_capacity(params.iterations);
+    let mut n_correspondences = 0;
+    let mut lambda = INITIAL_LAMBDA;
     for _ in 0..params.iterations {
         // transform the original points by the accumulated x
         let p_copy = transform_points(points, x);
 
-        let correspondences = find_correspondences(&p_copy, &q_tree);
+        let correspondences = match tree {
+            Some(q_tree) => find_correspondences(&p_copy, q_tree),
+            None => find_correspondences_brute_force(&p_copy, reference_points),
+        };
+        n_correspondences = correspondences.len();
 
         // let s = prepare_system(x, p, q, &correspondences);
-        let s = prepare_system_normals(
-            x,
-            points,
-            reference_points,
-            &correspondences,
-            &q_normals,
-            &params,
-        );
-
-        let dx = least_squares(s.hessian, s.gradient);
-        x += dx;
-
-        // normalize the angle
-        x[2] = f32::atan2(x[2].sin(), x[2].cos());
+        let system = |x| {
+            prepare_system_normals(
+                x,
+                points,
+                reference_points,
+                &correspondences,
+                q_normals,
+                &params,
+            )
+        };
+        let s = system(x);
 
         // log metrics
         chi_values.push(s.chi);
+
+        let Some(dx) = least_squares_lm(s.hessian, s.gradient, lambda) else {
+            // could not be solved even with the damping, try again with more of it
+            lambda *= LAMBDA_FACTOR;
+            continue;
+        };
+
+        let mut candidate = x + dx;
+
+        // normalize the angle
+        candidate[2] = Scalar::atan2(candidate[2].sin(), candidate[2].cos());
+
+        // only take the step if it reduces the error, otherwise dampen more (towards gradient
+        // descent) and try again with the next iteration
+        if system(candidate).weighted_chi <= s.weighted_chi {
+            x = candidate;
+            lambda /= LAMBDA_FACTOR;
+        } else {
+            lambda *= LAMBDA_FACTOR;
+        }
     }
 
     IcpResult {
         transformation: x,
         transformed_points: transform_points(points, x),
         chi_values,
+        correspondences: n_correspondences,
         execution_time: start_time.elapsed(),
     }
 }
 
+/// The damping of the first Levenberg-Marquardt step, see [`least_squares_lm`].
+const INITIAL_LAMBDA: Scalar = 1e-4;
+
+/// The damping is divided by this after a step that reduced the error and multiplied by it
+/// after a step that did not.
+const LAMBDA_FACTOR: Scalar = 10.0;
+
+/// The number of nearest neighbors to consider when looking for equidistant reference points.
+const TIE_CANDIDATES: usize = 4;
+
+/// Squared distances closer than this are considered equal when breaking ties.
+const TIE_EPSILON: Scalar = 1e-9;
+
 /// For each point in `p`, finds the closest point in `q` using euclidean distance. Returns tuples of (p,q) indices with the correspondences
-fn find_correspondences(p: &Matrix2xX<f32>, q: &KdMap<[f32; 2], usize>) -> Vec<(usize, usize)> {
+///
+/// If several points in `q` are equally close, the one with the lowest index is chosen so that
+/// the result does not depend on how the Kd-tree happens to be built.
+fn find_correspondences(
+    p: &Matrix2xX<Scalar>,
+    q: &KdMap<[Scalar; 2], usize>,
+) -> Vec<(usize, usize)> {
     let mut c = Vec::with_capacity(p.len());
 
     if p.is_empty() || q.is_empty() {
@@ -136,31 +292,78 @@ fn find_correspondences(p: &Matrix2xX<f32>, q: &KdMap<[f32; 2], usize>) -> Vec<(
     }
 
     for (i_p, p_p) in p.column_iter().enumerate() {
-        let nearest = q
-            .nearest(&[p_p.x, p_p.y])
-            .expect("Could not find nearest neighbor in Kd-tree");
+        let candidates = q.nearests(&[p_p.x, p_p.y], TIE_CANDIDATES);
+
+        let Some(closest) = candidates
+            .iter()
+            .map(|n| n.squared_distance)
+            .min_by(Scalar::total_cmp)
+        else {
+            continue;
+        };
+
+        let nearest = candidates
+            .iter()
+            .filter(|n| n.squared_distance - closest <= TIE_EPSILON)
+            .map(|n| n.item.1)
+            .min();
+
+        if let Some(i_q) = nearest {
+            c.push((i_p, i_q));
+        }
+    }
+    c
+}
+
+/// Same as [`find_correspondences`] but compares each point in `p` to all points in `q`.
+fn find_correspondences_brute_force(
+    p: &Matrix2xX<Scalar>,
+    q: &Matrix2xX<Scalar>,
+) -> Vec<(usize, usize)> {
+    let mut c = Vec::with_capacity(p.len());
+
+    if q.is_empty() {
+        return c;
+    }
+
+    for (i_p, p_p) in p.column_iter().enumerate() {
+        let mut nearest: Option<(usize, Scalar)> = None;
+        for (i_q, q_p) in q.column_iter().enumerate() {
+            let d = (q_p - p_p).norm_squared();
 
-        c.push((i_p, nearest.item.1));
+            // only a clearly closer point replaces the first one, the one with the lowest index
+            if nearest.map_or(true, |(_, closest)| d < closest - TIE_EPSILON) {
+                nearest = Some((i_q, d));
+            }
+        }
+
+        if let Some((i_q, _)) = nearest {
+            c.push((i_p, i_q));
+        }
     }
     c
 }
 
-fn dR(theta: f32) -> Matrix2<f32> {
+fn dR(theta: Scalar) -> Matrix2<Scalar> {
     Matrix2::new(-theta.sin(), -theta.cos(), theta.cos(), -theta.sin())
 }
 
-fn R(theta: f32) -> Matrix2<f32> {
+fn R(theta: Scalar) -> Matrix2<Scalar> {
     Matrix2::new(theta.cos(), -theta.sin(), theta.sin(), theta.cos())
 }
 
-fn jacobian(x: Vector3<f32>, p_point: Vector2<f32>) -> Matrix2x3<f32> {
+fn jacobian(x: Vector3<Scalar>, p_point: Vector2<Scalar>) -> Matrix2x3<Scalar> {
     let mut J = Matrix2x3::identity();
     let t = dR(x[2]) * p_point;
     J.set_column(2, &Vector2::new(t.x, t.y));
     J
 }
 
-fn error(x: Vector3<f32>, p_point: Vector2<f32>, q_point: Vector2<f32>) -> Vector2<f32> {
+fn error(
+    x: Vector3<Scalar>,
+    p_point: Vector2<Scalar>,
+    q_point: Vector2<Scalar>,
+) -> Vector2<Scalar> {
     let r = R(x[2]);
     let tr = x.xy();
 
@@ -170,22 +373,25 @@ fn error(x: Vector3<f32>, p_point: Vector2<f32>, q_point: Vector2<f32>) -> Vecto
 }
 
 struct PreparedSystem {
-    hessian: Matrix3<f32>,
-    gradient: Vector3<f32>,
-    chi: f32,
+    hessian: Matrix3<Scalar>,
+    gradient: Vector3<Scalar>,
+    chi: Scalar,
+    /// The error actually minimized, i.e. with the correspondence weights applied.
+    weighted_chi: Scalar,
 }
 
 // Computes the hessian and the gradient of the system based on the provided correspondences and the transformation (translation + rotation) x
 fn prepare_system(
-    x: Vector3<f32>,
-    p: &Matrix2xX<f32>,
-    q: &Matrix2xX<f32>,
+    x: Vector3<Scalar>,
+    p: &Matrix2xX<Scalar>,
+    q: &Matrix2xX<Scalar>,
     c: &[(usize, usize)],
     params: &IcpParameters,
 ) -> PreparedSystem {
     let mut H = Matrix3::zeros();
     let mut g = Vector3::zeros();
-    let mut chi = 0f32;
+    let mut chi: Scalar = 0.0;
+    let mut weighted_chi: Scalar = 0.0;
 
     for &(i, j) in c {
         let p_point = p.column(i);
@@ -199,31 +405,33 @@ fn prepare_system(
         g += weight * J.transpose() * e;
 
         chi += e.dot(&e);
+        weighted_chi += weight * e.dot(&e);
     }
 
     PreparedSystem {
         hessian: H,
         gradient: g,
         chi,
+        weighted_chi,
     }
 }
 
-fn least_squares(hessian: Matrix3<f32>, gradient: Vector3<f32>) -> Vector3<f32> {
-    lstsq::lstsq(&hessian, &(-gradient), 1e-8)
-        .expect("Could not solve least squares")
-        .solution
-}
-
-fn least_squares_lm(hessian: Matrix3<f32>, gradient: Vector3<f32>) -> Vector3<f32> {
-    let lambda: f32 = 0.0;
+/// Solves for the step that minimizes the error, with `lambda * I` added to the hessian
+/// (Levenberg-Marquardt). The damping keeps the system solvable when it is degenerate, e.g. when
+/// all correspondences lie on a line, and shortens the step towards gradient descent. Returns
+/// `None` if the system could not be solved.
+fn least_squares_lm(
+    hessian: Matrix3<Scalar>,
+    gradient: Vector3<Scalar>,
+    lambda: Scalar,
+) -> Option<Vector3<Scalar>> {
     let lhs = hessian + lambda * Matrix3::identity();
     let rhs = -gradient;
 
-    let r = lstsq::lstsq(&lhs, &rhs, 1e-8).expect("Could not solve least squares");
-    r.solution
+    lstsq::lstsq(&lhs, &rhs, 1e-8).ok().map(|r| r.solution)
 }
 
-fn compute_normals(points: &Matrix2xX<f32>) -> Matrix2xX<f32> {
+fn compute_normals(points: &Matrix2xX<Scalar>) -> Matrix2xX<Scalar> {
     // let mut normals = Vec::with_capacity(points.len());
 
     let mut normals = Matrix2xX::zeros(points.ncols());
@@ -254,16 +462,17 @@ fn compute_normals(points: &Matrix2xX<f32>) -> Matrix2xX<f32> {
 }
 
 fn prepare_system_normals(
-    x: Vector3<f32>,
-    p: &Matrix2xX<f32>,
-    q: &Matrix2xX<f32>,
+    x: Vector3<Scalar>,
+    p: &Matrix2xX<Scalar>,
+    q: &Matrix2xX<Scalar>,
     c: &[(usize, usize)],
-    q_normals: &Matrix2xX<f32>,
+    q_normals: &Matrix2xX<Scalar>,
     params: &IcpParameters,
 ) -> PreparedSystem {
     let mut H = Matrix3::zeros();
     let mut g = Vector3::zeros();
-    let mut chi = 0f32;
+    let mut chi: Scalar = 0.0;
+    let mut weighted_chi: Scalar = 0.0;
 
     for &(i, j) in c {
         let p_point = p.column(i);
@@ -278,12 +487,14 @@ fn prepare_system_normals(
         g += weight * J.transpose() * e;
 
         chi += e.dot(&e);
+        weighted_chi += weight * e.dot(&e);
     }
 
     PreparedSystem {
         hessian: H,
         gradient: g,
         chi,
+        weighted_chi,
     }
 }
 
@@ -293,6 +504,18 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn partial_parameters_use_defaults() {
+        let parameters: IcpParameters = serde_yaml::from_str("iterations: 3").unwrap();
+        assert!(
+            parameters
+                == IcpParameters {
+                    iterations: 3,
+                    ..IcpParameters::default()
+                }
+        );
+    }
+
     #[test]
     fn it_works() {
         let p = Matrix2xX::from_columns(&[
@@ -318,6 +541,7 @@ mod tests {
             IcpParameters {
                 correspondence_weights: CorrespondenceWeight::Uniform,
                 iterations: 10,
+                correspondence_backend: CorrespondenceBackend::KdTree,
             },
         );
 
@@ -325,4 +549,100 @@ mod tests {
 
         // assert_eq!(result, 4);
     }
+
+    #[test]
+    fn degenerate_system_keeps_pose() {
+        // too few reference points to compute any normals, so nothing constrains the pose
+        let p = Matrix2xX::from_columns(&[Vector2::new(0.0, 0.0), Vector2::new(1.0, 0.0)]);
+        let q = Matrix2xX::from_columns(&[Vector2::new(0.5, 0.5), Vector2::new(1.5, 0.5)]);
+
+        let initial = Vector3::new(0.1, -0.2, 0.3);
+        let r = icp_point_to_normal(&p, &q, initial, IcpParameters::default());
+
+        assert_relative_eq!(r.transformation, initial);
+        assert_eq!(r.chi_values.len(), 10);
+    }
+
+    #[test]
+    fn prebuilt_reference_can_be_reused() {
+        let q = Matrix2xX::from_fn(64, |r, c| if r == 0 { c as Scalar * 0.1 } else { 1.0 });
+        let reference = IcpReference::new(q.clone(), CorrespondenceBackend::KdTree);
+        assert!(reference.built_for(CorrespondenceBackend::KdTree));
+        assert!(!reference.built_for(CorrespondenceBackend::BruteForce));
+
+        for offset in [0.05, -0.1] {
+            let p = Matrix2xX::from_fn(20, |r, c| {
+                if r == 0 {
+                    c as Scalar * 0.1 + 1.0
+                } else {
+                    1.0 + offset
+                }
+            });
+
+            let params = IcpParameters {
+                correspondence_backend: CorrespondenceBackend::KdTree,
+                ..Default::default()
+            };
+            let prebuilt = icp_point_to_normal_prebuilt(&p, &reference, Vector3::zeros(), params);
+            let built = icp_point_to_normal(&p, &q, Vector3::zeros(), params);
+            assert_eq!(prebuilt.transformation, built.transformation);
+            assert_relative_eq!(prebuilt.transformation.y, -offset, epsilon = 1e-3);
+        }
+    }
+
+    #[test]
+    fn correspondence_ties_prefer_lowest_index() {
+        // the point is exactly in between the two reference points
+        let p = Matrix2xX::from_columns(&[Vector2::new(0.0, 0.0)]);
+
+        let q = Matrix2xX::from_columns(&[Vector2::new(1.0, 0.0), Vector2::new(-1.0, 0.0)]);
+        assert_eq!(find_correspondences(&p, &matrix_to_kdmap(&q)), vec![(0, 0)]);
+
+        let q = Matrix2xX::from_columns(&[Vector2::new(-1.0, 0.0), Vector2::new(1.0, 0.0)]);
+        assert_eq!(find_correspondences(&p, &matrix_to_kdmap(&q)), vec![(0, 0)]);
+
+        // four equidistant points
+        let q = Matrix2xX::from_columns(&[
+            Vector2::new(0.0, 1.0),
+            Vector2::new(1.0, 0.0),
+            Vector2::new(0.0, -1.0),
+            Vector2::new(-1.0, 0.0),
+        ]);
+        assert_eq!(find_correspondences(&p, &matrix_to_kdmap(&q)), vec![(0, 0)]);
+        assert_eq!(find_correspondences_brute_force(&p, &q), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn brute_force_matches_kd_tree() {
+        // points on a spiral, matched against a grid that is slightly offset
+        let p = Matrix2xX::from_fn(200, |r, c| {
+            let angle = c as Scalar * 0.1;
+            let radius = c as Scalar * 0.02;
+            if r == 0 {
+                radius * angle.cos()
+            } else {
+                radius * angle.sin()
+            }
+        });
+        let q = Matrix2xX::from_fn(400, |r, c| {
+            if r == 0 {
+                (c % 20) as Scalar * 0.2 - 2.0 + 0.013
+            } else {
+                (c / 20) as Scalar * 0.2 - 2.0 - 0.007
+            }
+        });
+
+        assert_eq!(
+            find_correspondences_brute_force(&p, &q),
+            find_correspondences(&p, &matrix_to_kdmap(&q))
+        );
+    }
+
+    #[test]
+    fn correspondences_with_empty_reference() {
+        let p = Matrix2xX::from_columns(&[Vector2::new(0.0, 0.0)]);
+        let q = Matrix2xX::zeros(0);
+        assert!(find_correspondences(&p, &matrix_to_kdmap(&q)).is_empty());
+        assert!(find_correspondences_brute_force(&p, &q).is_empty());
+    }
 }
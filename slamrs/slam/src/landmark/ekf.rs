@@ -1,21 +1,49 @@
 use common::robot::{LandmarkObservations, Odometry, Pose};
 
 use nalgebra as na;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct EKFLandmarkSlamConfig {}
+use crate::snapshot::SnapshotError;
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct EKFLandmarkSlamConfig {
+    /// Landmarks observed fewer times than this are forgotten once `prune_after_updates` updates
+    /// have passed since they were first seen. No landmarks are pruned if not set.
+    #[serde(default)]
+    pub prune_min_observations: Option<usize>,
+
+    #[serde(default = "default_prune_after_updates")]
+    pub prune_after_updates: usize,
+}
+
+fn default_prune_after_updates() -> usize {
+    20
+}
+
+/// The initial (and forgotten) variance of the landmark positions.
+const LANDMARK_VARIANCE: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct LandmarkTrack {
+    /// The update the landmark was first seen in.
+    first_seen: usize,
+    observations: usize,
+}
 
 #[derive(Debug)]
 pub struct EKFLandmarkSlam {
     state_mean: na::DVector<f32>,
     state_covariance: na::DMatrix<f32>,
     num_landmarks: usize,
-    landmark_seen: Vec<bool>,
+    /// Set for all landmarks that are part of the map.
+    landmark_tracks: Vec<Option<LandmarkTrack>>,
+    updates: usize,
+    prune_min_observations: Option<usize>,
+    prune_after_updates: usize,
 }
 
 impl EKFLandmarkSlam {
-    pub fn new(_config: &EKFLandmarkSlamConfig) -> Self {
+    pub fn new(config: &EKFLandmarkSlamConfig) -> Self {
         let num_landmarks = 10;
 
         // mean starts out as zero for both pose and pandmark positions
@@ -23,7 +51,7 @@ impl EKFLandmarkSlam {
 
         // "infinite" covariance for landmarks
         let mut state_covariance =
-            na::DMatrix::identity(3 + 2 * num_landmarks, 3 + 2 * num_landmarks) * 1000.0;
+            na::DMatrix::identity(3 + 2 * num_landmarks, 3 + 2 * num_landmarks) * LANDMARK_VARIANCE;
 
         // covariance for the robot pose is zero
         state_covariance[(0, 0)] = 0.0;
@@ -35,10 +63,19 @@ impl EKFLandmarkSlam {
             state_mean,
             state_covariance,
             num_landmarks,
-            landmark_seen: vec![false; num_landmarks],
+            landmark_tracks: vec![None; num_landmarks],
+            updates: 0,
+            prune_min_observations: config.prune_min_observations,
+            prune_after_updates: config.prune_after_updates,
         }
     }
 
+    /// Applies the pruning settings of `config` from now on, keeping the current state.
+    pub fn set_pruning(&mut self, config: &EKFLandmarkSlamConfig) {
+        self.prune_min_observations = config.prune_min_observations;
+        self.prune_after_updates = config.prune_after_updates;
+    }
+
     pub fn update(&mut self, observation: &LandmarkObservations, odometry: Odometry) {
         // This implementation of EKF Landmark SLAM comes from this video:
         // https://youtu.be/XeWG5D71gC0?list=PLgnQpQtFTOGQrZ4O5QzbIHgl3b1JHimN_
@@ -125,10 +162,16 @@ impl EKFLandmarkSlam {
                 continue;
             };
 
-            if !self.landmark_seen[landmark_idx] {
-                self.landmark_seen[landmark_idx] = true;
+            let track = self.landmark_tracks[landmark_idx].get_or_insert_with(|| {
                 log::info!("landmark seen for first time: {}", landmark_idx);
+                LandmarkTrack {
+                    first_seen: self.updates,
+                    observations: 0,
+                }
+            });
+            track.observations += 1;
 
+            if track.observations == 1 {
                 // initialize as if the landmark is exactly what we would expect
                 mu_bar[3 + 2 * landmark_idx] = mu_bar[0] + l.distance * (mu_bar[2] + l.angle).cos();
                 mu_bar[3 + 2 * landmark_idx + 1] =
@@ -201,6 +244,48 @@ impl EKFLandmarkSlam {
 
         self.state_mean = mu_bar;
         self.state_covariance = sigma_bar;
+
+        self.updates += 1;
+        self.prune();
+    }
+
+    /// Forgets all landmarks that have not been observed often enough since they were first
+    /// seen, as they are most likely the result of false associations.
+    fn prune(&mut self) {
+        let Some(min_observations) = self.prune_min_observations else {
+            return;
+        };
+
+        for i in 0..self.num_landmarks {
+            let Some(track) = self.landmark_tracks[i] else {
+                continue;
+            };
+
+            if self.updates - track.first_seen >= self.prune_after_updates
+                && track.observations < min_observations
+            {
+                log::info!(
+                    "pruning landmark {} after {} observations",
+                    i,
+                    track.observations
+                );
+                self.forget_landmark(i);
+            }
+        }
+    }
+
+    /// Resets the landmark to its initial state, removing all correlations with the rest of the
+    /// state so that it does not influence the pose or other landmarks anymore.
+    fn forget_landmark(&mut self, landmark_idx: usize) {
+        self.landmark_tracks[landmark_idx] = None;
+
+        for offset in 0..2 {
+            let i = 3 + 2 * landmark_idx + offset;
+            self.state_mean[i] = 0.0;
+            self.state_covariance.row_mut(i).fill(0.0);
+            self.state_covariance.column_mut(i).fill(0.0);
+            self.state_covariance[(i, i)] = LANDMARK_VARIANCE;
+        }
     }
 
     pub fn estimated_pose(&self) -> Pose {
@@ -213,10 +298,10 @@ impl EKFLandmarkSlam {
 
     pub fn estimated_landmarks(&self) -> Vec<Landmark> {
         let mut l = self
-            .landmark_seen
+            .landmark_tracks
             .iter()
             .enumerate()
-            .filter(|(_, &seen)| seen)
+            .filter(|(_, track)| track.is_some())
             .map(|(i, _)| {
                 let x = self.state_mean[3 + 2 * i];
                 let y = self.state_mean[3 + 2 * i + 1];
@@ -238,13 +323,176 @@ impl EKFLandmarkSlam {
         l
     }
 
+    /// Captures the full state of the filter.
+    pub fn snapshot(&self) -> LandmarkSnapshot {
+        LandmarkSnapshot {
+            state_mean: self.state_mean.clone(),
+            state_covariance: self.state_covariance.clone(),
+            landmark_tracks: self.landmark_tracks.clone(),
+            updates: self.updates,
+        }
+    }
+
+    /// Replaces the state of the filter with the one in the snapshot. Fails if the dimensions
+    /// of the snapshot do not match each other, in which case the current state is kept.
+    pub fn restore(&mut self, snapshot: LandmarkSnapshot) -> Result<(), SnapshotError> {
+        let num_landmarks = snapshot.landmark_tracks.len();
+        let dimension = 3 + 2 * num_landmarks;
+
+        if snapshot.state_mean.len() != dimension {
+            return Err(SnapshotError::SizeMismatch {
+                field: "state_mean",
+                expected: dimension,
+                actual: snapshot.state_mean.len(),
+            });
+        }
+        if snapshot.state_covariance.shape() != (dimension, dimension) {
+            return Err(SnapshotError::SizeMismatch {
+                field: "state_covariance",
+                expected: dimension * dimension,
+                actual: snapshot.state_covariance.len(),
+            });
+        }
+
+        self.state_mean = snapshot.state_mean;
+        self.state_covariance = snapshot.state_covariance;
+        self.num_landmarks = num_landmarks;
+        self.landmark_tracks = snapshot.landmark_tracks;
+        self.updates = snapshot.updates;
+
+        Ok(())
+    }
+
     pub fn raw_covariance(&self) -> &na::DMatrix<f32> {
         &self.state_covariance
     }
 }
 
+/// The state of an [`EKFLandmarkSlam`], to save and later restore a session.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LandmarkSnapshot {
+    state_mean: na::DVector<f32>,
+    state_covariance: na::DMatrix<f32>,
+    landmark_tracks: Vec<Option<LandmarkTrack>>,
+    updates: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct Landmark {
     pub mean: na::Vector2<f32>,
     pub covariance: na::Matrix2<f32>,
 }
+
+#[cfg(test)]
+mod tests {
+    use common::robot::LandmarkObservation;
+
+    use super::*;
+
+    fn observe(landmarks: &[(usize, f32, f32)]) -> LandmarkObservations {
+        LandmarkObservations {
+            landmarks: landmarks
+                .iter()
+                .map(|&(association, x, y)| LandmarkObservation {
+                    angle: y.atan2(x),
+                    distance: (x * x + y * y).sqrt(),
+                    association: Some(association),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn spurious_landmark_is_pruned() {
+        let mut slam = EKFLandmarkSlam::new(&EKFLandmarkSlamConfig {
+            prune_min_observations: Some(3),
+            prune_after_updates: 5,
+        });
+
+        // landmark 1 is only seen once, while 0 and 2 are seen in every update
+        slam.update(
+            &observe(&[(0, 1.0, 0.0), (1, 0.0, 2.0), (2, -1.0, 1.0)]),
+            Odometry::new(0.0, 0.0, 0.1),
+        );
+        assert_eq!(slam.estimated_landmarks().len(), 3 + 1);
+
+        for _ in 0..5 {
+            slam.update(
+                &observe(&[(0, 1.0, 0.0), (2, -1.0, 1.0)]),
+                Odometry::new(0.0, 0.0, 0.1),
+            );
+        }
+
+        assert!(slam.landmark_tracks[0].is_some());
+        assert!(slam.landmark_tracks[1].is_none());
+        assert!(slam.landmark_tracks[2].is_some());
+
+        // the stable landmarks and the pose remain
+        assert_eq!(slam.estimated_landmarks().len(), 2 + 1);
+
+        // no correlations with the forgotten landmark are left
+        for i in [5, 6] {
+            for j in 0..slam.state_covariance.ncols() {
+                if i != j {
+                    assert_eq!(slam.state_covariance[(i, j)], 0.0);
+                    assert_eq!(slam.state_covariance[(j, i)], 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let config = EKFLandmarkSlamConfig {
+            prune_min_observations: None,
+            prune_after_updates: 5,
+        };
+        let mut slam = EKFLandmarkSlam::new(&config);
+        slam.update(
+            &observe(&[(0, 1.0, 0.0), (3, -1.0, 1.0)]),
+            Odometry::new(0.05, 0.1, 0.1),
+        );
+
+        let yaml = serde_yaml::to_string(&slam.snapshot()).unwrap();
+
+        let mut restored = EKFLandmarkSlam::new(&config);
+        restored
+            .restore(serde_yaml::from_str(&yaml).unwrap())
+            .unwrap();
+
+        assert_eq!(restored.state_mean, slam.state_mean);
+        assert_eq!(restored.state_covariance, slam.state_covariance);
+        assert_eq!(restored.landmark_tracks, slam.landmark_tracks);
+        assert_eq!(restored.updates, slam.updates);
+    }
+
+    #[test]
+    fn restore_rejects_inconsistent_dimensions() {
+        let config = EKFLandmarkSlamConfig {
+            prune_min_observations: None,
+            prune_after_updates: 5,
+        };
+        let mut slam = EKFLandmarkSlam::new(&config);
+
+        let mut snapshot = slam.snapshot();
+        snapshot.landmark_tracks.push(None);
+
+        assert!(slam.restore(snapshot).is_err());
+        assert_eq!(slam.landmark_tracks.len(), 10);
+    }
+
+    #[test]
+    fn nothing_is_pruned_by_default() {
+        let mut slam = EKFLandmarkSlam::new(&EKFLandmarkSlamConfig {
+            prune_min_observations: None,
+            prune_after_updates: 5,
+        });
+
+        slam.update(&observe(&[(1, 0.0, 2.0)]), Odometry::new(0.0, 0.0, 0.1));
+        for _ in 0..50 {
+            slam.update(&observe(&[]), Odometry::new(0.0, 0.0, 0.1));
+        }
+
+        assert!(slam.landmark_tracks[1].is_some());
+    }
+}
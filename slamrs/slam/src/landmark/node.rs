@@ -1,10 +1,14 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use common::{
-    node::{Node, NodeConfig},
+    node::{ConfigUi, Node, NodeConfig, TopicDecl},
     robot::{LandmarkObservations, Odometry, Pose},
 };
 use eframe::egui;
+use egui::Slider;
 
 use graphics::primitiverenderer::Color;
 use pubsub::{Publisher, Subscription};
@@ -12,47 +16,93 @@ use serde::Deserialize;
 
 use nalgebra as na;
 
-use super::ekf::{EKFLandmarkSlam, EKFLandmarkSlamConfig, Landmark};
+use super::ekf::{EKFLandmarkSlam, EKFLandmarkSlamConfig, Landmark, LandmarkSnapshot};
+use crate::snapshot::{self, SnapshotError};
 
 pub struct EKFLandmarkSlamNode {
     sub_obs_odom: Subscription<(LandmarkObservations, Odometry)>,
     pub_pose: Publisher<Pose>,
     pub_map: Publisher<LandmarkMapMessage>,
     slam: EKFLandmarkSlam,
-    #[allow(dead_code)]
     config: EKFLandmarkSlamConfig,
+    snapshot_file: String,
+    /// The outcome of the last save or load, shown in the window.
+    snapshot_status: Option<String>,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct EKFLandmarkSlamNodeConfig {
     topic_pose: String,
     topic_observation_landmark: String,
     topic_map: String,
     config: EKFLandmarkSlamConfig,
+    /// The file the landmark map (and the pose) is saved to and loaded from.
+    #[serde(default = "default_snapshot_file")]
+    snapshot_file: PathBuf,
+}
+
+fn default_snapshot_file() -> PathBuf {
+    PathBuf::from("landmark_map.yaml")
 }
 
 impl NodeConfig for EKFLandmarkSlamNodeConfig {
     fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
         Box::new(EKFLandmarkSlamNode {
             sub_obs_odom: pubsub.subscribe(&self.topic_observation_landmark),
-            pub_pose: pubsub.publish(&self.topic_pose),
+            pub_pose: pubsub.publish_registered(&self.topic_pose),
             pub_map: pubsub.publish(&self.topic_map),
             slam: EKFLandmarkSlam::new(&self.config),
             config: self.config.clone(),
+            snapshot_file: self.snapshot_file.display().to_string(),
+            snapshot_status: None,
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        vec![
+            TopicDecl::subscribe::<(LandmarkObservations, Odometry)>(
+                &self.topic_observation_landmark,
+            ),
+            TopicDecl::publish::<Pose>(&self.topic_pose),
+            TopicDecl::publish::<LandmarkMapMessage>(&self.topic_map),
+        ]
+    }
+}
+
+impl EKFLandmarkSlamNode {
+    /// Rebuilds the filter, forgetting all landmarks seen so far.
+    fn reset(&mut self) {
+        self.slam = EKFLandmarkSlam::new(&self.config);
+        self.publish();
+    }
+
+    fn save(&self) -> Result<(), SnapshotError> {
+        snapshot::save(&self.slam.snapshot(), Path::new(&self.snapshot_file))
+    }
+
+    /// Replaces the filter state with the saved one, to continue localizing against a map
+    /// built earlier.
+    fn load(&mut self) -> Result<(), SnapshotError> {
+        let snapshot: LandmarkSnapshot = snapshot::load(Path::new(&self.snapshot_file))?;
+        self.slam.restore(snapshot)?;
+        self.publish();
+        Ok(())
+    }
+
+    fn publish(&mut self) {
+        self.pub_pose.publish(Arc::new(self.slam.estimated_pose()));
+
+        self.pub_map.publish(Arc::new(LandmarkMapMessage {
+            landmarks: self.slam.estimated_landmarks(),
+        }));
+    }
 }
 
 impl Node for EKFLandmarkSlamNode {
     fn update(&mut self) {
         if let Some(o) = self.sub_obs_odom.try_recv() {
             self.slam.update(&o.0, o.1);
-
-            self.pub_pose.publish(Arc::new(self.slam.estimated_pose()));
-
-            self.pub_map.publish(Arc::new(LandmarkMapMessage {
-                landmarks: self.slam.estimated_landmarks(),
-            }));
+            self.publish();
         }
     }
 
@@ -60,6 +110,37 @@ impl Node for EKFLandmarkSlamNode {
         egui::Window::new("EKF Landmark Slam").show(ui.ctx(), |ui| {
             ui.label("[WIP]");
 
+            if self.config.ui(ui) {
+                self.slam.set_pruning(&self.config);
+            }
+
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Map File: ");
+                ui.text_edit_singleline(&mut self.snapshot_file);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.snapshot_status = Some(match self.save() {
+                        Ok(()) => format!("Saved to {}", self.snapshot_file),
+                        Err(e) => format!("Save failed: {e}"),
+                    });
+                }
+                if ui.button("Load").clicked() {
+                    self.snapshot_status = Some(match self.load() {
+                        Ok(()) => format!("Loaded {}", self.snapshot_file),
+                        Err(e) => format!("Load failed: {e}"),
+                    });
+                }
+            });
+            if let Some(status) = &self.snapshot_status {
+                ui.label(status);
+            }
+
             let cov = self.slam.raw_covariance();
             let d: na::DMatrix<f32> = na::DMatrix::from_diagonal(&cov.diagonal().map(|v| v.sqrt()));
             if let Some(d_inv) = d.try_inverse() {
@@ -96,6 +177,27 @@ impl Node for EKFLandmarkSlamNode {
     }
 }
 
+impl ConfigUi for EKFLandmarkSlamConfig {
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut prune = self.prune_min_observations.is_some();
+        let mut changed = ui.checkbox(&mut prune, "Prune Landmarks").changed();
+        if changed {
+            self.prune_min_observations = prune.then_some(2);
+        }
+
+        if let Some(min_observations) = &mut self.prune_min_observations {
+            changed |= ui
+                .add(Slider::new(min_observations, 1..=20).text("Min Observations"))
+                .changed();
+            changed |= ui
+                .add(Slider::new(&mut self.prune_after_updates, 1..=200).text("After Updates"))
+                .changed();
+        }
+
+        changed
+    }
+}
+
 pub struct LandmarkMapMessage {
     pub landmarks: Vec<Landmark>,
 }
@@ -1,12 +1,37 @@
 mod grid;
 mod icp;
 mod landmark;
+mod outlier;
 mod pointmap;
+pub mod posegraph;
+mod publish;
+mod snapshot;
 
-pub use pointmap::{IcpPointMapNode, IcpPointMapNodeConfig, PointMap};
+/// The floating point type used for the scan matching and map math. Single precision by default,
+/// enable the `f64` feature when mapping large areas far from the origin.
+#[cfg(not(feature = "f64"))]
+pub type Scalar = f32;
 
-pub use grid::map::{Cell, GridData};
-pub use grid::node::{GridMapMessage, GridMapSlamNode, GridMapSlamNodeConfig};
+/// The floating point type used for the scan matching and map math. Single precision by default,
+/// enable the `f64` feature when mapping large areas far from the origin.
+#[cfg(feature = "f64")]
+pub type Scalar = f64;
 
-pub use landmark::ekf::{EKFLandmarkSlamConfig, Landmark};
+pub use icp::{
+    icp_point_to_normal, icp_point_to_normal_prebuilt, CorrespondenceBackend, IcpParameters,
+    IcpReference, IcpResult,
+};
+pub use outlier::OutlierFilter;
+pub use pointmap::{
+    IcpPointMapNode, IcpPointMapNodeConfig, IcpQuality, PointMap, PointMapSnapshot, ReferenceMode,
+};
+pub use publish::MapPublishMode;
+pub use snapshot::SnapshotError;
+
+pub use grid::frontier::{find_frontiers, Frontier, FrontierNode, FrontierNodeConfig};
+pub use grid::map::{Cell, GridData, Map};
+pub use grid::node::{GridMapMessage, GridMapSlamNode, GridMapSlamNodeConfig, HitCountMapMessage};
+pub use grid::slam::GridMapSnapshot;
+
+pub use landmark::ekf::{EKFLandmarkSlamConfig, Landmark, LandmarkSnapshot};
 pub use landmark::node::{EKFLandmarkSlamNode, EKFLandmarkSlamNodeConfig, LandmarkMapMessage};
@@ -0,0 +1,100 @@
+use nalgebra::Matrix2xX;
+use serde::Deserialize;
+
+use crate::{icp::matrix_to_kdmap, Scalar};
+
+/// Parameters for removing isolated points from a point cloud, by comparing how far away
+/// the neighbors of each point are compared to the rest of the cloud.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct OutlierFilter {
+    /// The number of nearest neighbors to compute the mean distance over.
+    pub k: usize,
+
+    /// Points with a mean neighbor distance more than this many standard deviations above
+    /// the global mean are removed.
+    pub std_mult: Scalar,
+}
+
+impl OutlierFilter {
+    /// Returns the points of `points` that are not considered outliers, in their original order.
+    pub fn apply(&self, points: &Matrix2xX<Scalar>) -> Matrix2xX<Scalar> {
+        // there must be at least k other points to compare with
+        if self.k == 0 || points.ncols() <= self.k {
+            return points.clone();
+        }
+
+        let tree = matrix_to_kdmap(points);
+
+        // the closest neighbor is always the point itself, so ask for one extra
+        let mean_distances: Vec<Scalar> = points
+            .column_iter()
+            .map(|p| {
+                let neighbors = tree.nearests(&[p.x, p.y], self.k + 1);
+                let total: Scalar = neighbors.iter().map(|n| n.squared_distance.sqrt()).sum();
+                total / (neighbors.len() - 1) as Scalar
+            })
+            .collect();
+
+        let n = mean_distances.len() as Scalar;
+        let mean = mean_distances.iter().sum::<Scalar>() / n;
+        let std = (mean_distances
+            .iter()
+            .map(|d| (d - mean).powi(2))
+            .sum::<Scalar>()
+            / n)
+            .sqrt();
+        let limit = mean + self.std_mult * std;
+
+        let inliers: Vec<_> = points
+            .column_iter()
+            .zip(mean_distances)
+            .filter(|(_, d)| *d <= limit)
+            .map(|(p, _)| p.into_owned())
+            .collect();
+
+        if inliers.is_empty() {
+            Matrix2xX::zeros(0)
+        } else {
+            Matrix2xX::from_columns(&inliers)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector2;
+
+    use super::*;
+
+    #[test]
+    fn removes_planted_outlier() {
+        // a dense line of points along the x-axis with a single point far away from it
+        let mut columns: Vec<Vector2<Scalar>> = (0..20)
+            .map(|i| Vector2::new(i as Scalar * 0.1, 0.0))
+            .collect();
+        columns.insert(7, Vector2::new(1.0, 5.0));
+        let points = Matrix2xX::from_columns(&columns);
+
+        let filtered = OutlierFilter {
+            k: 3,
+            std_mult: 1.0,
+        }
+        .apply(&points);
+
+        assert_eq!(filtered.ncols(), 20);
+        assert!(filtered.column_iter().all(|p| p.y == 0.0));
+    }
+
+    #[test]
+    fn keeps_too_small_clouds() {
+        let points = Matrix2xX::from_columns(&[Vector2::new(0.0, 0.0), Vector2::new(10.0, 10.0)]);
+
+        let filtered = OutlierFilter {
+            k: 3,
+            std_mult: 1.0,
+        }
+        .apply(&points);
+
+        assert_eq!(filtered, points);
+    }
+}
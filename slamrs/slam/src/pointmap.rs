@@ -1,59 +1,297 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use web_time::Instant;
 
 use common::{
-    node::{Node, NodeConfig},
+    node::{ConfigUi, Node, NodeConfig, TopicDecl},
     robot::{Observation, Pose},
     PerfStats,
 };
 use eframe::egui;
-use egui::{Label, RichText, Sense};
+use egui::{Label, RichText, Sense, Slider};
 
-use nalgebra::Matrix2xX;
+use nalgebra::{Matrix2xX, Vector3};
 use pubsub::{Publisher, Subscription};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::icp::{self, IcpParameters};
+use crate::{
+    icp::{
+        self, CorrespondenceBackend, CorrespondenceWeight, IcpParameters, IcpReference, IcpResult,
+    },
+    outlier::OutlierFilter,
+    publish::{MapPublishGate, MapPublishMode},
+    snapshot::{self, SnapshotError},
+    Scalar,
+};
 
 pub struct PointMap(pub Matrix2xX<f32>);
+
+impl PointMap {
+    /// Returns a copy of this map without the points considered outliers by `filter`.
+    pub fn remove_outliers(&self, filter: OutlierFilter) -> PointMap {
+        PointMap(filter.apply(&self.0.clone().cast()).cast())
+    }
+}
+
+/// What new scans are matched against.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Default)]
+pub enum ReferenceMode {
+    /// Only the last scan integrated into the map (scan-to-scan). Cheap, since the reference
+    /// stays small, but errors accumulate as drift.
+    PreviousScan,
+    /// The whole accumulated map (scan-to-map).
+    #[default]
+    AccumulatedMap,
+    /// Only the last this many scans are kept in the map and matched against, which bounds the
+    /// memory and keeps the reference dense around the robot.
+    SlidingWindow(usize),
+}
+
+/// Describes how well the latest scan matched the map.
+#[derive(Debug, Clone, Copy)]
+pub struct IcpQuality {
+    /// The chi value of the last ICP iteration.
+    pub chi: f32,
+    pub correspondences: usize,
+    /// If the scan was rejected and not integrated into the map.
+    pub rejected: bool,
+}
+
 #[derive(Default)]
 pub struct IcpPointMapper {
-    map_points: Option<Matrix2xX<f32>>,
-    pose_est: Pose,
+    map_points: Option<Matrix2xX<Scalar>>,
+    /// The reference points prepared for matching, kept until they change.
+    reference: Option<IcpReference>,
+    /// The estimated pose as `[x, y, theta]`, kept in full precision between matches.
+    pose_est: Vector3<Scalar>,
     perf_stats: PerfStats,
     icp_parameters: IcpParameters,
+    last_result: Option<IcpResult>,
+    reject_above_chi: Option<f32>,
+    /// The largest pose correction (meters) accepted from a single scan match.
+    max_step_translation: Option<f32>,
+    /// The largest pose correction (radians) accepted from a single scan match.
+    max_step_rotation: Option<f32>,
+    outlier_filter: Option<OutlierFilter>,
+    reference_mode: ReferenceMode,
+    /// The last scan integrated into the map, in the world frame. Only kept when matching
+    /// against the previous scan.
+    previous_scan: Option<Matrix2xX<Scalar>>,
+    /// The number of points each scan in `map_points` contributed, oldest first. Only kept
+    /// when using a window.
+    scan_sizes: VecDeque<usize>,
+    /// New points closer than this to a reference point are dropped, all are added if `None`.
+    merge_radius: Option<f32>,
+    /// The whole map prepared for lookups and the `revision` it was built for, to merge new
+    /// points against the map when matching against the previous scan.
+    map_lookup: Option<(u64, IcpReference)>,
+    /// The last matched scan (in the robot frame) and the pose it was matched from.
+    last_scan: Option<(Matrix2xX<Scalar>, Vector3<Scalar>)>,
+    /// Scans are only integrated once the pose moved this far from `mapped_pose`, see
+    /// [`IcpPointMapper::motion_since_map`]. Every scan is integrated if `None`.
+    min_motion_to_map: Option<f32>,
+    /// The pose estimate when the map was last updated.
+    mapped_pose: Vector3<Scalar>,
+    /// The number of matched scans not integrated because the robot was not moving.
+    stationary_scans: usize,
+    /// Incremented whenever `map_points` change.
+    revision: u64,
+}
+
+/// How an [`IcpPointMapper`] matches scans and builds its map. The defaults match every scan
+/// against the whole map and integrate all of them.
+#[derive(Clone, Copy, Default)]
+pub struct IcpPointMapperSettings {
+    pub icp: IcpParameters,
+    /// Scans with a final chi value above this are not integrated into the map.
+    pub reject_above_chi: Option<f32>,
+    /// The largest pose correction (meters) accepted from a single scan match.
+    pub max_step_translation: Option<f32>,
+    /// The largest pose correction (radians) accepted from a single scan match.
+    pub max_step_rotation: Option<f32>,
+    /// Removes isolated points from every scan before it is matched against the map.
+    pub outlier_filter: Option<OutlierFilter>,
+    pub reference: ReferenceMode,
+    /// New points closer than this to a point of the map are dropped, all are added if `None`.
+    pub merge_radius: Option<f32>,
+    /// Scans are only integrated once the pose moved this far since the last map update.
+    pub min_motion_to_map: Option<f32>,
 }
 
 impl IcpPointMapper {
-    pub fn new(icp_parameters: IcpParameters) -> Self {
+    pub fn new(settings: IcpPointMapperSettings) -> Self {
         Self {
-            icp_parameters,
+            icp_parameters: settings.icp,
+            reject_above_chi: settings.reject_above_chi,
+            max_step_translation: settings.max_step_translation,
+            max_step_rotation: settings.max_step_rotation,
+            outlier_filter: settings.outlier_filter,
+            reference_mode: match settings.reference {
+                ReferenceMode::SlidingWindow(n) => ReferenceMode::SlidingWindow(n.max(1)),
+                mode => mode,
+            },
+            merge_radius: settings.merge_radius,
+            min_motion_to_map: settings.min_motion_to_map,
             ..Self::default()
         }
     }
 
-    pub fn update(&mut self, observation: &Observation) {
+    /// Records that a scan of `n_points` was appended to the map, removing the oldest scans
+    /// from the map if the window is full.
+    fn push_scan(&mut self, n_points: usize) {
+        let ReferenceMode::SlidingWindow(window_size) = self.reference_mode else {
+            return;
+        };
+
+        self.scan_sizes.push_back(n_points);
+        while self.scan_sizes.len() > window_size {
+            let evicted = self.scan_sizes.pop_front().unwrap();
+            if let Some(map_points) = self.map_points.take() {
+                self.map_points = Some(map_points.remove_columns(0, evicted));
+            }
+        }
+    }
+
+    /// The points the next scan is matched against, `None` before the first scan.
+    fn reference_points(&self) -> Option<&Matrix2xX<Scalar>> {
+        match self.reference_mode {
+            // the map is all there is after restoring a snapshot
+            ReferenceMode::PreviousScan => self.previous_scan.as_ref().or(self.map_points.as_ref()),
+            ReferenceMode::AccumulatedMap | ReferenceMode::SlidingWindow(_) => {
+                self.map_points.as_ref()
+            }
+        }
+    }
+
+    /// How far the pose estimate moved since the map was last updated, as the distance travelled
+    /// plus the rotation in radians (the arc of a point one meter from the center).
+    fn motion_since_map(&self) -> Scalar {
+        let step = self.pose_est - self.mapped_pose;
+        step.xy().norm() + Scalar::atan2(step[2].sin(), step[2].cos()).abs()
+    }
+
+    /// The whole map prepared for lookups, only rebuilt if the map changed since it was last
+    /// used. `map_points` are the points of the current revision.
+    fn map_lookup(&mut self, map_points: &Matrix2xX<Scalar>) -> &IcpReference {
+        let revision = self.revision;
+        if !matches!(&self.map_lookup, Some((built, _)) if *built == revision) {
+            self.map_lookup = Some((
+                revision,
+                IcpReference::new(map_points.clone(), CorrespondenceBackend::KdTree),
+            ));
+        }
+        &self.map_lookup.as_ref().unwrap().1
+    }
+
+    /// Forgets the scan boundaries, the whole map is treated as a single scan from now on.
+    fn merge_scans(&mut self) {
+        self.scan_sizes.clear();
+        if let Some(n_points) = self.map_points.as_ref().map(|m| m.ncols()) {
+            self.push_scan(n_points);
+        }
+    }
+
+    /// Matches the observation against the map and integrates it. Returns the quality of the
+    /// match, or `None` if this was the first observation.
+    pub fn update(&mut self, observation: &Observation) -> Option<IcpQuality> {
         let start = Instant::now();
 
-        let newp = observation.to_matrix(Pose::default());
+        let mut newp: Matrix2xX<Scalar> = observation.to_points(None, false).cast();
+        if let Some(filter) = &self.outlier_filter {
+            newp = filter.apply(&newp);
+        }
 
         if self.map_points.is_none() {
+            let n_points = newp.ncols();
+            if self.reference_mode == ReferenceMode::PreviousScan {
+                self.previous_scan = Some(newp.clone());
+            }
             self.map_points = Some(newp);
-            return;
+            self.push_scan(n_points);
+            self.revision += 1;
+            return None;
         }
 
+        let backend = self.icp_parameters.correspondence_backend;
+        let reference = match self.reference.take() {
+            Some(reference) if reference.built_for(backend) => reference,
+            _ => IcpReference::new(self.reference_points()?.clone(), backend),
+        };
+
         if let Some(mut map_points) = self.map_points.take() {
             // match the new scan with the previous to get an estimate of the movement
-            let result = icp::icp_point_to_normal(
+            let result = icp::icp_point_to_normal_prebuilt(
                 &newp,
-                &map_points,
-                self.pose_est.into(),
+                &reference,
+                self.pose_est,
                 self.icp_parameters,
             );
+            self.last_scan = Some((newp, self.pose_est));
+
+            let mut quality = IcpQuality {
+                chi: result.chi_values.last().copied().unwrap_or_default() as f32,
+                correspondences: result.correspondences,
+                rejected: false,
+            };
+
+            // a large jump in the pose is more likely a bad match than actual motion
+            let step = result.transformation - self.pose_est;
+            let step_rotation = Scalar::atan2(step[2].sin(), step[2].cos()).abs();
+            let too_large_step = self
+                .max_step_translation
+                .is_some_and(|max| step.xy().norm() > max as Scalar)
+                || self
+                    .max_step_rotation
+                    .is_some_and(|max| step_rotation > max as Scalar);
+
+            // do not integrate scans that did not match well enough
+            if too_large_step
+                || self
+                    .reject_above_chi
+                    .is_some_and(|limit| quality.chi > limit)
+            {
+                quality.rejected = true;
+                self.map_points = Some(map_points);
+                self.reference = Some(reference);
+                self.last_result = Some(result);
+                self.perf_stats.update(start.elapsed());
+                return Some(quality);
+            }
 
-            self.pose_est = Pose::from(result.transformation);
+            self.pose_est = result.transformation;
 
-            let new_points = result.transformed_points;
+            // keep tracking the pose but do not reinforce the map while standing still
+            if self
+                .min_motion_to_map
+                .is_some_and(|min| self.motion_since_map() < min as Scalar)
+            {
+                self.stationary_scans += 1;
+                self.map_points = Some(map_points);
+                self.reference = Some(reference);
+                self.last_result = Some(result);
+                self.perf_stats.update(start.elapsed());
+                return Some(quality);
+            }
+            self.mapped_pose = self.pose_est;
+
+            // leave out the points of areas that are already in the map
+            let merged;
+            let new_points = match self.merge_radius {
+                Some(radius) => {
+                    // the reference is the map (or the part of it that is kept) and already
+                    // prepared for lookups, except when it is only the previous scan
+                    let lookup = match self.reference_mode {
+                        ReferenceMode::PreviousScan => self.map_lookup(&map_points),
+                        _ => &reference,
+                    };
+                    merged = points_not_in(&result.transformed_points, lookup, radius);
+                    &merged
+                }
+                None => &result.transformed_points,
+            };
 
             // make space for all new columns (NOTE: this will probably reallocate!)
             let n_map_points = map_points.ncols();
@@ -62,7 +300,7 @@ impl IcpPointMapper {
             // insert their values
             map_points
                 .columns_mut(n_map_points, new_points.ncols())
-                .copy_from(&new_points);
+                .copy_from(new_points);
 
             // TODO implement some kind of sub-sampling here (otherwise the points will grow to be too many!)
 
@@ -72,64 +310,450 @@ impl IcpPointMapper {
                 map_points.ncols()
             );
 
-            self.map_points = Some(map_points)
+            self.map_points = Some(map_points);
+            self.push_scan(new_points.ncols());
+            if self.reference_mode == ReferenceMode::PreviousScan {
+                self.previous_scan = Some(result.transformed_points.clone());
+            }
+            self.revision += 1;
+            self.last_result = Some(result);
+            self.perf_stats.update(start.elapsed());
+
+            return Some(quality);
         }
 
-        self.perf_stats.update(start.elapsed());
+        None
+    }
+
+    /// Clears the map and resets the pose estimate to the origin.
+    pub fn reset(&mut self) {
+        self.map_points = None;
+        self.reference = None;
+        self.previous_scan = None;
+        self.map_lookup = None;
+        self.scan_sizes.clear();
+        self.pose_est = Vector3::zeros();
+        self.mapped_pose = Vector3::zeros();
+        self.stationary_scans = 0;
+        self.perf_stats.reset();
+        self.last_result = None;
+        self.last_scan = None;
+        self.revision += 1;
+    }
+
+    /// Changes whenever the map changes, to tell if it needs to be published again.
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
     pub fn estimated_pose(&self) -> Pose {
-        self.pose_est
+        Pose::from(self.pose_est.cast::<f32>())
     }
 
     pub fn pointmap(&self) -> PointMap {
         if let Some(m) = &self.map_points {
-            PointMap(m.to_owned())
+            PointMap(m.clone().cast())
         } else {
             PointMap(Matrix2xX::zeros(0))
         }
     }
 
+    /// Captures the accumulated map and the pose estimate.
+    pub fn snapshot(&self) -> PointMapSnapshot {
+        PointMapSnapshot {
+            pose: self.pose_est,
+            points: self.map_points.clone(),
+        }
+    }
+
+    /// Replaces the map and the pose estimate with the ones in the snapshot.
+    pub fn restore(&mut self, snapshot: PointMapSnapshot) {
+        self.pose_est = snapshot.pose;
+        self.mapped_pose = snapshot.pose;
+        self.map_points = snapshot.points;
+        self.reference = None;
+        self.previous_scan = None;
+        self.last_result = None;
+        self.merge_scans();
+        self.revision += 1;
+    }
+
+    /// Removes the outliers from the accumulated map, using the configured filter. Does nothing
+    /// if no filter is configured.
+    pub fn remove_map_outliers(&mut self) {
+        if let (Some(filter), Some(map_points)) = (&self.outlier_filter, &mut self.map_points) {
+            *map_points = filter.apply(map_points);
+            self.reference = None;
+            self.merge_scans();
+            self.revision += 1;
+        }
+    }
+
     pub fn stats(&mut self) -> &mut PerfStats {
         &mut self.perf_stats
     }
+
+    /// The parameters used for all subsequent scan matches.
+    pub fn parameters_mut(&mut self) -> &mut IcpParameters {
+        &mut self.icp_parameters
+    }
+
+    /// The number of matched scans that were not integrated because the robot was not moving.
+    pub fn stationary_scans(&self) -> usize {
+        self.stationary_scans
+    }
+
+    pub fn last_result(&self) -> Option<&IcpResult> {
+        self.last_result.as_ref()
+    }
+
+    /// The last matched scan placed at the pose it was matched from (before) and at the pose
+    /// found by the match (after), to see how the match moved it.
+    pub fn alignment(&self) -> Option<(PointMap, PointMap)> {
+        let (scan, initial_pose) = self.last_scan.as_ref()?;
+        let result = self.last_result.as_ref()?;
+
+        Some((
+            PointMap(icp::transform_points(scan, *initial_pose).cast()),
+            PointMap(result.transformed_points.clone().cast()),
+        ))
+    }
+}
+
+/// Returns the points that are further than `radius` from all of the reference points.
+fn points_not_in(
+    points: &Matrix2xX<Scalar>,
+    reference: &IcpReference,
+    radius: f32,
+) -> Matrix2xX<Scalar> {
+    let radius_squared = (radius as Scalar).powi(2);
+    let kept: Vec<_> = points
+        .column_iter()
+        .filter(|p| {
+            reference
+                .nearest_squared_distance([p.x, p.y])
+                .map_or(true, |d| d > radius_squared)
+        })
+        .collect();
+
+    Matrix2xX::from_iterator(kept.len(), kept.iter().flat_map(|p| [p.x, p.y]))
+}
+
+/// The state of an [`IcpPointMapper`], to save and later restore a session.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PointMapSnapshot {
+    /// The estimated pose as `[x, y, theta]`.
+    pose: Vector3<Scalar>,
+    /// All points of the map, `None` if no scan has been integrated yet.
+    points: Option<Matrix2xX<Scalar>>,
+}
+
+impl ConfigUi for IcpParameters {
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = ui
+            .add(Slider::new(&mut self.iterations, 1..=100).text("Iterations"))
+            .changed();
+
+        ui.horizontal(|ui| {
+            ui.label("Weights: ");
+            let weights = &mut self.correspondence_weights;
+            if ui
+                .radio(matches!(weights, CorrespondenceWeight::Uniform), "Uniform")
+                .clicked()
+            {
+                changed |= !matches!(weights, CorrespondenceWeight::Uniform);
+                *weights = CorrespondenceWeight::Uniform;
+            }
+            if ui
+                .radio(matches!(weights, CorrespondenceWeight::Step { .. }), "Step")
+                .clicked()
+                && !matches!(weights, CorrespondenceWeight::Step { .. })
+            {
+                *weights = CorrespondenceWeight::Step { threshold: 0.1 };
+                changed = true;
+            }
+        });
+
+        if let CorrespondenceWeight::Step { threshold } = &mut self.correspondence_weights {
+            changed |= ui
+                .add(
+                    Slider::new(threshold, 0.001..=1.0)
+                        .step_by(0.001)
+                        .fixed_decimals(3)
+                        .text("Threshold (m)"),
+                )
+                .changed();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Search: ");
+            let backend = &mut self.correspondence_backend;
+            changed |= ui
+                .radio_value(backend, CorrespondenceBackend::BruteForce, "Brute Force")
+                .changed();
+            changed |= ui
+                .radio_value(backend, CorrespondenceBackend::KdTree, "k-d Tree")
+                .changed();
+        });
+
+        changed
+    }
 }
 
 pub struct IcpPointMapNode {
     sub_obs: Subscription<Observation>,
     pub_pose: Publisher<Pose>,
     pub_point_map: Publisher<PointMap>,
+    pub_quality: Option<Publisher<IcpQuality>>,
+    pub_scan_before: Option<Publisher<PointMap>>,
+    pub_scan_after: Option<Publisher<PointMap>>,
+    /// Publish the scans on `pub_scan_before` and `pub_scan_after`, off by default since it
+    /// copies every scan twice.
+    publish_alignment: bool,
     point_map: IcpPointMapper,
+    min_strength: Option<f64>,
+    stable_rpm: Option<[f32; 2]>,
+    /// The number of scans dropped because the scanner speed was outside `stable_rpm`.
+    unstable_scans: usize,
+    map_publish: MapPublishGate,
+    /// The revision of the map when it was last checked for changes.
+    seen_revision: u64,
+    snapshot_file: String,
+    /// The outcome of the last save or load, shown in the window.
+    snapshot_status: Option<String>,
 }
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, PartialEq)]
 pub struct IcpPointMapNodeConfig {
     topic_pose: String,
     topic_observation: String,
     topic_pointmap: String,
+    topic_quality: Option<String>,
+
+    /// For debugging the scan matching: every scan is published here placed at the pose it was
+    /// matched from, and after the match, once enabled in the UI (or with `publish_alignment`).
+    topic_scan_before: Option<String>,
+    topic_scan_after: Option<String>,
+    #[serde(default)]
+    publish_alignment: bool,
+
+    #[serde(default)]
     icp: IcpParameters,
+
+    /// Scans with a final chi value above this are not integrated into the map.
+    reject_above_chi: Option<f32>,
+
+    /// Scans that would move the pose estimate further than this (meters) are not integrated
+    /// into the map, the pose is kept as it was.
+    max_step_translation: Option<f32>,
+
+    /// Scans that would rotate the pose estimate more than this (radians) are not integrated
+    /// into the map, the pose is kept as it was.
+    max_step_rotation: Option<f32>,
+
+    /// Measurements weaker than this are ignored. All measurements are used if not set.
+    min_strength: Option<f64>,
+
+    /// Removes isolated points from every scan before it is matched against the map.
+    outlier_filter: Option<OutlierFilter>,
+
+    /// Scans taken while the scanner speed is outside this `[min, max]` rpm band are dropped,
+    /// they are warped while the scanner spins up or down. Scans of unknown speed are kept.
+    stable_rpm: Option<[f32; 2]>,
+
+    /// What new scans are matched against, the whole map by default.
+    #[serde(default)]
+    reference: ReferenceMode,
+
+    /// Same as `reference: !SlidingWindow n`, kept for older configs. Overrides `reference`.
+    window_size: Option<usize>,
+
+    /// How the points of new scans are added to the map.
+    #[serde(default)]
+    merge_strategy: MergeStrategy,
+
+    /// With `NearestMerge`, the new points closer than this (meters) to a point of the map are
+    /// dropped.
+    #[serde(default = "default_merge_radius")]
+    merge_radius: f32,
+
+    /// Scans are only integrated into the map once the estimated pose moved this far since the
+    /// last map update, counting the rotation in radians as distance. The pose is still
+    /// tracked in between. This avoids reinforcing the same points while standing still. Every
+    /// scan is integrated if not set.
+    min_motion_to_map: Option<f32>,
+
+    /// When the point map is published, the pose is published after every scan.
+    #[serde(default)]
+    publish_mode: MapPublishMode,
+
+    /// The file the point map (and the pose) is saved to and loaded from.
+    #[serde(default = "default_snapshot_file")]
+    snapshot_file: PathBuf,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq, Default)]
+enum MergeStrategy {
+    /// Add all points, also when revisiting an area. The map keeps growing.
+    #[default]
+    Append,
+    /// Only add the points that are not already covered by the map, keeping it compact.
+    NearestMerge,
+}
+
+fn default_merge_radius() -> f32 {
+    0.02
+}
+
+fn default_snapshot_file() -> PathBuf {
+    PathBuf::from("point_map.yaml")
 }
 
 impl NodeConfig for IcpPointMapNodeConfig {
     fn instantiate(&self, pubsub: &mut pubsub::PubSub) -> Box<dyn Node> {
         Box::new(IcpPointMapNode {
             sub_obs: pubsub.subscribe(&self.topic_observation),
-            pub_pose: pubsub.publish(&self.topic_pose),
+            pub_pose: pubsub.publish_registered(&self.topic_pose),
             pub_point_map: pubsub.publish(&self.topic_pointmap),
-            point_map: IcpPointMapper::new(self.icp),
+            pub_quality: self
+                .topic_quality
+                .as_ref()
+                .map(|t| pubsub.publish_registered(t)),
+            pub_scan_before: self.topic_scan_before.as_ref().map(|t| pubsub.publish(t)),
+            pub_scan_after: self.topic_scan_after.as_ref().map(|t| pubsub.publish(t)),
+            publish_alignment: self.publish_alignment,
+            point_map: IcpPointMapper::new(IcpPointMapperSettings {
+                icp: self.icp,
+                reject_above_chi: self.reject_above_chi,
+                max_step_translation: self.max_step_translation,
+                max_step_rotation: self.max_step_rotation,
+                outlier_filter: self.outlier_filter,
+                reference: match self.window_size {
+                    Some(n) => ReferenceMode::SlidingWindow(n),
+                    None => self.reference,
+                },
+                merge_radius: match self.merge_strategy {
+                    MergeStrategy::Append => None,
+                    MergeStrategy::NearestMerge => Some(self.merge_radius),
+                },
+                min_motion_to_map: self.min_motion_to_map,
+            }),
+            min_strength: self.min_strength,
+            stable_rpm: self.stable_rpm,
+            unstable_scans: 0,
+            map_publish: MapPublishGate::new(self.publish_mode),
+            seen_revision: 0,
+            snapshot_file: self.snapshot_file.display().to_string(),
+            snapshot_status: None,
         })
     }
+
+    fn topics(&self) -> Vec<TopicDecl> {
+        let mut topics = vec![
+            TopicDecl::subscribe::<Observation>(&self.topic_observation),
+            TopicDecl::publish::<Pose>(&self.topic_pose),
+            TopicDecl::publish::<PointMap>(&self.topic_pointmap),
+        ];
+
+        if let Some(topic) = &self.topic_quality {
+            topics.push(TopicDecl::publish::<IcpQuality>(topic));
+        }
+        for topic in [&self.topic_scan_before, &self.topic_scan_after]
+            .into_iter()
+            .flatten()
+        {
+            topics.push(TopicDecl::publish::<PointMap>(topic));
+        }
+
+        topics
+    }
+}
+
+impl IcpPointMapNode {
+    fn reset(&mut self) {
+        self.point_map.reset();
+
+        // let everyone know about the now empty map
+        self.publish_map();
+    }
+
+    fn save(&self) -> Result<(), SnapshotError> {
+        snapshot::save(&self.point_map.snapshot(), Path::new(&self.snapshot_file))
+    }
+
+    /// Replaces the map and the pose with the saved ones, to continue mapping where an earlier
+    /// session stopped.
+    fn load(&mut self) -> Result<(), SnapshotError> {
+        let snapshot: PointMapSnapshot = snapshot::load(Path::new(&self.snapshot_file))?;
+        self.point_map.restore(snapshot);
+        self.publish_map();
+        Ok(())
+    }
+
+    /// Publishes the pose and the map right away, regardless of the publish mode.
+    fn publish_map(&mut self) {
+        self.pub_pose
+            .publish(Arc::new(self.point_map.estimated_pose()));
+        self.pub_point_map
+            .publish(Arc::new(self.point_map.pointmap()));
+        self.seen_revision = self.point_map.revision();
+    }
+
+    fn publish_alignment(&mut self) {
+        let Some((before, after)) = self.point_map.alignment() else {
+            return;
+        };
+
+        if let Some(pub_scan_before) = &mut self.pub_scan_before {
+            pub_scan_before.publish(Arc::new(before));
+        }
+        if let Some(pub_scan_after) = &mut self.pub_scan_after {
+            pub_scan_after.publish(Arc::new(after));
+        }
+    }
+
+    /// Matches the scan against the map and publishes the new pose.
+    fn process(&mut self, o: &Observation) {
+        if let Some(band) = self.stable_rpm {
+            if !o.rpm_within(band) {
+                self.unstable_scans += 1;
+                return;
+            }
+        }
+
+        let quality = match self.min_strength {
+            Some(min_strength) => self.point_map.update(&o.filter_by_strength(min_strength)),
+            None => self.point_map.update(o),
+        };
+
+        if let (Some(pub_quality), Some(quality)) = (&mut self.pub_quality, quality) {
+            pub_quality.publish(Arc::new(quality));
+        }
+
+        if self.publish_alignment {
+            self.publish_alignment();
+        }
+
+        self.pub_pose
+            .publish(Arc::new(self.point_map.estimated_pose()));
+
+        if self.point_map.revision() != self.seen_revision {
+            self.seen_revision = self.point_map.revision();
+            self.map_publish.changed();
+        }
+        self.map_publish.updated();
+    }
 }
 
 impl Node for IcpPointMapNode {
     fn update(&mut self) {
         // TODO: move all processing to separate thread later, do it here for now (but only one observation per frame)
         if let Some(o) = self.sub_obs.try_recv() {
-            self.point_map.update(&o);
-
-            self.pub_pose
-                .publish(Arc::new(self.point_map.estimated_pose()));
+            self.process(&o);
+        }
 
+        // also without a new scan, the rate limit may have expired since the map changed
+        if self.map_publish.ready() {
             self.pub_point_map
                 .publish(Arc::new(self.point_map.pointmap()));
         }
@@ -149,6 +773,340 @@ impl Node for IcpPointMapNode {
                     self.point_map.stats().reset();
                 }
             });
+
+            ui.separator();
+            ui.label("ICP Parameters: ");
+
+            self.point_map.parameters_mut().ui(ui);
+
+            ui.separator();
+            ui.label("Last Match: ");
+            if let Some(result) = self.point_map.last_result() {
+                ui.label(
+                    RichText::new(format!(
+                        "chi: {:.6}\niterations: {}\ntime: {:.2} ms",
+                        result.chi_values.last().copied().unwrap_or_default(),
+                        result.chi_values.len(),
+                        result.execution_time.as_secs_f64() * 1000.0
+                    ))
+                    .monospace(),
+                );
+            } else {
+                ui.label("-");
+            }
+
+            if self.pub_scan_before.is_some() || self.pub_scan_after.is_some() {
+                ui.checkbox(&mut self.publish_alignment, "Publish Scan Alignment");
+            }
+
+            self.map_publish.mode.ui(ui);
+
+            if let Some([min, max]) = self.stable_rpm {
+                ui.label(format!(
+                    "Dropped {} scans outside {min:.0}-{max:.0} rpm",
+                    self.unstable_scans
+                ));
+            }
+
+            if self.point_map.min_motion_to_map.is_some() {
+                ui.label(format!(
+                    "Skipped mapping {} scans while stationary",
+                    self.point_map.stationary_scans()
+                ));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    self.reset();
+                }
+
+                if ui
+                    .add_enabled(
+                        self.point_map.outlier_filter.is_some(),
+                        egui::Button::new("Remove Outliers"),
+                    )
+                    .clicked()
+                {
+                    self.point_map.remove_map_outliers();
+                    self.pub_point_map
+                        .publish(Arc::new(self.point_map.pointmap()));
+                    self.seen_revision = self.point_map.revision();
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Map File: ");
+                ui.text_edit_singleline(&mut self.snapshot_file);
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    self.snapshot_status = Some(match self.save() {
+                        Ok(()) => format!("Saved to {}", self.snapshot_file),
+                        Err(e) => format!("Save failed: {e}"),
+                    });
+                }
+                if ui.button("Load").clicked() {
+                    self.snapshot_status = Some(match self.load() {
+                        Ok(()) => format!("Loaded {}", self.snapshot_file),
+                        Err(e) => format!("Load failed: {e}"),
+                    });
+                }
+            });
+            if let Some(status) = &self.snapshot_status {
+                ui.label(status);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::robot::Measurement;
+
+    use super::*;
+
+    fn observation() -> Observation {
+        Observation {
+            id: 0,
+            measurements: (0..8)
+                .map(|i| Measurement {
+                    angle: (i as f64 * 45.0).to_radians(),
+                    distance: 1.0 + i as f64 * 0.1,
+                    strength: 1.0,
+                    valid: true,
+                    phase: i as f32 / 8.0,
+                })
+                .collect(),
+            rpm: None,
+            angle_min: 0.0,
+            angle_max: 315f64.to_radians(),
+            angle_increment: 45f64.to_radians(),
+            timestamp: 0.0,
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings::default());
+        mapper.update(&observation());
+
+        let yaml = serde_yaml::to_string(&mapper.snapshot()).unwrap();
+
+        let mut restored = IcpPointMapper::default();
+        restored.restore(serde_yaml::from_str(&yaml).unwrap());
+
+        assert_eq!(restored.pointmap().0, mapper.pointmap().0);
+        assert_eq!(restored.pointmap().0.ncols(), 8);
+        assert_eq!(
+            Vector3::from(restored.estimated_pose()),
+            Vector3::from(mapper.estimated_pose())
+        );
+    }
+
+    #[test]
+    fn window_evicts_oldest_scans() {
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+            reference: ReferenceMode::SlidingWindow(2),
+            ..Default::default()
+        });
+
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), 8);
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), 16);
+
+        // the first scan is dropped once the window is full
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), 16);
+
+        // without a window everything is kept
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings::default());
+        for _ in 0..3 {
+            mapper.update(&observation());
+        }
+        assert_eq!(mapper.pointmap().0.ncols(), 24);
+    }
+
+    #[test]
+    fn reference_depends_on_mode() {
+        let reference_after_three_scans = |mode| {
+            let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+                reference: mode,
+                ..Default::default()
+            });
+            for _ in 0..3 {
+                mapper.update(&observation());
+            }
+            let (_, last_scan) = mapper.alignment().unwrap();
+            (mapper.reference_points().unwrap().clone(), last_scan.0)
+        };
+
+        let (reference, last_scan) = reference_after_three_scans(ReferenceMode::PreviousScan);
+        assert_eq!(reference.ncols(), 8);
+        assert_eq!(reference.cast::<f32>(), last_scan);
+
+        let (reference, _) = reference_after_three_scans(ReferenceMode::AccumulatedMap);
+        assert_eq!(reference.ncols(), 24);
+
+        let (reference, _) = reference_after_three_scans(ReferenceMode::SlidingWindow(2));
+        assert_eq!(reference.ncols(), 16);
+    }
+
+    #[test]
+    fn nearest_merge_drops_known_points() {
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+            merge_radius: Some(0.05),
+            ..Default::default()
+        });
+
+        mapper.update(&observation());
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), 8);
+
+        // points further away than the radius are still added
+        let mut further = observation();
+        for m in &mut further.measurements {
+            m.distance += 0.5;
+        }
+        mapper.update(&further);
+        assert!(mapper.pointmap().0.ncols() > 8);
+
+        // matching against the previous scan drops the points it already saw
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+            reference: ReferenceMode::PreviousScan,
+            merge_radius: Some(0.05),
+            ..Default::default()
         });
+        mapper.update(&observation());
+        mapper.update(&observation());
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), 8);
+
+        // the new points are compared with the whole map, not only with the previous scan
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+            // keep the pose where it is, the scans do not overlap
+            icp: IcpParameters {
+                iterations: 0,
+                ..Default::default()
+            },
+            reference: ReferenceMode::PreviousScan,
+            merge_radius: Some(0.05),
+            ..Default::default()
+        });
+        mapper.update(&observation());
+        mapper.update(&further);
+        let n_points = mapper.pointmap().0.ncols();
+        assert!(n_points > 8);
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), n_points);
+    }
+
+    #[test]
+    fn alignment_of_last_scan() {
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings::default());
+        mapper.update(&observation());
+        assert!(mapper.alignment().is_none());
+
+        mapper.update(&observation());
+        let (before, after) = mapper.alignment().unwrap();
+        assert_eq!(before.0.ncols(), 8);
+        assert_eq!(after.0, mapper.pointmap().0.columns(8, 8));
+    }
+
+    #[test]
+    fn large_steps_are_rejected() {
+        let mut rotated = observation();
+        for m in &mut rotated.measurements {
+            m.angle += 0.2;
+        }
+
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+            max_step_rotation: Some(0.05),
+            ..Default::default()
+        });
+        mapper.update(&observation());
+        let quality = mapper.update(&rotated).unwrap();
+
+        assert!(quality.rejected);
+        assert_eq!(mapper.pointmap().0.ncols(), 8);
+        assert_eq!(Vector3::from(mapper.estimated_pose()), Vector3::zeros());
+
+        // the same match is fine without the limit
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings::default());
+        mapper.update(&observation());
+        assert!(!mapper.update(&rotated).unwrap().rejected);
+        assert_eq!(mapper.pointmap().0.ncols(), 16);
+
+        // only the rotation is too large, the translation is within its limit
+        let rejected = |max_step_rotation| {
+            let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+                max_step_translation: Some(0.05),
+                max_step_rotation,
+                ..Default::default()
+            });
+            mapper.update(&observation());
+            mapper.update(&rotated).unwrap().rejected
+        };
+        assert!(rejected(Some(0.05)));
+        assert!(!rejected(None));
+    }
+
+    #[test]
+    fn stationary_scans_are_not_mapped() {
+        let mut mapper = IcpPointMapper::new(IcpPointMapperSettings {
+            min_motion_to_map: Some(0.05),
+            ..Default::default()
+        });
+
+        mapper.update(&observation());
+        mapper.update(&observation());
+        mapper.update(&observation());
+        assert_eq!(mapper.pointmap().0.ncols(), 8);
+        assert_eq!(mapper.stationary_scans(), 2);
+
+        // the pose is still tracked once the robot moves, and the scan is integrated
+        let mut moved = observation();
+        for m in &mut moved.measurements {
+            m.angle += 0.2;
+        }
+        assert!(!mapper.update(&moved).unwrap().rejected);
+        assert_eq!(mapper.pointmap().0.ncols(), 16);
+        assert_eq!(mapper.stationary_scans(), 2);
+    }
+
+    #[test]
+    fn rate_limited_map_is_published_without_new_scans() {
+        let config: IcpPointMapNodeConfig = serde_yaml::from_str(
+            "topic_pose: pose
+topic_observation: scan
+topic_pointmap: map
+publish_mode: !RateLimited 20.0",
+        )
+        .unwrap();
+
+        let mut pubsub = pubsub::PubSub::new();
+        let mut maps = pubsub.subscribe::<PointMap>("map");
+        let mut scans = pubsub.publish("scan");
+        let mut node = config.instantiate(&mut pubsub);
+        let mut step = |pubsub: &mut pubsub::PubSub| {
+            pubsub.tick();
+            node.update();
+            pubsub.tick();
+            std::iter::from_fn(|| maps.try_recv()).last()
+        };
+
+        // the (empty) map of a new node goes out right away
+        assert_eq!(step(&mut pubsub).unwrap().0.ncols(), 0);
+
+        // the first scan changes the map, but too soon after the last publish
+        scans.publish(Arc::new(observation()));
+        assert!(step(&mut pubsub).is_none());
+
+        // no new scan arrives, the map is sent once the limit expires
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        assert_eq!(step(&mut pubsub).unwrap().0.ncols(), 8);
+        assert!(step(&mut pubsub).is_none());
     }
 }
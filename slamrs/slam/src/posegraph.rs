@@ -0,0 +1,270 @@
+//! A 2D pose graph, the foundation for correcting the trajectory when a loop is closed. The
+//! nodes are the poses of the robot and the edges are measurements of the relative pose between
+//! two of them, e.g. from matching their scans. Optimizing the graph finds the poses that agree
+//! best with all measurements.
+
+use common::robot::Pose;
+use nalgebra::{DMatrix, DVector, Matrix2, Matrix3, Rotation2, Vector3};
+
+use crate::Scalar;
+
+/// A measurement of the pose of node `to` relative to node `from`.
+#[derive(Clone, Copy, Debug)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// The pose of `to` in the frame of `from`.
+    pub measurement: Pose,
+    /// The inverse of the covariance of the measurement, how much it is trusted.
+    pub information: Matrix3<Scalar>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PoseGraph {
+    nodes: Vec<Pose>,
+    edges: Vec<Edge>,
+}
+
+/// Returns the pose of `to` in the frame of `from`.
+pub fn relative_pose(from: &Pose, to: &Pose) -> Pose {
+    let xy = Rotation2::new(-from.theta) * (to.xy() - from.xy());
+    Pose {
+        x: xy.x,
+        y: xy.y,
+        theta: normalize_angle(to.theta - from.theta),
+    }
+}
+
+fn normalize_angle<T: nalgebra::RealField + Copy>(angle: T) -> T {
+    T::atan2(angle.sin(), angle.cos())
+}
+
+fn to_vector(pose: &Pose) -> Vector3<Scalar> {
+    Vector3::<f32>::from(*pose).cast()
+}
+
+/// The error of an edge between the poses `xi` and `xj` with the `measurement` of `xj` relative
+/// to `xi`, together with its Jacobians with respect to `xi` and `xj`.
+fn linearize(
+    xi: &Vector3<Scalar>,
+    xj: &Vector3<Scalar>,
+    measurement: &Vector3<Scalar>,
+) -> (Vector3<Scalar>, Matrix3<Scalar>, Matrix3<Scalar>) {
+    let ri_t = Rotation2::new(-xi[2]).into_inner();
+    let rij_t = Rotation2::new(-measurement[2]).into_inner();
+    let dt = xj.xy() - xi.xy();
+
+    let error_xy = rij_t * (ri_t * dt - measurement.xy());
+    let error = Vector3::new(
+        error_xy.x,
+        error_xy.y,
+        normalize_angle(xj[2] - xi[2] - measurement[2]),
+    );
+
+    // the derivative of the transposed rotation of `xi` with respect to its angle
+    let (sin, cos) = xi[2].sin_cos();
+    let dri_t = Matrix2::new(-sin, cos, -cos, -sin);
+
+    let mut a = Matrix3::zeros();
+    a.fixed_view_mut::<2, 2>(0, 0).copy_from(&(-rij_t * ri_t));
+    a.fixed_view_mut::<2, 1>(0, 2)
+        .copy_from(&(rij_t * dri_t * dt));
+    a[(2, 2)] = -1.0;
+
+    let mut b = Matrix3::zeros();
+    b.fixed_view_mut::<2, 2>(0, 0).copy_from(&(rij_t * ri_t));
+    b[(2, 2)] = 1.0;
+
+    (error, a, b)
+}
+
+impl PoseGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node with an initial guess of its pose, returns its index.
+    pub fn add_node(&mut self, pose: Pose) -> usize {
+        self.nodes.push(pose);
+        self.nodes.len() - 1
+    }
+
+    /// Adds a measurement of the pose of node `to` relative to node `from`.
+    pub fn add_edge(
+        &mut self,
+        from: usize,
+        to: usize,
+        measurement: Pose,
+        information: Matrix3<Scalar>,
+    ) {
+        assert!(
+            from < self.nodes.len() && to < self.nodes.len(),
+            "edge between unknown nodes {from} and {to}"
+        );
+        self.edges.push(Edge {
+            from,
+            to,
+            measurement,
+            information,
+        });
+    }
+
+    pub fn nodes(&self) -> &[Pose] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The sum of the squared errors of all edges, weighted by their information.
+    pub fn error(&self) -> Scalar {
+        self.edges
+            .iter()
+            .map(|edge| {
+                let (error, _, _) = linearize(
+                    &to_vector(&self.nodes[edge.from]),
+                    &to_vector(&self.nodes[edge.to]),
+                    &to_vector(&edge.measurement),
+                );
+                (error.transpose() * edge.information * error)[0]
+            })
+            .sum()
+    }
+
+    /// Moves the nodes to better agree with the edges using Gauss-Newton, for at most
+    /// `iterations` steps. The first node is kept in place, since the edges only constrain the
+    /// poses relative to each other. Returns the remaining error.
+    ///
+    /// Solves the full dense system, which is fine for graphs of up to a few hundred nodes.
+    pub fn optimize(&mut self, iterations: usize) -> Scalar {
+        let n = self.nodes.len();
+        if n == 0 {
+            return 0.0;
+        }
+
+        let mut x: Vec<Vector3<Scalar>> = self.nodes.iter().map(to_vector).collect();
+
+        for _ in 0..iterations {
+            let mut h = DMatrix::<Scalar>::zeros(3 * n, 3 * n);
+            let mut b = DVector::<Scalar>::zeros(3 * n);
+
+            for edge in &self.edges {
+                let (error, a, bj) =
+                    linearize(&x[edge.from], &x[edge.to], &to_vector(&edge.measurement));
+                let omega = &edge.information;
+                let (i, j) = (3 * edge.from, 3 * edge.to);
+
+                let mut h_ii = h.fixed_view_mut::<3, 3>(i, i);
+                h_ii += a.transpose() * omega * a;
+                let mut h_ij = h.fixed_view_mut::<3, 3>(i, j);
+                h_ij += a.transpose() * omega * bj;
+                let mut h_ji = h.fixed_view_mut::<3, 3>(j, i);
+                h_ji += bj.transpose() * omega * a;
+                let mut h_jj = h.fixed_view_mut::<3, 3>(j, j);
+                h_jj += bj.transpose() * omega * bj;
+
+                let mut b_i = b.fixed_rows_mut::<3>(i);
+                b_i += a.transpose() * omega * error;
+                let mut b_j = b.fixed_rows_mut::<3>(j);
+                b_j += bj.transpose() * omega * error;
+            }
+
+            // anchor the first node, otherwise the whole graph is free to move
+            let mut h_00 = h.fixed_view_mut::<3, 3>(0, 0);
+            h_00 += Matrix3::identity();
+
+            let Some(cholesky) = h.cholesky() else {
+                break;
+            };
+            let dx = cholesky.solve(&(-b));
+
+            for (k, xk) in x.iter_mut().enumerate() {
+                *xk += dx.fixed_rows::<3>(3 * k);
+                xk[2] = normalize_angle(xk[2]);
+            }
+
+            if dx.amax() < 1e-6 {
+                break;
+            }
+        }
+
+        self.nodes = x
+            .into_iter()
+            .map(|xk| Pose::from(xk.cast::<f32>()))
+            .collect();
+
+        self.error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    #[test]
+    fn relative_pose_round_trip() {
+        let from = Pose {
+            x: 1.0,
+            y: 2.0,
+            theta: FRAC_PI_2,
+        };
+        let to = Pose {
+            x: 1.0,
+            y: 3.0,
+            theta: 0.0,
+        };
+
+        let relative = relative_pose(&from, &to);
+        assert!((relative.x - 1.0).abs() < 1e-6);
+        assert!(relative.y.abs() < 1e-6);
+        assert!((relative.theta + FRAC_PI_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn loop_closure_reduces_error() {
+        // drive around a 1 m square, the odometry turns a little too much at every corner
+        let step = Pose {
+            x: 1.0,
+            y: 0.0,
+            theta: FRAC_PI_2,
+        };
+        let measured_step = Pose {
+            theta: FRAC_PI_2 + 0.05,
+            ..step
+        };
+
+        let mut graph = PoseGraph::new();
+        let mut pose = Pose::default();
+        let mut previous = graph.add_node(pose);
+        for _ in 0..4 {
+            let xy = pose.xy() + Rotation2::new(pose.theta) * measured_step.xy();
+            pose = Pose {
+                x: xy.x,
+                y: xy.y,
+                theta: pose.theta + measured_step.theta,
+            };
+            let node = graph.add_node(pose);
+            graph.add_edge(previous, node, measured_step, Matrix3::identity());
+            previous = node;
+        }
+
+        // recognizing the start again closes the loop, and is trusted more than the odometry
+        graph.add_edge(previous, 0, Pose::default(), Matrix3::identity() * 10.0);
+
+        let distance_to_start = |graph: &PoseGraph| graph.nodes()[4].xy().norm();
+        let error_before = graph.error();
+        let distance_before = distance_to_start(&graph);
+
+        let error_after = graph.optimize(10);
+
+        assert!(
+            error_after < 0.1 * error_before,
+            "{error_before} -> {error_after}"
+        );
+        assert!(distance_to_start(&graph) < 0.5 * distance_before);
+        assert!(Vector3::from(graph.nodes()[0]).norm() < 1e-3);
+    }
+}
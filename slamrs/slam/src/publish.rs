@@ -0,0 +1,133 @@
+use eframe::egui;
+use egui::Slider;
+use serde::Deserialize;
+use web_time::Instant;
+
+/// When a SLAM node publishes its map. Copying a large map for every scan is expensive, so it
+/// can be published only when it changed, optionally at a limited rate.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Default)]
+pub enum MapPublishMode {
+    /// After every processed scan, also if the map did not change.
+    #[default]
+    EveryUpdate,
+    /// Only when the map changed since it was last published.
+    OnChange,
+    /// When the map changed, but at most this often (Hz).
+    RateLimited(f32),
+}
+
+impl MapPublishMode {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Publish Map: ");
+            for (mode, name) in [
+                (MapPublishMode::EveryUpdate, "Every Update"),
+                (MapPublishMode::OnChange, "On Change"),
+                (MapPublishMode::RateLimited(1.0), "Rate Limited"),
+            ] {
+                let selected = std::mem::discriminant(self) == std::mem::discriminant(&mode);
+                if ui.selectable_label(selected, name).clicked() && !selected {
+                    *self = mode;
+                }
+            }
+        });
+
+        if let MapPublishMode::RateLimited(hz) = self {
+            ui.add(
+                Slider::new(hz, 0.1..=30.0)
+                    .logarithmic(true)
+                    .text("Max Rate (Hz)"),
+            );
+        }
+    }
+}
+
+/// Decides when to publish a map according to a [`MapPublishMode`]. A new gate is always ready,
+/// so the current map is published on the first update after creating (or resetting) a node.
+/// Nodes check it every frame, also when no scan arrived, so that a map held back by the rate
+/// limit is still published once the limit expires.
+pub(crate) struct MapPublishGate {
+    pub mode: MapPublishMode,
+    /// If the map changed since it was last published.
+    dirty: bool,
+    last_publish: Option<Instant>,
+}
+
+impl MapPublishGate {
+    pub fn new(mode: MapPublishMode) -> Self {
+        Self {
+            mode,
+            dirty: true,
+            last_publish: None,
+        }
+    }
+
+    /// Records that the map changed since it was last published.
+    pub fn changed(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Records that a scan was processed, which publishes the map also if it did not change in
+    /// [`MapPublishMode::EveryUpdate`].
+    pub fn updated(&mut self) {
+        if self.mode == MapPublishMode::EveryUpdate {
+            self.dirty = true;
+        }
+    }
+
+    /// Returns true if the map should be published now, in which case it is considered
+    /// published.
+    pub fn ready(&mut self) -> bool {
+        let ready = match self.mode {
+            MapPublishMode::EveryUpdate | MapPublishMode::OnChange => self.dirty,
+            MapPublishMode::RateLimited(hz) => {
+                self.dirty
+                    && self
+                        .last_publish
+                        .map_or(true, |last| last.elapsed().as_secs_f32() >= 1.0 / hz)
+            }
+        };
+
+        if ready {
+            self.dirty = false;
+            self.last_publish = Some(Instant::now());
+        }
+
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publishes_according_to_mode() {
+        let mut gate = MapPublishGate::new(MapPublishMode::EveryUpdate);
+        assert!(gate.ready());
+        assert!(!gate.ready(), "no scan was processed");
+        gate.updated();
+        assert!(gate.ready());
+
+        let mut gate = MapPublishGate::new(MapPublishMode::OnChange);
+        assert!(gate.ready(), "the first map is always published");
+        assert!(!gate.ready());
+        gate.updated();
+        assert!(!gate.ready(), "the map did not change");
+        gate.changed();
+        assert!(gate.ready());
+        assert!(!gate.ready());
+
+        let mut gate = MapPublishGate::new(MapPublishMode::RateLimited(0.01));
+        assert!(gate.ready());
+        gate.changed();
+        assert!(!gate.ready(), "too soon after the last publish");
+
+        let mut gate = MapPublishGate::new(MapPublishMode::RateLimited(1000.0));
+        assert!(gate.ready());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(!gate.ready(), "nothing changed");
+        gate.changed();
+        assert!(gate.ready());
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// Errors when restoring the state of a SLAM algorithm from a snapshot.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("expected {expected} values in {field}, got {actual}")]
+    SizeMismatch {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    // the causes are part of the messages (which is what the UI shows), so they are not
+    // reported as sources as well
+    #[error("could not access {}: {error}", path.display())]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    #[error("invalid snapshot file: {0}")]
+    Format(serde_yaml::Error),
+
+    #[error("the map is not square and was saved with the cells in an older order")]
+    OldCellOrder,
+}
+
+impl From<serde_yaml::Error> for SnapshotError {
+    fn from(error: serde_yaml::Error) -> Self {
+        SnapshotError::Format(error)
+    }
+}
+
+/// Writes a snapshot to a YAML file, replacing the file if it exists.
+pub fn save<T: Serialize>(snapshot: &T, path: &Path) -> Result<(), SnapshotError> {
+    let contents = serde_yaml::to_string(snapshot)?;
+    fs::write(path, contents).map_err(|error| SnapshotError::Io {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+/// Reads a snapshot written by [`save`].
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<T, SnapshotError> {
+    let contents = fs::read_to_string(path).map_err(|error| SnapshotError::Io {
+        path: path.to_owned(),
+        error,
+    })?;
+    Ok(serde_yaml::from_str(&contents)?)
+}